@@ -51,6 +51,7 @@ pub struct RpcHandle {
     pub host: host::HostRpcClient<Channel>,
     pub nexus: nexus::NexusRpcClient<Channel>,
     pub snapshot: snapshot::SnapshotRpcClient<Channel>,
+    pub event: event::EventRpcClient<Channel>,
 }
 
 impl RpcHandle {
@@ -103,6 +104,10 @@ impl RpcHandle {
             snapshot::SnapshotRpcClient::connect(format!("http://{endpoint}"))
                 .await
                 .unwrap();
+        let event =
+            event::EventRpcClient::connect(format!("http://{endpoint}"))
+                .await
+                .unwrap();
 
         Ok(Self {
             name,
@@ -114,6 +119,7 @@ impl RpcHandle {
             host,
             nexus,
             snapshot,
+            event,
         })
     }
 }
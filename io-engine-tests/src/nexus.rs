@@ -179,6 +179,14 @@ impl NexusBuilder {
     }
 
     pub async fn publish(&self) -> Result<Nexus, Status> {
+        self.publish_on(vec![]).await
+    }
+
+    /// Publishes the nexus, listening only on the given target addresses.
+    pub async fn publish_on(
+        &self,
+        listeners: Vec<String>,
+    ) -> Result<Nexus, Status> {
         self.rpc()
             .lock()
             .await
@@ -187,6 +195,7 @@ impl NexusBuilder {
                 uuid: self.uuid(),
                 key: String::new(),
                 share: 1,
+                listeners,
                 ..Default::default()
             })
             .await
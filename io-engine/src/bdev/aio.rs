@@ -14,7 +14,19 @@ use url::Url;
 use spdk_rs::libspdk::{bdev_aio_delete, create_aio_bdev};
 
 use crate::{
-    bdev::{dev::reject_unknown_parameters, util::uri, CreateDestroy, GetName},
+    bdev::{
+        dev::reject_unknown_parameters,
+        sparse_file::{
+            is_sparse_file,
+            sparse_file_create,
+            sparse_file_destroy,
+            SparseFileKind,
+            SparseFileParams,
+        },
+        util::uri,
+        CreateDestroy,
+        GetName,
+    },
     bdev_api::{self, BdevError},
     core::{UntypedBdev, VerboseError},
     ffihelper::{cb_arg, done_errno_cb, ErrnoResult},
@@ -25,6 +37,7 @@ pub(super) struct Aio {
     alias: String,
     blk_size: u32,
     uuid: Option<uuid::Uuid>,
+    sparse: SparseFileParams,
 }
 
 impl Debug for Aio {
@@ -67,6 +80,9 @@ impl TryFrom<&Url> for Aio {
             },
         )?;
 
+        let sparse =
+            SparseFileParams::from_parameters(url, &mut parameters, blk_size)?;
+
         reject_unknown_parameters(url, parameters)?;
 
         Ok(Aio {
@@ -74,6 +90,7 @@ impl TryFrom<&Url> for Aio {
             alias: url.to_string(),
             blk_size,
             uuid,
+            sparse,
         })
     }
 }
@@ -98,15 +115,30 @@ impl CreateDestroy for Aio {
 
         debug!("{:?}: creating bdev", self);
 
-        let cname = CString::new(self.get_name()).unwrap();
-
-        let errno = unsafe {
-            create_aio_bdev(
-                cname.as_ptr(),
-                cname.as_ptr(),
+        let errno = if self.sparse.is_sparse() {
+            if let Err(err) = sparse_file_create(
+                SparseFileKind::Aio,
+                &self.name,
                 self.blk_size,
-                false,
+                &self.sparse,
             )
+            .await
+            {
+                error!("{:?} error: {}", self, err.verbose());
+                return Err(err);
+            }
+            0
+        } else {
+            let cname = CString::new(self.get_name()).unwrap();
+
+            unsafe {
+                create_aio_bdev(
+                    cname.as_ptr(),
+                    cname.as_ptr(),
+                    self.blk_size,
+                    false,
+                )
+            }
         };
 
         if errno != 0 {
@@ -141,6 +173,13 @@ impl CreateDestroy for Aio {
     async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
         debug!("{:?}: deleting", self);
 
+        if is_sparse_file(&self.name) {
+            if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+                bdev.remove_alias(&self.alias);
+            }
+            return sparse_file_destroy(&self.name).await;
+        }
+
         match UntypedBdev::lookup_by_name(&self.name) {
             Some(mut bdev) => {
                 bdev.remove_alias(&self.alias);
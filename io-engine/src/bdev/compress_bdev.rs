@@ -0,0 +1,1442 @@
+//! A compressing bdev, stacked over a thin provisioned base device such as a
+//! thin lvol, which backs compressed lvols.
+//!
+//! The logical device is split into chunks, 64 KiB by default. Each chunk is
+//! compressed on its own, and stored in a variable number of 4 KiB units of
+//! the data area of the base device. A map at the start of the base device
+//! records the units of each chunk. Chunks which do not compress are stored
+//! as they are, and chunks which only have zeroes take no space at all.
+//!
+//! The base device holds:
+//!  - a 4 KiB header, which records the codec, the chunk size, the logical size
+//!    of the device and the number of chunks the map has room for;
+//!  - the map, 8 bytes per chunk, with room for `MAP_GROWTH` times the chunks
+//!    of the device when it is created, so that it can grow;
+//!  - the data area, allocated lowest units first, so that a thin base device
+//!    only allocates the clusters the compressed data takes.
+//!
+//! Chunks are written out of place: the new units are written first, then
+//! the map, and only then are the previous units of the chunk unmapped and
+//! freed. Partial writes of a chunk read, decompress and merge the chunk
+//! first. The base device is consistent at any time, so that a snapshot of
+//! the base device holds the data of the device when it is taken.
+//!
+//! The I/Os are processed on the core they are submitted on. The chunks of
+//! an I/O are processed concurrently, each with the chunk locked, so that the
+//! I/Os of a chunk from any core are processed one after another. The writes
+//! of a unit of the map are serialized the same way.
+//!
+//! The device is grown with `compress_resize`, after its base device has
+//! grown, as far as its map has room for.
+//!
+//! The compression is done by this device, not within the blobstore: the
+//! clusters of a compressed lvol are not mapped to compressed chunks, the
+//! lvol holds the compressed data of the device.
+//!
+//! The header is written when the device is opened for the first time, which
+//! requires the header and the map areas of the base device to be zeroed.
+//!
+//! The device is created from a URI:
+//! ```ignore
+//!     compress:///<base_bdev>?codec=lz4&chunk_size=64KiB&size=<bytes>
+//! ```
+use std::{
+    convert::TryFrom,
+    fmt::{Debug, Display, Formatter},
+    marker::PhantomData,
+    pin::Pin,
+    str::FromStr,
+};
+
+use async_trait::async_trait;
+use byte_unit::Byte;
+use futures::{channel::oneshot, future::join_all};
+use libc::c_void;
+use nix::errno::Errno;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use spdk_rs::{
+    libspdk::{spdk_bdev_io, spdk_bdev_notify_blockcnt_change},
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevOps,
+    IoChannel,
+    IoDevice,
+    IoType,
+    IoVec,
+    WithModuleInit,
+};
+use url::Url;
+
+use crate::{
+    bdev::{
+        dev::reject_unknown_parameters,
+        device_open,
+        stacked::{self, StackedDevice, StackedUri},
+        util::{iov::IovCursor, region_lock::RegionLocks},
+        CreateDestroy,
+        GetName,
+    },
+    bdev_api::BdevError,
+    core::{
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        CoreError,
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        IoCompletionStatus,
+        Mthread,
+        Reactors,
+        UntypedBdev,
+        VerboseError,
+    },
+    ffihelper::cb_arg,
+};
+
+/// Name of the compress bdev module, which is the driver name of its bdevs.
+/// SPDK has its own "compress" module already.
+pub(crate) const COMPRESS_MODULE_NAME: &str = "compress_ng";
+
+/// Product name of the compress bdevs.
+const COMPRESS_PRODUCT_ID: &str = "Compressed Device";
+
+/// Size of a unit of the base device, which is also the size of the header.
+const UNIT: u64 = 4096;
+
+/// Number of map entries in a unit.
+const ENTRIES_PER_UNIT: u64 = UNIT / 8;
+
+/// Default size of a chunk.
+pub const DEFAULT_CHUNK_SIZE: u64 = 64 * 1024;
+
+/// Largest chunk, as the number of units of a chunk is 8 bits in the map.
+const MAX_CHUNK_SIZE: u64 = 512 * 1024;
+
+/// Factor by which a device can grow, which sizes the room of its map.
+const MAP_GROWTH: u64 = 8;
+
+/// Magic of the compress header.
+const COMPRESS_MAGIC: [u8; 8] = *b"IOECMPRS";
+
+/// Version of the compress header format.
+const COMPRESS_VERSION: u32 = 1;
+
+/// Compression codecs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompressionCodec {
+    Lz4,
+}
+
+impl FromStr for CompressionCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lz4" => Ok(Self::Lz4),
+            _ => Err(format!("unknown compression codec '{s}'")),
+        }
+    }
+}
+
+impl Display for CompressionCodec {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
+
+impl CompressionCodec {
+    /// Compresses a chunk, prefixed with the compressed length.
+    fn compress(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Lz4 => {
+                let compressed = lz4_flex::block::compress(data);
+                let mut out = Vec::with_capacity(4 + compressed.len());
+                out.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+                out.extend_from_slice(&compressed);
+                out
+            }
+        }
+    }
+
+    /// Decompresses a chunk compressed by `compress`.
+    fn decompress(&self, data: &[u8], out: &mut [u8]) -> Result<(), String> {
+        let mut len = [0u8; 4];
+        len.copy_from_slice(data.get(.. 4).ok_or("truncated chunk")?);
+        let len = u32::from_le_bytes(len) as usize;
+        let data = data.get(4 .. 4 + len).ok_or("truncated chunk")?;
+
+        match self {
+            Self::Lz4 => match lz4_flex::block::decompress_into(data, out) {
+                Ok(n) if n == out.len() => Ok(()),
+                Ok(n) => Err(format!("chunk decompressed to {n} bytes")),
+                Err(e) => Err(e.to_string()),
+            },
+        }
+    }
+}
+
+/// Space usage of a compress device.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CompressStats {
+    /// Size of the device.
+    pub logical_size: u64,
+    /// Size of the chunks which have data.
+    pub logical_bytes: u64,
+    /// Size of the units which store these chunks.
+    pub stored_bytes: u64,
+}
+
+impl CompressStats {
+    /// Returns the compression ratio of the data stored.
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            1.0
+        } else {
+            self.logical_bytes as f64 / self.stored_bytes as f64
+        }
+    }
+}
+
+/// Header stored at the start of the base device.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct CompressHeader {
+    magic: [u8; 8],
+    version: u32,
+    codec: String,
+    chunk_size: u64,
+    map_chunks: u64,
+    logical_size: u64,
+}
+
+/// Layout of the base device of a compress device.
+#[derive(Debug, Clone, Copy)]
+struct Layout {
+    codec: CompressionCodec,
+    chunk_size: u64,
+    /// Number of chunks of the device, when it is opened or resized.
+    chunks: u64,
+    /// Number of chunks the map has room for.
+    map_chunks: u64,
+    map_offset: u64,
+    data_offset: u64,
+    /// Number of units of the data area, when the device is opened or
+    /// resized.
+    data_units: u64,
+}
+
+impl Layout {
+    /// Makes the layout of a device of `logical_size` bytes, rounded up to
+    /// whole chunks, with a map which has room for `map_chunks` chunks, or
+    /// returns `None` if the map has no room for the chunks or the base
+    /// device is too small.
+    fn new(
+        codec: CompressionCodec,
+        chunk_size: u64,
+        logical_size: u64,
+        map_chunks: u64,
+        base_size: u64,
+    ) -> Option<Self> {
+        let chunks = (logical_size + chunk_size - 1) / chunk_size;
+        let data_offset = UNIT + round_up(map_chunks * 8, UNIT);
+        if chunks == 0
+            || chunks > map_chunks
+            || base_size < data_offset + chunk_size
+        {
+            return None;
+        }
+
+        Some(Self {
+            codec,
+            chunk_size,
+            chunks,
+            map_chunks,
+            map_offset: UNIT,
+            data_offset,
+            data_units: (base_size - data_offset) / UNIT,
+        })
+    }
+
+    fn chunk_units(&self) -> u64 {
+        self.chunk_size / UNIT
+    }
+
+    fn logical_size(&self) -> u64 {
+        self.chunks * self.chunk_size
+    }
+
+    /// Writes the header of the device at the start of the base device.
+    async fn write_header(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+    ) -> Result<(), String> {
+        let mut buf = hdl
+            .dma_malloc(UNIT)
+            .map_err(|e| format!("failed to allocate a buffer: {e}"))?;
+        buf.fill(0);
+
+        let header = CompressHeader {
+            magic: COMPRESS_MAGIC,
+            version: COMPRESS_VERSION,
+            codec: self.codec.to_string(),
+            chunk_size: self.chunk_size,
+            map_chunks: self.map_chunks,
+            logical_size: self.logical_size(),
+        };
+        bincode::serialize_into(buf.as_mut_slice(), &header)
+            .map_err(|e| format!("failed to encode header: {e}"))?;
+        hdl.write_at(0, &buf)
+            .await
+            .map_err(|e| format!("I/O failed: {e}"))?;
+        Ok(())
+    }
+}
+
+fn round_up(n: u64, to: u64) -> u64 {
+    (n + to - 1) / to * to
+}
+
+/// Returns the size of a base device which can hold `logical_size` bytes of
+/// data that does not compress, with a map which has room to grow.
+pub fn compress_backing_size(logical_size: u64, chunk_size: u64) -> u64 {
+    let chunks = (logical_size + chunk_size - 1) / chunk_size;
+    UNIT + round_up(chunks * MAP_GROWTH * 8, UNIT) + chunks * chunk_size
+}
+
+/// Returns the largest logical size a base device of `base_size` bytes can
+/// hold, if its data does not compress.
+fn default_logical_size(base_size: u64, chunk_size: u64) -> u64 {
+    let mut chunks =
+        base_size.saturating_sub(UNIT) / (chunk_size + MAP_GROWTH * 8);
+    while chunks > 0
+        && compress_backing_size(chunks * chunk_size, chunk_size) > base_size
+    {
+        chunks -= 1;
+    }
+    chunks * chunk_size
+}
+
+/// A map entry, which records the units of a chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Entry(u64);
+
+impl Entry {
+    const MAPPED: u64 = 1 << 63;
+    const RAW: u64 = 1 << 62;
+    const UNMAPPED: Entry = Entry(0);
+
+    fn new(unit: u64, units: u64, raw: bool) -> Self {
+        Self(
+            Self::MAPPED
+                | if raw { Self::RAW } else { 0 }
+                | (units << 48)
+                | unit,
+        )
+    }
+
+    fn is_mapped(&self) -> bool {
+        self.0 & Self::MAPPED != 0
+    }
+
+    fn is_raw(&self) -> bool {
+        self.0 & Self::RAW != 0
+    }
+
+    fn unit(&self) -> u64 {
+        self.0 & ((1 << 48) - 1)
+    }
+
+    fn units(&self) -> u64 {
+        (self.0 >> 48) & 0xff
+    }
+}
+
+/// Map and allocation state of a compress device.
+struct CompressState {
+    map: Vec<u64>,
+    /// Bitmap of the units in use.
+    used: Vec<u64>,
+    /// Lowest unit which may be free.
+    free_hint: u64,
+    data_units: u64,
+    mapped_chunks: u64,
+    stored_units: u64,
+}
+
+impl CompressState {
+    fn new(layout: &Layout) -> Self {
+        Self {
+            map: vec![0; layout.chunks as usize],
+            used: vec![0; ((layout.data_units + 63) / 64) as usize],
+            free_hint: 0,
+            data_units: layout.data_units,
+            mapped_chunks: 0,
+            stored_units: 0,
+        }
+    }
+
+    /// Grows the map to `chunks` chunks and the data area to `data_units`
+    /// units, unless they are larger already.
+    fn grow(&mut self, chunks: u64, data_units: u64) {
+        if chunks as usize > self.map.len() {
+            self.map.resize(chunks as usize, 0);
+        }
+        if data_units > self.data_units {
+            self.data_units = data_units;
+            self.used.resize(((data_units + 63) / 64) as usize, 0);
+        }
+    }
+
+    fn is_used(&self, unit: u64) -> bool {
+        self.used[(unit / 64) as usize] & (1 << (unit % 64)) != 0
+    }
+
+    fn set_used(&mut self, unit: u64, units: u64, used: bool) {
+        for u in unit .. unit + units {
+            if used {
+                self.used[(u / 64) as usize] |= 1 << (u % 64);
+            } else {
+                self.used[(u / 64) as usize] &= !(1 << (u % 64));
+            }
+        }
+    }
+
+    /// Allocates the lowest `units` contiguous free units.
+    fn alloc(&mut self, units: u64) -> Option<u64> {
+        let mut start = self.free_hint;
+        let mut u = start;
+        while u < self.data_units {
+            if self.is_used(u) {
+                start = u + 1;
+            } else if u + 1 - start == units {
+                self.set_used(start, units, true);
+                if start == self.free_hint {
+                    self.free_hint = start + units;
+                }
+                return Some(start);
+            }
+            u += 1;
+        }
+        None
+    }
+
+    fn free(&mut self, entry: Entry) {
+        if entry.is_mapped() {
+            self.set_used(entry.unit(), entry.units(), false);
+            self.free_hint = self.free_hint.min(entry.unit());
+        }
+    }
+
+    /// Replaces the entry of a chunk, and returns the previous entry.
+    fn set_entry(&mut self, chunk: u64, entry: Entry) -> Entry {
+        let old = Entry(self.map[chunk as usize]);
+        self.map[chunk as usize] = entry.0;
+        for (e, sign) in [(old, false), (entry, true)] {
+            if e.is_mapped() {
+                if sign {
+                    self.mapped_chunks += 1;
+                    self.stored_units += e.units();
+                } else {
+                    self.mapped_chunks -= 1;
+                    self.stored_units -= e.units();
+                }
+            }
+        }
+        old
+    }
+}
+
+/// An I/O processed by a future on the core it was submitted on, sent back
+/// to the thread it was submitted on to be completed.
+struct CompleteIo {
+    ptr: *mut spdk_bdev_io,
+    success: bool,
+}
+
+// Required because `CompleteIo.ptr` is a raw pointer, which is not Send.
+unsafe impl Send for CompleteIo {}
+
+impl CompleteIo {
+    fn complete(self) {
+        let bio = BdevIo::<CompressDevice>::legacy_from_ptr(self.ptr);
+        if self.success {
+            bio.ok();
+        } else {
+            bio.fail();
+        }
+    }
+}
+
+/// Per-core channel of a compress device.
+pub(crate) struct CompressChannel {
+    handle: Option<Box<dyn BlockDeviceHandle>>,
+}
+
+/// A compress device.
+pub(crate) struct CompressDevice<'c> {
+    name: String,
+    base: String,
+    base_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    /// Layout of the base device when the device is opened. The number of
+    /// chunks and of data units are those of the state, which grow.
+    layout: Layout,
+    block_len: u64,
+    state: Mutex<CompressState>,
+    /// Locks of the chunks with an I/O in progress.
+    chunk_locks: RegionLocks,
+    /// Locks of the units of the map being written.
+    map_locks: RegionLocks,
+    event_sink: Option<DeviceEventSink>,
+    _c: PhantomData<&'c ()>,
+}
+
+impl Debug for CompressDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Compress '{}' over '{}' ({}, {} chunks)",
+            self.name, self.base, self.layout.codec, self.layout.chunk_size
+        )
+    }
+}
+
+impl<'c> CompressDevice<'c> {
+    /// Completes a flush or a reset passed through to the base device.
+    fn completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio = BdevIo::<CompressDevice<'c>>::legacy_from_ptr(
+            ctx as *mut spdk_bdev_io,
+        );
+        if status == IoCompletionStatus::Success {
+            bio.ok();
+        } else {
+            bio.fail();
+        }
+    }
+
+    fn stats(&self) -> CompressStats {
+        let state = self.state.lock();
+        CompressStats {
+            logical_size: state.map.len() as u64 * self.layout.chunk_size,
+            logical_bytes: state.mapped_chunks * self.layout.chunk_size,
+            stored_bytes: state.stored_units * UNIT,
+        }
+    }
+
+    /// Reads and decompresses a chunk.
+    async fn read_chunk(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        chunk: u64,
+    ) -> Result<Vec<u8>, CoreError> {
+        let mut data = vec![0; self.layout.chunk_size as usize];
+        let entry = Entry(self.state.lock().map[chunk as usize]);
+        if !entry.is_mapped() {
+            return Ok(data);
+        }
+
+        let len = entry.units() * UNIT;
+        let mut buf = hdl.dma_malloc(len).map_err(|_| {
+            CoreError::DmaAllocationFailed {
+                size: len,
+            }
+        })?;
+        let offset = self.layout.data_offset + entry.unit() * UNIT;
+        hdl.read_at(offset, &mut buf).await?;
+
+        if entry.is_raw() {
+            data.copy_from_slice(&buf.as_slice()[.. data.len()]);
+        } else if let Err(e) =
+            self.layout.codec.decompress(buf.as_slice(), &mut data)
+        {
+            error!("{self:?}: chunk {chunk} is corrupted: {e}");
+            return Err(CoreError::ReadDispatch {
+                source: Errno::EILSEQ,
+                offset,
+                len,
+            });
+        }
+        Ok(data)
+    }
+
+    /// Compresses and writes a chunk out of place, then updates the map.
+    async fn write_chunk(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        chunk: u64,
+        data: &[u8],
+    ) -> Result<(), CoreError> {
+        let entry = if data.iter().all(|b| *b == 0) {
+            Entry::UNMAPPED
+        } else {
+            let compressed = self.layout.codec.compress(data);
+            let units = round_up(compressed.len() as u64, UNIT) / UNIT;
+            let (payload, units, raw) = if units < self.layout.chunk_units() {
+                (compressed.as_slice(), units, false)
+            } else {
+                (data, self.layout.chunk_units(), true)
+            };
+
+            let unit = self.state.lock().alloc(units).ok_or_else(|| {
+                CoreError::WriteDispatch {
+                    source: Errno::ENOSPC,
+                    offset: chunk * self.layout.chunk_size,
+                    len: self.layout.chunk_size,
+                }
+            })?;
+            let entry = Entry::new(unit, units, raw);
+
+            let len = units * UNIT;
+            let mut buf = match hdl.dma_malloc(len) {
+                Ok(buf) => buf,
+                Err(_) => {
+                    self.state.lock().free(entry);
+                    return Err(CoreError::DmaAllocationFailed {
+                        size: len,
+                    });
+                }
+            };
+            buf.fill(0);
+            buf.as_mut_slice()[.. payload.len()].copy_from_slice(payload);
+
+            let offset = self.layout.data_offset + unit * UNIT;
+            if let Err(e) = hdl.write_at(offset, &buf).await {
+                self.state.lock().free(entry);
+                return Err(e);
+            }
+            entry
+        };
+
+        let old = match self.write_map(hdl, chunk, entry).await {
+            Ok(old) => old,
+            Err(e) => {
+                self.state.lock().free(entry);
+                return Err(e);
+            }
+        };
+        // The previous units stay in use until they are unmapped, so that
+        // they are not written by another chunk meanwhile.
+        self.unmap_units(hdl, old).await;
+        self.state.lock().free(old);
+        Ok(())
+    }
+
+    /// Writes the unit of the map which has the entry of a chunk, with the
+    /// new entry of the chunk, then sets the entry of the chunk and returns
+    /// its previous entry. The entry is only set once it is written, and the
+    /// writes of a unit of the map are serialized, so that a failed write
+    /// leaves the map as it was.
+    async fn write_map(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        chunk: u64,
+        entry: Entry,
+    ) -> Result<Entry, CoreError> {
+        let map_unit = chunk / ENTRIES_PER_UNIT;
+        let _guard = self.map_locks.lock(map_unit).await;
+
+        let mut buf = hdl.dma_malloc(UNIT).map_err(|_| {
+            CoreError::DmaAllocationFailed {
+                size: UNIT,
+            }
+        })?;
+        buf.fill(0);
+
+        let first = map_unit * ENTRIES_PER_UNIT;
+        {
+            let state = self.state.lock();
+            let last = (first + ENTRIES_PER_UNIT).min(state.map.len() as u64);
+            let page = buf.as_mut_slice();
+            for (i, e) in state.map[first as usize .. last as usize]
+                .iter()
+                .enumerate()
+            {
+                let e = if first + i as u64 == chunk {
+                    entry.0
+                } else {
+                    *e
+                };
+                page[i * 8 .. (i + 1) * 8].copy_from_slice(&e.to_le_bytes());
+            }
+        }
+
+        let offset = self.layout.map_offset + map_unit * UNIT;
+        hdl.write_at(offset, &buf).await?;
+        Ok(self.state.lock().set_entry(chunk, entry))
+    }
+
+    /// Unmaps the units of a previous entry of a chunk on the base device,
+    /// so that a thin base device releases the clusters they no longer use.
+    /// A failed unmap only leaves the units allocated on the base device.
+    async fn unmap_units(&self, hdl: &dyn BlockDeviceHandle, entry: Entry) {
+        if !entry.is_mapped()
+            || !hdl.get_device().io_type_supported(IoType::Unmap)
+        {
+            return;
+        }
+
+        let offset_blocks =
+            (self.layout.data_offset + entry.unit() * UNIT) / self.block_len;
+        let num_blocks = entry.units() * UNIT / self.block_len;
+        let (s, r) = oneshot::channel::<IoCompletionStatus>();
+        let ctx = cb_arg(s);
+        if let Err(e) =
+            hdl.unmap_blocks(offset_blocks, num_blocks, Self::unmapped, ctx)
+        {
+            drop(unsafe {
+                Box::from_raw(ctx as *mut oneshot::Sender<IoCompletionStatus>)
+            });
+            warn!("{self:?}: failed to unmap freed units: {e}");
+            return;
+        }
+
+        match r.await {
+            Ok(IoCompletionStatus::Success) => {}
+            status => {
+                warn!("{self:?}: failed to unmap freed units: {status:?}")
+            }
+        }
+    }
+
+    /// Completes the unmap of the freed units of a chunk.
+    fn unmapped(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let s = unsafe {
+            Box::from_raw(ctx as *mut oneshot::Sender<IoCompletionStatus>)
+        };
+        s.send(status).ok();
+    }
+
+    /// Processes a read, a write, a write zeroes or an unmap, with the
+    /// chunks it covers processed concurrently.
+    async fn process(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        io_type: IoType,
+        offset: u64,
+        num_blocks: u64,
+        iovs: &[IoVec],
+    ) -> Result<(), CoreError> {
+        let chunk_size = self.layout.chunk_size;
+        let start = offset * self.block_len;
+        let end = start + num_blocks * self.block_len;
+        let mut cur = IovCursor::new(iovs);
+
+        let mut chunks = Vec::new();
+        let mut pos = start;
+        while pos < end {
+            let chunk = pos / chunk_size;
+            let off = (pos - chunk * chunk_size) as usize;
+            let len = (((chunk + 1) * chunk_size).min(end) - pos) as usize;
+            let iovs = if matches!(io_type, IoType::Read | IoType::Write) {
+                cur.take(len)
+            } else {
+                Vec::new()
+            };
+            chunks
+                .push(self.process_chunk(hdl, io_type, chunk, off, len, iovs));
+            pos += len as u64;
+        }
+
+        join_all(chunks).await.into_iter().collect()
+    }
+
+    /// Processes the `len` bytes of an I/O from `off` in a chunk, with the
+    /// chunk locked. `iovs` has the data of a read or a write.
+    async fn process_chunk(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        io_type: IoType,
+        chunk: u64,
+        off: usize,
+        len: usize,
+        iovs: Vec<IoVec>,
+    ) -> Result<(), CoreError> {
+        let chunk_size = self.layout.chunk_size;
+        let mut cur = IovCursor::new(&iovs);
+
+        let _guard = self.chunk_locks.lock(chunk).await;
+        if io_type == IoType::Read {
+            let data = self.read_chunk(hdl, chunk).await?;
+            cur.copy_from(&data[off .. off + len]);
+            return Ok(());
+        }
+
+        let mut data = if len as u64 == chunk_size {
+            vec![0; chunk_size as usize]
+        } else {
+            self.read_chunk(hdl, chunk).await?
+        };
+        if io_type == IoType::Write {
+            cur.copy_to(&mut data[off .. off + len]);
+        } else {
+            data[off .. off + len].fill(0);
+        }
+        self.write_chunk(hdl, chunk, &data).await
+    }
+
+    /// Returns the size of the base device.
+    fn base_size(&self) -> Option<u64> {
+        self.base_desc
+            .as_ref()
+            .map(|d| d.get_device().num_blocks() * self.block_len)
+    }
+
+    /// Checks the device can grow to `size` bytes, and returns the size of
+    /// the base device it then needs to hold its data if it does not
+    /// compress.
+    fn check_resize(&self, size: u64) -> Result<u64, BdevError> {
+        let invalid = |message: String| BdevError::ResizeBdevInvalid {
+            name: self.name.clone(),
+            message,
+        };
+        let chunk_size = self.layout.chunk_size;
+        let chunks = (size + chunk_size - 1) / chunk_size;
+        let current = self.state.lock().map.len() as u64;
+        if chunks < current {
+            return Err(invalid(format!(
+                "cannot shrink from {} to {} bytes",
+                current * chunk_size,
+                chunks * chunk_size
+            )));
+        }
+        if chunks > self.layout.map_chunks {
+            return Err(invalid(format!(
+                "cannot grow beyond {} bytes",
+                self.layout.map_chunks * chunk_size
+            )));
+        }
+
+        Ok(self.layout.data_offset + chunks * chunk_size)
+    }
+
+    /// Grows the device to `size` bytes, rounded up to whole chunks. The
+    /// new size is recorded in the header before it is used.
+    async fn resize(&self, size: u64) -> Result<(), BdevError> {
+        self.check_resize(size)?;
+        let invalid = |message: String| BdevError::ResizeBdevInvalid {
+            name: self.name.clone(),
+            message,
+        };
+
+        let desc =
+            self.base_desc
+                .as_ref()
+                .ok_or_else(|| BdevError::BdevNotFound {
+                    name: self.base.clone(),
+                })?;
+        let base_size = self.base_size().unwrap_or_default();
+        let layout = Layout::new(
+            self.layout.codec,
+            self.layout.chunk_size,
+            size,
+            self.layout.map_chunks,
+            base_size,
+        )
+        .ok_or_else(|| invalid(format!("'{}' is too small", self.base)))?;
+        if layout.chunks == self.state.lock().map.len() as u64 {
+            return Ok(());
+        }
+
+        let hdl = desc
+            .get_io_handle_nonblock()
+            .await
+            .map_err(|e| invalid(format!("failed to get I/O handle: {e}")))?;
+        layout.write_header(&*hdl).await.map_err(invalid)?;
+        self.state.lock().grow(layout.chunks, layout.data_units);
+
+        let mut bdev =
+            UntypedBdev::lookup_by_name(&self.name).ok_or_else(|| {
+                BdevError::BdevNotFound {
+                    name: self.name.clone(),
+                }
+            })?;
+        let num_blocks = layout.logical_size() / self.block_len;
+        info!(
+            "{self:?}: resizing from {} to {num_blocks} blocks",
+            bdev.num_blocks()
+        );
+        let rc = unsafe {
+            spdk_bdev_notify_blockcnt_change(
+                bdev.unsafe_inner_mut_ptr(),
+                num_blocks,
+            )
+        };
+        if rc != 0 {
+            return Err(BdevError::ResizeBdevFailed {
+                source: Errno::from_i32(rc.abs()),
+                name: self.name.clone(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Grows the data area after the base device has been resized.
+    fn sync_data_units(&self) {
+        let base_size = match self.base_size() {
+            Some(size) => size,
+            None => return,
+        };
+        let data_units =
+            base_size.saturating_sub(self.layout.data_offset) / UNIT;
+
+        let mut state = self.state.lock();
+        if data_units < state.data_units {
+            error!(
+                "{self:?}: '{}' has shrunk below the data area, ignoring",
+                self.base
+            );
+            return;
+        }
+        let chunks = state.map.len() as u64;
+        state.grow(chunks, data_units);
+    }
+}
+
+impl StackedDevice for CompressDevice<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl DeviceEventListener for CompressDevice<'_> {
+    fn handle_device_event(&self, evt: DeviceEventType, dev_name: &str) {
+        if dev_name != self.base {
+            return;
+        }
+
+        if evt == DeviceEventType::DeviceResized {
+            self.sync_data_units();
+        } else if stacked::is_removal(evt) {
+            let name = self.name.clone();
+            stacked::destroy_removed(self, dev_name, async move {
+                compress_destroy(&name).await
+            });
+        }
+    }
+
+    fn get_listener_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl<'c> IoDevice for CompressDevice<'c> {
+    type ChannelData = CompressChannel;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        CompressChannel {
+            handle: stacked::base_io_handle(&*self, self.base_desc.as_deref()),
+        }
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'c> BdevOps for CompressDevice<'c> {
+    type ChannelData = CompressChannel;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        stacked::destruct(self, |s| {
+            s.event_sink = None;
+            s.base_desc = None;
+        });
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        bio: BdevIo<Self>,
+    ) {
+        match bio.io_type() {
+            IoType::Flush | IoType::Reset => {
+                let hdl = match chan.channel_data().handle.as_deref() {
+                    Some(hdl) => hdl,
+                    None => {
+                        bio.fail();
+                        return;
+                    }
+                };
+                let ctx = bio.legacy_as_ptr().cast();
+                let res = if bio.io_type() == IoType::Flush {
+                    hdl.flush_io(Self::completion, ctx)
+                } else {
+                    hdl.reset(Self::completion, ctx)
+                };
+                if let Err(e) = res {
+                    error!(
+                        "{self:?}: I/O submission failed: {e}",
+                        e = e.verbose()
+                    );
+                    bio.fail();
+                }
+            }
+            IoType::Read
+            | IoType::Write
+            | IoType::WriteZeros
+            | IoType::Unmap => {
+                let thread = Mthread::current();
+                let hdl = self
+                    .base_desc
+                    .as_ref()
+                    .and_then(|d| d.get_io_handle().ok());
+                let (thread, hdl) = match (thread, hdl) {
+                    (Some(thread), Some(hdl)) => (thread, hdl),
+                    _ => {
+                        bio.fail();
+                        return;
+                    }
+                };
+
+                let ptr = bio.legacy_as_ptr();
+                Reactors::current().send_future(async move {
+                    let bio = BdevIo::<CompressDevice>::legacy_from_ptr(ptr);
+                    let dev = bio.bdev_checked(COMPRESS_PRODUCT_ID).data();
+                    let iovs = if bio.iov_count() > 0 {
+                        unsafe {
+                            std::slice::from_raw_parts(
+                                bio.iovs(),
+                                bio.iov_count() as usize,
+                            )
+                        }
+                    } else {
+                        &[]
+                    };
+
+                    let res = dev
+                        .process(
+                            &*hdl,
+                            bio.io_type(),
+                            bio.offset(),
+                            bio.num_blocks(),
+                            iovs,
+                        )
+                        .await;
+                    if let Err(e) = &res {
+                        error!(
+                            "{dev:?}: {:?} I/O failed: {e}",
+                            bio.io_type(),
+                            e = e.verbose()
+                        );
+                    }
+
+                    thread.send_msg(
+                        CompleteIo {
+                            ptr,
+                            success: res.is_ok(),
+                        },
+                        |io| io.complete(),
+                    );
+                });
+            }
+            _ => bio.fail(),
+        }
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            IoType::Read => true,
+            // The device is read-only over a read-only base device, such as
+            // a snapshot.
+            IoType::Write | IoType::WriteZeros | IoType::Unmap => {
+                self.base_desc.as_ref().map_or(false, |d| {
+                    d.get_device().io_type_supported(IoType::Write)
+                })
+            }
+            IoType::Flush | IoType::Reset => self
+                .base_desc
+                .as_ref()
+                .map_or(false, |d| d.get_device().io_type_supported(io_type)),
+            _ => false,
+        }
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// Compress bdev module.
+pub(crate) struct CompressModule {}
+
+impl CompressModule {
+    /// Returns the compress bdev module instance.
+    /// Panics if the compress module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(COMPRESS_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for CompressModule {
+    fn module_init() -> i32 {
+        info!("Initializing Compress Module");
+        0
+    }
+}
+
+impl BdevModuleBuild for CompressModule {}
+
+pub fn register() {
+    CompressModule::builder(COMPRESS_MODULE_NAME)
+        .with_module_init()
+        .register();
+}
+
+/// Returns the space usage of a compress device.
+pub fn compress_bdev_stats(name: &str) -> Result<CompressStats, BdevError> {
+    stacked::lookup::<CompressDevice>(CompressModule::current(), name)
+        .map(|b| b.data().stats())
+}
+
+/// Returns the size of the base device of a compress device once the compress
+/// device is grown to `size` bytes, which it needs to hold its data if it does
+/// not compress. Fails if the compress device cannot grow to that size.
+pub fn compress_resize_backing_size(
+    name: &str,
+    size: u64,
+) -> Result<u64, BdevError> {
+    stacked::lookup::<CompressDevice>(CompressModule::current(), name)?
+        .data()
+        .check_resize(size)
+}
+
+/// Grows a compress device to `size` bytes, after its base device has been
+/// grown to the size returned by `compress_resize_backing_size`.
+pub async fn compress_resize(name: &str, size: u64) -> Result<(), BdevError> {
+    let bdev =
+        stacked::lookup::<CompressDevice>(CompressModule::current(), name)?;
+    bdev.data().resize(size).await
+}
+
+/// Destroys a compress device by its name.
+pub(crate) async fn compress_destroy(name: &str) -> Result<(), BdevError> {
+    stacked::destroy::<CompressDevice>(CompressModule::current(), name).await
+}
+
+/// Compress device URI.
+#[derive(Debug)]
+pub(super) struct Compress {
+    /// Name of the compress bdev.
+    name: String,
+    /// Alias which can be used to open the bdev.
+    alias: String,
+    /// Name of the base bdev.
+    base: String,
+    /// Codec, chunk size and logical size used when the base device has no
+    /// header yet.
+    codec: CompressionCodec,
+    chunk_size: u64,
+    size: Option<u64>,
+    /// UUID of the compress bdev.
+    uuid: Option<uuid::Uuid>,
+}
+
+impl TryFrom<&Url> for Compress {
+    type Error = BdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let StackedUri {
+            base,
+            name,
+            uuid,
+            mut parameters,
+        } = StackedUri::parse(url)?;
+
+        let codec = match parameters.remove("codec") {
+            Some(codec) => {
+                codec.parse().map_err(|message| BdevError::InvalidUri {
+                    uri: url.to_string(),
+                    message,
+                })?
+            }
+            None => CompressionCodec::Lz4,
+        };
+
+        let mut bytes = |param: &str| -> Result<Option<u64>, BdevError> {
+            parameters
+                .remove(param)
+                .map(|value| {
+                    Byte::from_str(&value)
+                        .map(|b| b.get_bytes() as u64)
+                        .map_err(|_| BdevError::InvalidUri {
+                            uri: url.to_string(),
+                            message: format!("invalid '{param}': {value}"),
+                        })
+                })
+                .transpose()
+        };
+        let chunk_size = bytes("chunk_size")?.unwrap_or(DEFAULT_CHUNK_SIZE);
+        let size = bytes("size")?;
+
+        if !chunk_size.is_power_of_two()
+            || !(UNIT ..= MAX_CHUNK_SIZE).contains(&chunk_size)
+        {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: format!(
+                    "'chunk_size' must be a power of two from {UNIT} to \
+                    {MAX_CHUNK_SIZE}"
+                ),
+            });
+        }
+
+        reject_unknown_parameters(url, parameters)?;
+
+        Ok(Self {
+            name,
+            alias: url.to_string(),
+            base,
+            codec,
+            chunk_size,
+            size,
+            uuid,
+        })
+    }
+}
+
+impl GetName for Compress {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Compress {
+    fn invalid(&self, message: String) -> BdevError {
+        BdevError::CompressBaseInvalid {
+            name: self.name.clone(),
+            message,
+        }
+    }
+
+    /// Reads the header and the map of the base device, or writes the header
+    /// if the base device has none yet.
+    async fn load(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        base_size: u64,
+    ) -> Result<(Layout, CompressState), BdevError> {
+        let io_err = |e: CoreError| self.invalid(format!("I/O failed: {e}"));
+        let dma_err = |_| BdevError::CreateBdevFailed {
+            source: Errno::ENOMEM,
+            name: self.name.clone(),
+        };
+
+        let mut buf = hdl.dma_malloc(UNIT).map_err(dma_err)?;
+        hdl.read_at(0, &mut buf).await.map_err(io_err)?;
+
+        if buf.as_slice().iter().all(|b| *b == 0) {
+            let logical_size = self.size.unwrap_or_else(|| {
+                default_logical_size(base_size, self.chunk_size)
+            });
+            let chunks = (logical_size + self.chunk_size - 1) / self.chunk_size;
+            let layout = Layout::new(
+                self.codec,
+                self.chunk_size,
+                logical_size,
+                chunks * MAP_GROWTH,
+                base_size,
+            )
+            .ok_or_else(|| {
+                self.invalid(format!("'{}' is too small", self.base))
+            })?;
+
+            info!(
+                "Compress '{}': initializing header on '{}'",
+                self.name, self.base
+            );
+            layout
+                .write_header(hdl)
+                .await
+                .map_err(|e| self.invalid(e))?;
+            return Ok((layout, CompressState::new(&layout)));
+        }
+
+        let header =
+            match bincode::deserialize::<CompressHeader>(buf.as_slice()) {
+                Ok(h)
+                    if h.magic == COMPRESS_MAGIC
+                        && h.version == COMPRESS_VERSION =>
+                {
+                    h
+                }
+                _ => {
+                    return Err(self.invalid(format!(
+                        "'{}' is neither a compressed device nor zeroed",
+                        self.base
+                    )))
+                }
+            };
+        let codec = header.codec.parse().map_err(|e| self.invalid(e))?;
+        let layout = Layout::new(
+            codec,
+            header.chunk_size,
+            header.logical_size,
+            header.map_chunks,
+            base_size,
+        )
+        .ok_or_else(|| {
+            self.invalid(format!("'{}' does not fit its header", self.base))
+        })?;
+
+        let mut state = CompressState::new(&layout);
+        let map_len = layout.data_offset - layout.map_offset;
+        let step = map_len.min(1 << 20);
+        let mut buf = hdl.dma_malloc(step).map_err(dma_err)?;
+        let mut chunk = 0;
+        let mut offset = 0;
+        while offset < map_len {
+            hdl.read_at(layout.map_offset + offset, &mut buf)
+                .await
+                .map_err(io_err)?;
+            for e in buf.as_slice().chunks_exact(8) {
+                if chunk == layout.chunks {
+                    break;
+                }
+                let mut le = [0u8; 8];
+                le.copy_from_slice(e);
+                let entry = Entry(u64::from_le_bytes(le));
+                if entry.is_mapped() {
+                    if entry.units() == 0
+                        || entry.unit() + entry.units() > layout.data_units
+                    {
+                        return Err(self.invalid(format!(
+                            "invalid map entry for chunk {chunk}"
+                        )));
+                    }
+                    state.set_used(entry.unit(), entry.units(), true);
+                }
+                state.set_entry(chunk, entry);
+                chunk += 1;
+            }
+            offset += step;
+        }
+
+        Ok((layout, state))
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Compress {
+    type Error = BdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        if UntypedBdev::lookup_by_name(&self.name).is_some() {
+            return Err(BdevError::BdevExists {
+                name: self.name.clone(),
+            });
+        }
+
+        let desc = device_open(&self.base, true).map_err(|e| {
+            error!(
+                "Compress '{}': failed to open base device '{}': {}",
+                self.name,
+                self.base,
+                e.verbose()
+            );
+            BdevError::BdevNotFound {
+                name: self.base.clone(),
+            }
+        })?;
+        let base = desc.get_device();
+        let block_len = base.block_len();
+        if UNIT % block_len != 0 {
+            return Err(self.invalid(format!(
+                "block size {block_len} does not divide {UNIT}"
+            )));
+        }
+
+        let hdl = desc.get_io_handle_nonblock().await.map_err(|e| {
+            self.invalid(format!("failed to get I/O handle: {e}"))
+        })?;
+        let (layout, state) =
+            self.load(&*hdl, base.num_blocks() * block_len).await?;
+
+        let compress = CompressDevice {
+            name: self.name.clone(),
+            base: self.base.clone(),
+            base_desc: Some(desc),
+            layout,
+            block_len,
+            state: Mutex::new(state),
+            chunk_locks: RegionLocks::default(),
+            map_locks: RegionLocks::default(),
+            event_sink: None,
+            _c: Default::default(),
+        };
+
+        let mut builder = CompressModule::current()
+            .bdev_builder()
+            .with_name(&self.name)
+            .with_product_name(COMPRESS_PRODUCT_ID)
+            .with_block_length(block_len as u32)
+            .with_block_count(layout.logical_size() / block_len)
+            .with_required_alignment(9);
+        if let Some(uuid) = self.uuid {
+            builder = builder.with_uuid(uuid.into());
+        }
+        let mut bdev = builder.with_data(compress).build();
+
+        unsafe {
+            let c = bdev.data_mut().get_unchecked_mut();
+            c.event_sink = Some(DeviceEventSink::new(bdev.data()));
+        }
+        if let Some(sink) = bdev.data().event_sink.clone() {
+            if let Err(e) = base.add_event_listener(sink) {
+                warn!(
+                    "Compress '{}': failed to listen to events of '{}': {}",
+                    self.name,
+                    self.base,
+                    e.verbose()
+                );
+            }
+        }
+
+        bdev.data().register_io_device(Some(&self.name));
+
+        if let Err(err) = bdev.register_bdev() {
+            error!(
+                "Compress '{}': bdev registration failed: {}",
+                self.name,
+                err.verbose()
+            );
+            return Err(BdevError::CreateBdevFailed {
+                source: err,
+                name: self.name.clone(),
+            });
+        }
+
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            if !bdev.add_alias(&self.alias) {
+                error!(
+                    "failed to add alias {} to device {}",
+                    self.alias,
+                    self.get_name()
+                );
+            }
+        }
+
+        info!("{:?}: created", bdev.data());
+        Ok(self.name.clone())
+    }
+
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            bdev.remove_alias(&self.alias);
+        }
+        compress_destroy(&self.name).await
+    }
+}
@@ -0,0 +1,950 @@
+//! A copy-on-read overlay bdev, which layers a local thin lvol over a
+//! read-only source device, typically a remote replica. It can be used as a
+//! nexus child, to seed a new replica which serves I/Os right away instead of
+//! after a full rebuild.
+//!
+//! The device is split into regions of the cluster size of the lvol. Reads
+//! and writes of regions not yet populated first copy the regions from the
+//! source to the lvol, and a background task populates all the other regions,
+//! one after another. Once all the regions are populated, the device only
+//! passes its I/Os through to the lvol.
+//!
+//! The population of the regions is not persisted: a region is known to be
+//! populated when its cluster is allocated on the lvol, as the lvol is only
+//! written after the region was populated. Regions which only have zeroes
+//! on the source are left unallocated on the lvol.
+//!
+//! The device is created from a URI, with the source URI percent-encoded:
+//! ```ignore
+//!     cor:///<local_lvol>?source=<source_uri>[&name=<name>][&uuid=<uuid>]
+//! ```
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::{Debug, Display, Formatter},
+    marker::PhantomData,
+    ops::Range,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use async_trait::async_trait;
+use futures::channel::oneshot;
+use libc::c_void;
+use nix::errno::Errno;
+use parking_lot::Mutex;
+use spdk_rs::{
+    libspdk::spdk_bdev_io,
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevOps,
+    IoChannel,
+    IoDevice,
+    IoType,
+    WithModuleGetCtxSize,
+    WithModuleInit,
+};
+use url::Url;
+
+use crate::{
+    bdev::{
+        dev::reject_unknown_parameters,
+        device_open,
+        stacked::{self, StackedDevice, StackedUri},
+        CreateDestroy,
+        GetName,
+    },
+    bdev_api::{bdev_create, bdev_destroy, bdev_get_name, BdevError},
+    core::{
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        CoreError,
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        IoCompletionStatus,
+        Mthread,
+        Reactors,
+        ReadMode,
+        UntypedBdev,
+        VerboseError,
+    },
+    lvs::{Lvol, LvsLvol},
+};
+
+/// Name of the copy-on-read bdev module, which is the driver name of its
+/// bdevs.
+pub(crate) const COR_MODULE_NAME: &str = "cor";
+
+/// Product name of the copy-on-read bdevs.
+const COR_PRODUCT_ID: &str = "Copy-on-read Device";
+
+/// State of the background population of a copy-on-read device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CorState {
+    /// Regions are being populated.
+    Populating,
+    /// All the regions are populated.
+    Complete,
+    /// The population was stopped before completing, as the device is
+    /// destroyed.
+    Stopped,
+    /// Populating a region failed. Regions are still populated when read or
+    /// written.
+    Failed(String),
+}
+
+impl Display for CorState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Populating => write!(f, "populating"),
+            Self::Complete => write!(f, "complete"),
+            Self::Stopped => write!(f, "stopped"),
+            Self::Failed(_) => write!(f, "failed"),
+        }
+    }
+}
+
+/// Progress of the population of a copy-on-read device.
+#[derive(Debug, Clone)]
+pub struct CorProgress {
+    /// URI of the source device.
+    pub source: String,
+    pub state: CorState,
+    /// Size of a region, in bytes.
+    pub region_size: u64,
+    pub total_regions: u64,
+    pub populated_regions: u64,
+}
+
+/// Population state of the regions of a copy-on-read device.
+struct RegionMap {
+    populated: Vec<bool>,
+    /// Regions being populated, with the senders to notify of the result.
+    populating: HashMap<u64, Vec<oneshot::Sender<Result<(), CoreError>>>>,
+}
+
+/// Regions of a copy-on-read device, shared by the device and its
+/// background population task.
+struct CorRegions {
+    region_blocks: u64,
+    block_len: u64,
+    source_blocks: u64,
+    total: u64,
+    populated: AtomicU64,
+    map: Mutex<RegionMap>,
+    state: Mutex<CorState>,
+    /// Set to stop the background population task.
+    stop: AtomicBool,
+    /// Signalled once the background population task ended.
+    task_done: Mutex<Option<oneshot::Receiver<()>>>,
+}
+
+impl CorRegions {
+    /// Returns the regions of a range of blocks.
+    fn range(&self, offset: u64, num_blocks: u64) -> Range<u64> {
+        let first = offset / self.region_blocks;
+        let last = (offset + num_blocks.max(1) - 1) / self.region_blocks;
+        first.min(self.total) .. (last + 1).min(self.total)
+    }
+
+    /// Checks if all the regions of a range are populated.
+    fn is_populated(&self, mut range: Range<u64>) -> bool {
+        if self.populated.load(Ordering::Acquire) == self.total {
+            return true;
+        }
+        let map = self.map.lock();
+        range.all(|r| map.populated[r as usize])
+    }
+}
+
+/// I/O handles of a copy-on-read device.
+struct CorHandles {
+    /// Handle to the local device.
+    local: Box<dyn BlockDeviceHandle>,
+    /// Handle to the local device, failing reads of unallocated clusters.
+    local_check: Box<dyn BlockDeviceHandle>,
+    /// Handle to the source device.
+    source: Box<dyn BlockDeviceHandle>,
+}
+
+impl CorHandles {
+    fn new(dev: &CorDevice) -> Result<Self, CoreError> {
+        let (local, source) = match (&dev.local_desc, &dev.source_desc) {
+            (Some(local), Some(source)) => (local, source),
+            _ => {
+                return Err(CoreError::GetIoChannel {
+                    name: dev.name.clone(),
+                })
+            }
+        };
+
+        let mut local_check = local.get_io_handle()?;
+        local_check.set_read_mode(ReadMode::UnwrittenFail);
+
+        Ok(Self {
+            local: local.get_io_handle()?,
+            local_check,
+            source: source.get_io_handle()?,
+        })
+    }
+}
+
+/// Copies a region from the source to the local device, unless the region
+/// is allocated on the local device already or only has zeroes.
+async fn copy_region(
+    regions: &CorRegions,
+    hdls: &CorHandles,
+    region: u64,
+) -> Result<(), CoreError> {
+    let start = region * regions.region_blocks;
+    let end = (start + regions.region_blocks).min(regions.source_blocks);
+    if start >= end {
+        return Ok(());
+    }
+    let offset = start * regions.block_len;
+    let len = (end - start) * regions.block_len;
+
+    let mut buf =
+        hdls.local_check
+            .dma_malloc(regions.block_len)
+            .map_err(|_| CoreError::DmaAllocationFailed {
+                size: regions.block_len,
+            })?;
+    match hdls.local_check.read_at(offset, &mut buf).await {
+        Ok(_) => return Ok(()),
+        Err(CoreError::ReadingUnallocatedBlock {
+            ..
+        }) => {}
+        Err(e) => return Err(e),
+    }
+
+    let mut buf = hdls.source.dma_malloc(len).map_err(|_| {
+        CoreError::DmaAllocationFailed {
+            size: len,
+        }
+    })?;
+    hdls.source.read_at(offset, &mut buf).await?;
+
+    // Zeroes are not written, for the cluster to stay unallocated.
+    if buf.as_slice().iter().all(|b| *b == 0) {
+        return Ok(());
+    }
+
+    hdls.local.write_at(offset, &buf).await?;
+    Ok(())
+}
+
+/// Populates a region, or waits for the region to be populated if it is
+/// being populated already.
+async fn populate(
+    regions: &CorRegions,
+    hdls: &CorHandles,
+    region: u64,
+) -> Result<(), CoreError> {
+    let wait = {
+        let mut map = regions.map.lock();
+        if map.populated[region as usize] {
+            return Ok(());
+        }
+        match map.populating.get_mut(&region) {
+            Some(waiters) => {
+                let (s, r) = oneshot::channel();
+                waiters.push(s);
+                Some(r)
+            }
+            None => {
+                map.populating.insert(region, Vec::new());
+                None
+            }
+        }
+    };
+
+    if let Some(r) = wait {
+        return r.await.unwrap_or_else(|_| {
+            Err(CoreError::ReadDispatch {
+                source: Errno::ECANCELED,
+                offset: region * regions.region_blocks * regions.block_len,
+                len: regions.region_blocks * regions.block_len,
+            })
+        });
+    }
+
+    let res = copy_region(regions, hdls, region).await;
+
+    let waiters = {
+        let mut map = regions.map.lock();
+        if res.is_ok() {
+            map.populated[region as usize] = true;
+            regions.populated.fetch_add(1, Ordering::AcqRel);
+        }
+        map.populating.remove(&region).unwrap_or_default()
+    };
+    waiters.into_iter().for_each(|w| {
+        w.send(res.clone()).ok();
+    });
+
+    res
+}
+
+/// Populates the regions not yet populated of a copy-on-read device, one
+/// after another.
+async fn populate_task(
+    name: String,
+    regions: Arc<CorRegions>,
+    hdls: CorHandles,
+    done: oneshot::Sender<()>,
+) {
+    for region in 0 .. regions.total {
+        if regions.stop.load(Ordering::Relaxed) {
+            info!("Copy-on-read '{name}': population stopped");
+            *regions.state.lock() = CorState::Stopped;
+            break;
+        }
+
+        if let Err(e) = populate(&regions, &hdls, region).await {
+            error!(
+                "Copy-on-read '{name}': failed to populate region {region}: \
+                {e}",
+                e = e.verbose()
+            );
+            *regions.state.lock() = CorState::Failed(e.to_string());
+            break;
+        }
+    }
+
+    if regions.populated.load(Ordering::Acquire) == regions.total {
+        info!("Copy-on-read '{name}': all regions populated");
+        *regions.state.lock() = CorState::Complete;
+    }
+
+    drop(hdls);
+    done.send(()).ok();
+}
+
+/// Per-I/O context of a copy-on-read device.
+struct CorIoCtx {
+    chan: *const CorChannel,
+}
+
+/// An I/O which waited for its regions to be populated, sent back to the
+/// thread it was submitted on.
+struct ResumeIo {
+    ptr: *mut spdk_bdev_io,
+    populated: bool,
+}
+
+// Required because `ResumeIo.ptr` is a raw pointer, which is not Send.
+unsafe impl Send for ResumeIo {}
+
+impl ResumeIo {
+    fn resume(self) {
+        let bio = BdevIo::<CorDevice>::legacy_from_ptr(self.ptr);
+        if !self.populated {
+            bio.fail();
+            return;
+        }
+
+        let chan = unsafe { &*bio.driver_ctx::<CorIoCtx>().chan };
+        match chan.handles.as_ref() {
+            Some(hdls) => {
+                if let Err(e) = CorDevice::passthrough(&*hdls.local, &bio) {
+                    error!("I/O submission failed: {e}", e = e.verbose());
+                    bio.fail();
+                }
+            }
+            None => bio.fail(),
+        }
+    }
+}
+
+/// Per-core channel of a copy-on-read device.
+pub(crate) struct CorChannel {
+    handles: Option<CorHandles>,
+}
+
+/// A copy-on-read device.
+pub(crate) struct CorDevice<'c> {
+    name: String,
+    local: String,
+    local_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    /// URI of the source device.
+    source_uri: String,
+    source: String,
+    source_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    /// Set if the source device was created for this device, and is to be
+    /// destroyed with it.
+    source_created: bool,
+    regions: Arc<CorRegions>,
+    event_sink: Option<DeviceEventSink>,
+    _c: PhantomData<&'c ()>,
+}
+
+impl Debug for CorDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Copy-on-read '{}' over '{}' from '{}' ({}/{})",
+            self.name,
+            self.local,
+            self.source,
+            self.regions.populated.load(Ordering::Relaxed),
+            self.regions.total
+        )
+    }
+}
+
+impl<'c> CorDevice<'c> {
+    /// Completes an I/O passed through to the local device.
+    fn completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio =
+            BdevIo::<CorDevice<'c>>::legacy_from_ptr(ctx as *mut spdk_bdev_io);
+        if status == IoCompletionStatus::Success {
+            bio.ok();
+        } else {
+            bio.fail();
+        }
+    }
+
+    /// Passes an I/O through to the local device.
+    fn passthrough(
+        hdl: &dyn BlockDeviceHandle,
+        bio: &BdevIo<Self>,
+    ) -> Result<(), CoreError> {
+        let ctx = bio.legacy_as_ptr().cast();
+        match bio.io_type() {
+            IoType::Read => hdl.readv_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Write => hdl.writev_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::WriteZeros => hdl.write_zeroes(
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Flush => hdl.flush_io(Self::completion, ctx),
+            IoType::Reset => hdl.reset(Self::completion, ctx),
+            _ => Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            }),
+        }
+    }
+}
+
+impl StackedDevice for CorDevice<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl DeviceEventListener for CorDevice<'_> {
+    fn handle_device_event(&self, evt: DeviceEventType, dev_name: &str) {
+        if (dev_name == self.local || dev_name == self.source)
+            && stacked::is_removal(evt)
+        {
+            let name = self.name.clone();
+            stacked::destroy_removed(self, dev_name, async move {
+                cor_destroy(&name).await
+            });
+        }
+    }
+
+    fn get_listener_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl<'c> IoDevice for CorDevice<'c> {
+    type ChannelData = CorChannel;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        let handles = match CorHandles::new(&self) {
+            Ok(hdls) => Some(hdls),
+            Err(e) => {
+                error!(
+                    "{self:?}: failed to get I/O handles: {e}",
+                    e = e.verbose()
+                );
+                None
+            }
+        };
+
+        CorChannel {
+            handles,
+        }
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'c> BdevOps for CorDevice<'c> {
+    type ChannelData = CorChannel;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        stacked::destruct(self, |s| {
+            s.event_sink = None;
+            s.local_desc = None;
+            s.source_desc = None;
+        });
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        mut bio: BdevIo<Self>,
+    ) {
+        let chan_data = chan.channel_data();
+        let hdls = match chan_data.handles.as_ref() {
+            Some(hdls) => hdls,
+            None => {
+                bio.fail();
+                return;
+            }
+        };
+
+        let range = match bio.io_type() {
+            IoType::Read | IoType::Write | IoType::WriteZeros => {
+                self.regions.range(bio.offset(), bio.num_blocks())
+            }
+            _ => 0 .. 0,
+        };
+
+        if self.regions.is_populated(range.clone()) {
+            if let Err(e) = Self::passthrough(&*hdls.local, &bio) {
+                error!("{self:?}: I/O submission failed: {e}", e = e.verbose());
+                bio.fail();
+            }
+            return;
+        }
+
+        let thread = match Mthread::current() {
+            Some(thread) => thread,
+            None => {
+                bio.fail();
+                return;
+            }
+        };
+
+        // Regions are populated on the primary core, and the I/O is then
+        // passed through on the thread it was submitted on.
+        *bio.driver_ctx_mut::<CorIoCtx>() = CorIoCtx {
+            chan: chan_data,
+        };
+        let ptr = bio.legacy_as_ptr();
+        Reactors::master().send_future(async move {
+            let bio = BdevIo::<CorDevice>::legacy_from_ptr(ptr);
+            let dev = bio.bdev_checked(COR_PRODUCT_ID).data();
+
+            let res = match CorHandles::new(dev) {
+                Ok(hdls) => {
+                    let mut res = Ok(());
+                    for region in range {
+                        res = populate(&dev.regions, &hdls, region).await;
+                        if res.is_err() {
+                            break;
+                        }
+                    }
+                    res
+                }
+                Err(e) => Err(e),
+            };
+            if let Err(e) = &res {
+                error!("{dev:?}: failed to populate: {e}", e = e.verbose());
+            }
+
+            thread.send_msg(
+                ResumeIo {
+                    ptr,
+                    populated: res.is_ok(),
+                },
+                |io| io.resume(),
+            );
+        });
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            IoType::Read | IoType::Write => true,
+            IoType::WriteZeros | IoType::Flush | IoType::Reset => self
+                .local_desc
+                .as_ref()
+                .map_or(false, |d| d.get_device().io_type_supported(io_type)),
+            _ => false,
+        }
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// Copy-on-read bdev module.
+pub(crate) struct CorModule {}
+
+impl CorModule {
+    /// Returns the copy-on-read bdev module instance.
+    /// Panics if the copy-on-read module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(COR_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for CorModule {
+    fn module_init() -> i32 {
+        info!("Initializing Copy-on-read Module");
+        0
+    }
+}
+
+impl WithModuleGetCtxSize for CorModule {
+    fn ctx_size() -> i32 {
+        std::mem::size_of::<CorIoCtx>() as i32
+    }
+}
+
+impl BdevModuleBuild for CorModule {}
+
+pub fn register() {
+    CorModule::builder(COR_MODULE_NAME)
+        .with_module_init()
+        .with_module_ctx_size()
+        .register();
+}
+
+/// Returns the population progress of a copy-on-read device.
+pub fn cor_bdev_progress(name: &str) -> Result<CorProgress, BdevError> {
+    stacked::lookup::<CorDevice>(CorModule::current(), name).map(|b| {
+        let d = b.data();
+        CorProgress {
+            source: d.source_uri.clone(),
+            state: d.regions.state.lock().clone(),
+            region_size: d.regions.region_blocks * d.regions.block_len,
+            total_regions: d.regions.total,
+            populated_regions: d.regions.populated.load(Ordering::Acquire),
+        }
+    })
+}
+
+/// Destroys a copy-on-read device by its name, after stopping its
+/// population, and destroys its source device if it was created for it.
+async fn cor_destroy(name: &str) -> Result<(), BdevError> {
+    let bdev = stacked::lookup::<CorDevice>(CorModule::current(), name)?;
+
+    let regions = bdev.data().regions.clone();
+    regions.stop.store(true, Ordering::Relaxed);
+    let task_done = regions.task_done.lock().take();
+    if let Some(task_done) = task_done {
+        task_done.await.ok();
+    }
+
+    let source_uri = bdev.data().source_uri.clone();
+    let source_created = bdev.data().source_created;
+
+    stacked::unregister(bdev).await?;
+
+    if source_created {
+        if let Err(e) = bdev_destroy(&source_uri).await {
+            warn!(
+                "Copy-on-read '{name}': failed to destroy source \
+                '{source_uri}': {e}",
+                e = e.verbose()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Copy-on-read device URI.
+#[derive(Debug)]
+pub(super) struct Cor {
+    /// Name of the copy-on-read bdev.
+    name: String,
+    /// Alias which can be used to open the bdev.
+    alias: String,
+    /// Name of the local lvol.
+    local: String,
+    /// URI of the source device.
+    source: String,
+    /// UUID of the copy-on-read bdev.
+    uuid: Option<uuid::Uuid>,
+}
+
+impl TryFrom<&Url> for Cor {
+    type Error = BdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let StackedUri {
+            base: local,
+            name,
+            uuid,
+            mut parameters,
+        } = StackedUri::parse(url)?;
+
+        let source = parameters.remove("source").ok_or_else(|| {
+            BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from("missing 'source' parameter"),
+            }
+        })?;
+
+        reject_unknown_parameters(url, parameters)?;
+
+        Ok(Self {
+            name,
+            alias: url.to_string(),
+            local,
+            source,
+            uuid,
+        })
+    }
+}
+
+impl GetName for Cor {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Cor {
+    /// Returns an error for an invalid local or source device.
+    fn invalid(&self, message: String) -> BdevError {
+        BdevError::CorDeviceInvalid {
+            name: self.name.clone(),
+            message,
+        }
+    }
+
+    /// Creates the copy-on-read bdev over its source device.
+    fn create_over(
+        &self,
+        source: &str,
+        source_created: bool,
+    ) -> Result<String, BdevError> {
+        let cluster_size = match UntypedBdev::lookup_by_name(&self.local)
+            .map(Lvol::try_from)
+        {
+            Some(Ok(lvol)) if lvol.is_thin() => lvol.usage().cluster_size,
+            Some(_) => {
+                return Err(self.invalid(format!(
+                    "'{}' is not a thin provisioned lvol",
+                    self.local
+                )))
+            }
+            None => {
+                return Err(BdevError::BdevNotFound {
+                    name: self.local.clone(),
+                })
+            }
+        };
+
+        let open = |name: &str, rw: bool| {
+            device_open(name, rw).map_err(|e| {
+                error!(
+                    "Copy-on-read '{}': failed to open device '{}': {}",
+                    self.name,
+                    name,
+                    e.verbose()
+                );
+                BdevError::BdevNotFound {
+                    name: name.to_string(),
+                }
+            })
+        };
+        let local_desc = open(&self.local, true)?;
+        let source_desc = open(source, false)?;
+        let local_dev = local_desc.get_device();
+        let source_dev = source_desc.get_device();
+
+        let block_len = local_dev.block_len();
+        if source_dev.block_len() != block_len {
+            return Err(self.invalid(format!(
+                "block sizes differ: {} for the local device, {} for the \
+                source",
+                block_len,
+                source_dev.block_len()
+            )));
+        }
+        if source_dev.num_blocks() > local_dev.num_blocks() {
+            return Err(self.invalid(String::from(
+                "the source is larger than the local device",
+            )));
+        }
+
+        let region_blocks = cluster_size / block_len;
+        let total =
+            (local_dev.num_blocks() + region_blocks - 1) / region_blocks;
+        let (done_sender, done) = oneshot::channel();
+        let regions = Arc::new(CorRegions {
+            region_blocks,
+            block_len,
+            source_blocks: source_dev.num_blocks(),
+            total,
+            populated: AtomicU64::new(0),
+            map: Mutex::new(RegionMap {
+                populated: vec![false; total as usize],
+                populating: HashMap::new(),
+            }),
+            state: Mutex::new(CorState::Populating),
+            stop: AtomicBool::new(false),
+            task_done: Mutex::new(Some(done)),
+        });
+
+        let cor = CorDevice {
+            name: self.name.clone(),
+            local: self.local.clone(),
+            local_desc: Some(local_desc),
+            source_uri: self.source.clone(),
+            source: source.to_string(),
+            source_desc: Some(source_desc),
+            source_created,
+            regions: regions.clone(),
+            event_sink: None,
+            _c: Default::default(),
+        };
+        let task_hdls = CorHandles::new(&cor).map_err(|e| {
+            error!(
+                "Copy-on-read '{}': failed to get I/O handles: {}",
+                self.name,
+                e.verbose()
+            );
+            BdevError::CreateBdevFailed {
+                source: Errno::ENODEV,
+                name: self.name.clone(),
+            }
+        })?;
+
+        let mut builder = CorModule::current()
+            .bdev_builder()
+            .with_name(&self.name)
+            .with_product_name(COR_PRODUCT_ID)
+            .with_block_length(block_len as u32)
+            .with_block_count(local_dev.num_blocks())
+            .with_required_alignment(9);
+        if let Some(uuid) = self.uuid {
+            builder = builder.with_uuid(uuid.into());
+        }
+        let mut bdev = builder.with_data(cor).build();
+
+        unsafe {
+            let d = bdev.data_mut().get_unchecked_mut();
+            d.event_sink = Some(DeviceEventSink::new(bdev.data()));
+        }
+        if let Some(sink) = bdev.data().event_sink.clone() {
+            for dev in [&local_dev, &source_dev] {
+                if let Err(e) = dev.add_event_listener(sink.clone()) {
+                    warn!(
+                        "Copy-on-read '{}': failed to listen to events of \
+                        '{}': {}",
+                        self.name,
+                        dev.device_name(),
+                        e.verbose()
+                    );
+                }
+            }
+        }
+
+        bdev.data().register_io_device(Some(&self.name));
+
+        if let Err(err) = bdev.register_bdev() {
+            error!(
+                "Copy-on-read '{}': bdev registration failed: {}",
+                self.name,
+                err.verbose()
+            );
+            return Err(BdevError::CreateBdevFailed {
+                source: err,
+                name: self.name.clone(),
+            });
+        }
+
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            if !bdev.add_alias(&self.alias) {
+                error!(
+                    "failed to add alias {} to device {}",
+                    self.alias,
+                    self.get_name()
+                );
+            }
+        }
+
+        info!("{:?}: created", bdev.data());
+        Reactors::master().send_future(populate_task(
+            self.name.clone(),
+            regions,
+            task_hdls,
+            done_sender,
+        ));
+
+        Ok(self.name.clone())
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Cor {
+    type Error = BdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        if UntypedBdev::lookup_by_name(&self.name).is_some() {
+            return Err(BdevError::BdevExists {
+                name: self.name.clone(),
+            });
+        }
+
+        // The source device may exist already, e.g. as the remote replica of
+        // a nexus on this node.
+        let (source, source_created) = match bdev_create(&self.source).await {
+            Ok(source) => (source, true),
+            Err(BdevError::BdevExists {
+                ..
+            }) => (bdev_get_name(&self.source)?, false),
+            Err(e) => return Err(e),
+        };
+
+        let res = self.create_over(&source, source_created);
+        if res.is_err() && source_created {
+            if let Err(e) = bdev_destroy(&self.source).await {
+                warn!(
+                    "Copy-on-read '{}': failed to destroy source '{}': {}",
+                    self.name,
+                    self.source,
+                    e.verbose()
+                );
+            }
+        }
+        res
+    }
+
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            bdev.remove_alias(&self.alias);
+        }
+        cor_destroy(&self.name).await
+    }
+}
@@ -0,0 +1,799 @@
+//! An encrypting bdev, stacked over any base bdev, which it claims so that
+//! the data of the base bdev cannot be modified through another module.
+//!
+//! The data is encrypted with AES-XTS, one XTS data unit per block, and the
+//! block number within the encrypted device used as the tweak. The key is
+//! read from a file: 32 bytes for AES-128-XTS or 64 bytes for AES-256-XTS.
+//!
+//! The first 4 KiB of the base device hold a header, which records the cipher
+//! and the identifier of the key, so that a device is never opened with the
+//! wrong key. The header is written when the device is opened for the first
+//! time, which requires the header area of the base device to be zeroed.
+//!
+//! The device is created from a URI:
+//! ```ignore
+//!     crypt:///<base_bdev>?key_file=<path>[&name=<name>][&uuid=<uuid>]
+//! ```
+use std::{
+    convert::TryFrom,
+    fmt::{Debug, Formatter},
+    marker::PhantomData,
+    pin::Pin,
+};
+
+use aes::{cipher::KeyInit, Aes128, Aes256};
+use async_trait::async_trait;
+use libc::c_void;
+use nix::errno::Errno;
+use sha2::{Digest, Sha256};
+use spdk_rs::{
+    libspdk::spdk_bdev_io,
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevOps,
+    DmaBuf,
+    IoChannel,
+    IoDevice,
+    IoType,
+    IoVec,
+    WithModuleGetCtxSize,
+    WithModuleInit,
+};
+use url::Url;
+use xts_mode::{get_tweak_default, Xts128};
+use zeroize::Zeroizing;
+
+use crate::{
+    bdev::{
+        dev::reject_unknown_parameters,
+        device::SpdkBlockDeviceDescriptor,
+        stacked::{self, StackedDevice, StackedUri},
+        util::iov::IovCursor,
+        CreateDestroy,
+        GetName,
+    },
+    bdev_api::BdevError,
+    core::{
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        CoreError,
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        IoCompletionStatus,
+        UntypedBdev,
+        VerboseError,
+    },
+};
+
+/// Name of the crypt bdev module, which is also the driver name of its bdevs.
+const CRYPT_MODULE_NAME: &str = "crypt";
+
+/// Product name of the crypt bdevs.
+const CRYPT_PRODUCT_ID: &str = "Crypt AES-XTS Device";
+
+/// Size of the header at the start of the base device.
+const CRYPT_HEADER_SIZE: u64 = 4096;
+
+/// Magic of the crypt header.
+const CRYPT_MAGIC: [u8; 8] = *b"IOECRYPT";
+
+/// Version of the crypt header format.
+const CRYPT_VERSION: u32 = 1;
+
+/// Prefix hashed with the key to make its identifier.
+const KEY_ID_CONTEXT: &[u8] = b"io-engine crypt key id";
+
+/// Header stored at the start of the base device.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+struct CryptHeader {
+    magic: [u8; 8],
+    version: u32,
+    cipher: String,
+    key_id: String,
+}
+
+/// AES-XTS cipher, for the supported key sizes.
+enum CryptCipher {
+    Aes128(Xts128<Aes128>),
+    Aes256(Xts128<Aes256>),
+}
+
+impl CryptCipher {
+    /// Makes a cipher from an XTS key, made of the data key followed by the
+    /// tweak key.
+    fn new(key: &[u8]) -> Result<Self, String> {
+        let (k1, k2) = key.split_at(key.len() / 2);
+        if k1 == k2 {
+            return Err("the two halves of the XTS key are equal".to_string());
+        }
+
+        match key.len() {
+            32 => Ok(Self::Aes128(Xts128::new(
+                Aes128::new_from_slice(k1).unwrap(),
+                Aes128::new_from_slice(k2).unwrap(),
+            ))),
+            64 => Ok(Self::Aes256(Xts128::new(
+                Aes256::new_from_slice(k1).unwrap(),
+                Aes256::new_from_slice(k2).unwrap(),
+            ))),
+            n => Err(format!(
+                "the key is {n} bytes long, 32 or 64 bytes are expected"
+            )),
+        }
+    }
+
+    /// Returns the name of the cipher.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Aes128(_) => "aes-xts-128",
+            Self::Aes256(_) => "aes-xts-256",
+        }
+    }
+
+    /// Encrypts a block in place.
+    fn encrypt(&self, block: &mut [u8], lba: u64) {
+        let tweak = get_tweak_default(lba as u128);
+        match self {
+            Self::Aes128(xts) => xts.encrypt_sector(block, tweak),
+            Self::Aes256(xts) => xts.encrypt_sector(block, tweak),
+        }
+    }
+
+    /// Decrypts a block in place.
+    fn decrypt(&self, block: &mut [u8], lba: u64) {
+        let tweak = get_tweak_default(lba as u128);
+        match self {
+            Self::Aes128(xts) => xts.decrypt_sector(block, tweak),
+            Self::Aes256(xts) => xts.decrypt_sector(block, tweak),
+        }
+    }
+}
+
+/// Returns the identifier of a key, which can be stored without revealing
+/// the key.
+fn key_id(key: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(KEY_ID_CONTEXT);
+    hasher.update(key);
+    hex::encode(&hasher.finalize()[.. 16])
+}
+
+/// Context of a write, which owns the encrypted copy of the data.
+struct CryptWriteCtx<'c> {
+    bio: BdevIo<CryptDevice<'c>>,
+    _buf: DmaBuf,
+    iov: IoVec,
+}
+
+/// Per-core channel of a crypt device, with a handle to the base device.
+pub(crate) struct CryptChannel {
+    handle: Option<Box<dyn BlockDeviceHandle>>,
+}
+
+/// A crypt device.
+pub(crate) struct CryptDevice<'c> {
+    name: String,
+    base: String,
+    /// Base bdev, claimed by the crypt module.
+    base_bdev: UntypedBdev,
+    base_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    cipher: CryptCipher,
+    key_id: String,
+    block_len: u64,
+    /// Number of blocks of the base device taken by the header.
+    data_offset: u64,
+    event_sink: Option<DeviceEventSink>,
+    _c: PhantomData<&'c ()>,
+}
+
+impl Debug for CryptDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Crypt '{}' over '{}' ({}, key {})",
+            self.name,
+            self.base,
+            self.cipher.name(),
+            self.key_id
+        )
+    }
+}
+
+impl<'c> CryptDevice<'c> {
+    /// Transforms the data of an I/O block by block, with `f`.
+    fn for_each_block(
+        &self,
+        bio: &BdevIo<CryptDevice<'c>>,
+        f: impl Fn(&CryptCipher, &mut [u8], u64),
+    ) {
+        let iovs = unsafe {
+            std::slice::from_raw_parts(bio.iovs(), bio.iov_count() as usize)
+        };
+        let block_len = self.block_len as usize;
+        let mut cur = IovCursor::new(iovs);
+        // May hold a decrypted block.
+        let mut tmp = Zeroizing::new(Vec::new());
+
+        for lba in bio.offset() .. bio.offset() + bio.num_blocks() {
+            if let Some(block) = cur.contiguous(block_len) {
+                f(&self.cipher, block, lba);
+                continue;
+            }
+
+            // The block spans several I/O vectors.
+            tmp.resize(block_len, 0);
+            let mut start = cur;
+            cur.copy_to(&mut tmp);
+            f(&self.cipher, &mut tmp, lba);
+            start.copy_from(&tmp);
+        }
+    }
+
+    /// Returns the handle to the base device of the channel, or fails the
+    /// I/O.
+    fn channel_handle<'a>(
+        chan: &'a IoChannel<CryptChannel>,
+        bio: &BdevIo<CryptDevice<'c>>,
+    ) -> Option<&'a dyn BlockDeviceHandle> {
+        let hdl = chan.channel_data().handle.as_deref();
+        if hdl.is_none() {
+            bio.fail();
+        }
+        hdl
+    }
+
+    /// Submits a read to the base device.
+    fn submit_read(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        bio: &BdevIo<CryptDevice<'c>>,
+    ) -> Result<(), CoreError> {
+        hdl.readv_blocks(
+            bio.iovs(),
+            bio.iov_count(),
+            bio.offset() + self.data_offset,
+            bio.num_blocks(),
+            Self::read_completion,
+            bio.legacy_as_ptr().cast(),
+        )
+    }
+
+    /// Decrypts the data read from the base device.
+    fn read_completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio = BdevIo::<CryptDevice<'c>>::legacy_from_ptr(
+            ctx as *mut spdk_bdev_io,
+        );
+        if status != IoCompletionStatus::Success {
+            bio.fail();
+            return;
+        }
+
+        let crypt = bio.bdev_checked(CRYPT_PRODUCT_ID).data();
+        crypt.for_each_block(&bio, |c, block, lba| c.decrypt(block, lba));
+        bio.ok();
+    }
+
+    /// Encrypts the data to write into a bounce buffer, and submits the write
+    /// of the buffer to the base device.
+    fn submit_write(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        bio: &BdevIo<CryptDevice<'c>>,
+    ) -> Result<(), CoreError> {
+        let block_len = self.block_len as usize;
+        let mut buf = hdl
+            .dma_malloc(bio.num_blocks() * self.block_len)
+            .map_err(|_| CoreError::WriteDispatch {
+                source: Errno::ENOMEM,
+                offset: bio.offset(),
+                len: bio.num_blocks(),
+            })?;
+
+        let iovs = unsafe {
+            std::slice::from_raw_parts(bio.iovs(), bio.iov_count() as usize)
+        };
+        IovCursor::new(iovs).copy_to(buf.as_mut_slice());
+        for (i, block) in
+            buf.as_mut_slice().chunks_exact_mut(block_len).enumerate()
+        {
+            self.cipher.encrypt(block, bio.offset() + i as u64);
+        }
+
+        let iov = IoVec {
+            iov_base: buf.as_mut_slice().as_mut_ptr() as *mut c_void,
+            iov_len: buf.len() as usize,
+        };
+        let mut ctx = Box::new(CryptWriteCtx {
+            bio: bio.clone(),
+            _buf: buf,
+            iov,
+        });
+        let iov_ptr = &mut ctx.iov as *mut IoVec;
+        let ctx = Box::into_raw(ctx);
+
+        hdl.writev_blocks(
+            iov_ptr,
+            1,
+            bio.offset() + self.data_offset,
+            bio.num_blocks(),
+            Self::write_completion,
+            ctx.cast(),
+        )
+        .map_err(|e| {
+            drop(unsafe { Box::from_raw(ctx) });
+            e
+        })
+    }
+
+    /// Completes a write, and frees its bounce buffer.
+    fn write_completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let ctx = unsafe { Box::from_raw(ctx as *mut CryptWriteCtx<'c>) };
+        if status == IoCompletionStatus::Success {
+            ctx.bio.ok();
+        } else {
+            ctx.bio.fail();
+        }
+    }
+
+    /// Completes an I/O passed through to the base device as is.
+    fn passthru_completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio = BdevIo::<CryptDevice<'c>>::legacy_from_ptr(
+            ctx as *mut spdk_bdev_io,
+        );
+        if status == IoCompletionStatus::Success {
+            bio.ok();
+        } else {
+            bio.fail();
+        }
+    }
+}
+
+impl StackedDevice for CryptDevice<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl DeviceEventListener for CryptDevice<'_> {
+    fn handle_device_event(&self, evt: DeviceEventType, dev_name: &str) {
+        if dev_name == self.base && stacked::is_removal(evt) {
+            let name = self.name.clone();
+            stacked::destroy_removed(self, dev_name, async move {
+                crypt_destroy(&name).await
+            });
+        }
+    }
+
+    fn get_listener_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl<'c> IoDevice for CryptDevice<'c> {
+    type ChannelData = CryptChannel;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        CryptChannel {
+            handle: stacked::base_io_handle(&*self, self.base_desc.as_deref()),
+        }
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'c> BdevOps for CryptDevice<'c> {
+    type ChannelData = CryptChannel;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        stacked::destruct(self, |s| {
+            s.event_sink = None;
+            release_base(&s.base_bdev);
+            s.base_desc = None;
+        });
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        bio: BdevIo<Self>,
+    ) {
+        let hdl = match Self::channel_handle(&chan, &bio) {
+            Some(hdl) => hdl,
+            None => return,
+        };
+
+        let ctx = bio.legacy_as_ptr().cast();
+        let offset = bio.offset() + self.data_offset;
+
+        let res = match bio.io_type() {
+            IoType::Read => self.submit_read(hdl, &bio),
+            IoType::Write => self.submit_write(hdl, &bio),
+            IoType::Unmap => hdl.unmap_blocks(
+                offset,
+                bio.num_blocks(),
+                Self::passthru_completion,
+                ctx,
+            ),
+            IoType::Flush => hdl.flush_io(Self::passthru_completion, ctx),
+            IoType::Reset => hdl.reset(Self::passthru_completion, ctx),
+            _ => Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            }),
+        };
+
+        if let Err(e) = res {
+            error!("{self:?}: I/O submission failed: {e}", e = e.verbose());
+            bio.fail();
+        }
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            IoType::Read | IoType::Write => true,
+            // Unmapped blocks do not read back as encrypted zeroes, but the
+            // content of unmapped blocks is not defined anyway.
+            IoType::Unmap | IoType::Flush | IoType::Reset => self
+                .base_desc
+                .as_ref()
+                .map_or(false, |d| d.get_device().io_type_supported(io_type)),
+            // Zeroes written by the base device would not decrypt to zeroes:
+            // leave it to the bdev layer to write encrypted zero blocks.
+            _ => false,
+        }
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// Crypt bdev module.
+pub(crate) struct CryptModule {}
+
+impl CryptModule {
+    /// Returns the crypt bdev module instance.
+    /// Panics if the crypt module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(CRYPT_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for CryptModule {
+    fn module_init() -> i32 {
+        info!("Initializing Crypt Module");
+        0
+    }
+}
+
+impl WithModuleGetCtxSize for CryptModule {
+    fn ctx_size() -> i32 {
+        0
+    }
+}
+
+impl BdevModuleBuild for CryptModule {}
+
+pub fn register() {
+    CryptModule::builder(CRYPT_MODULE_NAME)
+        .with_module_init()
+        .with_module_ctx_size()
+        .register();
+}
+
+/// Releases the claim of the crypt module on a base bdev.
+fn release_base(base: &UntypedBdev) {
+    if let Err(err) = CryptModule::current().release_bdev(base) {
+        error!("Failed to release base bdev '{}': {}", base.name(), err);
+    }
+}
+
+/// Destroys a crypt device by its name.
+async fn crypt_destroy(name: &str) -> Result<(), BdevError> {
+    stacked::destroy::<CryptDevice>(CryptModule::current(), name).await
+}
+
+/// Crypt device URI.
+#[derive(Debug)]
+pub(super) struct Crypt {
+    /// Name of the crypt bdev.
+    name: String,
+    /// Alias which can be used to open the bdev.
+    alias: String,
+    /// Name of the base bdev.
+    base: String,
+    /// Path of the key file.
+    key_file: String,
+    /// UUID of the crypt bdev.
+    uuid: Option<uuid::Uuid>,
+}
+
+impl TryFrom<&Url> for Crypt {
+    type Error = BdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let StackedUri {
+            base,
+            name,
+            uuid,
+            mut parameters,
+        } = StackedUri::parse(url)?;
+
+        let key_file = parameters.remove("key_file").ok_or_else(|| {
+            BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from("'key_file' is not specified"),
+            }
+        })?;
+
+        reject_unknown_parameters(url, parameters)?;
+
+        Ok(Self {
+            name,
+            alias: url.to_string(),
+            base,
+            key_file,
+            uuid,
+        })
+    }
+}
+
+impl GetName for Crypt {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl Crypt {
+    /// Loads the key, and makes its cipher.
+    fn load_key(&self) -> Result<(CryptCipher, String), BdevError> {
+        let key =
+            std::fs::read(&self.key_file)
+                .map(Zeroizing::new)
+                .map_err(|e| BdevError::CryptKeyInvalid {
+                    name: self.name.clone(),
+                    message: format!(
+                        "failed to read key file '{}': {e}",
+                        self.key_file
+                    ),
+                })?;
+
+        let cipher = CryptCipher::new(&key).map_err(|message| {
+            BdevError::CryptKeyInvalid {
+                name: self.name.clone(),
+                message,
+            }
+        })?;
+
+        Ok((cipher, key_id(&key)))
+    }
+
+    /// Checks the header of the base device against the key, or writes it
+    /// if the base device has none yet.
+    async fn check_header(
+        &self,
+        hdl: &dyn BlockDeviceHandle,
+        header_len: u64,
+        cipher: &CryptCipher,
+        key_id: &str,
+    ) -> Result<(), BdevError> {
+        let io_err = |e: CoreError| BdevError::CryptBaseInvalid {
+            name: self.name.clone(),
+            message: format!("header I/O failed: {e}"),
+        };
+        let dma_err = |_| BdevError::CreateBdevFailed {
+            source: Errno::ENOMEM,
+            name: self.name.clone(),
+        };
+
+        let mut buf = hdl.dma_malloc(header_len).map_err(dma_err)?;
+        hdl.read_at(0, &mut buf).await.map_err(io_err)?;
+
+        let expected = CryptHeader {
+            magic: CRYPT_MAGIC,
+            version: CRYPT_VERSION,
+            cipher: cipher.name().to_string(),
+            key_id: key_id.to_string(),
+        };
+
+        if buf.as_slice().iter().all(|b| *b == 0) {
+            info!(
+                "Crypt '{}': initializing header on '{}'",
+                self.name, self.base
+            );
+            bincode::serialize_into(buf.as_mut_slice(), &expected).map_err(
+                |e| BdevError::CryptBaseInvalid {
+                    name: self.name.clone(),
+                    message: format!("failed to encode header: {e}"),
+                },
+            )?;
+            hdl.write_at(0, &buf).await.map_err(io_err)?;
+            return Ok(());
+        }
+
+        match bincode::deserialize::<CryptHeader>(buf.as_slice()) {
+            Ok(h) if h.magic == CRYPT_MAGIC && h.version == CRYPT_VERSION => {
+                if h.key_id != expected.key_id || h.cipher != expected.cipher {
+                    return Err(BdevError::CryptKeyMismatch {
+                        name: self.name.clone(),
+                        key_id: h.key_id,
+                    });
+                }
+                Ok(())
+            }
+            _ => Err(BdevError::CryptBaseInvalid {
+                name: self.name.clone(),
+                message: format!(
+                    "'{}' is neither an encrypted device nor zeroed",
+                    self.base
+                ),
+            }),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Crypt {
+    type Error = BdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        if UntypedBdev::lookup_by_name(&self.name).is_some() {
+            return Err(BdevError::BdevExists {
+                name: self.name.clone(),
+            });
+        }
+
+        let (cipher, key_id) = self.load_key()?;
+
+        let desc =
+            UntypedBdev::open_by_name(&self.base, true).map_err(|e| {
+                error!(
+                    "Crypt '{}': failed to open base device '{}': {}",
+                    self.name,
+                    self.base,
+                    e.verbose()
+                );
+                BdevError::BdevNotFound {
+                    name: self.base.clone(),
+                }
+            })?;
+        let base_bdev = desc.bdev();
+
+        let block_len = base_bdev.block_len() as u64;
+        let data_offset = (CRYPT_HEADER_SIZE + block_len - 1) / block_len;
+        if base_bdev.num_blocks() <= data_offset {
+            return Err(BdevError::CryptBaseInvalid {
+                name: self.name.clone(),
+                message: format!("'{}' is too small", self.base),
+            });
+        }
+
+        CryptModule::current()
+            .claim_bdev(&base_bdev, &desc)
+            .map_err(|_| BdevError::CryptBaseInvalid {
+                name: self.name.clone(),
+                message: format!(
+                    "'{}' is claimed by another module",
+                    self.base
+                ),
+            })?;
+
+        let desc: Box<dyn BlockDeviceDescriptor> =
+            Box::new(SpdkBlockDeviceDescriptor::from(desc));
+        let base = desc.get_device();
+
+        let header = match desc.get_io_handle_nonblock().await {
+            Ok(hdl) => {
+                self.check_header(
+                    &*hdl,
+                    data_offset * block_len,
+                    &cipher,
+                    &key_id,
+                )
+                .await
+            }
+            Err(e) => Err(BdevError::CryptBaseInvalid {
+                name: self.name.clone(),
+                message: format!("failed to get I/O handle: {e}"),
+            }),
+        };
+        if let Err(e) = header {
+            release_base(&base_bdev);
+            return Err(e);
+        }
+
+        let crypt = CryptDevice {
+            name: self.name.clone(),
+            base: self.base.clone(),
+            base_bdev,
+            base_desc: Some(desc),
+            cipher,
+            key_id,
+            block_len,
+            data_offset,
+            event_sink: None,
+            _c: Default::default(),
+        };
+
+        let mut builder = CryptModule::current()
+            .bdev_builder()
+            .with_name(&self.name)
+            .with_product_name(CRYPT_PRODUCT_ID)
+            .with_block_length(block_len as u32)
+            .with_block_count(base.num_blocks() - data_offset)
+            .with_required_alignment(9);
+        if let Some(uuid) = self.uuid {
+            builder = builder.with_uuid(uuid.into());
+        }
+        let mut bdev = builder.with_data(crypt).build();
+
+        unsafe {
+            let c = bdev.data_mut().get_unchecked_mut();
+            c.event_sink = Some(DeviceEventSink::new(bdev.data()));
+        }
+        if let Some(sink) = bdev.data().event_sink.clone() {
+            if let Err(e) = base.add_event_listener(sink) {
+                warn!(
+                    "Crypt '{}': failed to listen to events of '{}': {}",
+                    self.name,
+                    self.base,
+                    e.verbose()
+                );
+            }
+        }
+
+        bdev.data().register_io_device(Some(&self.name));
+
+        if let Err(err) = bdev.register_bdev() {
+            error!(
+                "Crypt '{}': bdev registration failed: {}",
+                self.name,
+                err.verbose()
+            );
+            release_base(&base_bdev);
+            return Err(BdevError::CreateBdevFailed {
+                source: err,
+                name: self.name.clone(),
+            });
+        }
+
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            if !bdev.add_alias(&self.alias) {
+                error!(
+                    "failed to add alias {} to device {}",
+                    self.alias,
+                    self.get_name()
+                );
+            }
+        }
+
+        info!("{:?}: created", bdev.data());
+        Ok(self.name.clone())
+    }
+
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            bdev.remove_alias(&self.alias);
+        }
+        crypt_destroy(&self.name).await
+    }
+}
@@ -0,0 +1,637 @@
+//! A bdev that adds latency to the I/Os of a base block device, to simulate
+//! slow devices or remote replicas.
+//!
+//! The latencies are set separately for reads and for writes, as an average
+//! and a p99 latency: one I/O in a hundred is delayed by the p99 latency, and
+//! all the others by the average latency. The delay is added to the time the
+//! base device takes to complete the I/O. Reads use the read latencies, and
+//! all other I/O types the write latencies. Latencies can be changed at
+//! runtime with `delay_bdev_set_latency`.
+//!
+//! The device is created from a URI:
+//! ```ignore
+//!     delay:///<base_bdev>?read_avg_us=<us>&write_avg_us=<us>[&name=<name>]
+//! ```
+use std::{
+    convert::TryFrom,
+    fmt::{Debug, Formatter},
+    marker::PhantomData,
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use libc::c_void;
+use nix::errno::Errno;
+use parking_lot::Mutex;
+use rand::Rng;
+use snafu::ResultExt;
+use spdk_rs::{
+    libspdk::{spdk_bdev_io, spdk_get_ticks_hz},
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevOps,
+    IoChannel,
+    IoDevice,
+    IoType,
+    Poller,
+    PollerBuilder,
+    WithModuleGetCtxSize,
+    WithModuleInit,
+};
+use url::Url;
+
+use crate::{
+    bdev::{
+        dev::reject_unknown_parameters,
+        device_open,
+        stacked::{self, StackedDevice, StackedUri},
+        CreateDestroy,
+        GetName,
+    },
+    bdev_api::{self, BdevError},
+    core::{
+        io_start_ticks,
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        CoreError,
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        IoCompletionStatus,
+        UntypedBdev,
+        VerboseError,
+    },
+};
+
+/// Name of the delay bdev module, which is the driver name of its bdevs.
+/// SPDK has its own "delay" module already.
+pub(crate) const DELAY_MODULE_NAME: &str = "delay_ng";
+
+/// Product name of the delay bdevs.
+const DELAY_PRODUCT_ID: &str = "Delay Device";
+
+/// Latencies of a delay device.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DelayLatency {
+    pub read_avg: Duration,
+    pub read_p99: Duration,
+    pub write_avg: Duration,
+    pub write_p99: Duration,
+}
+
+impl DelayLatency {
+    /// Checks the p99 latencies are not below the average latencies.
+    fn is_valid(&self) -> bool {
+        self.read_p99 >= self.read_avg && self.write_p99 >= self.write_avg
+    }
+}
+
+/// Changes to the latencies of a delay device. Unset latencies are kept.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DelayLatencyUpdate {
+    pub read_avg: Option<Duration>,
+    pub read_p99: Option<Duration>,
+    pub write_avg: Option<Duration>,
+    pub write_p99: Option<Duration>,
+}
+
+/// Latencies of a delay device, in microseconds, changeable while I/Os run.
+#[derive(Default)]
+struct AtomicLatency {
+    read_avg_us: AtomicU64,
+    read_p99_us: AtomicU64,
+    write_avg_us: AtomicU64,
+    write_p99_us: AtomicU64,
+}
+
+impl AtomicLatency {
+    fn load(&self) -> DelayLatency {
+        let us =
+            |v: &AtomicU64| Duration::from_micros(v.load(Ordering::Relaxed));
+        DelayLatency {
+            read_avg: us(&self.read_avg_us),
+            read_p99: us(&self.read_p99_us),
+            write_avg: us(&self.write_avg_us),
+            write_p99: us(&self.write_p99_us),
+        }
+    }
+
+    fn store(&self, lat: &DelayLatency) {
+        let store = |v: &AtomicU64, d: Duration| {
+            v.store(d.as_micros() as u64, Ordering::Relaxed)
+        };
+        store(&self.read_avg_us, lat.read_avg);
+        store(&self.read_p99_us, lat.read_p99);
+        store(&self.write_avg_us, lat.write_avg);
+        store(&self.write_p99_us, lat.write_p99);
+    }
+
+    /// Draws the latency of an I/O, in microseconds.
+    fn sample_us(&self, io_type: IoType) -> u64 {
+        let (avg, p99) = match io_type {
+            IoType::Read => (&self.read_avg_us, &self.read_p99_us),
+            _ => (&self.write_avg_us, &self.write_p99_us),
+        };
+        if rand::thread_rng().gen_ratio(1, 100) {
+            p99.load(Ordering::Relaxed)
+        } else {
+            avg.load(Ordering::Relaxed)
+        }
+    }
+}
+
+/// An I/O completed by the base device, waiting for its delay to elapse.
+struct DelayedIo<'d> {
+    deadline: u64,
+    bio: BdevIo<DelayDevice<'d>>,
+    success: bool,
+}
+
+/// Queue of the delayed I/Os of a channel, polled by the channel poller.
+#[derive(Default)]
+struct DelayQueue<'d> {
+    ios: Mutex<Vec<DelayedIo<'d>>>,
+}
+
+// Required because `DelayQueue.ios` contains `BdevIo`, which
+// contains NonNull, which is not Send.
+unsafe impl<'d> Send for DelayQueue<'d> {}
+
+/// Per-I/O context of a delay device.
+struct DelayIoCtx<'d> {
+    queue: *const DelayQueue<'d>,
+    latency_ticks: u64,
+}
+
+/// Per-core channel of a delay device.
+pub(crate) struct DelayChannel<'d> {
+    handle: Option<Box<dyn BlockDeviceHandle>>,
+    poller: Poller<'d, DelayQueue<'d>>,
+}
+
+impl<'d> DelayChannel<'d> {
+    fn new(handle: Option<Box<dyn BlockDeviceHandle>>) -> Self {
+        let poller = PollerBuilder::new()
+            .with_data(DelayQueue::default())
+            .with_poll_fn(|q| {
+                let now = io_start_ticks();
+                let ready = {
+                    let mut ios = q.ios.lock();
+                    if ios.is_empty() {
+                        return 0;
+                    }
+                    let (ready, pending): (Vec<_>, Vec<_>) =
+                        ios.drain(..).partition(|io| io.deadline <= now);
+                    *ios = pending;
+                    ready
+                };
+
+                ready.iter().for_each(|io| {
+                    if io.success {
+                        io.bio.ok()
+                    } else {
+                        io.bio.fail()
+                    }
+                });
+                ready.len() as i32
+            })
+            .build();
+
+        Self {
+            handle,
+            poller,
+        }
+    }
+}
+
+/// A delay device.
+pub(crate) struct DelayDevice<'d> {
+    name: String,
+    base: String,
+    base_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    latency: AtomicLatency,
+    event_sink: Option<DeviceEventSink>,
+    _d: PhantomData<&'d ()>,
+}
+
+impl Debug for DelayDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Delay '{}' over '{}' ({:?})",
+            self.name,
+            self.base,
+            self.latency.load()
+        )
+    }
+}
+
+impl<'d> DelayDevice<'d> {
+    /// Queues an I/O completed by the base device, to be completed once its
+    /// delay elapses.
+    fn completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio = BdevIo::<DelayDevice<'d>>::legacy_from_ptr(
+            ctx as *mut spdk_bdev_io,
+        );
+        let io_ctx = bio.driver_ctx::<DelayIoCtx>();
+        let queue = unsafe { &*io_ctx.queue };
+        let deadline = io_start_ticks() + io_ctx.latency_ticks;
+
+        queue.ios.lock().push(DelayedIo {
+            deadline,
+            bio,
+            success: status == IoCompletionStatus::Success,
+        });
+    }
+}
+
+impl StackedDevice for DelayDevice<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl DeviceEventListener for DelayDevice<'_> {
+    fn handle_device_event(&self, evt: DeviceEventType, dev_name: &str) {
+        if dev_name == self.base && stacked::is_removal(evt) {
+            let name = self.name.clone();
+            stacked::destroy_removed(self, dev_name, async move {
+                delay_destroy(&name).await
+            });
+        }
+    }
+
+    fn get_listener_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl<'d> IoDevice for DelayDevice<'d> {
+    type ChannelData = DelayChannel<'d>;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        DelayChannel::new(stacked::base_io_handle(
+            &*self,
+            self.base_desc.as_deref(),
+        ))
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'d> BdevOps for DelayDevice<'d> {
+    type ChannelData = DelayChannel<'d>;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        stacked::destruct(self, |s| {
+            s.event_sink = None;
+            s.base_desc = None;
+        });
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        mut bio: BdevIo<Self>,
+    ) {
+        let chan_data = chan.channel_data();
+        let hdl = match chan_data.handle.as_deref() {
+            Some(hdl) => hdl,
+            None => {
+                bio.fail();
+                return;
+            }
+        };
+
+        let latency_us = self.latency.sample_us(bio.io_type());
+        *bio.driver_ctx_mut::<DelayIoCtx>() = DelayIoCtx {
+            queue: chan_data.poller.data(),
+            latency_ticks: latency_us * unsafe { spdk_get_ticks_hz() }
+                / 1_000_000,
+        };
+
+        let ctx = bio.legacy_as_ptr().cast();
+        let res = match bio.io_type() {
+            IoType::Read => hdl.readv_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Write => hdl.writev_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::WriteZeros => hdl.write_zeroes(
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Unmap => hdl.unmap_blocks(
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Flush => hdl.flush_io(Self::completion, ctx),
+            IoType::Reset => hdl.reset(Self::completion, ctx),
+            _ => Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            }),
+        };
+
+        if let Err(e) = res {
+            error!("{self:?}: I/O submission failed: {e}", e = e.verbose());
+            bio.fail();
+        }
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            IoType::Read | IoType::Write => true,
+            IoType::WriteZeros
+            | IoType::Unmap
+            | IoType::Flush
+            | IoType::Reset => self
+                .base_desc
+                .as_ref()
+                .map_or(false, |d| d.get_device().io_type_supported(io_type)),
+            _ => false,
+        }
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// Delay bdev module.
+pub(crate) struct DelayModule {}
+
+impl DelayModule {
+    /// Returns the delay bdev module instance.
+    /// Panics if the delay module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(DELAY_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for DelayModule {
+    fn module_init() -> i32 {
+        info!("Initializing Delay Module");
+        0
+    }
+}
+
+impl WithModuleGetCtxSize for DelayModule {
+    fn ctx_size() -> i32 {
+        std::mem::size_of::<DelayIoCtx>() as i32
+    }
+}
+
+impl BdevModuleBuild for DelayModule {}
+
+pub fn register() {
+    DelayModule::builder(DELAY_MODULE_NAME)
+        .with_module_init()
+        .with_module_ctx_size()
+        .register();
+}
+
+/// Looks up a delay device by its name, and applies `f` to it.
+fn with_delay_device<R>(
+    name: &str,
+    f: impl FnOnce(&DelayDevice) -> R,
+) -> Result<R, BdevError> {
+    stacked::lookup::<DelayDevice>(DelayModule::current(), name)
+        .map(|b| f(b.data()))
+}
+
+/// Returns the latencies of a delay device.
+pub fn delay_bdev_latency(name: &str) -> Result<DelayLatency, BdevError> {
+    with_delay_device(name, |d| d.latency.load())
+}
+
+/// Changes the latencies of a delay device, and returns the new latencies.
+pub fn delay_bdev_set_latency(
+    name: &str,
+    update: DelayLatencyUpdate,
+) -> Result<DelayLatency, BdevError> {
+    with_delay_device(name, |d| {
+        let cur = d.latency.load();
+        let lat = DelayLatency {
+            read_avg: update.read_avg.unwrap_or(cur.read_avg),
+            read_p99: update.read_p99.unwrap_or(cur.read_p99),
+            write_avg: update.write_avg.unwrap_or(cur.write_avg),
+            write_p99: update.write_p99.unwrap_or(cur.write_p99),
+        };
+        if !lat.is_valid() {
+            return Err(BdevError::DelayLatencyInvalid {
+                name: name.to_string(),
+            });
+        }
+
+        info!("{d:?}: setting latency to {lat:?}");
+        d.latency.store(&lat);
+        Ok(lat)
+    })?
+}
+
+/// Destroys a delay device by its name.
+async fn delay_destroy(name: &str) -> Result<(), BdevError> {
+    stacked::destroy::<DelayDevice>(DelayModule::current(), name).await
+}
+
+/// Delay device URI.
+#[derive(Debug)]
+pub(super) struct Delay {
+    /// Name of the delay bdev.
+    name: String,
+    /// Alias which can be used to open the bdev.
+    alias: String,
+    /// Name of the base bdev.
+    base: String,
+    /// Initial latencies.
+    latency: DelayLatency,
+    /// UUID of the delay bdev.
+    uuid: Option<uuid::Uuid>,
+}
+
+impl TryFrom<&Url> for Delay {
+    type Error = BdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let StackedUri {
+            base,
+            name,
+            uuid,
+            mut parameters,
+        } = StackedUri::parse(url)?;
+
+        let mut latency_us = |param: &str| -> Result<Option<u64>, BdevError> {
+            parameters
+                .remove(param)
+                .map(|value| {
+                    value.parse().context(bdev_api::IntParamParseFailed {
+                        uri: url.to_string(),
+                        parameter: param.to_string(),
+                        value: value.clone(),
+                    })
+                })
+                .transpose()
+        };
+
+        let read_avg = latency_us("read_avg_us")?.unwrap_or_default();
+        let read_p99 = latency_us("read_p99_us")?.unwrap_or(read_avg);
+        let write_avg = latency_us("write_avg_us")?.unwrap_or_default();
+        let write_p99 = latency_us("write_p99_us")?.unwrap_or(write_avg);
+
+        let latency = DelayLatency {
+            read_avg: Duration::from_micros(read_avg),
+            read_p99: Duration::from_micros(read_p99),
+            write_avg: Duration::from_micros(write_avg),
+            write_p99: Duration::from_micros(write_p99),
+        };
+        if !latency.is_valid() {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from(
+                    "p99 latencies must not be below the average latencies",
+                ),
+            });
+        }
+
+        reject_unknown_parameters(url, parameters)?;
+
+        Ok(Self {
+            name,
+            alias: url.to_string(),
+            base,
+            latency,
+            uuid,
+        })
+    }
+}
+
+impl GetName for Delay {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for Delay {
+    type Error = BdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        if UntypedBdev::lookup_by_name(&self.name).is_some() {
+            return Err(BdevError::BdevExists {
+                name: self.name.clone(),
+            });
+        }
+
+        let desc = device_open(&self.base, true).map_err(|e| {
+            error!(
+                "Delay '{}': failed to open base device '{}': {}",
+                self.name,
+                self.base,
+                e.verbose()
+            );
+            BdevError::BdevNotFound {
+                name: self.base.clone(),
+            }
+        })?;
+        let base = desc.get_device();
+
+        let delay = DelayDevice {
+            name: self.name.clone(),
+            base: self.base.clone(),
+            base_desc: Some(desc),
+            latency: AtomicLatency::default(),
+            event_sink: None,
+            _d: Default::default(),
+        };
+        delay.latency.store(&self.latency);
+
+        let mut builder = DelayModule::current()
+            .bdev_builder()
+            .with_name(&self.name)
+            .with_product_name(DELAY_PRODUCT_ID)
+            .with_block_length(base.block_len() as u32)
+            .with_block_count(base.num_blocks())
+            .with_required_alignment(9);
+        if let Some(uuid) = self.uuid {
+            builder = builder.with_uuid(uuid.into());
+        }
+        let mut bdev = builder.with_data(delay).build();
+
+        unsafe {
+            let d = bdev.data_mut().get_unchecked_mut();
+            d.event_sink = Some(DeviceEventSink::new(bdev.data()));
+        }
+        if let Some(sink) = bdev.data().event_sink.clone() {
+            if let Err(e) = base.add_event_listener(sink) {
+                warn!(
+                    "Delay '{}': failed to listen to events of '{}': {}",
+                    self.name,
+                    self.base,
+                    e.verbose()
+                );
+            }
+        }
+
+        bdev.data().register_io_device(Some(&self.name));
+
+        if let Err(err) = bdev.register_bdev() {
+            error!(
+                "Delay '{}': bdev registration failed: {}",
+                self.name,
+                err.verbose()
+            );
+            return Err(BdevError::CreateBdevFailed {
+                source: err,
+                name: self.name.clone(),
+            });
+        }
+
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            if !bdev.add_alias(&self.alias) {
+                error!(
+                    "failed to add alias {} to device {}",
+                    self.alias,
+                    self.get_name()
+                );
+            }
+        }
+
+        info!("{:?}: created", bdev.data());
+        Ok(self.name.clone())
+    }
+
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            bdev.remove_alias(&self.alias);
+        }
+        delay_destroy(&self.name).await
+    }
+}
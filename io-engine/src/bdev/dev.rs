@@ -39,6 +39,11 @@ pub(crate) mod uri {
     use crate::{
         bdev::{
             aio,
+            compress_bdev,
+            cor_bdev,
+            crypt,
+            delay_bdev,
+            error_bdev,
             loopback,
             malloc,
             null_bdev,
@@ -60,6 +65,13 @@ pub(crate) mod uri {
         match url.scheme() {
             "aio" => Ok(Box::new(aio::Aio::try_from(&url)?)),
             "bdev" => Ok(Box::new(loopback::Loopback::try_from(&url)?)),
+            "compress" => {
+                Ok(Box::new(compress_bdev::Compress::try_from(&url)?))
+            }
+            "cor" => Ok(Box::new(cor_bdev::Cor::try_from(&url)?)),
+            "crypt" => Ok(Box::new(crypt::Crypt::try_from(&url)?)),
+            "delay" => Ok(Box::new(delay_bdev::Delay::try_from(&url)?)),
+            "error" => Ok(Box::new(error_bdev::ErrorBdev::try_from(&url)?)),
             "loopback" => Ok(Box::new(loopback::Loopback::try_from(&url)?)),
             "malloc" => Ok(Box::new(malloc::Malloc::try_from(&url)?)),
             "null" => Ok(Box::new(null_bdev::Null::try_from(&url)?)),
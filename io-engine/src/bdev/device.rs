@@ -170,7 +170,7 @@ impl BlockDevice for SpdkBlockDevice {
 
 /// Wrapper around native SPDK block device descriptor, which mimics target SPDK
 /// descriptor as an abstract BlockDeviceDescriptor instance.
-struct SpdkBlockDeviceDescriptor(Arc<UntypedDescriptorGuard>);
+pub(crate) struct SpdkBlockDeviceDescriptor(Arc<UntypedDescriptorGuard>);
 
 impl From<UntypedDescriptorGuard> for SpdkBlockDeviceDescriptor {
     fn from(descr: UntypedDescriptorGuard) -> Self {
@@ -531,6 +531,31 @@ impl BlockDeviceHandle for SpdkBlockDeviceHandle {
 
         Ok(0)
     }
+
+    async fn destroy_snapshot(
+        &self,
+        snapshot_uuid: &str,
+    ) -> Result<(), CoreError> {
+        let bdev = self.handle.get_bdev();
+
+        // Snapshots are supported only for LVOLs.
+        if bdev.driver() != "lvol" {
+            return Err(CoreError::NotSupported {
+                source: Errno::ENXIO,
+            });
+        }
+
+        let lvol =
+            Lvol::try_from(bdev).map_err(|_e| CoreError::BdevNotFound {
+                name: bdev.name().to_string(),
+            })?;
+
+        lvol.destroy_snapshot(snapshot_uuid).await.map_err(|e| {
+            CoreError::SnapshotDestroy {
+                reason: e.to_string(),
+            }
+        })
+    }
     // Flush the io in buffer to disk, for the Local Block Device.
     fn flush_io(
         &self,
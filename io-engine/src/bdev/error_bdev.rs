@@ -0,0 +1,758 @@
+//! A bdev that fails selected I/Os of a base block device with chosen NVMe
+//! status codes, to test the error paths of the devices stacked over it.
+//!
+//! The device passes all I/Os to its base device, until rules are added with
+//! `error_bdev_add_rule`. An I/O which matches a rule is failed with the
+//! status of the rule, without being submitted to the base device. A rule
+//! matches the I/Os of a type over a range of blocks, and can be limited to a
+//! number of failures, or to a probability of failure.
+//!
+//! Unlike the nexus fault injection, this device is always built in.
+//!
+//! The device is created from a URI:
+//! ```ignore
+//!     error:///<base_bdev>[?name=<name>][&uuid=<uuid>]
+//! ```
+use std::{
+    convert::TryFrom,
+    fmt::{Debug, Display, Formatter},
+    marker::PhantomData,
+    pin::Pin,
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use async_trait::async_trait;
+use libc::c_void;
+use nix::errno::Errno;
+use parking_lot::Mutex;
+use rand::Rng;
+use spdk_rs::{
+    libspdk::{spdk_bdev_io, spdk_bdev_io_complete_nvme_status},
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevOps,
+    IoChannel,
+    IoDevice,
+    IoType,
+    WithModuleInit,
+};
+use url::Url;
+
+use crate::{
+    bdev::{
+        dev::reject_unknown_parameters,
+        device_open,
+        stacked::{self, StackedDevice, StackedUri},
+        CreateDestroy,
+        GetName,
+    },
+    bdev_api::BdevError,
+    core::{
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        CoreError,
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        IoCompletionStatus,
+        UntypedBdev,
+        VerboseError,
+    },
+};
+
+/// Name of the error bdev module, which is the driver name of its bdevs.
+/// SPDK has its own "error" module already.
+pub(crate) const ERROR_MODULE_NAME: &str = "error_ng";
+
+/// Product name of the error bdevs.
+const ERROR_PRODUCT_ID: &str = "Error Injection Device";
+
+/// Types of the I/Os an error rule applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorOp {
+    Read,
+    Write,
+    WriteZeroes,
+    Unmap,
+    Flush,
+    Reset,
+    All,
+}
+
+impl ErrorOp {
+    /// Checks if an I/O type matches this operation.
+    fn matches(&self, io_type: IoType) -> bool {
+        matches!(
+            (self, io_type),
+            (Self::All, _)
+                | (Self::Read, IoType::Read)
+                | (Self::Write, IoType::Write)
+                | (Self::WriteZeroes, IoType::WriteZeros)
+                | (Self::Unmap, IoType::Unmap)
+                | (Self::Flush, IoType::Flush)
+                | (Self::Reset, IoType::Reset)
+        )
+    }
+}
+
+impl FromStr for ErrorOp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "read" => Ok(Self::Read),
+            "write" => Ok(Self::Write),
+            "write_zeroes" => Ok(Self::WriteZeroes),
+            "unmap" => Ok(Self::Unmap),
+            "flush" => Ok(Self::Flush),
+            "reset" => Ok(Self::Reset),
+            "all" => Ok(Self::All),
+            _ => Err(format!("unknown I/O type '{s}'")),
+        }
+    }
+}
+
+impl Display for ErrorOp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Read => "read",
+            Self::Write => "write",
+            Self::WriteZeroes => "write_zeroes",
+            Self::Unmap => "unmap",
+            Self::Flush => "flush",
+            Self::Reset => "reset",
+            Self::All => "all",
+        };
+        f.write_str(s)
+    }
+}
+
+/// NVMe status an error rule fails I/Os with: a status code type and a
+/// status code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErrorStatus {
+    pub sct: u8,
+    pub sc: u8,
+}
+
+/// Named NVMe statuses.
+const ERROR_STATUS_NAMES: [(&str, ErrorStatus); 11] = [
+    // SPDK_NVME_SC_DATA_TRANSFER_ERROR
+    ("data_transfer", ErrorStatus::new(0, 0x04)),
+    // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
+    ("internal", ErrorStatus::new(0, 0x06)),
+    // SPDK_NVME_SC_ABORTED_SQ_DELETION
+    ("path", ErrorStatus::new(0, 0x08)),
+    // SPDK_NVME_SC_LBA_OUT_OF_RANGE
+    ("lba_out_of_range", ErrorStatus::new(0, 0x80)),
+    // SPDK_NVME_SC_NAMESPACE_NOT_READY
+    ("not_ready", ErrorStatus::new(0, 0x82)),
+    // SPDK_NVME_SC_RESERVATION_CONFLICT
+    ("reservation_conflict", ErrorStatus::new(0, 0x83)),
+    // SPDK_NVME_SC_WRITE_FAULTS
+    ("write_fault", ErrorStatus::new(2, 0x80)),
+    // SPDK_NVME_SC_UNRECOVERED_READ_ERROR
+    ("unrecovered_read", ErrorStatus::new(2, 0x81)),
+    // SPDK_NVME_SC_GUARD_CHECK_ERROR
+    ("media", ErrorStatus::new(2, 0x82)),
+    // SPDK_NVME_SC_DEALLOCATED_OR_UNWRITTEN_BLOCK
+    ("unwritten", ErrorStatus::new(2, 0x87)),
+    // Vendor specific ENOSPC, the status of a thin lvol out of space.
+    ("no_space", ErrorStatus::new(7, libc::ENOSPC as u8)),
+];
+
+impl ErrorStatus {
+    pub const fn new(sct: u8, sc: u8) -> Self {
+        Self {
+            sct,
+            sc,
+        }
+    }
+}
+
+impl Default for ErrorStatus {
+    fn default() -> Self {
+        // SPDK_NVME_SC_DATA_TRANSFER_ERROR
+        Self::new(0, 0x04)
+    }
+}
+
+impl FromStr for ErrorStatus {
+    type Err = String;
+
+    /// Parses a status name, or a `<sct>:<sc>` pair of numbers.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((_, status)) =
+            ERROR_STATUS_NAMES.iter().find(|(name, _)| *name == s)
+        {
+            return Ok(*status);
+        }
+
+        let parse = |v: &str| match v.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => v.parse(),
+        };
+        match s.split_once(':') {
+            Some((sct, sc)) => match (parse(sct), parse(sc)) {
+                (Ok(sct), Ok(sc)) if sct <= 7 => Ok(Self::new(sct, sc)),
+                _ => Err(format!("invalid NVMe status '{s}'")),
+            },
+            None => Err(format!("unknown NVMe status '{s}'")),
+        }
+    }
+}
+
+impl Display for ErrorStatus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match ERROR_STATUS_NAMES.iter().find(|(_, status)| status == self) {
+            Some((name, _)) => f.write_str(name),
+            None => write!(f, "{}:{:#04x}", self.sct, self.sc),
+        }
+    }
+}
+
+/// A rule of an error device, which fails the matching I/Os.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorRule {
+    /// Type of the I/Os to fail.
+    pub op: ErrorOp,
+    /// Status to fail the I/Os with.
+    pub status: ErrorStatus,
+    /// First block of the range of the rule.
+    pub offset: u64,
+    /// Number of blocks of the range of the rule, zero for all the blocks
+    /// from `offset`.
+    pub num_blocks: u64,
+    /// Maximum number of I/Os to fail, unlimited if not set.
+    pub count: Option<u64>,
+    /// Probability to fail a matching I/O, from 0 to 1.
+    pub prob: f64,
+}
+
+impl Default for ErrorRule {
+    fn default() -> Self {
+        Self {
+            op: ErrorOp::All,
+            status: ErrorStatus::default(),
+            offset: 0,
+            num_blocks: 0,
+            count: None,
+            prob: 1.0,
+        }
+    }
+}
+
+impl ErrorRule {
+    /// Checks if an I/O is within the block range of the rule. Flushes and
+    /// resets are not bound to blocks and are always within range.
+    fn in_range(&self, io_type: IoType, offset: u64, num_blocks: u64) -> bool {
+        if matches!(io_type, IoType::Flush | IoType::Reset) {
+            return true;
+        }
+        let end = match self.num_blocks {
+            0 => u64::MAX,
+            n => self.offset.saturating_add(n),
+        };
+        offset < end && offset + num_blocks > self.offset
+    }
+}
+
+/// An error rule of a device, with its identifier and its number of hits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ErrorRuleState {
+    /// Identifier of the rule on its device.
+    pub id: u64,
+    /// The rule.
+    pub rule: ErrorRule,
+    /// Number of I/Os failed by the rule.
+    pub hits: u64,
+}
+
+impl ErrorRuleState {
+    /// Checks if an I/O must be failed, and counts the hit if so.
+    fn check(&mut self, io_type: IoType, offset: u64, num_blocks: u64) -> bool {
+        let r = &self.rule;
+        if !r.op.matches(io_type)
+            || !r.in_range(io_type, offset, num_blocks)
+            || r.count.map_or(false, |c| self.hits >= c)
+            || (r.prob < 1.0 && !rand::thread_rng().gen_bool(r.prob))
+        {
+            return false;
+        }
+
+        self.hits += 1;
+        true
+    }
+}
+
+/// Rules of an error device.
+#[derive(Default)]
+struct ErrorRules {
+    next_id: u64,
+    rules: Vec<ErrorRuleState>,
+}
+
+impl ErrorRules {
+    /// Applies `f` to the rules, and updates the flag telling if there are
+    /// rules at all, which is read without taking the lock.
+    fn update<R>(
+        rules: &Mutex<Self>,
+        has_rules: &AtomicBool,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let mut rules = rules.lock();
+        let res = f(&mut rules);
+        has_rules.store(!rules.rules.is_empty(), Ordering::Release);
+        res
+    }
+}
+
+/// Per-core channel of an error device.
+pub(crate) struct ErrorChannel {
+    handle: Option<Box<dyn BlockDeviceHandle>>,
+}
+
+/// An error device.
+pub(crate) struct ErrorDevice<'e> {
+    name: String,
+    base: String,
+    base_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    rules: Mutex<ErrorRules>,
+    /// Set if there are rules, so that I/Os pass through without taking the
+    /// lock of the rules otherwise.
+    has_rules: AtomicBool,
+    event_sink: Option<DeviceEventSink>,
+    _e: PhantomData<&'e ()>,
+}
+
+impl Debug for ErrorDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Error '{}' over '{}' ({} rules)",
+            self.name,
+            self.base,
+            self.rules.lock().rules.len()
+        )
+    }
+}
+
+impl<'e> ErrorDevice<'e> {
+    /// Completes an I/O passed to the base device.
+    fn completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio = BdevIo::<ErrorDevice<'e>>::legacy_from_ptr(
+            ctx as *mut spdk_bdev_io,
+        );
+        if status == IoCompletionStatus::Success {
+            bio.ok();
+        } else {
+            bio.fail();
+        }
+    }
+
+    /// Returns the status to fail an I/O with, if it matches a rule.
+    fn check_rules(&self, bio: &BdevIo<Self>) -> Option<ErrorStatus> {
+        if !self.has_rules.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let mut rules = self.rules.lock();
+
+        let (io_type, offset, num_blocks) =
+            (bio.io_type(), bio.offset(), bio.num_blocks());
+        rules
+            .rules
+            .iter_mut()
+            .find(|r| r.check(io_type, offset, num_blocks))
+            .map(|r| r.rule.status)
+    }
+}
+
+impl StackedDevice for ErrorDevice<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl DeviceEventListener for ErrorDevice<'_> {
+    fn handle_device_event(&self, evt: DeviceEventType, dev_name: &str) {
+        if dev_name == self.base && stacked::is_removal(evt) {
+            let name = self.name.clone();
+            stacked::destroy_removed(self, dev_name, async move {
+                error_destroy(&name).await
+            });
+        }
+    }
+
+    fn get_listener_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl<'e> IoDevice for ErrorDevice<'e> {
+    type ChannelData = ErrorChannel;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        ErrorChannel {
+            handle: stacked::base_io_handle(&*self, self.base_desc.as_deref()),
+        }
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'e> BdevOps for ErrorDevice<'e> {
+    type ChannelData = ErrorChannel;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        stacked::destruct(self, |s| {
+            s.event_sink = None;
+            s.base_desc = None;
+        });
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        bio: BdevIo<Self>,
+    ) {
+        if let Some(status) = self.check_rules(&bio) {
+            debug!(
+                "{self:?}: failing {:?} I/O at {} with {status}",
+                bio.io_type(),
+                bio.offset()
+            );
+            unsafe {
+                spdk_bdev_io_complete_nvme_status(
+                    bio.legacy_as_ptr(),
+                    0,
+                    status.sct as i32,
+                    status.sc as i32,
+                );
+            }
+            return;
+        }
+
+        let hdl = match chan.channel_data().handle.as_deref() {
+            Some(hdl) => hdl,
+            None => {
+                bio.fail();
+                return;
+            }
+        };
+
+        let ctx = bio.legacy_as_ptr().cast();
+        let res = match bio.io_type() {
+            IoType::Read => hdl.readv_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Write => hdl.writev_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::WriteZeros => hdl.write_zeroes(
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Unmap => hdl.unmap_blocks(
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Flush => hdl.flush_io(Self::completion, ctx),
+            IoType::Reset => hdl.reset(Self::completion, ctx),
+            _ => Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            }),
+        };
+
+        if let Err(e) = res {
+            error!("{self:?}: I/O submission failed: {e}", e = e.verbose());
+            bio.fail();
+        }
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            IoType::Read | IoType::Write => true,
+            IoType::WriteZeros
+            | IoType::Unmap
+            | IoType::Flush
+            | IoType::Reset => self
+                .base_desc
+                .as_ref()
+                .map_or(false, |d| d.get_device().io_type_supported(io_type)),
+            _ => false,
+        }
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// Error bdev module.
+pub(crate) struct ErrorModule {}
+
+impl ErrorModule {
+    /// Returns the error bdev module instance.
+    /// Panics if the error module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(ERROR_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for ErrorModule {
+    fn module_init() -> i32 {
+        info!("Initializing Error Module");
+        0
+    }
+}
+
+impl BdevModuleBuild for ErrorModule {}
+
+pub fn register() {
+    ErrorModule::builder(ERROR_MODULE_NAME)
+        .with_module_init()
+        .register();
+}
+
+/// Looks up an error device by its name, and applies `f` to it.
+fn with_error_device<R>(
+    name: &str,
+    f: impl FnOnce(&ErrorDevice) -> R,
+) -> Result<R, BdevError> {
+    stacked::lookup::<ErrorDevice>(ErrorModule::current(), name)
+        .map(|b| f(b.data()))
+}
+
+/// Adds a rule to an error device, and returns the state of the new rule.
+pub fn error_bdev_add_rule(
+    name: &str,
+    rule: ErrorRule,
+) -> Result<ErrorRuleState, BdevError> {
+    if !(0.0 ..= 1.0).contains(&rule.prob) {
+        return Err(BdevError::ErrorRuleInvalid {
+            name: name.to_string(),
+            message: format!("probability {} is not within [0, 1]", rule.prob),
+        });
+    }
+
+    with_error_device(name, |d| {
+        let state = ErrorRules::update(&d.rules, &d.has_rules, |rules| {
+            let state = ErrorRuleState {
+                id: rules.next_id,
+                rule,
+                hits: 0,
+            };
+            rules.next_id += 1;
+            rules.rules.push(state.clone());
+            state
+        });
+
+        info!("{d:?}: added rule {state:?}");
+        state
+    })
+}
+
+/// Removes a rule of an error device, or all its rules if no rule is given.
+pub fn error_bdev_remove_rule(
+    name: &str,
+    id: Option<u64>,
+) -> Result<(), BdevError> {
+    with_error_device(name, |d| {
+        ErrorRules::update(&d.rules, &d.has_rules, |rules| match id {
+            Some(id) => match rules.rules.iter().position(|r| r.id == id) {
+                Some(idx) => {
+                    rules.rules.remove(idx);
+                    Ok(())
+                }
+                None => Err(BdevError::ErrorRuleNotFound {
+                    name: name.to_string(),
+                    id,
+                }),
+            },
+            None => {
+                rules.rules.clear();
+                Ok(())
+            }
+        })?;
+
+        info!("Error '{name}': removed rules {id:?}");
+        Ok(())
+    })?
+}
+
+/// Returns the rules of an error device.
+pub fn error_bdev_list_rules(
+    name: &str,
+) -> Result<Vec<ErrorRuleState>, BdevError> {
+    with_error_device(name, |d| d.rules.lock().rules.clone())
+}
+
+/// Destroys an error device by its name.
+async fn error_destroy(name: &str) -> Result<(), BdevError> {
+    stacked::destroy::<ErrorDevice>(ErrorModule::current(), name).await
+}
+
+/// Error device URI.
+#[derive(Debug)]
+pub(super) struct ErrorBdev {
+    /// Name of the error bdev.
+    name: String,
+    /// Alias which can be used to open the bdev.
+    alias: String,
+    /// Name of the base bdev.
+    base: String,
+    /// UUID of the error bdev.
+    uuid: Option<uuid::Uuid>,
+}
+
+impl TryFrom<&Url> for ErrorBdev {
+    type Error = BdevError;
+
+    fn try_from(url: &Url) -> Result<Self, Self::Error> {
+        let StackedUri {
+            base,
+            name,
+            uuid,
+            parameters,
+        } = StackedUri::parse(url)?;
+
+        reject_unknown_parameters(url, parameters)?;
+
+        Ok(Self {
+            name,
+            alias: url.to_string(),
+            base,
+            uuid,
+        })
+    }
+}
+
+impl GetName for ErrorBdev {
+    fn get_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+#[async_trait(?Send)]
+impl CreateDestroy for ErrorBdev {
+    type Error = BdevError;
+
+    async fn create(&self) -> Result<String, Self::Error> {
+        if UntypedBdev::lookup_by_name(&self.name).is_some() {
+            return Err(BdevError::BdevExists {
+                name: self.name.clone(),
+            });
+        }
+
+        let desc = device_open(&self.base, true).map_err(|e| {
+            error!(
+                "Error '{}': failed to open base device '{}': {}",
+                self.name,
+                self.base,
+                e.verbose()
+            );
+            BdevError::BdevNotFound {
+                name: self.base.clone(),
+            }
+        })?;
+        let base = desc.get_device();
+
+        let dev = ErrorDevice {
+            name: self.name.clone(),
+            base: self.base.clone(),
+            base_desc: Some(desc),
+            rules: Mutex::new(ErrorRules::default()),
+            has_rules: AtomicBool::new(false),
+            event_sink: None,
+            _e: Default::default(),
+        };
+
+        let mut builder = ErrorModule::current()
+            .bdev_builder()
+            .with_name(&self.name)
+            .with_product_name(ERROR_PRODUCT_ID)
+            .with_block_length(base.block_len() as u32)
+            .with_block_count(base.num_blocks())
+            .with_required_alignment(9);
+        if let Some(uuid) = self.uuid {
+            builder = builder.with_uuid(uuid.into());
+        }
+        let mut bdev = builder.with_data(dev).build();
+
+        unsafe {
+            let d = bdev.data_mut().get_unchecked_mut();
+            d.event_sink = Some(DeviceEventSink::new(bdev.data()));
+        }
+        if let Some(sink) = bdev.data().event_sink.clone() {
+            if let Err(e) = base.add_event_listener(sink) {
+                warn!(
+                    "Error '{}': failed to listen to events of '{}': {}",
+                    self.name,
+                    self.base,
+                    e.verbose()
+                );
+            }
+        }
+
+        bdev.data().register_io_device(Some(&self.name));
+
+        if let Err(err) = bdev.register_bdev() {
+            error!(
+                "Error '{}': bdev registration failed: {}",
+                self.name,
+                err.verbose()
+            );
+            return Err(BdevError::CreateBdevFailed {
+                source: err,
+                name: self.name.clone(),
+            });
+        }
+
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            if !bdev.add_alias(&self.alias) {
+                error!(
+                    "failed to add alias {} to device {}",
+                    self.alias,
+                    self.get_name()
+                );
+            }
+        }
+
+        info!("{:?}: created", bdev.data());
+        Ok(self.name.clone())
+    }
+
+    async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+            bdev.remove_alias(&self.alias);
+        }
+        error_destroy(&self.name).await
+    }
+}
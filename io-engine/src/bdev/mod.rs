@@ -7,11 +7,18 @@ pub use nvmx::{
     nvme_io_ctx_pool_init,
     NvmeController,
     NvmeControllerState,
+    NvmePathInfo,
+    NvmePathState,
     NVME_CONTROLLERS,
 };
 
 mod aio;
+pub mod compress_bdev;
+pub mod cor_bdev;
+pub(crate) mod crypt;
+pub mod delay_bdev;
 pub(crate) mod dev;
+pub mod error_bdev;
 use crate::core::{MayastorEnvironment, PtplProps};
 pub(crate) use dev::uri;
 
@@ -24,7 +31,10 @@ pub mod null_ng;
 mod nvme;
 mod nvmf;
 pub(crate) mod nvmx;
+pub mod sparse_file;
+pub(crate) mod stacked;
 mod uring;
+pub mod uring_ng;
 pub mod util;
 
 pub trait BdevCreateDestroy: CreateDestroy + GetName + std::fmt::Debug {}
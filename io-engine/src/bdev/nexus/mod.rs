@@ -16,11 +16,15 @@ mod nexus_injection;
 mod nexus_io;
 mod nexus_io_log;
 mod nexus_io_subsystem;
+mod nexus_io_trace;
 mod nexus_iter;
+mod nexus_layout;
 mod nexus_module;
 mod nexus_nbd;
+mod nexus_parity;
 mod nexus_persistence;
 mod nexus_share;
+mod nexus_ublk;
 
 use crate::bdev::nexus::nexus_iter::NexusIterMut;
 pub(crate) use nexus_bdev::NEXUS_PRODUCT_ID;
@@ -50,6 +54,18 @@ pub use nexus_child::{
 use nexus_io::{NexusBio, NioCtx};
 use nexus_io_log::{IOLog, IOLogChannel};
 use nexus_io_subsystem::{NexusIoSubsystem, NexusPauseState};
+pub(crate) use nexus_io_trace::IoTrace;
+pub use nexus_io_trace::{
+    IoTraceCapture,
+    IoTraceChild,
+    IoTraceError,
+    IoTraceHeader,
+    IoTraceOp,
+    IoTraceRecord,
+    IoTraceStatus,
+    DEFAULT_IO_TRACE_RECORDS,
+    IO_TRACE_NEXUS,
+};
 pub use nexus_iter::{
     nexus_iter,
     nexus_iter_mut,
@@ -58,13 +74,18 @@ pub use nexus_iter::{
     nexus_lookup_name_uuid,
     nexus_lookup_uuid_mut,
 };
+pub use nexus_layout::NexusLayout;
 pub(crate) use nexus_module::{NexusModule, NEXUS_MODULE_NAME};
 pub(crate) use nexus_nbd::{NbdDisk, NbdError};
 pub(crate) use nexus_persistence::PersistOp;
 pub use nexus_persistence::{ChildInfo, NexusInfo};
 pub(crate) use nexus_share::NexusPtpl;
+pub(crate) use nexus_ublk::{UblkDisk, UblkError};
 
 pub use nexus_bdev_snapshot::{
+    create_group_snapshot,
+    NexusGroupSnapshotDescriptor,
+    NexusGroupSnapshotStatus,
     NexusReplicaSnapshotDescriptor,
     NexusReplicaSnapshotStatus,
     NexusSnapshotStatus,
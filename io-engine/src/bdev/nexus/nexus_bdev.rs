@@ -23,32 +23,41 @@ use uuid::Uuid;
 use super::{
     nexus_err,
     nexus_injection::Injections,
+    nexus_layout::LayoutGeometry,
     nexus_lookup_name_uuid,
     DrEvent,
     Error,
+    IoTrace,
+    IoTraceCapture,
     NbdDisk,
     NexusBio,
     NexusChannel,
     NexusChild,
+    NexusLayout,
     NexusModule,
     PersistOp,
+    UblkDisk,
 };
 
 use crate::{
     bdev::{
         device_destroy,
         nexus::{nexus_persistence::PersistentNexusInfo, NexusIoSubsystem},
+        util::region_lock::RegionLocks,
     },
     core::{
         partition,
         Bdev,
         DeviceEventSink,
+        IoLatencyRecorder,
+        IoLatencyStats,
         IoType,
         Protocol,
         Reactor,
         Share,
         VerboseError,
     },
+    events::nexus_status_check,
     rebuild::HistoryRecord,
     subsys::NvmfSubsystem,
 };
@@ -74,6 +83,7 @@ pub(crate) static NEXUS_PRODUCT_ID: &str = "Nexus CAS Driver v0.0.1";
 pub enum NexusTarget {
     NbdDisk(NbdDisk),
     NexusNvmfTarget,
+    UblkDisk(UblkDisk),
 }
 
 /// Sensitive nexus operations that might require extra checks against
@@ -239,6 +249,13 @@ pub struct Nexus<'n> {
     req_size: u64,
     /// Vector of nexus children.
     pub(super) children: Vec<NexusChild<'n>>,
+    /// Layout of the data across the children.
+    layout: NexusLayout,
+    /// Mapping of the blocks to the children, for the striped, concatenated
+    /// and parity layouts.
+    pub(super) geometry: LayoutGeometry,
+    /// Locks of the rows of a parity nexus.
+    pub(super) row_locks: RegionLocks,
     /// NVMe parameters
     pub(crate) nvme_params: NexusNvmeParams,
     /// uuid of the nexus (might not be the same as the nexus bdev!)
@@ -270,6 +287,10 @@ pub struct Nexus<'n> {
     _pin: PhantomPinned,
     /// Initiators.
     initiators: parking_lot::Mutex<HashSet<String>>,
+    /// Latency histograms of the nexus I/O operations.
+    pub(super) io_latency: IoLatencyRecorder,
+    /// Runtime I/O tracing of the nexus.
+    pub(super) io_trace: IoTrace,
 }
 
 impl<'n> Debug for Nexus<'n> {
@@ -354,10 +375,14 @@ impl<'n> Nexus<'n> {
         nexus_uuid: Option<uuid::Uuid>,
         nvme_params: NexusNvmeParams,
         nexus_info_key: Option<String>,
+        layout: NexusLayout,
     ) -> spdk_rs::Bdev<Nexus<'n>> {
         let n = Nexus {
             name: name.to_string(),
             children: Vec::new(),
+            layout,
+            geometry: LayoutGeometry::default(),
+            row_locks: RegionLocks::default(),
             state: parking_lot::Mutex::new(NexusState::Init),
             bdev: None,
             data_ent_offset: 0,
@@ -376,6 +401,8 @@ impl<'n> Nexus<'n> {
             injections: Injections::new(),
             shutdown_requested: AtomicCell::new(false),
             _pin: Default::default(),
+            io_latency: IoLatencyRecorder::new(),
+            io_trace: IoTrace::new(),
         };
 
         let mut bdev = NexusModule::current()
@@ -475,10 +502,53 @@ impl<'n> Nexus<'n> {
         self.initiators.lock().len()
     }
 
+    /// Returns the latency histograms of the nexus I/O operations,
+    /// resetting them afterwards if requested.
+    pub fn io_latency(&self, reset: bool) -> IoLatencyStats {
+        let stats = self.io_latency.stats();
+        if reset {
+            self.io_latency.reset();
+        }
+        stats
+    }
+
+    /// Enables or disables the I/O tracing of the nexus. Enabling discards
+    /// the records of any previous trace.
+    pub fn set_io_trace(&self, enable: bool, records_per_core: usize) {
+        if enable {
+            info!("{self:?}: enabling I/O tracing");
+            self.io_trace.enable(records_per_core);
+        } else {
+            info!("{self:?}: disabling I/O tracing");
+            self.io_trace.disable();
+        }
+    }
+
+    /// Checks if the I/O tracing of the nexus is enabled, and returns the
+    /// number of records kept.
+    pub fn io_trace_status(&self) -> (bool, usize) {
+        (self.io_trace.is_enabled(), self.io_trace.len())
+    }
+
+    /// Merges the I/O trace records of the nexus into a capture.
+    pub fn io_trace_capture(&self) -> IoTraceCapture {
+        self.io_trace.capture(
+            &self.name,
+            &self.uuid().to_string(),
+            self.block_len(),
+            |device| {
+                self.children_iter()
+                    .find(|c| c.get_device_name().as_deref() == Some(device))
+                    .map(|c| c.uri().to_string())
+            },
+        )
+    }
+
     /// Sets the state of the Nexus.
     fn set_state(self: Pin<&mut Self>, state: NexusState) -> NexusState {
         debug!("{:?}: changing state to '{}'", self, state);
         *self.state.lock() = state;
+        nexus_status_check(&self.name);
         state
     }
 
@@ -492,6 +562,11 @@ impl<'n> Nexus<'n> {
         self.req_size
     }
 
+    /// Returns the layout of the data across the children.
+    pub fn layout(&self) -> NexusLayout {
+        self.layout
+    }
+
     /// Returns the actual size of the Nexus instance, in bytes.
     pub fn size_in_bytes(&self) -> u64 {
         unsafe { self.bdev().size_in_bytes() }
@@ -567,8 +642,44 @@ impl<'n> Nexus<'n> {
     /// Check whether nexus can perform target operation.
     pub(crate) fn check_nexus_operation(
         &self,
-        _op: NexusOperation,
+        op: NexusOperation,
     ) -> Result<(), Error> {
+        // The children of a striped or concatenated nexus each hold a part
+        // of the data, which cannot be rebuilt.
+        if self.layout.is_split()
+            && matches!(
+                op,
+                NexusOperation::ReplicaAdd
+                    | NexusOperation::ReplicaRemove
+                    | NexusOperation::ReplicaOnline
+            )
+        {
+            return Err(Error::OperationNotAllowed {
+                reason: format!(
+                    "children of a {} nexus cannot be added, removed or \
+                    onlined",
+                    self.layout
+                ),
+            });
+        }
+
+        // The children of a parity nexus keep their place in the rows, they
+        // can only be replaced.
+        if self.layout.is_parity()
+            && matches!(
+                op,
+                NexusOperation::ReplicaAdd | NexusOperation::ReplicaRemove
+            )
+        {
+            return Err(Error::OperationNotAllowed {
+                reason: format!(
+                    "children of a {} nexus cannot be added or removed, \
+                    only replaced",
+                    self.layout
+                ),
+            });
+        }
+
         match *self.state.lock() {
             // When nexus under shutdown or is shutdown, no further nexus
             // operations allowed.
@@ -681,10 +792,16 @@ impl<'n> Nexus<'n> {
             }
         }
 
+        let num_blocks = if self.layout.is_mirror() {
+            end_blk - start_blk
+        } else {
+            self.as_mut().setup_geometry(start_blk, blk_size)?
+        };
+
         unsafe {
             self.as_mut().set_data_ent_offset(start_blk);
             self.as_mut().set_block_len(blk_size as u32);
-            self.as_mut().set_num_blocks(end_blk - start_blk);
+            self.as_mut().set_num_blocks(num_blocks);
         }
 
         info!(
@@ -700,6 +817,72 @@ impl<'n> Nexus<'n> {
         Ok(())
     }
 
+    /// Maps the blocks of a striped, concatenated or parity nexus to the data
+    /// partitions of its children, and returns the number of blocks of the
+    /// nexus.
+    fn setup_geometry(
+        self: Pin<&mut Self>,
+        start_blk: u64,
+        blk_size: u64,
+    ) -> Result<u64, Error> {
+        let child_blocks = self
+            .children_iter()
+            .map(|c| {
+                c.get_device().ok().map(|dev| {
+                    let nb = dev.num_blocks();
+                    partition::calc_data_partition(nb * blk_size, nb, blk_size)
+                        .map_or(0, |(start, end)| end - start)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // The data of a split or parity nexus is placed on its children by
+        // their index: a child which is not open would shift the data of the
+        // children after it.
+        if !self.layout.is_mirror() && child_blocks.iter().any(Option::is_none)
+        {
+            return Err(Error::NexusIncomplete {
+                name: self.name.clone(),
+                reason: format!(
+                    "all the children of a {} nexus must be open",
+                    self.layout
+                ),
+            });
+        }
+        let child_blocks =
+            child_blocks.into_iter().flatten().collect::<Vec<_>>();
+
+        let geometry =
+            LayoutGeometry::new(self.layout, blk_size, &child_blocks).map_err(
+                |args| Error::InvalidArguments {
+                    name: self.name.clone(),
+                    args,
+                },
+            )?;
+
+        let req_blk = self.req_size() / blk_size;
+        if req_blk > geometry.num_blocks() {
+            return Err(Error::NexusIncomplete {
+                name: self.name.clone(),
+                reason: format!(
+                    "children of the {} nexus can only hold {} blocks",
+                    self.layout,
+                    geometry.num_blocks()
+                ),
+            });
+        }
+
+        info!(
+            "{self:?}: {layout} layout over {n} children, data partition \
+            start block={start_blk}",
+            layout = self.layout,
+            n = child_blocks.len(),
+        );
+
+        unsafe { self.unpin_mut().geometry = geometry };
+        Ok(req_blk)
+    }
+
     /// Opens the Nexus instance for IO.
     /// Once this function is called, the device is visible and can
     /// be used for IO.
@@ -711,6 +894,10 @@ impl<'n> Nexus<'n> {
 
         info!("{:?}: registering nexus bdev...", nex);
 
+        let persisted = nex.load_persisted().await;
+        if let Some(info) = &persisted {
+            nex.check_persisted_layout(info)?;
+        }
         nex.as_mut().setup_nexus_bdev().await?;
 
         // Register the bdev with SPDK and set the callbacks for io channel
@@ -750,6 +937,14 @@ impl<'n> Nexus<'n> {
         nex.as_mut().set_state(NexusState::Open);
         info!("{:?}: nexus bdev registered successfully", nex);
 
+        // The parity of the rows written at the time of an unclean shutdown
+        // may not match their data.
+        if nex.layout().is_parity()
+            && matches!(&persisted, Some(info) if !info.clean_shutdown)
+        {
+            nex.start_parity_resync();
+        }
+
         Ok(())
     }
 
@@ -855,6 +1050,7 @@ impl<'n> Nexus<'n> {
             // Init/Reconfiguring state
             NexusState::Reconfiguring | NexusState::Init => {
                 *state = NexusState::Open;
+                nexus_status_check(&self.name);
                 true
             }
             _ => false,
@@ -888,6 +1084,7 @@ impl<'n> Nexus<'n> {
                 }
             }
         };
+        nexus_status_check(&self.name);
 
         // Step 1: pause subsystem.
         // In case of error, restore previous nexus state.
@@ -904,6 +1101,7 @@ impl<'n> Nexus<'n> {
 
             // Restore previous nexus state.
             *self.state.lock() = prev_state;
+            nexus_status_check(&self.name);
             error
         })?;
 
@@ -926,6 +1124,7 @@ impl<'n> Nexus<'n> {
 
         // Finally, mark nexus as being fully shutdown.
         *self.state.lock() = NexusState::Shutdown;
+        nexus_status_check(&self.name);
 
         info!(
             nexus=%self.name,
@@ -1011,7 +1210,33 @@ impl<'n> Nexus<'n> {
             NexusState::ShuttingDown => NexusStatus::ShuttingDown,
             NexusState::Shutdown => NexusStatus::Shutdown,
             NexusState::Open | NexusState::Reconfiguring => {
-                if self
+                if self.layout.is_split() {
+                    // Every child of a striped or concatenated nexus holds
+                    // a part of the data.
+                    if self.children.iter().all(|c| c.is_healthy()) {
+                        NexusStatus::Online
+                    } else {
+                        NexusStatus::Faulted
+                    }
+                } else if let NexusLayout::Parity {
+                    parity_children, ..
+                } = self.layout
+                {
+                    // The data of a parity nexus can be reconstructed while
+                    // no more children than its parity children are missing.
+                    let missing = self
+                        .children
+                        .iter()
+                        .filter(|c| !c.is_healthy())
+                        .count();
+                    if missing == 0 {
+                        NexusStatus::Online
+                    } else if missing <= parity_children as usize {
+                        NexusStatus::Degraded
+                    } else {
+                        NexusStatus::Faulted
+                    }
+                } else if self
                     .children
                     .iter()
                     // All children are online, so the Nexus is also online
@@ -1259,6 +1484,7 @@ pub async fn nexus_create(
         NexusNvmeParams::default(),
         children,
         None,
+        NexusLayout::Mirror,
     )
     .await
 }
@@ -1266,6 +1492,7 @@ pub async fn nexus_create(
 /// As create_nexus with additional parameters:
 /// min_cntlid, max_cntldi: NVMe controller ID range when sharing over NVMf
 /// resv_key: NVMe reservation key for children
+/// layout: layout of the data across the children
 pub async fn nexus_create_v2(
     name: &str,
     size: u64,
@@ -1273,6 +1500,7 @@ pub async fn nexus_create_v2(
     nvme_params: NexusNvmeParams,
     children: &[String],
     nexus_info_key: Option<String>,
+    layout: NexusLayout,
 ) -> Result<(), Error> {
     if nvme_params.min_cntlid < NVME_MIN_CNTLID
         || nvme_params.min_cntlid > nvme_params.max_cntlid
@@ -1318,6 +1546,7 @@ pub async fn nexus_create_v2(
                 nvme_params,
                 children,
                 nexus_info_key,
+                layout,
             )
             .await
         }
@@ -1330,6 +1559,7 @@ pub async fn nexus_create_v2(
                 nvme_params,
                 children,
                 nexus_info_key,
+                layout,
             )
             .await
         }
@@ -1344,9 +1574,11 @@ async fn nexus_create_internal(
     nvme_params: NexusNvmeParams,
     children: &[String],
     nexus_info_key: Option<String>,
+    layout: NexusLayout,
 ) -> Result<(), Error> {
     info!(
-        "Creating new nexus '{}' ({} child(ren): {:?})...",
+        "Creating new {} nexus '{}' ({} child(ren): {:?})...",
+        layout,
         name,
         children.len(),
         children
@@ -1383,6 +1615,7 @@ async fn nexus_create_internal(
         nexus_uuid,
         nvme_params,
         nexus_info_key,
+        layout,
     );
 
     for uri in children {
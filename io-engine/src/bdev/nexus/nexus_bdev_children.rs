@@ -20,6 +20,10 @@
 //! child requires rebuild first. If the rebuild flag is set then the rebuild
 //! is also started otherwise it has to be started through `start_rebuild`.
 //!
+//! `replace_child` swaps a faulted child of a parity nexus for a new one, at
+//! the same place in the rows of the nexus, and reconstructs it from the
+//! other children.
+//!
 //! When reconfiguring the nexus, we traverse all our children, create new IO
 //! channels for all children that are in the open state.
 
@@ -50,6 +54,7 @@ use crate::{
     bdev_api::BdevError,
     core::{
         device_cmd_queue,
+        partition,
         DeviceCommand,
         DeviceEventListener,
         DeviceEventType,
@@ -325,6 +330,155 @@ impl<'n> Nexus<'n> {
         res
     }
 
+    /// Replaces a faulted child of a parity nexus with a new child, which
+    /// takes its place in the rows of the nexus, and starts reconstructing
+    /// the new child from the other children.
+    pub async fn replace_child(
+        mut self: Pin<&mut Self>,
+        old_uri: &str,
+        new_uri: &str,
+    ) -> Result<NexusStatus, Error> {
+        info!("{self:?}: replace child request: '{old_uri}' -> '{new_uri}'");
+
+        if !self.layout().is_parity() {
+            return Err(Error::OperationNotAllowed {
+                reason: format!(
+                    "children of a {} nexus cannot be replaced",
+                    self.layout()
+                ),
+            });
+        }
+
+        let idx = match self.children_iter().position(|c| c.uri() == old_uri) {
+            Some(idx) => idx,
+            None => {
+                return Err(Error::ChildNotFound {
+                    child: old_uri.to_owned(),
+                    name: self.name.clone(),
+                })
+            }
+        };
+        let old = self.child_at(idx);
+        if old.is_healthy() {
+            return Err(Error::ChildNotDegraded {
+                child: old_uri.to_owned(),
+                name: self.name.clone(),
+                state: old.state().to_string(),
+            });
+        }
+        if old.rebuild_job().is_some() {
+            return Err(Error::RebuildJobAlreadyExists {
+                child: old_uri.to_owned(),
+                name: self.name.clone(),
+            });
+        }
+
+        let name =
+            device_create(new_uri)
+                .await
+                .context(nexus_err::CreateChild {
+                    name: self.name.clone(),
+                })?;
+
+        // The new child must hold the units of the rows of the old one.
+        let child_bdev = match device_lookup(&name) {
+            Some(dev) => {
+                let bs = self.block_len();
+                let nb = dev.num_blocks();
+                let blocks = partition::calc_data_partition(nb * bs, nb, bs)
+                    .map_or(0, |(start, end)| end - start);
+                if dev.block_len() != bs
+                    || blocks < self.geometry.child_num_blocks()
+                {
+                    if let Err(err) = device_destroy(new_uri).await {
+                        error!(
+                            "{self:?}: failed to destroy child '{new_uri}' \
+                            with wrong geometry: {err}"
+                        );
+                    }
+                    return Err(Error::ChildGeometry {
+                        child: name,
+                        name: self.name.clone(),
+                    });
+                }
+                dev
+            }
+            None => {
+                return Err(Error::ChildMissing {
+                    child: name,
+                    name: self.name.clone(),
+                })
+            }
+        };
+
+        let mut child = NexusChild::new(
+            new_uri.to_owned(),
+            self.nexus_name().to_owned(),
+            Some(child_bdev),
+        );
+
+        let mut res = child.open(0, ChildSyncState::OutOfSync);
+        if res.is_ok() {
+            if let Err(e) = child.reservation_acquire(&self.nvme_params).await {
+                res = Err(e);
+            }
+        }
+        if let Err(e) = res {
+            if let Err(err) = device_destroy(new_uri).await {
+                error!(
+                    "{self:?}: failed to destroy child '{new_uri}' which \
+                    failed to open: {err}"
+                );
+            }
+            return Err(e).context(nexus_err::OpenChild {
+                child: new_uri.to_owned(),
+                name: self.name.clone(),
+            });
+        }
+        child.set_event_listener(self.get_event_sink());
+
+        // Swap the children with the I/Os paused.
+        if let Err(e) = self.as_mut().pause().await {
+            error!("{self:?}: replace child: failed to pause subsystem: {e}");
+            child.close().await.ok();
+            return Err(e);
+        }
+
+        let old = self.child_at(idx);
+        if let Some(device) = old.get_device_name() {
+            self.disconnect_device_from_channels(device).await;
+        }
+        if let Err(e) = old.close().await {
+            warn!("{old:?}: failed to close replaced child: {e}");
+        }
+        unsafe {
+            self.as_mut().unpin_mut().children[idx] = child;
+        }
+
+        self.persist(PersistOp::ReplaceChild {
+            old_uri: old_uri.to_string(),
+            new_uri: new_uri.to_string(),
+            healthy: false,
+        })
+        .await;
+
+        if let Err(e) = self.as_mut().resume().await {
+            error!("{self:?}: replace child: failed to resume subsystem: {e}");
+        }
+
+        if let Err(e) = self.start_rebuild(new_uri).await {
+            error!(
+                "{self:?}: child replaced but rebuild failed to start: {e}",
+                e = e.verbose()
+            );
+            if let Ok(child) = self.child(new_uri) {
+                child.close_faulted(FaultReason::RebuildFailed).await;
+            }
+        }
+
+        Ok(self.status())
+    }
+
     /// Faults a child with the given reason.
     pub async fn fault_child(
         mut self: Pin<&mut Self>,
@@ -396,7 +550,7 @@ impl<'n> Nexus<'n> {
         child_uri: &str,
     ) -> Result<NexusStatus, Error> {
         let nexus_name = self.name.clone();
-        let nexus_size = self.req_size();
+        let nexus_size = self.child_open_size();
 
         self.check_nexus_operation(NexusOperation::ReplicaOnline)?;
 
@@ -439,6 +593,17 @@ impl<'n> Nexus<'n> {
         Ok(self.status())
     }
 
+    /// Returns the size the children must have to be opened. The children of
+    /// a striped, concatenated or parity nexus only hold a part of its data,
+    /// their sizes are checked when mapping the blocks.
+    fn child_open_size(&self) -> u64 {
+        if self.layout().is_mirror() {
+            self.req_size()
+        } else {
+            0
+        }
+    }
+
     /// Close each child that belongs to this nexus.
     pub(crate) async fn close_children(&self) {
         let futures = self.children_iter().map(|c| c.close());
@@ -458,7 +623,7 @@ impl<'n> Nexus<'n> {
 
         let name = self.name.clone();
 
-        let size = self.req_size();
+        let size = self.child_open_size();
 
         // Take the child vec, try open and re-add.
         // NOTE: self.child_count is not affected by this algorithm!
@@ -792,7 +957,7 @@ impl<'n> Nexus<'n> {
         // Otherwise, any reconfiguration (Nexus::reconfigure()) that may run
         // in parallel, would skip connecting both child's device as a writer
         // and child's I/O log.
-        let has_io_log = c.start_io_log();
+        let has_io_log = !self.layout().is_split() && c.start_io_log();
 
         // Fail and retire an open child.
         if Ok(ChildState::Open)
@@ -7,6 +7,7 @@ use super::{
     ChildError,
     NbdError,
     NexusPauseState,
+    UblkError,
 };
 
 use crate::{
@@ -52,6 +53,8 @@ pub enum Error {
     NotSharedNvmf { name: String },
     #[snafu(display("Failed to share nexus over NBD {}", name))]
     ShareNbdNexus { source: NbdError, name: String },
+    #[snafu(display("Failed to share nexus over ublk {}", name))]
+    ShareUblkNexus { source: UblkError, name: String },
     #[snafu(display("Failed to share nvmf nexus {}", name))]
     ShareNvmfNexus { source: CoreError, name: String },
     #[snafu(display("Failed to unshare nexus {}", name))]
@@ -15,6 +15,7 @@ use super::{
 
 use crate::{
     core::{Reactors, VerboseError},
+    events::EngineEvent,
     rebuild::{
         HistoryRecord,
         RebuildError,
@@ -81,16 +82,27 @@ impl<'n> Nexus<'n> {
         let name = self.name.clone();
         info!("{self:?}: start rebuild request for {child_uri}");
 
-        // Find a healthy child to rebuild from.
-        let src_child_uri = match self
+        if self.layout().is_split() {
+            return Err(Error::OperationNotAllowed {
+                reason: format!("a {} nexus cannot rebuild", self.layout()),
+            });
+        }
+
+        // Find a healthy child to rebuild from, or all the other children of
+        // a parity nexus, which must be healthy to reconstruct the child.
+        let src_child_uris: Vec<String> = self
             .children_iter()
-            .find(|c| c.is_healthy() && c.uri() != child_uri)
+            .filter(|c| c.is_healthy() && c.uri() != child_uri)
+            .map(|c| c.uri().to_owned())
+            .collect();
+        if src_child_uris.is_empty()
+            || (self.layout().is_parity()
+                && src_child_uris.len() + 1 < self.child_count())
         {
-            Some(child) => Ok(child.uri().to_owned()),
-            None => Err(Error::NoRebuildSource {
+            return Err(Error::NoRebuildSource {
                 name: name.clone(),
-            }),
-        }?;
+            });
+        }
 
         let dst_child_uri = match self.lookup_child(child_uri) {
             Some(c) if c.is_opened_unsync() => {
@@ -115,7 +127,7 @@ impl<'n> Nexus<'n> {
         }?;
 
         // Create a rebuild job for the child.
-        self.create_rebuild_job(&src_child_uri, &dst_child_uri)
+        self.create_rebuild_job(&src_child_uris, &dst_child_uri)
             .await?;
 
         // We're now rebuilding the `dst_child` which means it HAS to become an
@@ -145,32 +157,53 @@ impl<'n> Nexus<'n> {
             })
     }
 
-    /// TODO
+    /// Creates a rebuild job which copies the first source child into the
+    /// destination child, or reconstructs the destination child of a parity
+    /// nexus from all the source children.
     async fn create_rebuild_job(
         &self,
-        src_child_uri: &str,
+        src_child_uris: &[String],
         dst_child_uri: &str,
     ) -> Result<(), Error> {
-        RebuildJob::new(
-            &self.name,
-            src_child_uri,
-            dst_child_uri,
-            std::ops::Range::<u64> {
-                start: self.data_ent_offset,
-                end: self.num_blocks() + self.data_ent_offset,
-            },
-            |nexus, job| {
-                Reactors::current().send_future(async move {
-                    Nexus::notify_rebuild(nexus, job).await;
-                });
-            },
-        )
-        .await
-        .and_then(RebuildJob::store)
-        .context(nexus_err::CreateRebuild {
-            child: dst_child_uri.to_owned(),
-            name: self.name.clone(),
-        })
+        let notify_fn: fn(String, String) = |nexus, job| {
+            Reactors::current().send_future(async move {
+                Nexus::notify_rebuild(nexus, job).await;
+            });
+        };
+
+        let job = if self.layout().is_parity() {
+            RebuildJob::new_reconstruct(
+                &self.name,
+                src_child_uris,
+                dst_child_uri,
+                std::ops::Range::<u64> {
+                    start: self.data_ent_offset,
+                    end: self.geometry.child_num_blocks()
+                        + self.data_ent_offset,
+                },
+                (self.geometry.unit_blocks(), self.geometry.data_units()),
+                notify_fn,
+            )
+            .await
+        } else {
+            RebuildJob::new(
+                &self.name,
+                &src_child_uris[0],
+                dst_child_uri,
+                std::ops::Range::<u64> {
+                    start: self.data_ent_offset,
+                    end: self.num_blocks() + self.data_ent_offset,
+                },
+                notify_fn,
+            )
+            .await
+        };
+
+        job.and_then(RebuildJob::store)
+            .context(nexus_err::CreateRebuild {
+                child: dst_child_uri.to_owned(),
+                name: self.name.clone(),
+            })
     }
 
     /// Translates the job into a new history record and pushes into
@@ -453,9 +486,39 @@ impl<'n> Nexus<'n> {
         Ok(())
     }
 
+    /// Publishes the events for the rebuild job state changes received on
+    /// the notification channel of the job.
+    fn publish_rebuild_events(&self, dst_uri: &str) {
+        let Ok(job) = self.rebuild_job(dst_uri) else {
+            return;
+        };
+
+        for state in job.notify_chan().try_iter() {
+            let event = match state {
+                RebuildState::Running => EngineEvent::RebuildStarted {
+                    nexus: self.name.clone(),
+                    child: dst_uri.to_string(),
+                    src_uri: job.src_uri().to_string(),
+                },
+                RebuildState::Completed => EngineEvent::RebuildCompleted {
+                    nexus: self.name.clone(),
+                    child: dst_uri.to_string(),
+                },
+                RebuildState::Failed => EngineEvent::RebuildFailed {
+                    nexus: self.name.clone(),
+                    child: dst_uri.to_string(),
+                    error: job.error_desc(),
+                },
+                _ => continue,
+            };
+            event.publish();
+        }
+    }
+
     /// Rebuild updated callback when a rebuild job state updates
     async fn notify_rebuild(nexus: String, dst_uri: String) {
         if let Some(nexus) = nexus_lookup_mut(&nexus) {
+            nexus.publish_rebuild_events(&dst_uri);
             let msg = format!("{nexus:?}: rebuilding '{dst_uri}'");
             if let Err(e) = nexus.on_rebuild_update(&dst_uri).await {
                 error!(
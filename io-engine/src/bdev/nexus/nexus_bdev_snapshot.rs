@@ -1,20 +1,29 @@
 //! Implements snapshot operations on a nexus.
 use std::collections::{HashMap, HashSet};
 
-use futures::future::join_all;
+use futures::{
+    future::{join_all, select, Either},
+    pin_mut,
+    stream::{FuturesUnordered, StreamExt},
+};
 
-use super::{Error, Nexus, NexusOperation, NexusState};
+use super::{nexus_lookup_uuid_mut, Error, Nexus, NexusOperation, NexusState};
 use crate::{
     bdev::nexus::NexusChild,
     core::{
         snapshot::SnapshotDescriptor,
         BlockDeviceHandle,
         IntoErrno,
+        Reactors,
         SnapshotParams,
     },
+    sleep::mayastor_sleep,
 };
 use chrono::{DateTime, Utc};
-use std::pin::Pin;
+use std::{
+    pin::Pin,
+    time::{Duration, Instant},
+};
 
 /// Per-replica descriptor for nexus snapshot operation.
 #[derive(Debug)]
@@ -32,6 +41,10 @@ pub struct NexusReplicaSnapshotStatus {
 }
 
 /// Status of a nexus snapshot operation.
+/// Replicas which were explicitly skipped, or which are not open and synced,
+/// are reported as skipped. If snapshotting any replica fails, the
+/// snapshots taken on the other replicas are destroyed again and reported
+/// with ECANCELED status.
 #[derive(Debug)]
 pub struct NexusSnapshotStatus {
     pub snapshot_timestamp: DateTime<Utc>,
@@ -39,6 +52,22 @@ pub struct NexusSnapshotStatus {
     pub replicas_skipped: Vec<String>,
 }
 
+/// Per-nexus descriptor for a group snapshot operation.
+#[derive(Debug)]
+pub struct NexusGroupSnapshotDescriptor {
+    pub nexus_uuid: String,
+    pub snapshot: SnapshotParams,
+    pub replicas: Vec<NexusReplicaSnapshotDescriptor>,
+}
+
+/// Status of a group snapshot operation, per nexus UUID.
+#[derive(Debug)]
+pub struct NexusGroupSnapshotStatus {
+    pub group_id: String,
+    pub snapshot_timestamp: DateTime<Utc>,
+    pub nexuses: Vec<(String, NexusSnapshotStatus)>,
+}
+
 /// Driver for performing snapshot creation on multiple nexus replicas in
 /// parallel.
 struct ReplicaSnapshotExecutor {
@@ -117,18 +146,20 @@ impl ReplicaSnapshotExecutor {
                 }
             };
 
-            if !r.skip {
-                // Replica must be healthy for a snapshot to be taken.
-                if !replica.is_healthy() {
-                    return Err(Error::FailedCreateSnapshot {
-                        name: nexus.bdev_name(),
-                        reason: format!(
-                            "Replica {} is not healthy",
-                            &r.replica_uuid,
-                        ),
-                    });
-                }
-
+            if r.skip {
+                skipped_replicas.push(r.replica_uuid.clone());
+            } else if !replica.is_healthy() {
+                // Replicas which are not open, or out of sync, do not hold
+                // consistent data, so they are skipped rather than failing
+                // the whole operation.
+                warn!(
+                    ?replica,
+                    state = %replica.state(),
+                    rebuilding = replica.is_rebuilding(),
+                    "Replica is not healthy, skipping it for nexus snapshot"
+                );
+                skipped_replicas.push(r.replica_uuid.clone());
+            } else {
                 // Snapshot UUID must be provided if the replica is not
                 // explicitly skipped.
                 let snapshot_uuid = match &r.snapshot_uuid {
@@ -161,11 +192,16 @@ impl ReplicaSnapshotExecutor {
                     snapshot_uuid,
                     handle,
                 });
-            } else {
-                skipped_replicas.push(r.replica_uuid.clone());
             }
         }
 
+        if replica_ctx.is_empty() {
+            return Err(Error::FailedCreateSnapshot {
+                name: nexus.bdev_name(),
+                reason: "No healthy replicas to snapshot".to_string(),
+            });
+        }
+
         Ok(Self {
             replica_ctx,
             skipped_replicas,
@@ -176,7 +212,7 @@ impl ReplicaSnapshotExecutor {
     async fn take_snapshot(
         &self,
         snapshot: SnapshotParams,
-    ) -> (Vec<NexusReplicaSnapshotStatus>, Vec<String>) {
+    ) -> Vec<NexusReplicaSnapshotStatus> {
         let futures = self
             .replica_ctx
             .iter()
@@ -212,7 +248,7 @@ impl ReplicaSnapshotExecutor {
 
         let result = join_all(futures).await;
 
-        let res = result
+        result
             .into_iter()
             .map(|(u, r)| {
                 // Transform snapshot operation status into errno.
@@ -223,9 +259,50 @@ impl ReplicaSnapshotExecutor {
                     status,
                 }
             })
+            .collect::<Vec<_>>()
+    }
+
+    /// Destroy the snapshots which were successfully taken, so that the
+    /// replicas are left as they were before the operation.
+    async fn rollback(&self, statuses: &mut [NexusReplicaSnapshotStatus]) {
+        let futures = self
+            .replica_ctx
+            .iter()
+            .zip(statuses.iter())
+            .filter(|(_, status)| status.status == 0)
+            .map(|(ctx, _)| async move {
+                warn!(
+                    replica_uuid = ctx.replica_uuid,
+                    snapshot_uuid = ctx.snapshot_uuid,
+                    "Rolling back nexus replica snapshot"
+                );
+                (
+                    ctx.replica_uuid.clone(),
+                    ctx.handle.destroy_snapshot(&ctx.snapshot_uuid).await,
+                )
+            })
             .collect::<Vec<_>>();
 
-        (res, self.skipped_replicas.clone())
+        for (replica_uuid, r) in join_all(futures).await {
+            let Some(status) =
+                statuses.iter_mut().find(|s| s.replica_uuid == replica_uuid)
+            else {
+                continue;
+            };
+
+            match r {
+                Ok(()) => status.status = libc::ECANCELED as u32,
+                Err(error) => {
+                    // The snapshot is still there, so keep reporting it as
+                    // done.
+                    error!(
+                        replica_uuid,
+                        ?error,
+                        "Failed to roll back nexus replica snapshot"
+                    );
+                }
+            }
+        }
     }
 }
 
@@ -233,21 +310,11 @@ impl<'n> Nexus<'n> {
     fn check_nexus_state(&self) -> Result<(), Error> {
         self.check_nexus_operation(NexusOperation::NexusSnapshot)?;
 
-        // Check that nexus has exactly 1 replica.
-        match self.children().len() {
-            0 => {
-                return Err(Error::FailedCreateSnapshot {
-                    name: self.bdev_name(),
-                    reason: "Nexus has no replicas".to_string(),
-                })
-            }
-            1 => {} // Only one replica nexuses are supported.
-            _ => {
-                return Err(Error::FailedCreateSnapshot {
-                    name: self.bdev_name(),
-                    reason: "Nexus has more than one replica".to_string(),
-                })
-            }
+        if self.children().is_empty() {
+            return Err(Error::FailedCreateSnapshot {
+                name: self.bdev_name(),
+                reason: "Nexus has no replicas".to_string(),
+            });
         }
 
         // Check that nexus is healthy and not being reconfigured.
@@ -262,28 +329,32 @@ impl<'n> Nexus<'n> {
         Ok(())
     }
 
-    /// Create a snapshot on all nexus replicas (currently only on 1)
+    /// Create a snapshot on all healthy nexus replicas, all sharing the same
+    /// transaction ID.
     async fn do_nexus_snapshot(
         self: Pin<&mut Self>,
         snapshot: SnapshotParams,
         replicas: Vec<NexusReplicaSnapshotDescriptor>,
     ) -> Result<NexusSnapshotStatus, Error> {
-        let (replicas_done, replicas_skipped) =
-            ReplicaSnapshotExecutor::new(self.as_ref(), replicas)
-                .await?
-                .take_snapshot(snapshot)
-                .await;
+        let executor =
+            ReplicaSnapshotExecutor::new(self.as_ref(), replicas).await?;
+        let mut replicas_done = executor.take_snapshot(snapshot).await;
+
+        if replicas_done.iter().any(|r| r.status != 0) {
+            executor.rollback(&mut replicas_done).await;
+        }
 
         let ts = Utc::now(); // TODO: make timestamp a snapshot attribute.
 
         Ok(NexusSnapshotStatus {
             replicas_done,
-            replicas_skipped,
+            replicas_skipped: executor.skipped_replicas,
             snapshot_timestamp: ts,
         })
     }
 
-    /// Create a snapshot on all children
+    /// Create a crash-consistent snapshot on all healthy children, with I/O
+    /// paused for the duration of the operation.
     pub async fn create_snapshot(
         mut self: Pin<&mut Self>,
         snapshot: SnapshotParams,
@@ -298,14 +369,6 @@ impl<'n> Nexus<'n> {
 
         self.check_nexus_state()?;
 
-        // For now only single replica nexus is supported.
-        if self.children_iter().len() != 1 {
-            return Err(Error::FailedCreateSnapshot {
-                name: self.bdev_name(),
-                reason: "Nexus has more than one replica".to_string(),
-            });
-        }
-
         // Step 1: Pause I/O subsystem for nexus.
         self.as_mut().pause().await.map_err(|error| {
             error!(
@@ -331,3 +394,232 @@ impl<'n> Nexus<'n> {
         res
     }
 }
+
+/// Create snapshots of several nexuses at the same point in time.
+/// The I/O subsystems of all nexuses are paused while the replica snapshots
+/// are taken, all of them sharing the group ID as their transaction ID.
+/// Either all replica snapshots are created, or none: if any of them fails,
+/// the other ones are destroyed again. The nexuses must be paused, and the
+/// snapshots taken or destroyed again, within `max_pause`, otherwise the
+/// operation fails and the nexuses are resumed at once, the ones which are
+/// still pausing as soon as their pause completes. The snapshot timestamp is
+/// taken while all nexuses are paused.
+pub async fn create_group_snapshot(
+    group_id: &str,
+    nexuses: Vec<NexusGroupSnapshotDescriptor>,
+    max_pause: Duration,
+) -> Result<NexusGroupSnapshotStatus, Error> {
+    let failed = |reason: String| Error::FailedCreateSnapshot {
+        name: group_id.to_string(),
+        reason,
+    };
+
+    if group_id.is_empty() {
+        return Err(failed("Group ID must be provided".to_string()));
+    }
+    if nexuses.is_empty() {
+        return Err(failed("No nexuses given".to_string()));
+    }
+
+    // Validate all nexuses and get the replica handles before pausing any
+    // I/O, to keep the pause as short as possible.
+    let mut group = Vec::with_capacity(nexuses.len());
+    let mut group_nexuses = Vec::with_capacity(nexuses.len());
+    for d in nexuses {
+        if group.iter().any(|(uuid, ..)| uuid == &d.nexus_uuid) {
+            return Err(failed(format!("Duplicated nexus {}", d.nexus_uuid)));
+        }
+
+        let nexus = nexus_lookup_uuid_mut(&d.nexus_uuid).ok_or_else(|| {
+            Error::NexusNotFound {
+                name: d.nexus_uuid.clone(),
+            }
+        })?;
+
+        if d.snapshot.name().is_none() {
+            return Err(failed(format!(
+                "Snapshot name must be provided for nexus {}",
+                d.nexus_uuid
+            )));
+        }
+        nexus.check_nexus_state()?;
+
+        let executor =
+            ReplicaSnapshotExecutor::new(nexus.as_ref(), d.replicas).await?;
+
+        let mut snapshot = d.snapshot;
+        snapshot.set_txn_id(group_id.to_string());
+
+        group.push((d.nexus_uuid, snapshot, executor));
+        group_nexuses.push(nexus);
+    }
+
+    // Step 1: Pause I/O subsystems of all nexuses in parallel, bounded by
+    // `max_pause`.
+    let started = Instant::now();
+    let mut pausing = group_nexuses
+        .into_iter()
+        .enumerate()
+        .map(|(idx, mut nexus)| async move {
+            let res = nexus.as_mut().pause().await;
+            (idx, nexus, res)
+        })
+        .collect::<FuturesUnordered<_>>();
+    let mut deadline = mayastor_sleep(max_pause);
+    let mut paused = Vec::with_capacity(group.len());
+    let mut res = Ok(());
+    loop {
+        match select(pausing.next(), &mut deadline).await {
+            Either::Left((None, _)) => break,
+            Either::Left((Some((_, nexus, Ok(()))), _)) => {
+                paused.push(nexus);
+            }
+            Either::Left((Some((idx, _, Err(error))), _)) => {
+                error!(
+                    group_id,
+                    nexus = group[idx].0.as_str(),
+                    ?error,
+                    "Failed to pause I/O subsystem, group snapshot creation failed"
+                );
+                res = Err(error);
+                break;
+            }
+            Either::Right(_) => {
+                error!(
+                    group_id,
+                    ?max_pause,
+                    "Timed out pausing I/O subsystems, group snapshot creation failed"
+                );
+                res = Err(failed(format!(
+                    "Pausing nexuses took more than {max_pause:?}"
+                )));
+                break;
+            }
+        }
+    }
+
+    // Don't hold up the nexuses which are paused already: the ones still
+    // pausing are resumed in the background once their pause completes.
+    if !pausing.is_empty() {
+        let group_id = group_id.to_string();
+        Reactors::current().send_future(async move {
+            while let Some((_, mut nexus, res)) = pausing.next().await {
+                if res.is_ok() {
+                    if let Err(error) = nexus.as_mut().resume().await {
+                        error!(
+                            group_id = group_id.as_str(),
+                            ?nexus,
+                            ?error,
+                            "Failed to unpause nexus I/O subsystem, nexus might be not accessible by initiator"
+                        );
+                    }
+                }
+            }
+        });
+    }
+
+    // Step 2: Create snapshots on all replicas of all nexuses, and destroy
+    // them again if any of them fails. The nexuses are resumed as soon as
+    // the pause exceeds `max_pause`, in which case all snapshots are
+    // destroyed once they complete.
+    let ts = Utc::now();
+    let mut statuses = Vec::new();
+    if res.is_ok() {
+        let snapshots =
+            join_all(group.iter().map(|(_, snapshot, executor)| {
+                executor.take_snapshot(snapshot.clone())
+            }));
+        pin_mut!(snapshots);
+        statuses = match select(snapshots, &mut deadline).await {
+            Either::Left((statuses, _)) => statuses,
+            Either::Right((_, snapshots)) => {
+                error!(
+                    group_id,
+                    ?max_pause,
+                    "Timed out taking replica snapshots, group snapshot creation failed"
+                );
+                res = Err(failed(format!(
+                    "Taking snapshots took more than {max_pause:?}"
+                )));
+                resume_group(group_id, std::mem::take(&mut paused)).await;
+                snapshots.await
+            }
+        };
+
+        if res.is_ok() {
+            let failed_replica =
+                statuses.iter().flatten().find(|r| r.status != 0);
+            if let Some(r) = failed_replica {
+                res = Err(failed(format!(
+                    "Failed to snapshot replica {}: errno {}",
+                    r.replica_uuid, r.status
+                )));
+            }
+        }
+
+        if res.is_err() {
+            let rollback =
+                join_all(group.iter().zip(statuses.iter_mut()).map(
+                    |((.., executor), replicas)| executor.rollback(replicas),
+                ));
+            pin_mut!(rollback);
+            if paused.is_empty() {
+                rollback.await;
+            } else if let Either::Right((_, rollback)) =
+                select(rollback, &mut deadline).await
+            {
+                error!(
+                    group_id,
+                    ?max_pause,
+                    "Timed out rolling back replica snapshots"
+                );
+                resume_group(group_id, std::mem::take(&mut paused)).await;
+                rollback.await;
+            }
+        }
+    }
+
+    // Step 3: Resume I/O of the nexuses which are still paused.
+    resume_group(group_id, paused).await;
+
+    info!(
+        group_id,
+        pause = ?started.elapsed(),
+        ok = res.is_ok(),
+        "Group snapshot operation completed"
+    );
+    res?;
+
+    Ok(NexusGroupSnapshotStatus {
+        group_id: group_id.to_string(),
+        snapshot_timestamp: ts,
+        nexuses: group
+            .into_iter()
+            .zip(statuses)
+            .map(|((uuid, .., executor), replicas_done)| {
+                (
+                    uuid,
+                    NexusSnapshotStatus {
+                        snapshot_timestamp: ts,
+                        replicas_done,
+                        replicas_skipped: executor.skipped_replicas,
+                    },
+                )
+            })
+            .collect(),
+    })
+}
+
+/// Resume the I/O subsystems of the paused nexuses of a group snapshot.
+async fn resume_group(group_id: &str, nexuses: Vec<Pin<&mut Nexus<'_>>>) {
+    for mut nexus in nexuses {
+        if let Err(error) = nexus.as_mut().resume().await {
+            error!(
+                group_id,
+                ?nexus,
+                ?error,
+                "Failed to unpause nexus I/O subsystem, nexus might be not accessible by initiator"
+            );
+        }
+    }
+}
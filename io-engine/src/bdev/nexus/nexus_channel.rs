@@ -15,6 +15,9 @@ use crate::core::{BlockDeviceHandle, CoreError, Cores};
 pub struct NexusChannel<'n> {
     writers: Vec<Box<dyn BlockDeviceHandle>>,
     readers: Vec<Box<dyn BlockDeviceHandle>>,
+    /// Handles of the children of a striped or concatenated nexus, in the
+    /// order of the children, `None` for the children not healthy.
+    members: Vec<Option<Box<dyn BlockDeviceHandle>>>,
     io_logs: Vec<IOLogChannel>,
     previous_reader: UnsafeCell<usize>,
     fail_fast: u32,
@@ -90,6 +93,7 @@ impl<'n> NexusChannel<'n> {
         Self {
             writers,
             readers,
+            members: Self::member_handles(&nexus),
             io_logs: nexus.io_log_channels(),
             previous_reader: UnsafeCell::new(0),
             nexus: unsafe { nexus.pinned_mut() },
@@ -107,9 +111,30 @@ impl<'n> NexusChannel<'n> {
         );
         self.writers.clear();
         self.readers.clear();
+        self.members.clear();
         self.io_logs.clear();
     }
 
+    /// Gets the handles of the children of a striped or concatenated nexus.
+    fn member_handles(
+        nexus: &Nexus<'n>,
+    ) -> Vec<Option<Box<dyn BlockDeviceHandle>>> {
+        if !nexus.layout().is_split() {
+            return Vec::new();
+        }
+
+        nexus
+            .children_iter()
+            .map(|c| {
+                if c.is_healthy() {
+                    c.get_io_handle().ok()
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
     /// Returns reference to channel's Nexus.
     #[inline(always)]
     #[allow(dead_code)]
@@ -137,6 +162,13 @@ impl<'n> NexusChannel<'n> {
         self.writers.iter().try_for_each(|h| f(h.as_ref()))
     }
 
+    /// Returns the handle of a child of a striped or concatenated nexus by
+    /// its index, if the child is healthy.
+    #[inline(always)]
+    pub(super) fn member(&self, idx: usize) -> Option<&dyn BlockDeviceHandle> {
+        self.members.get(idx)?.as_deref()
+    }
+
     /// Calls the given callback for each active I/O log.
     #[inline(always)]
     pub(super) fn for_each_io_log<F>(&self, f: F)
@@ -176,6 +208,12 @@ impl<'n> NexusChannel<'n> {
             .retain(|c| c.get_device().device_name() != device_name);
         self.writers
             .retain(|c| c.get_device().device_name() != device_name);
+        self.members.iter_mut().for_each(|m| {
+            let name = m.as_ref().map(|h| h.get_device().device_name());
+            if name.as_deref() == Some(device_name) {
+                *m = None;
+            }
+        });
 
         debug!("{self:?}: device '{device_name}' disconnected");
     }
@@ -238,6 +276,7 @@ impl<'n> NexusChannel<'n> {
 
         self.writers = writers;
         self.readers = readers;
+        self.members = Self::member_handles(self.nexus());
 
         self.reconnect_io_logs();
 
@@ -28,6 +28,7 @@ use crate::{
         Reactors,
         VerboseError,
     },
+    events::{nexus_status_check, EngineEvent},
     persistent_store::PersistentStore,
     rebuild::{RebuildJob, RebuildMap},
 };
@@ -328,12 +329,19 @@ impl<'c> NexusChild<'c> {
         debug!("{self:?}: changing state to '{state}'");
         let prev_state = self.state.swap(state);
         self.prev_state.store(prev_state);
+        nexus_status_check(&self.parent);
     }
 
     /// Unconditionally sets child's state as faulted with the given reason.
     pub(crate) fn set_faulted_state(&self, reason: FaultReason) {
         self.set_state(ChildState::Faulted(reason));
         self.set_fault_timestamp();
+        EngineEvent::ChildFaulted {
+            nexus: self.parent.clone(),
+            child: self.name.clone(),
+            reason,
+        }
+        .publish();
     }
 
     /// Open the child in RW mode and claim the device to be ours. If the child
@@ -1001,7 +1009,13 @@ impl<'c> NexusChild<'c> {
             return Err(ChildError::ChildInaccessible {});
         }
 
-        self.open(parent_size, ChildSyncState::OutOfSync)
+        let name = self.open(parent_size, ChildSyncState::OutOfSync)?;
+        EngineEvent::ChildOnlined {
+            nexus: self.parent.clone(),
+            child: self.name.clone(),
+        }
+        .publish();
+        Ok(name)
     }
 
     /// Extract a UUID from a URI.
@@ -2,6 +2,8 @@ use snafu::Snafu;
 use std::time::Duration;
 use url::ParseError;
 
+use crate::core::IoCompletionStatus;
+
 #[derive(Debug, Snafu)]
 #[snafu(visibility(pub(crate)), context(suffix(false)))]
 pub enum InjectionError {
@@ -46,6 +48,18 @@ pub enum InjectionOp {
     WriteSubmission,
 }
 
+/// Fault applied to a child I/O by a matching injection.
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InjectedFault {
+    /// Fails the child I/O with the given status.
+    Fail(IoCompletionStatus),
+    /// Delays the completion of the nexus I/O by the given duration.
+    Delay(Duration),
+    /// Corrupts the data read by the child I/O.
+    Corrupt,
+}
+
 #[cfg(feature = "nexus-fault-injection")]
 mod inj_impl {
     use std::{
@@ -56,13 +70,23 @@ mod inj_impl {
         time::{Duration, Instant},
     };
 
+    use rand::Rng;
     use snafu::ResultExt;
+    use spdk_rs::{MediaErrorStatusCode, PathStatusCode};
     use url::Url;
 
-    use crate::core::{BlockDevice, VerboseError};
+    use crate::core::{
+        BlockDevice,
+        GenericStatusCode,
+        IoCompletionStatus,
+        LvolFailure,
+        NvmeStatus,
+        VerboseError,
+    };
 
     use super::{
         super::{nexus_err, Error, Nexus},
+        InjectedFault,
         InjectionError,
         InjectionInfo,
         InjectionOp,
@@ -96,6 +120,46 @@ mod inj_impl {
         Ok(op)
     }
 
+    /// Parses the completion status of a failing injection.
+    fn parse_status(
+        k: &str,
+        v: &str,
+    ) -> Result<IoCompletionStatus, InjectionError> {
+        let status = match v {
+            "data_transfer" => IoCompletionStatus::NvmeError(
+                NvmeStatus::Generic(GenericStatusCode::DataTransferError),
+            ),
+            "internal" => IoCompletionStatus::NvmeError(NvmeStatus::Generic(
+                GenericStatusCode::InternalDeviceError,
+            )),
+            "path" => IoCompletionStatus::NvmeError(NvmeStatus::Path(
+                PathStatusCode::HostPathingError,
+            )),
+            "ana_inaccessible" => IoCompletionStatus::NvmeError(
+                NvmeStatus::Path(PathStatusCode::AsymmetricAccessInaccessible),
+            ),
+            "reservation_conflict" => IoCompletionStatus::NvmeError(
+                NvmeStatus::Generic(GenericStatusCode::ReservationConflict),
+            ),
+            "media" => IoCompletionStatus::NvmeError(NvmeStatus::MediaError(
+                MediaErrorStatusCode::Guard,
+            )),
+            "unwritten" => {
+                IoCompletionStatus::NvmeError(NvmeStatus::MediaError(
+                    MediaErrorStatusCode::DeallocatedOrUnwrittenBlock,
+                ))
+            }
+            "no_space" => IoCompletionStatus::LvolError(LvolFailure::NoSpace),
+            _ => {
+                return Err(InjectionError::UnknownParameter {
+                    name: k.to_string(),
+                    value: v.to_string(),
+                })
+            }
+        };
+        Ok(status)
+    }
+
     /// TODO
     fn parse_timer(k: &str, v: &str) -> Result<Duration, InjectionError> {
         let b = v.parse::<u64>().map_err(|_| {
@@ -117,19 +181,48 @@ mod inj_impl {
             })
     }
 
+    /// Parses a probability in the range [0, 1].
+    fn parse_prob(k: &str, v: &str) -> Result<f64, InjectionError> {
+        match v.parse::<f64>() {
+            Ok(p) if (0.0 ..= 1.0).contains(&p) => Ok(p),
+            _ => Err(InjectionError::BadParameterValue {
+                name: k.to_string(),
+                value: v.to_string(),
+            }),
+        }
+    }
+
     /// Tests if teo ranges overlap.
     fn is_overlapping(a: &Range<u64>, b: &Range<u64>) -> bool {
         a.end > b.start && b.end > a.start
     }
 
+    /// Type of an injected fault.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum InjectionType {
+        /// Fails the I/O with the given status.
+        Fail(IoCompletionStatus),
+        /// Adds a latency, random within the given range.
+        Delay { min: Duration, max: Duration },
+        /// Corrupts the data read.
+        Corrupt,
+    }
+
     /// Injected failures.
     struct Injection {
         name: String,
         op: InjectionOp,
+        kind: InjectionType,
         started: Option<Instant>,
         begin: Duration,
         end: Duration,
         range: Range<u64>,
+        /// Applies the fault to every Nth matching I/O only.
+        every: u64,
+        /// Applies the fault to a matching I/O with this probability.
+        prob: f64,
+        /// Number of matching I/Os so far.
+        hits: u64,
     }
 
     impl Debug for Injection {
@@ -152,7 +245,8 @@ mod inj_impl {
 
             write!(
                 f,
-                "{op:?} Injection '{name}' [{b:?} -> {e} ({t:?})] @ {rs}..{re}",
+                "{op:?} Injection '{name}' [{b:?} -> {e} ({t:?})] @ {rs}..{re} \
+                {kind:?} (every: {every}, prob: {prob}, hits: {hits})",
                 op = self.op,
                 name = self.name,
                 b = self.begin,
@@ -160,6 +254,10 @@ mod inj_impl {
                 t = self.now(),
                 rs = self.range.start,
                 re = fmt_u64(self.range.end),
+                kind = self.kind,
+                every = self.every,
+                prob = self.prob,
+                hits = self.hits,
             )
         }
     }
@@ -191,15 +289,45 @@ mod inj_impl {
                     path = p.path()
                 ),
                 op: InjectionOp::Read,
+                kind: InjectionType::Corrupt,
                 started: None,
                 begin: Duration::ZERO,
                 end: Duration::MAX,
                 range: 0 .. u64::MAX,
+                every: 1,
+                prob: 1.0,
+                hits: 0,
             };
 
+            let mut kind = "fail".to_string();
+            let mut status = IoCompletionStatus::NvmeError(
+                NvmeStatus::Generic(GenericStatusCode::DataTransferError),
+            );
+            let mut delay = Duration::ZERO;
+            let mut delay_max = None;
+
             for (k, v) in p.query_pairs() {
                 match k.as_ref() {
                     "op" => r.op = parse_op(&k, &v)?,
+                    "type" => kind = v.to_string(),
+                    "status" => status = parse_status(&k, &v)?,
+                    "delay_us" => {
+                        delay = Duration::from_micros(parse_num(&k, &v)?)
+                    }
+                    "delay_max_us" => {
+                        delay_max =
+                            Some(Duration::from_micros(parse_num(&k, &v)?))
+                    }
+                    "every" => match parse_num(&k, &v)? {
+                        0 => {
+                            return Err(InjectionError::BadParameterValue {
+                                name: k.to_string(),
+                                value: v.to_string(),
+                            })
+                        }
+                        n => r.every = n,
+                    },
+                    "prob" => r.prob = parse_prob(&k, &v)?,
                     "begin" => r.begin = parse_timer(&k, &v)?,
                     "end" => r.end = parse_timer(&k, &v)?,
                     "offset" => r.range.start = parse_num(&k, &v)?,
@@ -215,6 +343,39 @@ mod inj_impl {
 
             r.range.end = r.range.start.saturating_add(r.range.end);
 
+            let is_submission = matches!(
+                r.op,
+                InjectionOp::ReadSubmission | InjectionOp::WriteSubmission
+            );
+
+            // Only failures can be injected upon submission, and only read
+            // data can be corrupted.
+            r.kind = match kind.as_str() {
+                "fail" => InjectionType::Fail(status),
+                "delay" if !is_submission => {
+                    let max = delay_max.unwrap_or(delay);
+                    if max < delay {
+                        return Err(InjectionError::BadParameterValue {
+                            name: "delay_max_us".to_string(),
+                            value: max.as_micros().to_string(),
+                        });
+                    }
+                    InjectionType::Delay {
+                        min: delay,
+                        max,
+                    }
+                }
+                "corrupt" if r.op == InjectionOp::Read => {
+                    InjectionType::Corrupt
+                }
+                _ => {
+                    return Err(InjectionError::BadParameterValue {
+                        name: "type".to_string(),
+                        value: kind,
+                    })
+                }
+            };
+
             if r.begin > r.end {
                 return Err(InjectionError::BadDurations {
                     name: r.name,
@@ -239,15 +400,16 @@ mod inj_impl {
             d >= self.begin && d < self.end
         }
 
-        /// Checks if the injection is applied to the given device.
+        /// Checks if the injection is applied to the given device, and
+        /// returns the fault to apply.
         fn is_applied(
             &mut self,
             dev: &dyn BlockDevice,
             op: InjectionOp,
             range: Range<u64>,
-        ) -> bool {
+        ) -> Option<InjectedFault> {
             if op != self.op || dev.device_name() != self.name {
-                return false;
+                return None;
             }
 
             if self.started.is_none() {
@@ -255,7 +417,29 @@ mod inj_impl {
                 self.started = Some(Instant::now());
             }
 
-            self.is_active() && is_overlapping(&self.range, &range)
+            if !self.is_active() || !is_overlapping(&self.range, &range) {
+                return None;
+            }
+
+            self.hits += 1;
+            if self.hits % self.every != 0
+                || (self.prob < 1.0 && !rand::thread_rng().gen_bool(self.prob))
+            {
+                return None;
+            }
+
+            Some(match self.kind {
+                InjectionType::Fail(status) => InjectedFault::Fail(status),
+                InjectionType::Delay {
+                    min,
+                    max,
+                } => InjectedFault::Delay(if min == max {
+                    min
+                } else {
+                    rand::thread_rng().gen_range(min ..= max)
+                }),
+                InjectionType::Corrupt => InjectedFault::Corrupt,
+            })
         }
     }
 
@@ -370,19 +554,20 @@ mod inj_impl {
                 .collect())
         }
 
-        /// Tests if there exists an active injected fault for the device.
-        pub fn inject_is_faulted(
+        /// Returns the fault of the first active injection that applies to
+        /// the I/O on the device, if any.
+        pub fn inject_check(
             &self,
             dev: &dyn BlockDevice,
             op: InjectionOp,
             offset: u64,
             num_blocks: u64,
-        ) -> bool {
+        ) -> Option<InjectedFault> {
             if !injections_enabled() {
-                return false;
+                return None;
             }
 
-            self.injections.items.lock().iter_mut().any(|inj| {
+            self.injections.items.lock().iter_mut().find_map(|inj| {
                 inj.is_applied(dev, op, offset .. offset + num_blocks)
             })
         }
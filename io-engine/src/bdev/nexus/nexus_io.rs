@@ -9,12 +9,14 @@ use nix::errno::Errno;
 use spdk_rs::{
     libspdk::{spdk_bdev_io, spdk_io_channel},
     BdevIo,
+    IoVec,
 };
 
 use super::{
     nexus_lookup,
     FaultReason,
     IOLogChannel,
+    IoTraceStatus,
     Nexus,
     NexusChannel,
     NexusState,
@@ -24,25 +26,31 @@ use super::{
 #[allow(unused_imports)]
 use super::{
     nexus_injection::injections_enabled,
+    nexus_injection::InjectedFault,
     nexus_injection::InjectionOp,
 };
 
-use crate::core::{
-    device_cmd_queue,
-    BlockDevice,
-    BlockDeviceHandle,
-    CoreError,
-    Cores,
-    DeviceCommand,
-    GenericStatusCode,
-    IoCompletionStatus,
-    IoStatus,
-    IoSubmissionFailure,
-    IoType,
-    LvolFailure,
-    Mthread,
-    NvmeStatus,
-    Reactors,
+use crate::{
+    bdev::util::iov::IovCursor,
+    core::{
+        device_cmd_queue,
+        io_start_ticks,
+        BlockDevice,
+        BlockDeviceHandle,
+        CoreError,
+        Cores,
+        DeviceCommand,
+        GenericStatusCode,
+        IoCompletionStatus,
+        IoStatus,
+        IoSubmissionFailure,
+        IoType,
+        LvolFailure,
+        Mthread,
+        NvmeStatus,
+        Reactors,
+    },
+    events::nexus_status_check,
 };
 
 #[cfg(feature = "nexus-io-tracing")]
@@ -66,6 +74,16 @@ macro_rules! trace_nexus_io {
     ($($arg:tt)*) => {};
 }
 
+/// An I/O of a parity nexus processed on the primary core, sent back to the
+/// core it was submitted on to be completed.
+struct ParityIo {
+    ptr: *mut spdk_bdev_io,
+    success: bool,
+}
+
+// Required because `ParityIo.ptr` is a raw pointer, which is not Send.
+unsafe impl Send for ParityIo {}
+
 /// TODO
 #[repr(C)]
 pub(super) struct NioCtx<'n> {
@@ -82,6 +100,13 @@ pub(super) struct NioCtx<'n> {
     failed: u8,
     /// Number of resubmissions. Incremented with each resubmission.
     resubmits: u8,
+    /// Ticks at the submission of the I/O, for latency accounting.
+    start_ticks: u64,
+    /// I/O vectors of the child I/Os of a striped or concatenated nexus.
+    split: *mut Vec<Vec<IoVec>>,
+    /// Injected delay of the completion of the I/O.
+    #[cfg(feature = "nexus-fault-injection")]
+    inject_delay: std::time::Duration,
     /// Debug serial number.
     #[cfg(feature = "nexus-io-tracing")]
     serial: u64,
@@ -172,6 +197,13 @@ impl<'n> NexusBio<'n> {
         ctx.resubmits = 0;
         ctx.successful = 0;
         ctx.failed = 0;
+        ctx.start_ticks = io_start_ticks();
+        ctx.split = std::ptr::null_mut();
+
+        #[cfg(feature = "nexus-fault-injection")]
+        {
+            ctx.inject_delay = std::time::Duration::ZERO;
+        }
 
         #[cfg(feature = "nexus-io-tracing")]
         {
@@ -185,8 +217,20 @@ impl<'n> NexusBio<'n> {
 
     /// TODO
     pub(super) fn submit_request(mut self) {
+        let split = self.nexus().layout().is_split();
+        let parity = self.nexus().layout().is_parity();
+
         if let Err(_e) = match self.io_type() {
             IoType::Read => self.readv(),
+            // these IOs update the rows of a parity nexus
+            IoType::Write | IoType::WriteZeros | IoType::Unmap if parity => {
+                self.submit_parity()
+            }
+            // these IOs are split among the children of a striped or
+            // concatenated nexus
+            IoType::Write | IoType::WriteZeros | IoType::Unmap if split => {
+                self.submit_split()
+            }
             // these IOs are submitted to all the underlying children
             IoType::Write
             | IoType::WriteZeros
@@ -245,7 +289,9 @@ impl<'n> NexusBio<'n> {
         status: IoCompletionStatus,
     ) {
         #[cfg(feature = "nexus-fault-injection")]
-        let status = self.inject_completion_error(child, status);
+        let status = self.inject_completion(child, status);
+
+        self.trace_io(Some(child), status.into());
 
         debug_assert!(self.ctx().in_flight > 0);
         self.ctx_mut().in_flight -= 1;
@@ -265,19 +311,60 @@ impl<'n> NexusBio<'n> {
             return;
         }
 
+        #[cfg(feature = "nexus-fault-injection")]
+        if self.inject_delayed_completion() {
+            return;
+        }
+
+        self.complete_nexus_io();
+    }
+
+    /// Completes the nexus I/O once all its child I/Os have completed.
+    fn complete_nexus_io(&mut self) {
+        self.free_split();
+
         if self.ctx().failed == 0 {
             // No child failures, complete nexus I/O with success.
             trace_nexus_io!("Success: {self:?}");
+            self.nexus()
+                .io_latency
+                .record(self.io_type(), self.ctx().start_ticks);
+            self.trace_io(None, IoTraceStatus::Success);
             self.ok();
-        } else if self.ctx().successful > 0 {
+        } else if self.ctx().successful > 0 && self.nexus().layout().is_mirror()
+        {
             // Having some child failures, resubmit the I/O.
             self.resubmit();
         } else {
             error!("{self:?}: failing nexus I/O: all child I/Os failed");
+            self.trace_io(None, IoTraceStatus::Failed);
             self.fail();
         }
     }
 
+    /// Records the completion of the nexus I/O, or of one of its child I/Os,
+    /// if the I/O tracing of the nexus is enabled.
+    #[inline]
+    fn trace_io(&self, child: Option<&dyn BlockDevice>, status: IoTraceStatus) {
+        let trace = &self.nexus().io_trace;
+        if !trace.is_enabled() {
+            return;
+        }
+
+        let (device, offset) = match child {
+            Some(child) => (Some(child.device_name()), self.effective_offset()),
+            None => (None, self.offset()),
+        };
+        trace.record(
+            device.as_deref(),
+            self.io_type(),
+            offset,
+            self.num_blocks(),
+            status,
+            self.ctx().start_ticks,
+        );
+    }
+
     /// Resubmits the I/O.
     fn resubmit(&mut self) {
         warn!("{self:?}: resubmitting nexus I/O due to a child I/O failure");
@@ -388,6 +475,13 @@ impl<'n> NexusBio<'n> {
     /// In case of submission error the requiest is transparently resubmitted
     /// to the next available replica.
     fn do_readv(&mut self) -> Result<(), CoreError> {
+        if self.nexus().layout().is_split() {
+            return self.submit_split();
+        }
+        if self.nexus().layout().is_parity() {
+            return self.submit_parity();
+        }
+
         match self.__do_readv_one() {
             Err(e) => {
                 match e {
@@ -641,6 +735,213 @@ impl<'n> NexusBio<'n> {
         result
     }
 
+    /// Submits the I/O of a striped or concatenated nexus to the children
+    /// which hold its blocks, one child I/O per child. The data of a child
+    /// I/O can be scattered in the buffer of the nexus I/O, so its vectors
+    /// are kept until the nexus I/O completes. Failed I/Os are not
+    /// resubmitted, as the children have no copies of the data.
+    fn submit_split(&mut self) -> Result<(), CoreError> {
+        let extents = self
+            .nexus()
+            .geometry
+            .split(self.offset(), self.num_blocks());
+
+        if extents
+            .iter()
+            .any(|e| self.channel().member(e.child).is_none())
+        {
+            error!("{self:?}: I/O submission failed: children unavailable");
+            self.fail();
+            return Err(CoreError::NoDevicesAvailable {});
+        }
+
+        let block_len = self.nexus().block_len() as usize;
+        let mut split: Box<Vec<Vec<IoVec>>> = Box::default();
+        if matches!(self.io_type(), IoType::Read | IoType::Write) {
+            let iovs = unsafe {
+                std::slice::from_raw_parts(
+                    self.iovs(),
+                    self.iov_count() as usize,
+                )
+            };
+            for e in &extents {
+                let mut cursor = IovCursor::new(iovs);
+                let mut pos = 0;
+                let mut child_iovs = Vec::new();
+                for (start, len) in &e.ranges {
+                    cursor.skip((*start - pos) as usize * block_len);
+                    child_iovs.extend(cursor.take(*len as usize * block_len));
+                    pos = start + len;
+                }
+                split.push(child_iovs);
+            }
+        }
+        self.ctx_mut().split = Box::into_raw(split);
+
+        let mut inflight = 0;
+        let mut result = Ok(());
+
+        for (i, e) in extents.iter().enumerate() {
+            let hdl = self.channel().member(e.child).unwrap();
+            let offset = e.offset + self.data_ent_offset();
+            let cb_arg = self.as_ptr().cast();
+
+            #[cfg(feature = "nexus-fault-injection")]
+            let r = self.inject_submission_error(hdl);
+            #[cfg(not(feature = "nexus-fault-injection"))]
+            let r = Ok(());
+
+            let r = r.and_then(|_| match self.io_type() {
+                IoType::Read | IoType::Write => {
+                    let iovs = unsafe { &mut (*self.ctx().split)[i] };
+                    if self.io_type() == IoType::Read {
+                        hdl.readv_blocks(
+                            iovs.as_mut_ptr(),
+                            iovs.len() as i32,
+                            offset,
+                            e.num_blocks,
+                            Self::child_completion,
+                            cb_arg,
+                        )
+                    } else {
+                        hdl.writev_blocks(
+                            iovs.as_mut_ptr(),
+                            iovs.len() as i32,
+                            offset,
+                            e.num_blocks,
+                            Self::child_completion,
+                            cb_arg,
+                        )
+                    }
+                }
+                IoType::WriteZeros => hdl.write_zeroes(
+                    offset,
+                    e.num_blocks,
+                    Self::child_completion,
+                    cb_arg,
+                ),
+                IoType::Unmap => hdl.unmap_blocks(
+                    offset,
+                    e.num_blocks,
+                    Self::child_completion,
+                    cb_arg,
+                ),
+                // we should never reach here, if we do it is a bug.
+                _ => unreachable!(),
+            });
+
+            if let Err(err) = r {
+                let device = hdl.get_device().device_name();
+                error!(
+                    "{self:?}: I/O to '{device}' submission failed: {err:?}, \
+                    I/Os submitted: {inflight}"
+                );
+
+                self.ctx_mut().failed += 1;
+                self.channel_mut().disconnect_device(&device);
+                self.fault_device(
+                    &device,
+                    IoCompletionStatus::IoSubmissionError(
+                        if self.io_type() == IoType::Read {
+                            IoSubmissionFailure::Read
+                        } else {
+                            IoSubmissionFailure::Write
+                        },
+                    ),
+                );
+                result = Err(err);
+                break;
+            }
+
+            inflight += 1;
+        }
+
+        if inflight > 0 {
+            self.ctx_mut().in_flight = inflight;
+            self.ctx_mut().status = IoStatus::Success;
+        } else {
+            error!(
+                "{self:?}: failing nexus I/O: all child I/O submissions failed"
+            );
+            self.free_split();
+            self.fail();
+        }
+
+        result
+    }
+
+    /// Processes the I/O of a parity nexus on the current core, with the
+    /// handles of the children of the current thread, and completes it back
+    /// on the current thread.
+    fn submit_parity(&mut self) -> Result<(), CoreError> {
+        let thread = Mthread::current().unwrap();
+        let members = self.nexus().parity_members();
+        let ptr = self.as_ptr();
+
+        Reactors::current().send_future(async move {
+            let bio = NexusBio::from(ptr);
+            let iovs = if bio.iov_count() > 0 {
+                unsafe {
+                    std::slice::from_raw_parts(
+                        bio.iovs(),
+                        bio.iov_count() as usize,
+                    )
+                }
+            } else {
+                &[]
+            };
+
+            let res = bio
+                .nexus()
+                .parity_io(
+                    members,
+                    bio.io_type(),
+                    bio.offset(),
+                    bio.num_blocks(),
+                    iovs,
+                )
+                .await;
+            if let Err(e) = &res {
+                error!("{bio:?}: failing nexus I/O: {e}");
+            }
+
+            thread.send_msg(
+                ParityIo {
+                    ptr,
+                    success: res.is_ok(),
+                },
+                |io| NexusBio::from(io.ptr).complete_parity(io.success),
+            );
+        });
+
+        Ok(())
+    }
+
+    /// Completes the I/O of a parity nexus.
+    fn complete_parity(&mut self, success: bool) {
+        if success {
+            trace_nexus_io!("Success: {self:?}");
+            self.nexus()
+                .io_latency
+                .record(self.io_type(), self.ctx().start_ticks);
+            self.trace_io(None, IoTraceStatus::Success);
+            self.ok();
+        } else {
+            self.trace_io(None, IoTraceStatus::Failed);
+            self.fail();
+        }
+    }
+
+    /// Frees the I/O vectors of the child I/Os of a striped or concatenated
+    /// nexus.
+    fn free_split(&mut self) {
+        let split = self.ctx().split;
+        if !split.is_null() {
+            drop(unsafe { Box::from_raw(split) });
+            self.ctx_mut().split = std::ptr::null_mut();
+        }
+    }
+
     /// Logs all write-like operation in the rebuild logs, if any exist.
     #[inline]
     fn log_io(&self, log: &IOLogChannel) {
@@ -684,6 +985,7 @@ impl<'n> NexusBio<'n> {
                         };
                         *s = NexusState::ShuttingDown;
                     }
+                    nexus_status_check(&nexus_name);
 
                     // 1: Close I/O channels for all children.
                     for d in nexus.child_devices() {
@@ -710,6 +1012,7 @@ impl<'n> NexusBio<'n> {
                     // Note: we don't persist nexus's state in ETCd as nexus
                     // might be recreated on onother node.
                     *nexus.state.lock() = NexusState::Shutdown;
+                    nexus_status_check(&nexus_name);
                 }
             });
         }
@@ -818,7 +1121,7 @@ impl<'n> NexusBio<'n> {
             _ => InjectionOp::WriteSubmission,
         };
 
-        if self.nexus().inject_is_faulted(
+        if let Some(InjectedFault::Fail(_)) = self.nexus().inject_check(
             hdl.get_device(),
             op,
             self.offset(),
@@ -835,11 +1138,13 @@ impl<'n> NexusBio<'n> {
         }
     }
 
-    /// Checks if an error is to be injected upon completion.
+    /// Applies the fault injected upon completion, if any: replaces the
+    /// completion status, corrupts the data read or schedules a delay of the
+    /// nexus I/O completion.
     #[cfg(feature = "nexus-fault-injection")]
     #[inline]
-    fn inject_completion_error(
-        &self,
+    fn inject_completion(
+        &mut self,
         child: &dyn BlockDevice,
         status: IoCompletionStatus,
     ) -> IoCompletionStatus {
@@ -852,17 +1157,61 @@ impl<'n> NexusBio<'n> {
             _ => InjectionOp::Write,
         };
 
-        if self.nexus().inject_is_faulted(
+        match self.nexus().inject_check(
             child,
             op,
             self.offset(),
             self.num_blocks(),
         ) {
-            IoCompletionStatus::NvmeError(NvmeStatus::Generic(
-                GenericStatusCode::DataTransferError,
-            ))
-        } else {
-            status
+            Some(InjectedFault::Fail(s)) => s,
+            Some(InjectedFault::Delay(d)) => {
+                let ctx = self.ctx_mut();
+                ctx.inject_delay = ctx.inject_delay.max(d);
+                status
+            }
+            Some(InjectedFault::Corrupt)
+                if status == IoCompletionStatus::Success =>
+            {
+                self.inject_corruption();
+                status
+            }
+            _ => status,
         }
     }
+
+    /// Silently corrupts the data read, by flipping the bits of the first
+    /// byte of every I/O vector.
+    #[cfg(feature = "nexus-fault-injection")]
+    fn inject_corruption(&self) {
+        let iovs = unsafe {
+            std::slice::from_raw_parts(self.iovs(), self.iov_count() as usize)
+        };
+        for iov in iovs.iter().filter(|iov| iov.iov_len > 0) {
+            unsafe {
+                *(iov.iov_base as *mut u8) ^= 0xff;
+            }
+        }
+    }
+
+    /// Defers the completion of the nexus I/O when one of its child I/Os
+    /// had an injected delay. Returns true if the completion is deferred.
+    #[cfg(feature = "nexus-fault-injection")]
+    fn inject_delayed_completion(&mut self) -> bool {
+        let delay = std::mem::take(&mut self.ctx_mut().inject_delay);
+        if delay.is_zero() {
+            return false;
+        }
+
+        trace_nexus_io!("Delayed by {delay:?}: {self:?}");
+
+        // The deferred completion runs on the reactor of the current core,
+        // which is the one the nexus I/O must complete on.
+        let ptr = self.as_ptr();
+        Reactors::current().send_future(async move {
+            crate::sleep::mayastor_sleep(delay).await.ok();
+            NexusBio::from(ptr).complete_nexus_io();
+        });
+
+        true
+    }
 }
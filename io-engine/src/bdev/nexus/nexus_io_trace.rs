@@ -0,0 +1,409 @@
+//!
+//! Runtime I/O tracing of a nexus.
+//!
+//! While enabled, every nexus I/O and every child I/O completion is recorded
+//! into a ring buffer of the core it completes on, so that recording never
+//! contends with other cores. The rings are merged into an `IoTraceCapture`
+//! on request, which can be written to a file and decoded by the
+//! `nexus-io-trace` tool.
+//!
+//! The capture file is the bincode encoding of an `IoTraceHeader` followed
+//! by the number of records and the records themselves, sorted by
+//! timestamp.
+use std::{
+    collections::VecDeque,
+    io::{Read, Write},
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+};
+
+use snafu::{ResultExt, Snafu};
+use spdk_rs::libspdk::spdk_get_ticks_hz;
+
+use crate::core::{io_start_ticks, Cores, IoCompletionStatus, IoType};
+
+/// Magic of the capture files.
+pub const IO_TRACE_MAGIC: [u8; 8] = *b"NXIOTRC1";
+/// Version of the capture format.
+pub const IO_TRACE_VERSION: u32 = 1;
+/// Default number of records kept per core.
+pub const DEFAULT_IO_TRACE_RECORDS: usize = 65536;
+/// Child index of the records of the nexus I/Os.
+pub const IO_TRACE_NEXUS: u16 = u16::MAX;
+
+/// I/O operation of a trace record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoTraceOp {
+    Read,
+    Write,
+    Unmap,
+    Flush,
+    WriteZeros,
+    Reset,
+    Other,
+}
+
+impl IoTraceOp {
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code {
+            0 => Self::Read,
+            1 => Self::Write,
+            2 => Self::Unmap,
+            3 => Self::Flush,
+            4 => Self::WriteZeros,
+            5 => Self::Reset,
+            _ => Self::Other,
+        }
+    }
+}
+
+impl From<IoType> for IoTraceOp {
+    fn from(op: IoType) -> Self {
+        match op {
+            IoType::Read => Self::Read,
+            IoType::Write => Self::Write,
+            IoType::Unmap => Self::Unmap,
+            IoType::Flush => Self::Flush,
+            IoType::WriteZeros => Self::WriteZeros,
+            IoType::Reset => Self::Reset,
+            _ => Self::Other,
+        }
+    }
+}
+
+/// Completion status of a trace record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoTraceStatus {
+    Success,
+    NvmeError,
+    LvolError,
+    SubmissionError,
+    AdminCommandError,
+    /// The nexus I/O failed.
+    Failed,
+}
+
+impl IoTraceStatus {
+    fn code(self) -> u8 {
+        self as u8
+    }
+
+    fn from_code(code: u8) -> Self {
+        match code {
+            0 => Self::Success,
+            1 => Self::NvmeError,
+            2 => Self::LvolError,
+            3 => Self::SubmissionError,
+            4 => Self::AdminCommandError,
+            _ => Self::Failed,
+        }
+    }
+}
+
+impl From<IoCompletionStatus> for IoTraceStatus {
+    fn from(status: IoCompletionStatus) -> Self {
+        match status {
+            IoCompletionStatus::Success => Self::Success,
+            IoCompletionStatus::NvmeError(_) => Self::NvmeError,
+            IoCompletionStatus::LvolError(_) => Self::LvolError,
+            IoCompletionStatus::IoSubmissionError(_) => Self::SubmissionError,
+            IoCompletionStatus::AdminCommandError => Self::AdminCommandError,
+        }
+    }
+}
+
+/// A traced I/O, 32 bytes once encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoTraceRecord {
+    /// Completion time, in nanoseconds since the tracing was enabled.
+    pub timestamp_ns: u64,
+    /// Offset of the I/O, in blocks.
+    pub offset: u64,
+    /// Time between the submission of the nexus I/O and the completion, in
+    /// nanoseconds. For child I/Os this includes the time the nexus I/O took
+    /// to be submitted to the child.
+    pub latency_ns: u64,
+    /// Length of the I/O, in blocks.
+    pub num_blocks: u32,
+    /// Index of the child in the capture header, or `IO_TRACE_NEXUS`.
+    pub child: u16,
+    op: u8,
+    status: u8,
+}
+
+impl IoTraceRecord {
+    /// I/O operation of the record.
+    pub fn op(&self) -> IoTraceOp {
+        IoTraceOp::from_code(self.op)
+    }
+
+    /// Completion status of the record.
+    pub fn status(&self) -> IoTraceStatus {
+        IoTraceStatus::from_code(self.status)
+    }
+}
+
+/// A child of the nexus referenced by the trace records.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoTraceChild {
+    /// Name of the child device.
+    pub device: String,
+    /// URI of the child, empty if it was removed from the nexus.
+    pub uri: String,
+}
+
+/// Header of a capture file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoTraceHeader {
+    pub magic: [u8; 8],
+    pub version: u32,
+    pub nexus: String,
+    pub uuid: String,
+    pub block_len: u64,
+    /// Time the tracing was enabled, in nanoseconds since the Unix epoch.
+    pub start_time_ns: u64,
+    pub children: Vec<IoTraceChild>,
+}
+
+/// Decoding or encoding error of a capture.
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(false)))]
+pub enum IoTraceError {
+    #[snafu(display("Invalid I/O trace capture: {}", source))]
+    Encoding { source: bincode::Error },
+    #[snafu(display("Not an I/O trace capture"))]
+    BadMagic {},
+    #[snafu(display("Unsupported I/O trace capture version {}", version))]
+    BadVersion { version: u32 },
+}
+
+/// Merged I/O trace of a nexus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IoTraceCapture {
+    pub header: IoTraceHeader,
+    pub records: Vec<IoTraceRecord>,
+}
+
+impl IoTraceCapture {
+    /// Writes the capture in the capture file format.
+    pub fn write_to(&self, mut w: impl Write) -> Result<(), IoTraceError> {
+        bincode::serialize_into(&mut w, &self.header).context(Encoding)?;
+        bincode::serialize_into(&mut w, &self.records).context(Encoding)
+    }
+
+    /// Reads a capture in the capture file format.
+    pub fn read_from(mut r: impl Read) -> Result<Self, IoTraceError> {
+        let header: IoTraceHeader =
+            bincode::deserialize_from(&mut r).context(Encoding)?;
+        if header.magic != IO_TRACE_MAGIC {
+            return Err(IoTraceError::BadMagic {});
+        }
+        if header.version != IO_TRACE_VERSION {
+            return Err(IoTraceError::BadVersion {
+                version: header.version,
+            });
+        }
+        let records = bincode::deserialize_from(&mut r).context(Encoding)?;
+        Ok(Self {
+            header,
+            records,
+        })
+    }
+}
+
+/// Records of a core, with the child devices they reference.
+#[derive(Default)]
+struct IoTraceRing {
+    records: VecDeque<IoTraceRecord>,
+    children: Vec<String>,
+}
+
+impl IoTraceRing {
+    fn child_index(&mut self, device: &str) -> u16 {
+        match self.children.iter().position(|c| c == device) {
+            Some(idx) => idx as u16,
+            None => {
+                self.children.push(device.to_string());
+                (self.children.len() - 1) as u16
+            }
+        }
+    }
+}
+
+/// Per-core I/O trace rings of a nexus.
+pub(crate) struct IoTrace {
+    enabled: AtomicBool,
+    capacity: AtomicU64,
+    start_ticks: AtomicU64,
+    start_time_ns: AtomicU64,
+    tick_rate: u64,
+    rings: Box<[parking_lot::Mutex<IoTraceRing>]>,
+}
+
+impl std::fmt::Debug for IoTrace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IoTrace")
+            .field("enabled", &self.is_enabled())
+            .field("capacity", &self.capacity.load(Ordering::Relaxed))
+            .finish()
+    }
+}
+
+impl IoTrace {
+    pub(crate) fn new() -> Self {
+        let num_cores = Cores::last().id() as usize + 1;
+        Self {
+            enabled: AtomicBool::new(false),
+            capacity: AtomicU64::new(DEFAULT_IO_TRACE_RECORDS as u64),
+            start_ticks: AtomicU64::new(0),
+            start_time_ns: AtomicU64::new(0),
+            tick_rate: unsafe { spdk_get_ticks_hz() }.max(1),
+            rings: (0 .. num_cores).map(|_| Default::default()).collect(),
+        }
+    }
+
+    /// Checks if the tracing is enabled.
+    #[inline]
+    pub(crate) fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Enables the tracing, discarding the records of any previous trace.
+    /// Each core keeps up to `records_per_core` of its latest records, or
+    /// `DEFAULT_IO_TRACE_RECORDS` if 0.
+    pub(crate) fn enable(&self, records_per_core: usize) {
+        let capacity = if records_per_core == 0 {
+            DEFAULT_IO_TRACE_RECORDS
+        } else {
+            records_per_core
+        };
+
+        self.enabled.store(false, Ordering::SeqCst);
+        for ring in self.rings.iter() {
+            *ring.lock() = IoTraceRing::default();
+        }
+        self.capacity.store(capacity as u64, Ordering::SeqCst);
+        self.start_ticks.store(io_start_ticks(), Ordering::SeqCst);
+        self.start_time_ns.store(
+            chrono::Utc::now().timestamp_nanos() as u64,
+            Ordering::SeqCst,
+        );
+        self.enabled.store(true, Ordering::SeqCst);
+    }
+
+    /// Disables the tracing. The records are kept until the tracing is
+    /// enabled again.
+    pub(crate) fn disable(&self) {
+        self.enabled.store(false, Ordering::SeqCst);
+    }
+
+    /// Number of records currently kept.
+    pub(crate) fn len(&self) -> usize {
+        self.rings.iter().map(|r| r.lock().records.len()).sum()
+    }
+
+    fn ticks_to_ns(&self, ticks: u64) -> u64 {
+        (ticks as u128 * 1_000_000_000 / self.tick_rate as u128) as u64
+    }
+
+    /// Records the completion of an I/O started at `start_ticks`, on the
+    /// nexus itself if `child` is None.
+    pub(crate) fn record(
+        &self,
+        child: Option<&str>,
+        op: IoType,
+        offset: u64,
+        num_blocks: u64,
+        status: IoTraceStatus,
+        start_ticks: u64,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = io_start_ticks();
+        let trace_start = self.start_ticks.load(Ordering::Relaxed);
+        let capacity = self.capacity.load(Ordering::Relaxed) as usize;
+        let core = Cores::current() as usize % self.rings.len();
+        let mut ring = self.rings[core].lock();
+
+        let child = match child {
+            Some(device) => ring.child_index(device),
+            None => IO_TRACE_NEXUS,
+        };
+        let record = IoTraceRecord {
+            timestamp_ns: self.ticks_to_ns(now.saturating_sub(trace_start)),
+            offset,
+            latency_ns: self.ticks_to_ns(now.saturating_sub(start_ticks)),
+            num_blocks: num_blocks as u32,
+            child,
+            op: IoTraceOp::from(op).code(),
+            status: status.code(),
+        };
+        if ring.records.len() >= capacity {
+            ring.records.pop_front();
+        }
+        ring.records.push_back(record);
+    }
+
+    /// Merges the records of all the cores into a capture, sorted by
+    /// timestamp. `child_uri` resolves the URI of a child device.
+    pub(crate) fn capture(
+        &self,
+        nexus: &str,
+        uuid: &str,
+        block_len: u64,
+        child_uri: impl Fn(&str) -> Option<String>,
+    ) -> IoTraceCapture {
+        let mut children: Vec<IoTraceChild> = Vec::new();
+        let mut records = Vec::new();
+
+        for ring in self.rings.iter() {
+            let ring = ring.lock();
+            let indexes = ring
+                .children
+                .iter()
+                .map(|device| {
+                    match children.iter().position(|c| &c.device == device) {
+                        Some(idx) => idx as u16,
+                        None => {
+                            children.push(IoTraceChild {
+                                device: device.clone(),
+                                uri: child_uri(device).unwrap_or_default(),
+                            });
+                            (children.len() - 1) as u16
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            records.extend(ring.records.iter().map(|r| IoTraceRecord {
+                child: if r.child == IO_TRACE_NEXUS {
+                    IO_TRACE_NEXUS
+                } else {
+                    indexes[r.child as usize]
+                },
+                ..*r
+            }));
+        }
+        records.sort_by_key(|r| r.timestamp_ns);
+
+        IoTraceCapture {
+            header: IoTraceHeader {
+                magic: IO_TRACE_MAGIC,
+                version: IO_TRACE_VERSION,
+                nexus: nexus.to_string(),
+                uuid: uuid.to_string(),
+                block_len,
+                start_time_ns: self.start_time_ns.load(Ordering::Relaxed),
+                children,
+            },
+            records,
+        }
+    }
+}
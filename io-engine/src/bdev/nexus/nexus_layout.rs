@@ -0,0 +1,399 @@
+//! Layouts of the data of a nexus across its children.
+//!
+//! A mirrored nexus holds all of its data on every child. A striped nexus
+//! splits its data in stripes, which go to the children in turn, and a
+//! concatenated nexus places its data on the children one after another.
+//! Striped and concatenated nexuses add up the capacity of their children,
+//! but have no redundancy: they can neither rebuild nor log I/Os, and their
+//! children can be neither added, removed nor onlined.
+//!
+//! A parity nexus splits its data in rows of stripe units, one unit per
+//! child, where one unit of each row holds the parity of the other units of
+//! the row. The parity unit rotates among the children from one row to the
+//! next, and the units of a row are at the same offset on every child.
+use std::{
+    cmp::min,
+    fmt::{Display, Formatter},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Layout of the data of a nexus across its children.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum NexusLayout {
+    /// Every child holds all the data.
+    #[default]
+    Mirror,
+    /// The data is split in stripes of `stripe_size` bytes, placed on the
+    /// children in turn.
+    Stripe { stripe_size: u64 },
+    /// The data is placed on the children one after another.
+    Concat,
+    /// The data is split in units of `stripe_size` bytes, placed on the
+    /// children in rows with `parity_children` units of parity per row.
+    Parity {
+        parity_children: u32,
+        stripe_size: u64,
+    },
+}
+
+impl Display for NexusLayout {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Mirror => write!(f, "mirror"),
+            Self::Stripe {
+                stripe_size,
+            } => write!(f, "stripe({stripe_size})"),
+            Self::Concat => write!(f, "concat"),
+            Self::Parity {
+                parity_children,
+                stripe_size,
+            } => write!(f, "parity({parity_children}, {stripe_size})"),
+        }
+    }
+}
+
+impl NexusLayout {
+    /// Returns true if every child holds all the data.
+    pub fn is_mirror(&self) -> bool {
+        matches!(self, Self::Mirror)
+    }
+
+    /// Returns true if the I/Os are split among the children, which hold
+    /// no redundant data.
+    pub fn is_split(&self) -> bool {
+        matches!(self, Self::Stripe { .. } | Self::Concat)
+    }
+
+    /// Returns true if the children hold the parity of the data.
+    pub fn is_parity(&self) -> bool {
+        matches!(self, Self::Parity { .. })
+    }
+}
+
+/// Blocks of a nexus I/O held by one child.
+#[derive(Debug, PartialEq)]
+pub(super) struct ChildExtent {
+    /// Index of the child.
+    pub(super) child: usize,
+    /// Offset of the blocks in the data partition of the child.
+    pub(super) offset: u64,
+    /// Number of blocks.
+    pub(super) num_blocks: u64,
+    /// Ranges of the buffer of the nexus I/O which hold these blocks, in
+    /// order, as offsets and numbers of blocks.
+    pub(super) ranges: Vec<(u64, u64)>,
+}
+
+/// Mapping of the blocks of a striped, concatenated or parity nexus to its
+/// children.
+#[derive(Debug, Default, Clone)]
+pub(super) struct LayoutGeometry {
+    layout: NexusLayout,
+    /// Stripe size, or stripe unit size, in blocks.
+    stripe_blocks: u64,
+    /// Number of blocks of each child the nexus uses.
+    child_blocks: Vec<u64>,
+}
+
+impl LayoutGeometry {
+    /// Makes the geometry of a layout over children with data partitions of
+    /// the given numbers of blocks.
+    pub(super) fn new(
+        layout: NexusLayout,
+        block_len: u64,
+        child_blocks: &[u64],
+    ) -> Result<Self, String> {
+        match layout {
+            NexusLayout::Mirror => {
+                Err("not a striped or concatenated layout".into())
+            }
+            NexusLayout::Stripe {
+                stripe_size,
+            } => {
+                if stripe_size == 0 || stripe_size % block_len != 0 {
+                    return Err(format!(
+                        "stripe size {stripe_size} is not a multiple of the \
+                        block size {block_len}"
+                    ));
+                }
+                let stripe_blocks = stripe_size / block_len;
+                let smallest =
+                    child_blocks.iter().copied().min().unwrap_or_default();
+                let blocks = smallest / stripe_blocks * stripe_blocks;
+                if blocks == 0 {
+                    return Err(format!(
+                        "children are smaller than the stripe size \
+                        {stripe_size}"
+                    ));
+                }
+                Ok(Self {
+                    layout,
+                    stripe_blocks,
+                    child_blocks: vec![blocks; child_blocks.len()],
+                })
+            }
+            NexusLayout::Concat => Ok(Self {
+                layout,
+                stripe_blocks: 0,
+                child_blocks: child_blocks.to_vec(),
+            }),
+            NexusLayout::Parity {
+                parity_children,
+                stripe_size,
+            } => {
+                if parity_children != 1 {
+                    return Err(format!(
+                        "{parity_children} parity children are not \
+                        supported, only 1 is"
+                    ));
+                }
+                if child_blocks.len() < 3 {
+                    return Err(format!(
+                        "a parity layout needs at least 3 children, not {}",
+                        child_blocks.len()
+                    ));
+                }
+                let mut geometry = Self::new(
+                    NexusLayout::Stripe {
+                        stripe_size,
+                    },
+                    block_len,
+                    child_blocks,
+                )?;
+                geometry.layout = layout;
+                Ok(geometry)
+            }
+        }
+    }
+
+    /// Returns the number of blocks the children can hold.
+    pub(super) fn num_blocks(&self) -> u64 {
+        match self.layout {
+            NexusLayout::Parity {
+                ..
+            } => self.data_units() * self.child_num_blocks(),
+            _ => self.child_blocks.iter().sum(),
+        }
+    }
+
+    /// Returns the number of blocks of the largest child the nexus uses.
+    pub(super) fn child_num_blocks(&self) -> u64 {
+        self.child_blocks.iter().copied().max().unwrap_or_default()
+    }
+
+    /// Returns the size of the stripe units of a parity layout, in blocks.
+    pub(super) fn unit_blocks(&self) -> u64 {
+        self.stripe_blocks
+    }
+
+    /// Returns the number of data units of a row of a parity layout.
+    pub(super) fn data_units(&self) -> u64 {
+        match self.layout {
+            NexusLayout::Parity {
+                parity_children, ..
+            } => self.child_blocks.len() as u64 - parity_children as u64,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Returns the index of the child which holds the parity of a row of a
+    /// parity layout, and the indexes of the children which hold its data
+    /// units, in order.
+    pub(super) fn parity_row(&self, row: u64) -> (usize, Vec<usize>) {
+        let n = self.child_blocks.len();
+        let parity = n - 1 - (row % n as u64) as usize;
+        let data = (1 .. n).map(|j| (parity + j) % n).collect();
+        (parity, data)
+    }
+
+    /// Splits the blocks of a nexus I/O into the extents of the children
+    /// which hold them, at most one per child.
+    pub(super) fn split(
+        &self,
+        offset: u64,
+        num_blocks: u64,
+    ) -> Vec<ChildExtent> {
+        let end = offset + num_blocks;
+        match self.layout {
+            NexusLayout::Stripe {
+                ..
+            } => {
+                // The stripes of a child are contiguous on the child, so the
+                // blocks of an I/O on a child are contiguous too.
+                let n = self.child_blocks.len() as u64;
+                let sb = self.stripe_blocks;
+                let mut extents: Vec<Option<ChildExtent>> =
+                    (0 .. n).map(|_| None).collect();
+                let mut pos = offset;
+                while pos < end {
+                    let stripe = pos / sb;
+                    let len = min(sb - pos % sb, end - pos);
+                    let child = (stripe % n) as usize;
+                    let range = (pos - offset, len);
+                    match &mut extents[child] {
+                        Some(e) => {
+                            e.num_blocks += len;
+                            e.ranges.push(range);
+                        }
+                        e => {
+                            *e = Some(ChildExtent {
+                                child,
+                                offset: stripe / n * sb + pos % sb,
+                                num_blocks: len,
+                                ranges: vec![range],
+                            })
+                        }
+                    }
+                    pos += len;
+                }
+                extents.into_iter().flatten().collect()
+            }
+            NexusLayout::Concat => {
+                let mut extents = Vec::new();
+                let mut start = 0;
+                for (child, blocks) in self.child_blocks.iter().enumerate() {
+                    let lo = offset.max(start);
+                    let hi = end.min(start + blocks);
+                    if lo < hi {
+                        extents.push(ChildExtent {
+                            child,
+                            offset: lo - start,
+                            num_blocks: hi - lo,
+                            ranges: vec![(lo - offset, hi - lo)],
+                        });
+                    }
+                    start += blocks;
+                }
+                extents
+            }
+            NexusLayout::Mirror
+            | NexusLayout::Parity {
+                ..
+            } => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stripe_split() {
+        let g = LayoutGeometry::new(
+            NexusLayout::Stripe {
+                stripe_size: 4096,
+            },
+            512,
+            &[100, 90, 200],
+        )
+        .unwrap();
+        // 88 blocks of each child, in stripes of 8 blocks.
+        assert_eq!(g.num_blocks(), 3 * 88);
+
+        // Blocks 4 .. 36: stripes 0 to 4, on children 0, 1, 2, 0, 1.
+        let extents = g.split(4, 32);
+        assert_eq!(
+            extents,
+            vec![
+                ChildExtent {
+                    child: 0,
+                    offset: 4,
+                    num_blocks: 12,
+                    ranges: vec![(0, 4), (20, 8)],
+                },
+                ChildExtent {
+                    child: 1,
+                    offset: 0,
+                    num_blocks: 12,
+                    ranges: vec![(4, 8), (28, 4)],
+                },
+                ChildExtent {
+                    child: 2,
+                    offset: 0,
+                    num_blocks: 8,
+                    ranges: vec![(12, 8)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn concat_split() {
+        let g = LayoutGeometry::new(NexusLayout::Concat, 512, &[100, 50, 200])
+            .unwrap();
+        assert_eq!(g.num_blocks(), 350);
+
+        let extents = g.split(90, 80);
+        assert_eq!(
+            extents,
+            vec![
+                ChildExtent {
+                    child: 0,
+                    offset: 90,
+                    num_blocks: 10,
+                    ranges: vec![(0, 10)],
+                },
+                ChildExtent {
+                    child: 1,
+                    offset: 0,
+                    num_blocks: 50,
+                    ranges: vec![(10, 50)],
+                },
+                ChildExtent {
+                    child: 2,
+                    offset: 0,
+                    num_blocks: 20,
+                    ranges: vec![(60, 20)],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn parity_rows() {
+        let layout = NexusLayout::Parity {
+            parity_children: 1,
+            stripe_size: 4096,
+        };
+        let g = LayoutGeometry::new(layout, 512, &[100, 90, 200]).unwrap();
+        // 88 blocks of each child, 2 data units of 8 blocks per row.
+        assert_eq!(g.num_blocks(), 2 * 88);
+        assert_eq!(g.child_num_blocks(), 88);
+        assert_eq!(g.unit_blocks(), 8);
+        assert_eq!(g.data_units(), 2);
+
+        assert_eq!(g.parity_row(0), (2, vec![0, 1]));
+        assert_eq!(g.parity_row(1), (1, vec![2, 0]));
+        assert_eq!(g.parity_row(2), (0, vec![1, 2]));
+        assert_eq!(g.parity_row(3), (2, vec![0, 1]));
+
+        assert!(LayoutGeometry::new(layout, 512, &[100, 100]).is_err());
+        assert!(LayoutGeometry::new(
+            NexusLayout::Parity {
+                parity_children: 2,
+                stripe_size: 4096,
+            },
+            512,
+            &[100, 100, 100, 100],
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn stripe_size_invalid() {
+        for stripe_size in [0, 1000, 1 << 20] {
+            assert!(LayoutGeometry::new(
+                NexusLayout::Stripe {
+                    stripe_size
+                },
+                512,
+                &[100, 100],
+            )
+            .is_err());
+        }
+    }
+}
@@ -51,29 +51,20 @@ extern "C" {
 /// perspective. This is somewhat annoying, but what makes matters worse is that
 /// if we are running the device creation path, on the same core that is
 /// handling the IO, we get into a state where we make no forward progress.
-pub(crate) fn wait_until_ready(path: &str) {
+/// The optional `prepare` function is called with the device path from the
+/// waiting thread, before waiting.
+pub(crate) fn wait_until_ready(path: &str, prepare: Option<fn(&str)>) {
     let started = Arc::new(AtomicBool::new(false));
 
     let tpath = String::from(path);
     let s = started.clone();
 
-    debug!("Waiting for NBD device {} to become ready...", path);
+    debug!("Waiting for device {} to become ready...", path);
     // start a thread that loops and tries to open us and asks for our size
     Mthread::spawn_unaffinitized(move || {
-        // this should not be needed but for some unknown reason, we end up with
-        // stale NBD devices. Setting this to non zero, prevents that from
-        // happening (although we dont actually timeout).
-        let timeout = 3;
-        let f = OpenOptions::new().read(true).open(Path::new(&tpath));
-        unsafe {
-            convert_ioctl_res!(libc::ioctl(
-                f.unwrap().as_raw_fd(),
-                SET_TIMEOUT as u64,
-                timeout
-            ))
+        if let Some(prepare) = prepare {
+            prepare(&tpath);
         }
-        .unwrap();
-        debug!("Timeout of NBD device {} was set to {}", tpath, timeout);
         let size: u64 = 0;
         let mut delay = 1;
         for _i in 0i32 .. 10 {
@@ -114,6 +105,24 @@ pub(crate) fn wait_until_ready(path: &str) {
     }
 }
 
+/// Sets the timeout of the NBD device.
+fn set_timeout(path: &str) {
+    // this should not be needed but for some unknown reason, we end up with
+    // stale NBD devices. Setting this to non zero, prevents that from
+    // happening (although we dont actually timeout).
+    let timeout = 3;
+    let f = OpenOptions::new().read(true).open(Path::new(path));
+    unsafe {
+        convert_ioctl_res!(libc::ioctl(
+            f.unwrap().as_raw_fd(),
+            SET_TIMEOUT as u64,
+            timeout
+        ))
+    }
+    .unwrap();
+    debug!("Timeout of NBD device {} was set to {}", path, timeout);
+}
+
 /// Return first unused nbd device in /dev.
 ///
 /// NOTE: We do a couple of syscalls in this function which by normal
@@ -221,7 +230,7 @@ impl NbdDisk {
         // we wait for the dev to come up online because
         // otherwise the mount done too early would fail.
         // If it times out, continue anyway and let the mount fail.
-        wait_until_ready(&device_path);
+        wait_until_ready(&device_path, Some(set_timeout));
         info!("Started nbd disk {} for {}", device_path, bdev_name);
 
         Ok(Self {
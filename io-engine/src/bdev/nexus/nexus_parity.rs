@@ -0,0 +1,482 @@
+//! I/O path of a parity nexus.
+//!
+//! The I/Os of a parity nexus are processed on the core they are submitted
+//! on, one row at a time, with the row locked while its units are read and
+//! written. The child I/Os of a row are submitted concurrently. A read of a
+//! unit held by a missing child is reconstructed from the other units of the
+//! row. A write reads the units of the row it does not cover, to compute the
+//! parity of the row, then writes the units it covers and the parity.
+//!
+//! A write interrupted between the writes of the units of a row leaves a row
+//! whose parity does not match its data. Such rows are repaired by a resync
+//! of the parity of all the rows when a nexus is created after an unclean
+//! shutdown, as recorded in its persisted nexus info. The resync runs in the
+//! background once the nexus is open, locking each row while it is resynced.
+use std::ops::Range;
+
+use futures::future::join_all;
+use spdk_rs::{DmaBuf, IoVec};
+
+use super::{nexus_lookup, FaultReason, Nexus, NexusLayout, NexusState};
+use crate::{
+    bdev::util::iov::IovCursor,
+    core::{BlockDeviceHandle, CoreError, IoType, Reactors},
+};
+
+/// Number of rows resynced with the same child handles, before the nexus is
+/// looked up again.
+const RESYNC_BATCH_ROWS: u64 = 256;
+
+/// A child of a parity nexus, as seen by an I/O.
+pub(super) struct Member {
+    /// Name of the device of the child.
+    device: String,
+    /// Handle to the child, unless the child is missing.
+    hdl: Option<Box<dyn BlockDeviceHandle>>,
+    /// True if the data of the child can be read, false if the child is
+    /// missing or being rebuilt.
+    synced: bool,
+}
+
+/// XORs the source buffer into the destination buffer.
+fn xor_into(dst: &mut [u8], src: &[u8]) {
+    dst.iter_mut().zip(src).for_each(|(d, s)| *d ^= s);
+}
+
+impl<'n> Nexus<'n> {
+    /// Processes a read, a write, a write zeroes or an unmap of a parity
+    /// nexus, row by row, with the given children. Unmaps are written as
+    /// zeroes, to keep the parity of the rows.
+    pub(super) async fn parity_io(
+        &self,
+        mut members: Vec<Member>,
+        io_type: IoType,
+        offset: u64,
+        num_blocks: u64,
+        iovs: &[IoVec],
+    ) -> Result<(), CoreError> {
+        let row_blocks =
+            self.geometry.unit_blocks() * self.geometry.data_units();
+        let mut cur = IovCursor::new(iovs);
+
+        let end = offset + num_blocks;
+        let mut pos = offset;
+        while pos < end {
+            let row = pos / row_blocks;
+            let lo = pos - row * row_blocks;
+            let hi = (end - row * row_blocks).min(row_blocks);
+
+            let _guard = self.row_locks.lock(row).await;
+            if io_type == IoType::Read {
+                self.read_row(&mut members, row, lo, hi, &mut cur).await?;
+            } else {
+                self.write_row(&mut members, io_type, row, lo, hi, &mut cur)
+                    .await?;
+            }
+
+            pos += hi - lo;
+        }
+        Ok(())
+    }
+
+    /// Gets the children of a parity nexus, in order. Only the healthy
+    /// children are read, and the children being rebuilt are written too.
+    /// Their handles are for the current thread.
+    pub(super) fn parity_members(&self) -> Vec<Member> {
+        self.children_iter()
+            .map(|c| {
+                let synced = c.is_healthy();
+                let hdl = if synced || c.is_rebuilding() {
+                    c.get_io_handle().ok()
+                } else {
+                    None
+                };
+                Member {
+                    device: c.get_device_name().unwrap_or_default(),
+                    synced: synced && hdl.is_some(),
+                    hdl,
+                }
+            })
+            .collect()
+    }
+
+    /// Faults a child which failed an I/O, and leaves it out of the rest of
+    /// the nexus I/O.
+    fn parity_member_failed(
+        &self,
+        members: &mut [Member],
+        idx: usize,
+        err: CoreError,
+    ) {
+        let m = &mut members[idx];
+        error!("{self:?}: I/O to '{dev}' failed: {err}", dev = m.device);
+        m.hdl = None;
+        m.synced = false;
+        self.retire_child_device(&m.device, FaultReason::IoError, true);
+    }
+
+    /// Allocates a buffer of the given size, with the alignment of the
+    /// children.
+    fn parity_buf(
+        &self,
+        members: &[Member],
+        len: u64,
+    ) -> Result<DmaBuf, CoreError> {
+        let hdl = members
+            .iter()
+            .find_map(|m| m.hdl.as_deref())
+            .ok_or(CoreError::NoDevicesAvailable {})?;
+        hdl.dma_malloc(len)
+            .map_err(|_| CoreError::DmaAllocationFailed {
+                size: len,
+            })
+    }
+
+    /// Returns the offset in bytes of a block of the units of a row on the
+    /// children.
+    fn unit_offset(&self, row: u64, blk: u64) -> u64 {
+        (self.data_ent_offset + row * self.geometry.unit_blocks() + blk)
+            * self.block_len()
+    }
+
+    /// Reads the blocks of the units of children in a row, given as the
+    /// child and the blocks `lo .. hi` of its unit, concurrently. The units
+    /// of missing children are reconstructed from the units of the other
+    /// children.
+    async fn read_units(
+        &self,
+        members: &mut [Member],
+        row: u64,
+        units: &[(usize, u64, u64)],
+    ) -> Result<Vec<DmaBuf>, CoreError> {
+        let mut bufs = units
+            .iter()
+            .map(|(_, lo, hi)| {
+                self.parity_buf(members, (hi - lo) * self.block_len())
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let results = {
+            let members = &*members;
+            join_all(units.iter().zip(bufs.iter_mut()).map(
+                |(&(child, lo, _), buf)| async move {
+                    match &members[child] {
+                        Member {
+                            hdl: Some(hdl),
+                            synced: true,
+                            ..
+                        } => Some(
+                            hdl.read_at(self.unit_offset(row, lo), buf).await,
+                        ),
+                        _ => None,
+                    }
+                },
+            ))
+            .await
+        };
+
+        for (i, res) in results.into_iter().enumerate() {
+            let (child, lo, hi) = units[i];
+            match res {
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => self.parity_member_failed(members, child, e),
+                None => {}
+            }
+            bufs[i] =
+                self.reconstruct_unit(members, row, child, lo, hi).await?;
+        }
+        Ok(bufs)
+    }
+
+    /// Reconstructs the blocks `lo .. hi` of the unit of a child in a row
+    /// from the units of the other children, read concurrently.
+    async fn reconstruct_unit(
+        &self,
+        members: &mut [Member],
+        row: u64,
+        child: usize,
+        lo: u64,
+        hi: u64,
+    ) -> Result<DmaBuf, CoreError> {
+        let offset = self.unit_offset(row, lo);
+        let len = (hi - lo) * self.block_len();
+
+        let others = (0 .. members.len())
+            .filter(|i| *i != child)
+            .collect::<Vec<_>>();
+        if let Some(i) = others.iter().find(|i| !members[**i].synced) {
+            error!(
+                "{self:?}: cannot reconstruct row {row} of '{dev}': \
+                '{other}' is missing too",
+                dev = members[child].device,
+                other = members[*i].device,
+            );
+            return Err(CoreError::NoDevicesAvailable {});
+        }
+
+        let mut bufs = others
+            .iter()
+            .map(|_| self.parity_buf(members, len))
+            .collect::<Result<Vec<_>, _>>()?;
+        let results = {
+            let members = &*members;
+            join_all(others.iter().zip(bufs.iter_mut()).map(|(i, buf)| {
+                members[*i].hdl.as_deref().unwrap().read_at(offset, buf)
+            }))
+            .await
+        };
+
+        let mut failed = false;
+        for (i, res) in others.iter().zip(results) {
+            if let Err(e) = res {
+                self.parity_member_failed(members, *i, e);
+                failed = true;
+            }
+        }
+        if failed {
+            return Err(CoreError::NoDevicesAvailable {});
+        }
+
+        let mut buf = bufs.pop().unwrap();
+        for b in &bufs {
+            xor_into(buf.as_mut_slice(), b.as_slice());
+        }
+        Ok(buf)
+    }
+
+    /// Writes buffers to the units of children in a row, given as the child
+    /// and its buffer, concurrently. The children which are missing are
+    /// skipped, and the children which fail their write are faulted.
+    async fn write_units(
+        &self,
+        members: &mut [Member],
+        offset: u64,
+        units: Vec<(usize, &DmaBuf)>,
+    ) {
+        let results = {
+            let members = &*members;
+            join_all(units.into_iter().filter_map(|(child, buf)| {
+                members[child].hdl.as_deref().map(|hdl| async move {
+                    (child, hdl.write_at(offset, buf).await)
+                })
+            }))
+            .await
+        };
+
+        for (child, res) in results {
+            if let Err(e) = res {
+                self.parity_member_failed(members, child, e);
+            }
+        }
+    }
+
+    /// Reads the blocks `lo .. hi` of the data of a row.
+    async fn read_row(
+        &self,
+        members: &mut [Member],
+        row: u64,
+        lo: u64,
+        hi: u64,
+        cur: &mut IovCursor<'_>,
+    ) -> Result<(), CoreError> {
+        let unit = self.geometry.unit_blocks();
+        let (_, data) = self.geometry.parity_row(row);
+
+        let units = (lo / unit ..= (hi - 1) / unit)
+            .map(|j| {
+                let ulo = lo.max(j * unit) - j * unit;
+                let uhi = hi.min((j + 1) * unit) - j * unit;
+                (data[j as usize], ulo, uhi)
+            })
+            .collect::<Vec<_>>();
+        for buf in self.read_units(members, row, &units).await? {
+            cur.copy_from(buf.as_slice());
+        }
+        Ok(())
+    }
+
+    /// Writes the blocks `lo .. hi` of the data of a row, and the parity of
+    /// the row.
+    async fn write_row(
+        &self,
+        members: &mut [Member],
+        io_type: IoType,
+        row: u64,
+        lo: u64,
+        hi: u64,
+        cur: &mut IovCursor<'_>,
+    ) -> Result<(), CoreError> {
+        let unit = self.geometry.unit_blocks();
+        let block_len = self.block_len();
+        let (parity, data) = self.geometry.parity_row(row);
+        let first = lo / unit;
+        let last = (hi - 1) / unit;
+
+        // Blocks of the units over which the parity is computed.
+        let (wlo, whi) = if first == last {
+            (lo - first * unit, hi - first * unit)
+        } else {
+            (0, unit)
+        };
+        let len = (whi - wlo) * block_len;
+
+        // The units not covered by the write are read, concurrently.
+        let covered = |j: usize| {
+            let start = j as u64 * unit;
+            start + wlo >= lo && start + whi <= hi
+        };
+        let reads = data
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| !covered(*j))
+            .map(|(_, child)| (*child, wlo, whi))
+            .collect::<Vec<_>>();
+        let mut read = self.read_units(members, row, &reads).await?.into_iter();
+        let mut units = Vec::with_capacity(data.len());
+        for j in 0 .. data.len() {
+            let buf = if covered(j) {
+                self.parity_buf(members, len)?
+            } else {
+                read.next().unwrap()
+            };
+            units.push(buf);
+        }
+
+        for j in first ..= last {
+            let ulo = lo.max(j * unit) - j * unit;
+            let uhi = hi.min((j + 1) * unit) - j * unit;
+            let part = &mut units[j as usize].as_mut_slice()[((ulo - wlo)
+                * block_len)
+                as usize
+                .. ((uhi - wlo) * block_len) as usize];
+            if io_type == IoType::Write {
+                cur.copy_to(part);
+            } else {
+                part.fill(0);
+            }
+        }
+
+        let mut parity_buf = self.parity_buf(members, len)?;
+        parity_buf.fill(0);
+        for buf in &units {
+            xor_into(parity_buf.as_mut_slice(), buf.as_slice());
+        }
+
+        let writes = (first ..= last)
+            .map(|j| (data[j as usize], &units[j as usize]))
+            .chain(std::iter::once((parity, &parity_buf)))
+            .collect();
+        self.write_units(members, self.unit_offset(row, wlo), writes)
+            .await;
+
+        // Record the write in the I/O logs of the faulted children.
+        for log in self.io_log_channels() {
+            log.log_io(
+                IoType::Write,
+                self.data_ent_offset + row * unit + wlo,
+                whi - wlo,
+            );
+        }
+
+        let missing = members.iter().filter(|m| m.hdl.is_none()).count();
+        match self.layout() {
+            NexusLayout::Parity {
+                parity_children, ..
+            } if missing > parity_children as usize => {
+                error!(
+                    "{self:?}: row {row} written with {missing} children \
+                    missing"
+                );
+                Err(CoreError::NoDevicesAvailable {})
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts recomputing the parity of all the rows of a parity nexus from
+    /// their data, in the background on the current core. This repairs the
+    /// rows left inconsistent by an unclean shutdown while the nexus serves
+    /// I/O: each row is locked while it is resynced. The resync stops if the
+    /// nexus shuts down, or if a child is missing.
+    pub(super) fn start_parity_resync(&self) {
+        let name = self.name.clone();
+        let rows =
+            self.geometry.child_num_blocks() / self.geometry.unit_blocks();
+        info!("{self:?}: resyncing the parity of {rows} rows...");
+
+        Reactors::current().send_future(async move {
+            let mut row = 0;
+            while row < rows {
+                // The nexus is looked up again for each batch of rows, as it
+                // may be destroyed in the meantime.
+                let Some(nexus) = nexus_lookup(&name) else {
+                    warn!("Nexus '{name}' destroyed, parity resync stopped");
+                    return;
+                };
+                if matches!(
+                    *nexus.state.lock(),
+                    NexusState::ShuttingDown
+                        | NexusState::Shutdown
+                        | NexusState::Closed
+                ) {
+                    warn!("{nexus:?}: parity resync stopped");
+                    return;
+                }
+
+                let mut members = nexus.parity_members();
+                if let Some(m) = members.iter().find(|m| !m.synced) {
+                    warn!(
+                        "{nexus:?}: cannot resync the parity with '{dev}' \
+                        missing, rows written at the time of the shutdown \
+                        may be inconsistent",
+                        dev = m.device,
+                    );
+                    return;
+                }
+
+                let end = (row + RESYNC_BATCH_ROWS).min(rows);
+                if let Err(err) =
+                    nexus.resync_parity_rows(&mut members, row .. end).await
+                {
+                    error!("{nexus:?}: failed to resync parity: {err}");
+                    return;
+                }
+                row = end;
+            }
+
+            info!("Nexus '{name}': parity resynced");
+        });
+    }
+
+    /// Recomputes the parity of the given rows from their data.
+    async fn resync_parity_rows(
+        &self,
+        members: &mut [Member],
+        rows: Range<u64>,
+    ) -> Result<(), CoreError> {
+        let unit = self.geometry.unit_blocks();
+        for row in rows {
+            let _guard = self.row_locks.lock(row).await;
+            let (parity, data) = self.geometry.parity_row(row);
+            let reads = data.iter().map(|c| (*c, 0, unit)).collect::<Vec<_>>();
+            let units = self.read_units(members, row, &reads).await?;
+
+            let mut parity_buf =
+                self.parity_buf(members, unit * self.block_len())?;
+            parity_buf.fill(0);
+            for buf in &units {
+                xor_into(parity_buf.as_mut_slice(), buf.as_slice());
+            }
+            self.write_units(
+                members,
+                self.unit_offset(row, 0),
+                vec![(parity, &parity_buf)],
+            )
+            .await;
+            if members[parity].hdl.is_none() {
+                return Err(CoreError::NoDevicesAvailable {});
+            }
+        }
+        Ok(())
+    }
+}
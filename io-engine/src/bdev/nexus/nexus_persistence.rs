@@ -1,5 +1,9 @@
-use super::{Nexus, NexusChild};
-use crate::{persistent_store::PersistentStore, sleep::mayastor_sleep};
+use super::{Error, Nexus, NexusChild, NexusLayout};
+use crate::{
+    persistent_store::PersistentStore,
+    sleep::mayastor_sleep,
+    store::store_defs::StoreError,
+};
 use serde::{Deserialize, Serialize};
 use std::time::Duration;
 
@@ -25,6 +29,15 @@ impl PersistentNexusInfo {
     fn inner_mut(&mut self) -> &mut NexusInfo {
         &mut self.inner
     }
+
+    /// Get the key the NexusInfo structure is persisted with: the key
+    /// supplied by the control plane, or otherwise the nexus uuid.
+    fn key(&self, nexus: &Nexus) -> String {
+        match &self.key {
+            Some(k) => k.clone(),
+            None => nexus.uuid().to_string(),
+        }
+    }
 }
 
 /// Definition of the nexus information that gets saved in the persistent
@@ -35,6 +48,10 @@ pub struct NexusInfo {
     pub clean_shutdown: bool,
     /// Information about children.
     pub children: Vec<ChildInfo>,
+    /// Layout of the data across the children, which are in the order of
+    /// the layout.
+    #[serde(default)]
+    pub layout: NexusLayout,
 }
 
 /// Definition of the child information that gets saved in the persistent
@@ -55,6 +72,13 @@ pub(crate) enum PersistOp<'a> {
     AddChild { child_uri: String, healthy: bool },
     /// Remove a child from an existing persistent entry.
     RemoveChild { child_uri: String },
+    /// Replace a child of an existing persistent entry, keeping its place in
+    /// the order of the children.
+    ReplaceChild {
+        old_uri: String,
+        new_uri: String,
+        healthy: bool,
+    },
     /// Update a persistent entry.
     Update { child_uri: String, healthy: bool },
     /// Update a persistent entry only when a precondition on this NexusInfo
@@ -88,6 +112,7 @@ impl<'n> Nexus<'n> {
                 // expect the NexusInfo structure to contain default values.
                 assert!(nexus_info.children.is_empty());
                 assert!(!nexus_info.clean_shutdown);
+                nexus_info.layout = self.layout();
                 self.children_iter().for_each(|c| {
                     let child_info = ChildInfo {
                         uuid: NexusChild::uuid(c.uri())
@@ -129,6 +154,28 @@ impl<'n> Nexus<'n> {
 
                 nexus_info.children.retain(|child| child.uuid != uuid);
             }
+            PersistOp::ReplaceChild {
+                old_uri,
+                new_uri,
+                healthy,
+            } => {
+                let old_uuid = NexusChild::uuid(&old_uri)
+                    .expect("Failed to get child UUID.");
+                let child_info = ChildInfo {
+                    uuid: NexusChild::uuid(&new_uri)
+                        .expect("Failed to get child UUID."),
+                    healthy,
+                };
+
+                match nexus_info
+                    .children
+                    .iter()
+                    .position(|r| r.uuid == old_uuid)
+                {
+                    Some(idx) => nexus_info.children[idx] = child_info,
+                    None => nexus_info.children.push(child_info),
+                }
+            }
             PersistOp::Update {
                 child_uri,
                 healthy,
@@ -174,6 +221,84 @@ impl<'n> Nexus<'n> {
         self.save(&persistent_nexus_info).await;
     }
 
+    /// Load the information persisted by a previous instance of the nexus,
+    /// if any.
+    pub(crate) async fn load_persisted(&self) -> Option<NexusInfo> {
+        if !PersistentStore::enabled() {
+            return None;
+        }
+
+        let key = self.nexus_info.lock().await.key(self);
+        match PersistentStore::get(&key).await {
+            Ok(value) => match serde_json::from_value(value) {
+                Ok(info) => Some(info),
+                Err(e) => {
+                    warn!(
+                        "{self:?}: ignoring invalid persisted nexus \
+                        information: {e}"
+                    );
+                    None
+                }
+            },
+            Err(StoreError::MissingEntry {
+                ..
+            }) => None,
+            Err(e) => {
+                warn!(
+                    "{self:?}: failed to load persisted nexus information: {e}"
+                );
+                None
+            }
+        }
+    }
+
+    /// Check that the nexus is created with the layout it was persisted
+    /// with. The data of a split or parity nexus is placed on its children
+    /// by their index, so its children must also be in the persisted order.
+    pub(crate) fn check_persisted_layout(
+        &self,
+        info: &NexusInfo,
+    ) -> Result<(), Error> {
+        if info.layout != self.layout() {
+            return Err(Error::InvalidArguments {
+                name: self.name.clone(),
+                args: format!(
+                    "the nexus was persisted with a {} layout, not {}",
+                    info.layout,
+                    self.layout()
+                ),
+            });
+        }
+
+        if !self.layout().is_mirror() {
+            let persisted = info
+                .children
+                .iter()
+                .map(|c| Some(c.uuid.clone()))
+                .collect::<Vec<_>>();
+            let children = self
+                .children_iter()
+                .map(|c| NexusChild::uuid(c.uri()))
+                .collect::<Vec<_>>();
+            if persisted != children {
+                return Err(Error::InvalidArguments {
+                    name: self.name.clone(),
+                    args: format!(
+                        "the children of the {} nexus do not match the \
+                        persisted children {:?}",
+                        self.layout(),
+                        info.children
+                            .iter()
+                            .map(|c| c.uuid.as_str())
+                            .collect::<Vec<_>>()
+                    ),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
     // Save the nexus info to the store. This is integral to ensuring data
     // consistency across restarts of Mayastor. Therefore, keep retrying
     // until successful.
@@ -183,10 +308,7 @@ impl<'n> Nexus<'n> {
         let nexus_uuid = self.uuid().to_string();
         // If a key has been provided use this to store the NexusInfo.
         // If a key is not provided, use the nexus uuid as the key.
-        let key = match &info.key {
-            Some(k) => k.clone(),
-            None => self.uuid().to_string(),
-        };
+        let key = info.key(self);
 
         loop {
             match PersistentStore::put(&key, &info.inner).await {
@@ -3,7 +3,7 @@ use async_trait::async_trait;
 use snafu::ResultExt;
 use std::pin::Pin;
 
-use super::{nexus_err, Error, NbdDisk, Nexus, NexusTarget};
+use super::{nexus_err, Error, NbdDisk, Nexus, NexusTarget, UblkDisk};
 
 use crate::core::{Protocol, Share, ShareProps, UpdateProps};
 
@@ -25,7 +25,12 @@ impl<'n> Share for Nexus<'n> {
         props: Option<ShareProps>,
     ) -> Result<Self::Output, Self::Error> {
         let uri = match self.shared() {
-            Some(Protocol::Off) | None => {
+            Some(Protocol::Nvmf) => {
+                let uri = self.share_uri().unwrap();
+                info!("{:?}: already shared as '{}'", self, uri);
+                uri
+            }
+            _ => {
                 info!("{:?}: sharing NVMF target...", self);
 
                 let name = self.name.clone();
@@ -41,11 +46,6 @@ impl<'n> Share for Nexus<'n> {
                 info!("{:?}: shared NVMF target as '{}'", self, uri);
                 uri
             }
-            Some(Protocol::Nvmf) => {
-                let uri = self.share_uri().unwrap();
-                info!("{:?}: already shared as '{}'", self, uri);
-                uri
-            }
         };
 
         Ok(uri)
@@ -108,6 +108,7 @@ impl From<&NexusTarget> for Protocol {
     fn from(target: &NexusTarget) -> Protocol {
         match target {
             NexusTarget::NexusNvmfTarget => Protocol::Nvmf,
+            NexusTarget::UblkDisk(_) => Protocol::Ublk,
             _ => Protocol::Off,
         }
     }
@@ -120,15 +121,18 @@ impl<'n> Nexus<'n> {
         protocol: Protocol,
         key: Option<String>,
     ) -> Result<String, Error> {
-        self.share_ext(protocol, key, vec![]).await
+        self.share_ext(protocol, key, vec![], vec![]).await
     }
 
-    /// TODO
+    /// Share the nexus, allowing only the given hosts to connect.
+    /// The nexus listens on the given target addresses, or on all of them
+    /// if none are given.
     pub async fn share_ext(
         mut self: Pin<&mut Self>,
         protocol: Protocol,
         _key: Option<String>,
         allowed_hosts: Vec<String>,
+        listeners: Vec<String>,
     ) -> Result<String, Error> {
         // This function should be idempotent as it's possible that
         // we get called more than once for some odd reason.
@@ -140,7 +144,9 @@ impl<'n> Nexus<'n> {
 
                 self.as_mut()
                     .update_properties(
-                        UpdateProps::new().with_allowed_hosts(allowed_hosts),
+                        UpdateProps::new()
+                            .with_allowed_hosts(allowed_hosts)
+                            .with_listeners(listeners),
                     )
                     .await?;
 
@@ -169,6 +175,19 @@ impl<'n> Nexus<'n> {
                 }
                 Ok(uri)
             }
+            Protocol::Ublk => {
+                let disk = UblkDisk::create(&self.name).await.context(
+                    nexus_err::ShareUblkNexus {
+                        name: self.name.clone(),
+                    },
+                )?;
+                let uri = disk.as_uri();
+                unsafe {
+                    self.as_mut().get_unchecked_mut().nexus_target =
+                        Some(NexusTarget::UblkDisk(disk));
+                }
+                Ok(uri)
+            }
             Protocol::Nvmf => {
                 let props = ShareProps::new()
                     .with_range(Some((
@@ -177,6 +196,7 @@ impl<'n> Nexus<'n> {
                     )))
                     .with_ana(true)
                     .with_allowed_hosts(allowed_hosts)
+                    .with_listeners(listeners)
                     .with_ptpl(self.ptpl().create().map_err(|source| {
                         Error::ShareNvmfNexus {
                             source: crate::core::CoreError::Ptpl {
@@ -206,6 +226,10 @@ impl<'n> Nexus<'n> {
             Some(NexusTarget::NexusNvmfTarget) => {
                 info!("{:?}: unsharing NVMF target...", self);
             }
+            Some(NexusTarget::UblkDisk(disk)) => {
+                info!("{:?}: destroying ublk device target...", self);
+                disk.destroy().await;
+            }
             None => {
                 // Try unshare nexus bdev anyway, just in case it was shared
                 // via bdev API. It is no-op if bdev was not shared.
@@ -219,6 +243,7 @@ impl<'n> Nexus<'n> {
     pub fn get_share_uri(&self) -> Option<String> {
         match self.nexus_target {
             Some(NexusTarget::NbdDisk(ref disk)) => Some(disk.as_uri()),
+            Some(NexusTarget::UblkDisk(ref disk)) => Some(disk.as_uri()),
             Some(NexusTarget::NexusNvmfTarget) => self.share_uri(),
             None => None,
         }
@@ -0,0 +1,219 @@
+//! Utility functions and wrappers for working with ublk devices in SPDK.
+//!
+//! A ublk disk exposes a bdev as a local block device (/dev/ublkb...),
+//! served through the `ublk_drv` kernel driver. Unlike NBD, a ublk disk has
+//! multiple queues, which the ublk target of SPDK spreads over the reactors.
+
+use futures::channel::oneshot;
+use nix::errno::Errno;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use snafu::Snafu;
+use std::{
+    collections::HashSet,
+    ffi::{c_void, CString},
+    fmt,
+    os::raw::c_char,
+    path::Path,
+};
+
+use super::nexus_nbd::wait_until_ready;
+use crate::{core::Cores, ffihelper::cb_arg};
+
+/// Highest number of ublk devices looked up for a free device id.
+const UBLK_MAX_DEVICES: u32 = 1024;
+/// Depth of each queue of a ublk disk.
+const UBLK_QUEUE_DEPTH: u32 = 128;
+
+#[derive(Debug, Snafu)]
+#[snafu(context(suffix(false)))]
+pub enum UblkError {
+    #[snafu(display(
+        "Failed to create the ublk target (is ublk_drv kmod loaded?)"
+    ))]
+    CreateTarget { source: Errno },
+    #[snafu(display("No free ublk devices available"))]
+    UblkUnavailable {},
+    #[snafu(display("Failed to start ublk device {} for {}", dev, bdev))]
+    StartUblk {
+        source: Errno,
+        dev: String,
+        bdev: String,
+    },
+}
+
+extern "C" {
+    fn ublk_create_target(cpumask_str: *const c_char) -> i32;
+    fn ublk_start_disk(
+        bdev_name: *const c_char,
+        ublk_id: u32,
+        num_queues: u32,
+        queue_depth: u32,
+    ) -> i32;
+    fn ublk_stop_disk(
+        ublk_id: u32,
+        del_cb: Option<extern "C" fn(*mut c_void)>,
+        cb_arg: *mut c_void,
+    ) -> i32;
+    fn ublk_dev_find_by_id(ublk_id: u32) -> *mut c_void;
+}
+
+/// Creates the ublk target of SPDK, with the ublk queues polled on all the
+/// reactors, unless it is created already.
+fn create_target() -> Result<(), UblkError> {
+    static TARGET: OnceCell<()> = OnceCell::new();
+
+    TARGET
+        .get_or_try_init(|| {
+            let cores = Cores::list_cores()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>();
+            let cpumask =
+                CString::new(format!("[{}]", cores.join(","))).unwrap();
+            let rc = unsafe { ublk_create_target(cpumask.as_ptr()) };
+            if rc != 0 {
+                return Err(UblkError::CreateTarget {
+                    source: Errno::from_i32(rc.abs()),
+                });
+            }
+            info!("Created ublk target on cores {cpumask:?}");
+            Ok(())
+        })
+        .map(|_| ())
+}
+
+/// Ids of the ublk devices being started or in use by this process. The
+/// kernel only creates the device of an id some time after it is started,
+/// so concurrent starts must not rely on the device to pick distinct ids.
+static RESERVED_IDS: Lazy<Mutex<HashSet<u32>>> =
+    Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// Reserves the first ublk device id which is free, both in the kernel and
+/// within SPDK, and not in the given list of ids to skip.
+fn reserve_unused(skip: &[u32]) -> Result<u32, UblkError> {
+    let mut reserved = RESERVED_IDS.lock();
+    let id = (0 .. UBLK_MAX_DEVICES)
+        .find(|id| {
+            !reserved.contains(id)
+                && !skip.contains(id)
+                && !Path::new(&format!("/dev/ublkc{id}")).exists()
+                && unsafe { ublk_dev_find_by_id(*id) }.is_null()
+        })
+        .ok_or(UblkError::UblkUnavailable {})?;
+    reserved.insert(id);
+    Ok(id)
+}
+
+/// Releases a ublk device id reserved with `reserve_unused`.
+fn release(id: u32) {
+    RESERVED_IDS.lock().remove(&id);
+}
+
+/// Callback for ublk_stop_disk().
+extern "C" fn stop_cb(sender_ptr: *mut c_void) {
+    let sender =
+        unsafe { Box::from_raw(sender_ptr as *mut oneshot::Sender<()>) };
+    sender.send(()).ok();
+}
+
+/// ublk disk representation.
+pub struct UblkDisk {
+    id: u32,
+}
+
+impl UblkDisk {
+    /// Allocate a ublk device for the bdev and start it, with one queue per
+    /// reactor. When the function returns the ublk disk is ready for IO.
+    pub async fn create(bdev_name: &str) -> Result<Self, UblkError> {
+        create_target()?;
+
+        let num_queues = Cores::list_cores().count() as u32;
+        let c_bdev_name = CString::new(bdev_name).unwrap();
+
+        // another process may take the id before the device is added
+        let mut taken = Vec::new();
+        let disk = loop {
+            let id = reserve_unused(&taken)?;
+            let disk = Self {
+                id,
+            };
+            let rc = unsafe {
+                ublk_start_disk(
+                    c_bdev_name.as_ptr(),
+                    id,
+                    num_queues,
+                    UBLK_QUEUE_DEPTH,
+                )
+            };
+            if rc == 0 {
+                break disk;
+            }
+            match Errno::from_i32(rc.abs()) {
+                Errno::EEXIST | Errno::EBUSY => {
+                    debug!("ublk device {disk} is taken, trying the next one");
+                    release(id);
+                    taken.push(id);
+                }
+                errno => {
+                    release(id);
+                    return Err(UblkError::StartUblk {
+                        source: errno,
+                        dev: disk.get_path(),
+                        bdev: bdev_name.to_owned(),
+                    });
+                }
+            }
+        };
+
+        wait_until_ready(&disk.get_path(), None);
+        info!(
+            "Started ublk disk {} for {} with {} queues",
+            disk, bdev_name, num_queues
+        );
+
+        Ok(disk)
+    }
+
+    /// Stop and release the ublk device.
+    pub async fn destroy(self) {
+        let (sender, receiver) = oneshot::channel::<()>();
+        debug!("Stopping ublk device {}...", self);
+
+        let rc =
+            unsafe { ublk_stop_disk(self.id, Some(stop_cb), cb_arg(sender)) };
+        if rc != 0 {
+            error!(
+                "Failed to stop ublk device {}: {}",
+                self,
+                Errno::from_i32(rc.abs())
+            );
+            return;
+        }
+        receiver.await.ok();
+        release(self.id);
+
+        info!("ublk {} device stopped", self);
+    }
+
+    /// Get the ublk block device path (/dev/ublkb...) for the ublk disk.
+    pub fn get_path(&self) -> String {
+        format!("/dev/ublkb{}", self.id)
+    }
+
+    /// Get the ublk device path uri (file:///dev/ublkb...) for the ublk disk.
+    pub fn as_uri(&self) -> String {
+        format!("file://{}", self.get_path())
+    }
+}
+
+impl fmt::Debug for UblkDisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}#{}", self.get_path(), self.id)
+    }
+}
+
+impl fmt::Display for UblkDisk {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.get_path())
+    }
+}
@@ -20,6 +20,7 @@ use spdk_rs::{
         SPDK_NVME_IO_FLAGS_PRCHK_REFTAG,
         SPDK_NVME_TRANSPORT_TCP,
         SPDK_NVMF_ADRFAM_IPV4,
+        SPDK_NVMF_ADRFAM_IPV6,
     },
 };
 
@@ -56,7 +57,7 @@ impl TryFrom<&Url> for Nvmf {
     type Error = BdevError;
 
     fn try_from(url: &Url) -> Result<Self, Self::Error> {
-        let host = url.host_str().ok_or_else(|| BdevError::InvalidUri {
+        let host = uri::host(url).ok_or_else(|| BdevError::InvalidUri {
             uri: url.to_string(),
             message: String::from("missing host"),
         })?;
@@ -118,7 +119,7 @@ impl TryFrom<&Url> for Nvmf {
             name: url[url::Position::BeforeHost .. url::Position::AfterPath]
                 .into(),
             alias: url.to_string(),
-            host: host.to_string(),
+            host,
             port: url.port().unwrap_or(DEFAULT_NVMF_PORT),
             subnqn: segments[0].to_string(),
             prchk_flags,
@@ -283,7 +284,11 @@ impl NvmeCreateContext {
         copy_str_with_null(&nvmf.subnqn, &mut trid.subnqn);
 
         trid.trtype = SPDK_NVME_TRANSPORT_TCP;
-        trid.adrfam = SPDK_NVMF_ADRFAM_IPV4;
+        trid.adrfam = if nvmf.host.parse::<std::net::Ipv6Addr>().is_ok() {
+            SPDK_NVMF_ADRFAM_IPV6
+        } else {
+            SPDK_NVMF_ADRFAM_IPV4
+        };
 
         NvmeCreateContext {
             trid,
@@ -1,11 +1,19 @@
 /* I/O channel for NVMe controller, one per core. */
 
-use std::{mem::size_of, os::raw::c_void, ptr::NonNull, time::Duration};
+use std::{
+    mem::size_of,
+    os::raw::c_void,
+    ptr::NonNull,
+    sync::Arc,
+    time::Duration,
+};
 
 use spdk_rs::{
     libspdk::{
         nvme_qpair_abort_all_queued_reqs,
         nvme_transport_qpair_abort_reqs,
+        spdk_get_ticks,
+        spdk_get_ticks_hz,
         spdk_io_channel,
         spdk_nvme_poll_group_process_completions,
         spdk_nvme_qpair,
@@ -17,10 +25,12 @@ use spdk_rs::{
 
 use crate::{
     bdev::device_lookup,
-    core::{BlockDevice, BlockDeviceIoStats, IoType},
+    core::{BlockDevice, BlockDeviceIoStats, IoLatencyRecorder, IoType},
 };
 
 use super::{
+    controller_inner::TimeoutConfig,
+    handle::{FailoverIo, MAX_IO_FAILOVERS},
     nvme_bdev_running_config,
     NvmeControllerState,
     PollGroup,
@@ -29,6 +39,10 @@ use super::{
     NVME_CONTROLLERS,
 };
 
+/// Delay before an I/O operation which failed on the current path again is
+/// resubmitted, doubled with every further failure.
+const FAILOVER_BACKOFF_US: u64 = 1000;
+
 /// TODO
 #[repr(C)]
 pub struct NvmeIoChannel<'a> {
@@ -66,6 +80,7 @@ impl std::fmt::Debug for NvmeIoChannelInner<'_> {
         f.debug_struct("NvmeIoChannelInner")
             .field("qpair", &self.qpair)
             .field("pending IO", &self.num_pending_ios)
+            .field("failover IO", &self.failover_ios.len())
             .finish()
     }
 }
@@ -75,6 +90,7 @@ pub struct NvmeIoChannelInner<'a> {
     poll_group: PollGroup,
     poller: Poller<'a>,
     io_stats_controller: IoStatsController,
+    io_latency: Arc<IoLatencyRecorder>,
     pub device: Box<dyn BlockDevice>,
     /// to prevent the controller from being destroyed before the channel
     ctrl: Option<
@@ -94,6 +110,17 @@ pub struct NvmeIoChannelInner<'a> {
     // shutdown (if case reset is initiated before shutdown), and
     // not to reinitialize channels already processed by shutdown logic.
     is_shutdown: bool,
+
+    /// Timeout config of the controller, which also drives path failover.
+    timeout_config: NonNull<TimeoutConfig>,
+    /// Failover epoch of the controller when the channel was last
+    /// (re)initialized.
+    failover_epoch: u64,
+    /// Set while the channel is reset for failover to another path.
+    failover: bool,
+    /// I/O operations to be resubmitted once the channel is reinitialized
+    /// on another path.
+    failover_ios: Vec<FailoverIo>,
 }
 
 impl NvmeIoChannelInner<'_> {
@@ -156,11 +183,101 @@ impl NvmeIoChannelInner<'_> {
         let rc = self.reset();
         if rc == 0 {
             self.is_shutdown = true;
+            self.abort_failover();
             self.ctrl.take();
         }
         rc
     }
 
+    /// Checks whether the controller has alternate paths to fail over to.
+    #[inline]
+    pub(crate) fn is_multipath(&self) -> bool {
+        unsafe { self.timeout_config.as_ref().is_multipath() }
+    }
+
+    /// Checks whether the channel is being reset for failover, in which case
+    /// I/O operations are kept till it's reinitialized on another path.
+    #[inline]
+    pub(crate) fn is_failover(&self) -> bool {
+        self.failover
+    }
+
+    /// Prepare the channel to be reset for failover to another path.
+    pub(crate) fn start_failover(&mut self) {
+        self.failover = true;
+    }
+
+    /// Keep an I/O operation which failed due to its path for resubmission
+    /// on another path, failing the controller over if needed.
+    /// The operation is handed back if failover is not possible or it has
+    /// been failed over too many times already.
+    pub(crate) fn failover_io(
+        &mut self,
+        mut io: FailoverIo,
+    ) -> Option<FailoverIo> {
+        if self.is_shutdown || io.failovers() > MAX_IO_FAILOVERS {
+            return Some(io);
+        }
+
+        if self.failover {
+            self.failover_ios.push(io);
+            return None;
+        }
+
+        let timeout_config = unsafe { self.timeout_config.as_mut() };
+        if !timeout_config.failover_controller() {
+            return Some(io);
+        }
+
+        // The channel has already been moved over to the new path by an
+        // ongoing failover, so the operation failed on the new path as well:
+        // back off before resubmitting it, the poller picks it up.
+        if timeout_config.failover_epoch() == self.failover_epoch {
+            let backoff_us =
+                FAILOVER_BACKOFF_US << io.failovers().saturating_sub(1);
+            let (now, hz) = unsafe { (spdk_get_ticks(), spdk_get_ticks_hz()) };
+            io.set_not_before(now + backoff_us * hz / 1_000_000);
+        }
+        self.failover_ios.push(io);
+        None
+    }
+
+    /// Resubmit the I/O operations kept for failover whose backoff has
+    /// expired, unless the channel is being failed over.
+    fn resubmit_backed_off_ios(&mut self) {
+        if self.failover || self.failover_ios.is_empty() {
+            return;
+        }
+
+        let now = unsafe { spdk_get_ticks() };
+        let (due, pending) = std::mem::take(&mut self.failover_ios)
+            .into_iter()
+            .partition::<Vec<_>, _>(|io| io.not_before() <= now);
+        self.failover_ios = pending;
+        for io in due {
+            io.resubmit(self);
+        }
+    }
+
+    /// Resubmit all I/O operations kept for failover.
+    fn resubmit_failover_ios(&mut self) {
+        let ios = std::mem::take(&mut self.failover_ios);
+        if !ios.is_empty() {
+            debug!(?self, "resubmitting {} I/O operations", ios.len());
+        }
+        for io in ios {
+            io.resubmit(self);
+        }
+    }
+
+    /// Stop failover and fail all I/O operations kept for resubmission.
+    pub(crate) fn abort_failover(&mut self) {
+        self.failover = false;
+        for io in std::mem::take(&mut self.failover_ios) {
+            io.fail();
+        }
+    }
+
     /// Account active I/O for channel.
     #[inline]
     pub fn account_io(&mut self) {
@@ -191,6 +308,23 @@ impl NvmeIoChannelInner<'_> {
             return -libc::ENODEV;
         }
 
+        let rc = self.create_qpair(ctrlr_name, ctrlr_handle);
+        if rc == 0 {
+            self.failover_epoch =
+                unsafe { self.timeout_config.as_ref().failover_epoch() };
+            self.failover = false;
+            self.resubmit_failover_ios();
+        } else {
+            self.abort_failover();
+        }
+        rc
+    }
+
+    fn create_qpair(
+        &mut self,
+        ctrlr_name: &str,
+        ctrlr_handle: SpdkNvmeController,
+    ) -> i32 {
         // We assume that channel is reinitialized after being reset, so we
         // expect to see no I/O qpair.
         if self.remove_qpair().is_some() {
@@ -229,6 +363,12 @@ impl NvmeIoChannelInner<'_> {
         0
     }
 
+    /// Get I/O latency recorder for channel.
+    #[inline]
+    pub fn io_latency(&self) -> &IoLatencyRecorder {
+        &self.io_latency
+    }
+
     /// Get I/O statistics for channel.
     #[inline]
     pub fn get_io_stats_controller(&mut self) -> &mut IoStatsController {
@@ -318,6 +458,8 @@ extern "C" fn disconnected_qpair_cb(
 extern "C" fn nvme_poll(ctx: *mut c_void) -> i32 {
     let inner = NvmeIoChannel::from_raw(ctx).inner_mut();
 
+    inner.resubmit_backed_off_ios();
+
     let num_completions = unsafe {
         spdk_nvme_poll_group_process_completions(
             inner.poll_group.as_ptr(),
@@ -347,7 +489,7 @@ impl NvmeControllerIoChannel {
             Some(c) => c,
         };
 
-        let (cname, controller, block_size) = {
+        let (cname, controller, block_size, timeout_config, io_latency) = {
             let controller = carc.lock();
             // Make sure controller is available.
             if controller.get_state() != NvmeControllerState::Running {
@@ -372,6 +514,8 @@ impl NvmeControllerIoChannel {
                 controller.get_name(),
                 controller.controller().unwrap(),
                 block_size,
+                controller.timeout_config,
+                controller.io_latency(),
             )
         };
 
@@ -428,10 +572,15 @@ impl NvmeControllerIoChannel {
             poll_group,
             poller,
             io_stats_controller: IoStatsController::new(block_size),
+            io_latency,
             is_shutdown: false,
             device,
             ctrl: Some(carc),
             num_pending_ios: 0,
+            timeout_config,
+            failover_epoch: unsafe { timeout_config.as_ref().failover_epoch() },
+            failover: false,
+            failover_ios: Vec::new(),
         });
 
         nvme_channel.inner = Box::into_raw(inner);
@@ -451,6 +600,8 @@ impl NvmeControllerIoChannel {
             let ch = NvmeIoChannel::from_raw(ctx);
             let mut inner = unsafe { Box::from_raw(ch.inner) };
 
+            inner.is_shutdown = true;
+            inner.abort_failover();
             let qpair = inner.remove_qpair();
 
             // Stop the poller and do extra handling for I/O qpair, as it needs
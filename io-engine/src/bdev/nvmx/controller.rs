@@ -2,6 +2,7 @@
 //!
 //! This file contains the main structures for a NVMe controller
 use std::{
+    collections::VecDeque,
     convert::From,
     fmt,
     os::raw::c_void,
@@ -32,26 +33,34 @@ use spdk_rs::{
 };
 
 use crate::{
-    bdev::nvmx::{
-        channel::{NvmeControllerIoChannel, NvmeIoChannel, NvmeIoChannelInner},
-        controller_inner::{SpdkNvmeController, TimeoutConfig},
-        controller_state::{
-            ControllerFailureReason,
-            ControllerFlag,
-            ControllerStateMachine,
+    bdev::{
+        nexus::NvmeAnaState,
+        nvmx::{
+            channel::{
+                NvmeControllerIoChannel,
+                NvmeIoChannel,
+                NvmeIoChannelInner,
+            },
+            controller_inner::{SpdkNvmeController, TimeoutConfig},
+            controller_state::{
+                ControllerFailureReason,
+                ControllerFlag,
+                ControllerStateMachine,
+            },
+            nvme_bdev_running_config,
+            path::{self, NvmePath, NvmePathInfo, NvmePathState},
+            uri::NvmeControllerContext,
+            utils::{
+                nvme_cpl_succeeded,
+                NvmeAerInfoNotice,
+                NvmeAerInfoNvmCommandSet,
+                NvmeAerType,
+            },
+            NvmeControllerState,
+            NvmeControllerState::*,
+            NvmeNamespace,
+            NVME_CONTROLLERS,
         },
-        nvme_bdev_running_config,
-        uri::NvmeControllerContext,
-        utils::{
-            nvme_cpl_succeeded,
-            NvmeAerInfoNotice,
-            NvmeAerInfoNvmCommandSet,
-            NvmeAerType,
-        },
-        NvmeControllerState,
-        NvmeControllerState::*,
-        NvmeNamespace,
-        NVME_CONTROLLERS,
     },
     bdev_api::BdevError,
     core::{
@@ -61,6 +70,7 @@ use crate::{
         DeviceEventSink,
         DeviceEventType,
         IoDevice,
+        IoLatencyRecorder,
         OpCompletionCallback,
         OpCompletionCallbackArg,
     },
@@ -76,6 +86,7 @@ struct ResetCtx {
     spdk_handle: SpdkNvmeController,
     io_device: Arc<IoDevice>,
     shutdown_in_progress: bool,
+    failover: bool,
 }
 
 struct ShutdownCtx {
@@ -153,6 +164,13 @@ pub struct NvmeController<'a> {
     /// so it needs to be a raw pointer. Mutable members are made atomic to
     /// eliminate lock contention between API path and callback path.
     pub(crate) timeout_config: NonNull<TimeoutConfig>,
+    /// Paths to the target subsystem, only one of which is active at a time.
+    paths: Vec<NvmePath>,
+    /// Index of the path the controller is connected over.
+    active_path: usize,
+    /// Latency histograms of the I/O operations completed on the channels
+    /// of the controller.
+    io_latency: Arc<IoLatencyRecorder>,
 }
 
 impl<'a> fmt::Debug for NvmeController<'a> {
@@ -161,6 +179,7 @@ impl<'a> fmt::Debug for NvmeController<'a> {
             .field("name", &self.name)
             .field("prchk_flags", &self.prchk_flags)
             .field("state_machine", &self.state_machine)
+            .field("paths", &self.paths)
             .finish()
     }
 }
@@ -182,6 +201,9 @@ impl<'a> NvmeController<'a> {
                 TimeoutConfig::new(name),
             )))
             .expect("failed to box timeout context"),
+            paths: Vec::new(),
+            active_path: 0,
+            io_latency: Arc::new(IoLatencyRecorder::new()),
         };
 
         debug!("{}: new NVMe controller created", l.name);
@@ -198,6 +220,11 @@ impl<'a> NvmeController<'a> {
         self.prchk_flags
     }
 
+    /// returns the I/O latency recorder of the controller
+    pub fn io_latency(&self) -> Arc<IoLatencyRecorder> {
+        self.io_latency.clone()
+    }
+
     /// returns the ID of the controller
     pub fn id(&self) -> u64 {
         // If controller is initialized, ID must be set.
@@ -214,6 +241,34 @@ impl<'a> NvmeController<'a> {
         id
     }
 
+    /// Get the status of all paths of the controller.
+    pub fn path_info(&self) -> Vec<NvmePathInfo> {
+        self.paths.iter().map(NvmePath::info).collect()
+    }
+
+    /// Check whether the namespace is inaccessible over the active path, as
+    /// per the ANA state last reported by the target, recording the state.
+    fn update_active_path_ana_state(&mut self) -> bool {
+        let Some(ana_state) = self.namespace().map(|ns| ns.ana_state()) else {
+            return false;
+        };
+        let Some(path) = self.paths.get_mut(self.active_path) else {
+            return false;
+        };
+
+        path.set_ana_state(ana_state);
+        if path::ana_accessible(ana_state) {
+            return false;
+        }
+
+        warn!(
+            "{}: namespace is inaccessible over path {:?} (ANA state {:?})",
+            self.name, path, ana_state
+        );
+        path.set_state(NvmePathState::Inaccessible);
+        true
+    }
+
     // As of now, only 1 namespace per controller is supported.
     pub fn namespace(&self) -> Option<Arc<NvmeNamespace>> {
         let inner = self
@@ -305,7 +360,9 @@ impl<'a> NvmeController<'a> {
 
     /// Reset the controller.
     /// Upon reset all pending I/O operations are cancelled and all I/O handles
-    /// are reinitialized.
+    /// are reinitialized. In case of failover, the controller is reconnected
+    /// over the next usable path in between, and I/O operations failed due
+    /// to the path are resubmitted once the I/O handles are reinitialized.
     pub fn reset(
         &mut self,
         cb: OpCompletionCallback,
@@ -340,12 +397,13 @@ impl<'a> NvmeController<'a> {
             self.name, failover
         );
 
-        if failover {
+        if failover && self.paths.len() < 2 {
             warn!(
-                "{} failover is not supported for controller reset",
+                "{} failover is not possible without alternate paths",
                 self.name
             );
         }
+        let failover = failover && self.paths.len() > 1;
 
         let io_device = self.inner.as_ref().unwrap().io_device.clone();
         let reset_ctx = ResetCtx {
@@ -357,6 +415,7 @@ impl<'a> NvmeController<'a> {
                 .expect("controller is may not be NULL"),
             io_device,
             shutdown_in_progress: false,
+            failover,
         };
 
         debug!("{}: starting reset", self.name);
@@ -601,6 +660,12 @@ impl<'a> NvmeController<'a> {
             return 0;
         }
 
+        // Keep the I/O operations failed by the reset for resubmission once
+        // the channel is reinitialized on the new path.
+        if ctx.failover {
+            channel.start_failover();
+        }
+
         let rc = channel.reset();
 
         if rc == 0 {
@@ -631,6 +696,7 @@ impl<'a> NvmeController<'a> {
             spdk_handle: self.controller().expect("controller may not be NULL"),
             io_device,
             shutdown_in_progress: false,
+            failover: false,
         };
 
         let inner = self.inner.as_mut().unwrap();
@@ -665,6 +731,13 @@ impl<'a> NvmeController<'a> {
             return;
         }
 
+        // Switch the controller over to another path before restoring the
+        // I/O channels.
+        if reset_ctx.failover {
+            FailoverCtx::start(reset_ctx);
+            return;
+        }
+
         debug!(
             "{} controller successfully reset, reinitializing I/O channels",
             reset_ctx.name
@@ -711,6 +784,19 @@ impl<'a> NvmeController<'a> {
         NvmeController::_complete_reset(reset_ctx, status);
     }
 
+    fn _failover_abort_channels(
+        channel: &mut NvmeIoChannelInner,
+        _reset_ctx: &mut ResetCtx,
+    ) -> i32 {
+        channel.abort_failover();
+        0
+    }
+
+    fn _failover_abort_channels_done(_status: i32, reset_ctx: ResetCtx) {
+        error!("{} controller failover failed", reset_ctx.name);
+        NvmeController::_complete_reset(reset_ctx, -libc::ENXIO);
+    }
+
     /// Notifies all listeners of this controller.
     ///
     /// Note: Keep a separate copy of all registered listeners in order to not
@@ -776,6 +862,222 @@ impl<'a> Drop for NvmeController<'a> {
     }
 }
 
+/// Context for failing the controller over to another path, after all I/O
+/// channels have been reset.
+struct FailoverCtx {
+    reset_ctx: Option<ResetCtx>,
+    ctrlr: SpdkNvmeController,
+    /// Paths left to try, in order.
+    candidates: VecDeque<usize>,
+    /// Path the controller is being connected over.
+    path: usize,
+    /// Whether the admin queue is disconnected and reconnect has started.
+    reconnecting: bool,
+    poller: Option<Poller<'static>>,
+}
+
+impl FailoverCtx {
+    fn start(reset_ctx: ResetCtx) {
+        let Some(carc) = NVME_CONTROLLERS.lookup_by_name(&reset_ctx.name)
+        else {
+            NvmeController::_complete_reset(reset_ctx, -libc::ENODEV);
+            return;
+        };
+        let candidates = {
+            let controller = carc.lock();
+            path::failover_order(&controller.paths, controller.active_path)
+        };
+
+        let ctx = Box::into_raw(Box::new(FailoverCtx {
+            ctrlr: reset_ctx.spdk_handle,
+            reset_ctx: Some(reset_ctx),
+            candidates,
+            path: 0,
+            reconnecting: false,
+            poller: None,
+        }));
+
+        let poller = PollerBuilder::new()
+            .with_name("nvme_failover_poller")
+            .with_interval(Duration::from_micros(1000))
+            .with_poll_fn(move |_| FailoverCtx::poll(ctx))
+            .build();
+
+        let failover = unsafe { &mut *ctx };
+        failover.poller = Some(poller);
+
+        if !failover.next_path(&carc) {
+            FailoverCtx::finish(ctx, false);
+        }
+    }
+
+    fn name(&self) -> &str {
+        &self.reset_ctx.as_ref().expect("reset context is gone").name
+    }
+
+    /// Start connecting over the next candidate path.
+    /// Returns false if there are no more paths to try.
+    /// Note: the controller must not be locked while calling into SPDK, as
+    /// aborted admin commands and async events complete inline.
+    fn next_path(
+        &mut self,
+        carc: &Arc<parking_lot::Mutex<NvmeController>>,
+    ) -> bool {
+        while let Some(path) = self.candidates.pop_front() {
+            let (trid, running) = {
+                let controller = carc.lock();
+                (
+                    controller.paths[path].trid().clone(),
+                    controller.get_state() == Running,
+                )
+            };
+            if !running {
+                return false;
+            }
+
+            // The controller must be failed in order to change its transport
+            // id, the disconnect clears the failed state.
+            self.ctrlr.fail();
+            let mut rc = self.ctrlr.set_trid(&trid);
+            if rc == 0 {
+                rc = self.ctrlr.disconnect();
+            }
+
+            let mut controller = carc.lock();
+            if rc == 0 {
+                info!(
+                    "{}: failing over to path {:?}",
+                    self.name(),
+                    controller.paths[path]
+                );
+                self.path = path;
+                self.reconnecting = false;
+                return true;
+            }
+
+            error!(
+                "{}: failed to switch over to path {:?}, rc = {}",
+                self.name(),
+                controller.paths[path],
+                rc
+            );
+            controller.paths[path].set_state(NvmePathState::Failed);
+        }
+
+        false
+    }
+
+    fn poll(ctx: *mut FailoverCtx) -> i32 {
+        let failover = unsafe { &mut *ctx };
+
+        // Holding a reference keeps the SPDK controller attached, but it must
+        // still be in Running state as a concurrent shutdown might be in
+        // place.
+        let Some(carc) = NVME_CONTROLLERS
+            .lookup_by_name(failover.name())
+            .filter(|c| c.lock().get_state() == Running)
+        else {
+            warn!(
+                "{}: controller is going away, failover aborted",
+                failover.name()
+            );
+            FailoverCtx::finish(ctx, false);
+            return 1;
+        };
+
+        // Reconnect once the admin queue is disconnected.
+        if !failover.reconnecting {
+            if failover.ctrlr.process_admin_completions() < 0 {
+                failover.ctrlr.reconnect_async();
+                failover.reconnecting = true;
+            }
+            return 0;
+        }
+
+        let rc = failover.ctrlr.reconnect_poll_async();
+        if rc == -libc::EAGAIN {
+            return 0;
+        }
+
+        let path = failover.path;
+        let mut controller = carc.lock();
+        if rc == 0 {
+            // Connected, but the namespace must be accessible over the path.
+            let ana_state = controller
+                .namespace()
+                .map_or(NvmeAnaState::InvalidState, |ns| ns.ana_state());
+            controller.paths[path].set_ana_state(ana_state);
+
+            if path::ana_accessible(ana_state) {
+                let active = controller.active_path;
+                if active != path
+                    && controller.paths[active].state() == NvmePathState::Active
+                {
+                    controller.paths[active].set_state(NvmePathState::Failed);
+                }
+                controller.paths[path].set_state(NvmePathState::Active);
+                controller.active_path = path;
+
+                info!(
+                    "{}: controller reconnected over path {:?}",
+                    failover.name(),
+                    controller.paths[path]
+                );
+                drop(controller);
+                FailoverCtx::finish(ctx, true);
+                return 1;
+            }
+
+            warn!(
+                "{}: namespace is inaccessible over path {:?}",
+                failover.name(),
+                controller.paths[path]
+            );
+            controller.paths[path].set_state(NvmePathState::Inaccessible);
+        } else {
+            error!(
+                "{}: failed to reconnect over path {:?}, rc = {}",
+                failover.name(),
+                controller.paths[path],
+                rc
+            );
+            controller.paths[path].set_state(NvmePathState::Failed);
+        }
+        drop(controller);
+
+        if !failover.next_path(&carc) {
+            FailoverCtx::finish(ctx, false);
+        }
+        1
+    }
+
+    /// Restore the I/O channels on success, otherwise fail all I/O
+    /// operations kept for resubmission, and complete the reset.
+    fn finish(ctx: *mut FailoverCtx, success: bool) {
+        let mut failover = unsafe { Box::from_raw(ctx) };
+        failover.poller.take();
+
+        let reset_ctx = failover.reset_ctx.take().expect("no reset context");
+        let io_device = reset_ctx.io_device.clone();
+
+        if success {
+            io_device.traverse_io_channels(
+                NvmeController::_reset_create_channels,
+                NvmeController::_reset_create_channels_done,
+                NvmeIoChannel::inner_from_channel,
+                reset_ctx,
+            );
+        } else {
+            io_device.traverse_io_channels(
+                NvmeController::_failover_abort_channels,
+                NvmeController::_failover_abort_channels_done,
+                NvmeIoChannel::inner_from_channel,
+                reset_ctx,
+            );
+        }
+    }
+}
+
 extern "C" fn aer_cb(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
     let mut event = spdk_nvme_async_event_completion::default();
 
@@ -817,6 +1119,22 @@ extern "C" fn aer_cb(ctx: *mut c_void, cpl: *const spdk_nvme_cpl) {
                 );
             }
         }
+    } else if event_type == NvmeAerType::Notice as u32
+        && event_info == NvmeAerInfoNotice::AnaChange as u32
+    {
+        let cid = ctx as u64;
+
+        // The ANA states of the namespaces have already been updated by the
+        // time the event is delivered.
+        if let Some(c) = NVME_CONTROLLERS.lookup_by_name(cid.to_string()) {
+            let mut ctrlr = c.lock();
+            let inaccessible = ctrlr.update_active_path_ana_state();
+            let mut timeout_config = ctrlr.timeout_config;
+            drop(ctrlr);
+            if inaccessible {
+                unsafe { timeout_config.as_mut().failover_controller() };
+            }
+        }
     } else if event_type == NvmeAerType::Io as u32
         && event_info == NvmeAerInfoNvmCommandSet::ReservationLogAvail as u32
     {
@@ -831,6 +1149,11 @@ pub extern "C" fn nvme_poll_adminq(ctx: *mut c_void) -> i32 {
         .expect("ctx pointer may never be null");
     let context = unsafe { context.as_mut() };
 
+    // The admin queue is driven by the failover while it's in progress.
+    if context.failover_in_progress() {
+        return 0;
+    }
+
     // returns number of completions processed (maybe 0) or the negated error,
     // which is one of:
     //
@@ -841,28 +1164,10 @@ pub extern "C" fn nvme_poll_adminq(ctx: *mut c_void) -> i32 {
     let result = context.process_adminq();
 
     if result < 0 {
-        if context.start_device_destroy() {
-            error!(
-                "process adminq: {}: {}",
-                context.name,
-                Errno::from_i32(result.abs())
-            );
-            info!("dispatching nexus fault and retire: {}", context.name);
-            let dev_name = context.name.to_string();
-            let carc = NVME_CONTROLLERS.lookup_by_name(&dev_name).unwrap();
-            debug!(
-                ?dev_name,
-                "notifying listeners of admin command completion failure"
-            );
-            let controller = carc.lock();
-            let num_listeners = controller.notify_listeners(
-                DeviceEventType::AdminCommandCompletionFailed,
-            );
-            debug!(
-                ?dev_name,
-                ?num_listeners,
-                "listeners notified of admin command completion failure"
-            );
+        // Losing the admin queue means losing the path, so try the others
+        // first.
+        if !context.failover_controller() {
+            device_failed(context, Errno::from_i32(result.abs()));
         }
         return 1;
     }
@@ -874,6 +1179,31 @@ pub extern "C" fn nvme_poll_adminq(ctx: *mut c_void) -> i32 {
     }
 }
 
+/// Notify all listeners that the controller can no longer be used, which
+/// results in the device being retired.
+pub(crate) fn device_failed(context: &mut TimeoutConfig, error: Errno) {
+    if context.start_device_destroy() {
+        error!("process adminq: {}: {}", context.name, error);
+        info!("dispatching nexus fault and retire: {}", context.name);
+        let dev_name = context.name.to_string();
+        let Some(carc) = NVME_CONTROLLERS.lookup_by_name(&dev_name) else {
+            return;
+        };
+        debug!(
+            ?dev_name,
+            "notifying listeners of admin command completion failure"
+        );
+        let controller = carc.lock();
+        let num_listeners = controller
+            .notify_listeners(DeviceEventType::AdminCommandCompletionFailed);
+        debug!(
+            ?dev_name,
+            ?num_listeners,
+            "listeners notified of admin command completion failure"
+        );
+    }
+}
+
 /// Destroy target controller and notify all listeners about device removal.
 pub(crate) async fn destroy_device(name: String) -> Result<(), BdevError> {
     let carc = NVME_CONTROLLERS.lookup_by_name(&name).ok_or(
@@ -983,6 +1313,10 @@ pub(crate) fn connected_attached_cb(
     // set the controller as a pointer within the context of the time out config
     unsafe { controller.timeout_config.as_mut().set_controller(ctrlr) };
     controller.set_id(cid);
+    controller.paths = ctx.take_paths();
+    controller.active_path = 0;
+    let multipath = controller.paths.len() > 1;
+    unsafe { controller.timeout_config.as_mut().set_multipath(multipath) };
     controller.inner = Some(NvmeControllerInner::new(
         ctrlr,
         controller.get_name(),
@@ -1009,6 +1343,15 @@ pub(crate) fn connected_attached_cb(
         .transition(Running)
         .expect("Failed to transition controller into Running state");
 
+    // Fail over right away if the namespace is not accessible over the
+    // first path.
+    let inaccessible = controller.update_active_path_ana_state();
+    let mut timeout_config = controller.timeout_config;
+    drop(controller);
+    if inaccessible {
+        unsafe { timeout_config.as_mut().failover_controller() };
+    }
+
     // Wake up the waiter and complete controller registration.
     ctx.sender()
         .send(Ok(()))
@@ -1155,13 +1498,14 @@ pub(crate) mod options {
 }
 
 pub(crate) mod transport {
-    use std::{ffi::CStr, fmt::Debug};
+    use std::{ffi::CStr, fmt::Debug, net::Ipv6Addr};
 
     use spdk_rs::{
         ffihelper::copy_str_with_null,
         libspdk::spdk_nvme_transport_id,
     };
 
+    #[derive(Clone)]
     pub struct NvmeTransportId(spdk_nvme_transport_id);
 
     impl Debug for NvmeTransportId {
@@ -1285,11 +1629,17 @@ pub(crate) mod transport {
             self
         }
 
-        /// builder for transportID currently defaults to TCP IPv4
+        /// builder for transportID currently defaults to TCP, the address
+        /// family is derived from the address (IPv4 or IPv6)
         pub fn build(self) -> NvmeTransportId {
             let trtype = String::from(TransportId::TCP);
+            let adrfam = if self.traddr.parse::<Ipv6Addr>().is_ok() {
+                AdressFamily::NvmfAdrfamIpv6
+            } else {
+                self.adrfam
+            };
             let mut trid = spdk_nvme_transport_id {
-                adrfam: AdressFamily::NvmfAdrfamIpv4 as u32,
+                adrfam: adrfam as u32,
                 trtype: TransportId::TCP as u32,
                 ..Default::default()
             };
@@ -1318,6 +1668,25 @@ pub(crate) mod transport {
             assert_eq!(transport.traddr(), "127.0.0.1");
             assert_eq!(transport.subnqn(), "nqn.2021-01-01:test.nqn");
             assert_eq!(transport.svcid(), "4420");
+            assert_eq!(
+                transport.0.adrfam,
+                transport::AdressFamily::NvmfAdrfamIpv4 as u32
+            );
+        }
+
+        #[test]
+        fn test_transport_id_ipv6() {
+            let transport = transport::Builder::new()
+                .with_subnqn("nqn.2021-01-01:test.nqn")
+                .with_svcid("4420")
+                .with_traddr("fd00::1")
+                .build();
+
+            assert_eq!(transport.traddr(), "fd00::1");
+            assert_eq!(
+                transport.0.adrfam,
+                transport::AdressFamily::NvmfAdrfamIpv6 as u32
+            );
         }
     }
 }
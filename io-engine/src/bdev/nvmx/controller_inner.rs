@@ -7,16 +7,21 @@ use std::{
 };
 
 use crossbeam::atomic::AtomicCell;
+use nix::errno::Errno;
 
 use spdk_rs::libspdk::{
     spdk_nvme_cmd_cb,
     spdk_nvme_cpl,
     spdk_nvme_ctrlr,
     spdk_nvme_ctrlr_cmd_abort,
+    spdk_nvme_ctrlr_disconnect,
     spdk_nvme_ctrlr_fail,
     spdk_nvme_ctrlr_get_regs_csts,
     spdk_nvme_ctrlr_process_admin_completions,
+    spdk_nvme_ctrlr_reconnect_async,
+    spdk_nvme_ctrlr_reconnect_poll_async,
     spdk_nvme_ctrlr_register_timeout_callback,
+    spdk_nvme_ctrlr_set_trid,
     spdk_nvme_qpair,
     SPDK_BDEV_NVME_TIMEOUT_ACTION_ABORT,
     SPDK_BDEV_NVME_TIMEOUT_ACTION_NONE,
@@ -25,6 +30,7 @@ use spdk_rs::libspdk::{
 
 use crate::{
     bdev::nvmx::{
+        controller::{device_failed, transport::NvmeTransportId},
         nvme_bdev_running_config,
         utils::nvme_cpl_succeeded,
         NvmeController,
@@ -74,6 +80,9 @@ pub(crate) struct TimeoutConfig {
     reset_attempts: u32,
     next_reset_time: Instant,
     destroy_in_progress: AtomicCell<bool>,
+    multipath: AtomicCell<bool>,
+    failover_in_progress: AtomicCell<bool>,
+    failover_epoch: AtomicCell<u64>,
 }
 
 impl Drop for TimeoutConfig {
@@ -94,6 +103,9 @@ impl TimeoutConfig {
             reset_attempts: MAX_RESET_ATTEMPTS,
             next_reset_time: Instant::now(),
             destroy_in_progress: AtomicCell::new(false),
+            multipath: AtomicCell::new(false),
+            failover_in_progress: AtomicCell::new(false),
+            failover_epoch: AtomicCell::new(0),
         }
     }
 
@@ -110,6 +122,29 @@ impl TimeoutConfig {
         self.ctrlr = ctrlr;
     }
 
+    /// Set whether the controller has alternate paths to fail over to.
+    pub fn set_multipath(&mut self, multipath: bool) {
+        self.multipath.store(multipath);
+    }
+
+    /// Check whether the controller has alternate paths to fail over to.
+    pub fn is_multipath(&self) -> bool {
+        self.multipath.load()
+    }
+
+    /// Check whether the controller is failing over to another path, in
+    /// which case the admin queue is driven by the failover itself.
+    pub fn failover_in_progress(&self) -> bool {
+        self.failover_in_progress.load()
+    }
+
+    /// Get the number of failovers initiated so far. I/O channels record it
+    /// when they are (re)initialized, to tell whether a failover has yet to
+    /// reset them.
+    pub fn failover_epoch(&self) -> u64 {
+        self.failover_epoch.load()
+    }
+
     pub fn process_adminq(&self) -> i32 {
         unsafe {
             spdk_nvme_ctrlr_process_admin_completions(self.ctrlr.as_ptr())
@@ -237,6 +272,82 @@ impl TimeoutConfig {
         );
     }
 
+    fn failover_cb(success: bool, ctx: *mut c_void) {
+        let timeout_ctx = TimeoutConfig::from_ptr(ctx as *mut TimeoutConfig);
+
+        // Clear the flag as we are the exclusive owner.
+        assert!(
+            timeout_ctx
+                .failover_in_progress
+                .compare_exchange(true, false)
+                .is_ok(),
+            "non-exclusive access to controller failover flag"
+        );
+
+        if success {
+            info!("{} controller successfully failed over", timeout_ctx.name);
+        } else {
+            error!(
+                "{} failed to fail over to any path, giving up the controller",
+                timeout_ctx.name
+            );
+            device_failed(timeout_ctx, Errno::ENXIO);
+        }
+    }
+
+    /// Fails the controller over to another path exclusively, unless a
+    /// failover is already in progress.
+    /// Returns false if failover is not possible, in which case the caller
+    /// must handle the path failure itself.
+    pub(crate) fn failover_controller(&mut self) -> bool {
+        if !self.multipath.load()
+            || self.destroy_in_progress.load()
+            || self.reset_in_progress.load()
+        {
+            return false;
+        }
+
+        // The ongoing failover will take care of this failure as well.
+        if self
+            .failover_in_progress
+            .compare_exchange(false, true)
+            .is_err()
+        {
+            return true;
+        }
+
+        self.failover_epoch.fetch_add(1);
+
+        if let Some(c) = NVME_CONTROLLERS.lookup_by_name(&self.name) {
+            let mut c = c.lock();
+            match c.reset(
+                TimeoutConfig::failover_cb,
+                self as *mut TimeoutConfig as *mut c_void,
+                true,
+            ) {
+                Ok(()) => {
+                    info!("{} controller failover initiated", self.name);
+                    return true;
+                }
+                Err(e) => {
+                    error!(
+                        "{}: failed to initiate controller failover: {}",
+                        self.name, e
+                    );
+                }
+            }
+        }
+
+        // Clear the flag as we are the exclusive owner.
+        assert!(
+            self.failover_in_progress
+                .compare_exchange(true, false)
+                .is_ok(),
+            "non-exclusive access to controller failover flag"
+        );
+        false
+    }
+
     /// Set new I/O timeout action.
     pub fn set_timeout_action(&mut self, action: DeviceTimeoutAction) {
         self.timeout_action.store(action);
@@ -287,6 +398,37 @@ impl SpdkNvmeController {
         unsafe { spdk_nvme_ctrlr_fail(self.0.as_ptr()) }
     }
 
+    /// Switch the controller over to the given transport id, which must
+    /// refer to the same subsystem. The controller must be failed and it
+    /// connects over the new transport id on the next reconnect.
+    pub fn set_trid(&self, trid: &NvmeTransportId) -> i32 {
+        unsafe {
+            spdk_nvme_ctrlr_set_trid(self.0.as_ptr(), trid.as_ptr() as *mut _)
+        }
+    }
+
+    /// Start disconnecting the controller, which completes once processing
+    /// admin completions fails.
+    pub fn disconnect(&self) -> i32 {
+        unsafe { spdk_nvme_ctrlr_disconnect(self.0.as_ptr()) }
+    }
+
+    /// Process admin queue completions.
+    pub fn process_admin_completions(&self) -> i32 {
+        unsafe { spdk_nvme_ctrlr_process_admin_completions(self.0.as_ptr()) }
+    }
+
+    /// Start reconnecting a disconnected controller.
+    pub fn reconnect_async(&self) {
+        unsafe { spdk_nvme_ctrlr_reconnect_async(self.0.as_ptr()) }
+    }
+
+    /// Poll the reconnect started by `reconnect_async`, returns -EAGAIN
+    /// while reconnect is in progress.
+    pub fn reconnect_poll_async(&self) -> i32 {
+        unsafe { spdk_nvme_ctrlr_reconnect_poll_async(self.0.as_ptr()) }
+    }
+
     /// Abort command on a given I/O qpair.
     pub fn abort_queued_command(
         &self,
@@ -23,6 +23,7 @@ use crate::{
         DeviceEventSink,
         DeviceIoController,
         DeviceTimeoutAction,
+        IoLatencyStats,
         IoType,
     },
     ffihelper::{cb_arg, done_cb},
@@ -232,6 +233,18 @@ impl BlockDevice for NvmeBlockDevice {
         r.await.expect("Failed awaiting at io_stats")
     }
 
+    fn io_latency(&self) -> Option<IoLatencyStats> {
+        NVME_CONTROLLERS
+            .lookup_by_name(&self.name)
+            .map(|carc| carc.lock().io_latency().stats())
+    }
+
+    fn reset_io_latency(&self) {
+        if let Some(carc) = NVME_CONTROLLERS.lookup_by_name(&self.name) {
+            carc.lock().io_latency().reset();
+        }
+    }
+
     fn claimed_by(&self) -> Option<String> {
         None
     }
@@ -16,6 +16,7 @@ use spdk_rs::{
         spdk_nvme_ctrlr_cmd_admin_raw,
         spdk_nvme_ctrlr_cmd_io_raw,
         spdk_nvme_dsm_range,
+        spdk_nvme_ns,
         spdk_nvme_ns_cmd_dataset_management,
         spdk_nvme_ns_cmd_flush,
         spdk_nvme_ns_cmd_read,
@@ -23,6 +24,7 @@ use spdk_rs::{
         spdk_nvme_ns_cmd_write,
         spdk_nvme_ns_cmd_write_zeroes,
         spdk_nvme_ns_cmd_writev,
+        spdk_nvme_qpair,
         SPDK_NVME_IO_FLAGS_UNWRITTEN_READ_FAIL,
     },
     nvme_admin_opc,
@@ -39,15 +41,23 @@ use crate::{
         channel::NvmeControllerIoChannel,
         controller_inner::SpdkNvmeController,
         utils,
-        utils::{nvme_cpl_is_pi_error, nvme_cpl_succeeded},
+        utils::{
+            nvme_cpl_is_path_error,
+            nvme_cpl_is_pi_error,
+            nvme_cpl_succeeded,
+        },
         NvmeBlockDevice,
         NvmeIoChannel,
         NvmeNamespace,
+        NvmeSnapshotDestroyMessage,
+        NvmeSnapshotDestroyMessageV1,
         NvmeSnapshotMessage,
         NvmeSnapshotMessageV1,
+        NVME_ADMIN_DESTROY_SNAPSHOT,
         NVME_CONTROLLERS,
     },
     core::{
+        io_start_ticks,
         mempool::MemoryPool,
         BlockDevice,
         BlockDeviceHandle,
@@ -81,6 +91,13 @@ struct NvmeIoCtx {
     op: IoType,
     num_blocks: u64,
     channel: *mut spdk_io_channel,
+    // The remaining fields allow resubmission on another path.
+    offset_blocks: u64,
+    ns: *mut spdk_nvme_ns,
+    prchk_flags: u32,
+    failovers: u8,
+    // Ticks at the submission of the I/O, for latency accounting.
+    start_ticks: u64,
 }
 
 unsafe impl Send for NvmeIoCtx {}
@@ -90,6 +107,9 @@ unsafe impl Sync for NvmeIoCtx {}
 // which is used in every user BIO-based I/O operation.
 static NVME_IOCTX_POOL: OnceCell<MemoryPool<NvmeIoCtx>> = OnceCell::new();
 
+// Maximum number of times an I/O operation is failed over to another path.
+pub(crate) const MAX_IO_FAILOVERS: u8 = 3;
+
 // Maximum number of range sets that may be specified in the dataset management
 // command.
 const SPDK_NVME_DATASET_MANAGEMENT_MAX_RANGES: u64 = 256;
@@ -296,17 +316,199 @@ extern "C" fn nvme_queued_next_sge(
     0
 }
 
-/// Notify the caller and deallocate Nvme IO context.
+/// I/O operation which failed due to its path, kept for resubmission once
+/// the controller fails over to another path.
+pub(crate) struct FailoverIo {
+    ctx: *mut NvmeIoCtx,
+    status: IoCompletionStatus,
+    /// Ticks before which the operation must not be resubmitted.
+    not_before: u64,
+}
+
+impl FailoverIo {
+    /// Number of times the operation failed due to its path so far.
+    #[inline]
+    pub(crate) fn failovers(&self) -> u8 {
+        unsafe { (*self.ctx).failovers }
+    }
+
+    /// Ticks before which the operation must not be resubmitted.
+    #[inline]
+    pub(crate) fn not_before(&self) -> u64 {
+        self.not_before
+    }
+
+    /// Delay the resubmission of the operation till the given ticks.
+    #[inline]
+    pub(crate) fn set_not_before(&mut self, ticks: u64) {
+        self.not_before = ticks;
+    }
+
+    /// Resubmit the operation on the (reinitialized) channel, failing it
+    /// with its original status if that's not possible.
+    pub(crate) fn resubmit(self, inner: &mut NvmeIoChannelInner) {
+        let rc = match inner.qpair() {
+            Some(qpair) => submit_nvme_io(self.ctx, qpair.as_ptr()),
+            None => -libc::ENODEV,
+        };
+
+        if rc < 0 {
+            error!(rc, "failed to resubmit I/O after failover");
+            self.fail();
+        }
+    }
+
+    /// Fail the operation with its original status.
+    pub(crate) fn fail(self) {
+        finish_nvme_command(self.ctx, self.status);
+    }
+}
+
+/// Submit the I/O operation described by the context on the given qpair.
+/// Only operations which can be failed over to another path are supported.
+fn submit_nvme_io(bio: *mut NvmeIoCtx, qpair: *mut spdk_nvme_qpair) -> i32 {
+    let ctx = unsafe { &mut *bio };
+
+    // (Re)start from the beginning of the I/O vector.
+    ctx.iovpos = 0;
+    ctx.iov_offset = 0;
+
+    unsafe {
+        match ctx.op {
+            IoType::Read if ctx.iovcnt == 1 => spdk_nvme_ns_cmd_read(
+                ctx.ns,
+                qpair,
+                (*ctx.iov).iov_base,
+                ctx.offset_blocks,
+                ctx.num_blocks as u32,
+                Some(nvme_io_done),
+                bio as *mut c_void,
+                ctx.prchk_flags,
+            ),
+            IoType::Read => spdk_nvme_ns_cmd_readv(
+                ctx.ns,
+                qpair,
+                ctx.offset_blocks,
+                ctx.num_blocks as u32,
+                Some(nvme_io_done),
+                bio as *mut c_void,
+                ctx.prchk_flags,
+                Some(nvme_queued_reset_sgl),
+                Some(nvme_queued_next_sge),
+            ),
+            IoType::Write if ctx.iovcnt == 1 => spdk_nvme_ns_cmd_write(
+                ctx.ns,
+                qpair,
+                (*ctx.iov).iov_base,
+                ctx.offset_blocks,
+                ctx.num_blocks as u32,
+                Some(nvme_io_done),
+                bio as *mut c_void,
+                ctx.prchk_flags,
+            ),
+            IoType::Write => spdk_nvme_ns_cmd_writev(
+                ctx.ns,
+                qpair,
+                ctx.offset_blocks,
+                ctx.num_blocks as u32,
+                Some(nvme_writev_done),
+                bio as *mut c_void,
+                ctx.prchk_flags,
+                Some(nvme_queued_reset_sgl),
+                Some(nvme_queued_next_sge),
+            ),
+            IoType::WriteZeros => spdk_nvme_ns_cmd_write_zeroes(
+                ctx.ns,
+                qpair,
+                ctx.offset_blocks,
+                ctx.num_blocks as u32,
+                Some(nvme_io_done),
+                bio as *mut c_void,
+                ctx.prchk_flags,
+            ),
+            IoType::Flush => spdk_nvme_ns_cmd_flush(
+                ctx.ns,
+                qpair,
+                Some(nvme_flush_completion),
+                bio as *mut c_void,
+            ),
+            _ => -libc::ENOTSUP,
+        }
+    }
+}
+
+/// Submit the I/O operation, unless the channel is failing over to another
+/// path, in which case the operation is kept and submitted on the new path.
+fn submit_or_keep_nvme_io(
+    bio: *mut NvmeIoCtx,
+    inner: &mut NvmeIoChannelInner,
+) -> i32 {
+    if !inner.is_failover() {
+        return submit_nvme_io(bio, unsafe { inner.qpair_ptr() });
+    }
+
+    let io = FailoverIo {
+        ctx: bio,
+        status: IoCompletionStatus::NvmeError(NvmeStatus::Generic(
+            GenericStatusCode::AbortedSubmissionQueueDeleted,
+        )),
+        not_before: 0,
+    };
+    match inner.failover_io(io) {
+        None => 0,
+        Some(_) => -libc::ENODEV,
+    }
+}
+
+/// Notify the caller and deallocate Nvme IO context, unless the operation
+/// failed due to its path and can be failed over to another one.
 #[inline]
 fn complete_nvme_command(ctx: *mut NvmeIoCtx, cpl: *const spdk_nvme_cpl) {
     let io_ctx = unsafe { &mut *ctx };
-    let op_succeeded = nvme_cpl_succeeded(cpl);
+
+    let status = if nvme_cpl_succeeded(cpl) {
+        IoCompletionStatus::Success
+    } else {
+        IoCompletionStatus::from(NvmeStatus::from(cpl))
+    };
+
+    if status != IoCompletionStatus::Success
+        && nvme_cpl_is_path_error(cpl)
+        && matches!(
+            io_ctx.op,
+            IoType::Read | IoType::Write | IoType::WriteZeros | IoType::Flush
+        )
+    {
+        let inner = NvmeIoChannel::inner_from_channel(io_ctx.channel);
+        if inner.is_multipath() {
+            io_ctx.failovers += 1;
+            let io = FailoverIo {
+                ctx,
+                status,
+                not_before: 0,
+            };
+            if let Some(io) = inner.failover_io(io) {
+                io.fail();
+            }
+            return;
+        }
+    }
+
+    finish_nvme_command(ctx, status);
+}
+
+/// Notify the caller with the given status and deallocate Nvme IO context.
+#[inline]
+fn finish_nvme_command(ctx: *mut NvmeIoCtx, status: IoCompletionStatus) {
+    let io_ctx = unsafe { &mut *ctx };
+    let op_succeeded = status == IoCompletionStatus::Success;
     let inner = NvmeIoChannel::inner_from_channel(io_ctx.channel);
 
     // Update I/O statistics in case the operation succeeded.
     if op_succeeded {
         let stats_controller = inner.get_io_stats_controller();
         stats_controller.account_block_io(io_ctx.op, 1, io_ctx.num_blocks);
+        inner.io_latency().record(io_ctx.op, io_ctx.start_ticks);
     }
 
     // Adjust the number of active I/O operations in case operation is
@@ -317,12 +519,6 @@ fn complete_nvme_command(ctx: *mut NvmeIoCtx, cpl: *const spdk_nvme_cpl) {
     }
 
     // Invoke caller's callback and free I/O context.
-    let status = if op_succeeded {
-        IoCompletionStatus::Success
-    } else {
-        IoCompletionStatus::from(NvmeStatus::from(cpl))
-    };
-
     (io_ctx.cb)(&*inner.device, status, io_ctx.cb_arg);
 
     free_nvme_io_ctx(ctx);
@@ -710,8 +906,15 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         let channel = self.io_channel.as_ptr();
         let inner = NvmeIoChannel::inner_from_channel(channel);
 
-        // Make sure channel allows I/O.
-        check_channel_for_io(IoType::Read, inner, offset_blocks, num_blocks)?;
+        // Make sure channel allows I/O, unless I/O is kept for failover.
+        if !inner.is_failover() {
+            check_channel_for_io(
+                IoType::Read,
+                inner,
+                offset_blocks,
+                num_blocks,
+            )?;
+        }
 
         let bio = alloc_nvme_io_ctx(
             IoType::Read,
@@ -725,39 +928,17 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Read,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                failovers: 0,
+                start_ticks: io_start_ticks(),
             },
             offset_blocks,
             num_blocks,
         )?;
 
-        let rc = if iovcnt == 1 {
-            unsafe {
-                spdk_nvme_ns_cmd_read(
-                    self.ns.as_ptr(),
-                    inner.qpair_ptr(),
-                    (*iov).iov_base,
-                    offset_blocks,
-                    num_blocks as u32,
-                    Some(nvme_io_done),
-                    bio as *mut c_void,
-                    self.prchk_flags,
-                )
-            }
-        } else {
-            unsafe {
-                spdk_nvme_ns_cmd_readv(
-                    self.ns.as_ptr(),
-                    inner.qpair_ptr(),
-                    offset_blocks,
-                    num_blocks as u32,
-                    Some(nvme_io_done),
-                    bio as *mut c_void,
-                    self.prchk_flags,
-                    Some(nvme_queued_reset_sgl),
-                    Some(nvme_queued_next_sge),
-                )
-            }
-        };
+        let rc = submit_or_keep_nvme_io(bio, inner);
 
         if rc < 0 {
             Err(CoreError::ReadDispatch {
@@ -785,8 +966,15 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         let channel = self.io_channel.as_ptr();
         let inner = NvmeIoChannel::inner_from_channel(channel);
 
-        // Make sure channel allows I/O.
-        check_channel_for_io(IoType::Write, inner, offset_blocks, num_blocks)?;
+        // Make sure channel allows I/O, unless I/O is kept for failover.
+        if !inner.is_failover() {
+            check_channel_for_io(
+                IoType::Write,
+                inner,
+                offset_blocks,
+                num_blocks,
+            )?;
+        }
 
         let bio = alloc_nvme_io_ctx(
             IoType::Write,
@@ -800,39 +988,17 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Write,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                failovers: 0,
+                start_ticks: io_start_ticks(),
             },
             offset_blocks,
             num_blocks,
         )?;
 
-        let rc = if iovcnt == 1 {
-            unsafe {
-                spdk_nvme_ns_cmd_write(
-                    self.ns.as_ptr(),
-                    inner.qpair_ptr(),
-                    (*iov).iov_base,
-                    offset_blocks,
-                    num_blocks as u32,
-                    Some(nvme_io_done),
-                    bio as *mut c_void,
-                    self.prchk_flags,
-                )
-            }
-        } else {
-            unsafe {
-                spdk_nvme_ns_cmd_writev(
-                    self.ns.as_ptr(),
-                    inner.qpair_ptr(),
-                    offset_blocks,
-                    num_blocks as u32,
-                    Some(nvme_writev_done),
-                    bio as *mut c_void,
-                    self.prchk_flags,
-                    Some(nvme_queued_reset_sgl),
-                    Some(nvme_queued_next_sge),
-                )
-            }
-        };
+        let rc = submit_or_keep_nvme_io(bio, inner);
 
         if rc < 0 {
             Err(CoreError::WriteDispatch {
@@ -881,8 +1047,10 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         let inner = NvmeIoChannel::inner_from_channel(channel);
         let num_blocks = self.block_device.num_blocks();
 
-        // Make sure channel allows I/O.
-        check_channel_for_io(IoType::Flush, inner, 0, num_blocks)?;
+        // Make sure channel allows I/O, unless I/O is kept for failover.
+        if !inner.is_failover() {
+            check_channel_for_io(IoType::Flush, inner, 0, num_blocks)?;
+        }
 
         let bio = alloc_nvme_io_ctx(
             IoType::Flush,
@@ -896,20 +1064,17 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Flush,
                 num_blocks,
+                offset_blocks: 0,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                failovers: 0,
+                start_ticks: io_start_ticks(),
             },
             0,
             num_blocks, // Flush all device blocks.
         )?;
 
-        // Setup range that describes the remaining blocks and schedule unmap.
-        let rc = unsafe {
-            spdk_nvme_ns_cmd_flush(
-                self.ns.as_ptr(),
-                inner.qpair_ptr(),
-                Some(nvme_flush_completion),
-                bio as *mut c_void,
-            )
-        };
+        let rc = submit_or_keep_nvme_io(bio, inner);
 
         if rc < 0 {
             Err(CoreError::FlushDispatch {
@@ -957,6 +1122,11 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::Unmap,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                failovers: 0,
+                start_ticks: io_start_ticks(),
             },
             offset_blocks,
             num_blocks,
@@ -1034,13 +1204,15 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         let channel = self.io_channel.as_ptr();
         let inner = NvmeIoChannel::inner_from_channel(channel);
 
-        // Make sure channel allows I/O
-        check_channel_for_io(
-            IoType::WriteZeros,
-            inner,
-            offset_blocks,
-            num_blocks,
-        )?;
+        // Make sure channel allows I/O, unless I/O is kept for failover.
+        if !inner.is_failover() {
+            check_channel_for_io(
+                IoType::WriteZeros,
+                inner,
+                offset_blocks,
+                num_blocks,
+            )?;
+        }
 
         let bio = alloc_nvme_io_ctx(
             IoType::WriteZeros,
@@ -1054,22 +1226,17 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
                 channel,
                 op: IoType::WriteZeros,
                 num_blocks,
+                offset_blocks,
+                ns: self.ns.as_ptr(),
+                prchk_flags: self.prchk_flags,
+                failovers: 0,
+                start_ticks: io_start_ticks(),
             },
             offset_blocks,
             num_blocks,
         )?;
 
-        let rc = unsafe {
-            spdk_nvme_ns_cmd_write_zeroes(
-                self.ns.as_ptr(),
-                inner.qpair_ptr(),
-                offset_blocks,
-                num_blocks as u32,
-                Some(nvme_io_done),
-                bio as *mut c_void,
-                self.prchk_flags,
-            )
-        };
+        let rc = submit_or_keep_nvme_io(bio, inner);
 
         if rc < 0 {
             Err(CoreError::WriteZeroesDispatch {
@@ -1110,6 +1277,32 @@ impl BlockDeviceHandle for NvmeDeviceHandle {
         Ok(now)
     }
 
+    async fn destroy_snapshot(
+        &self,
+        snapshot_uuid: &str,
+    ) -> Result<(), CoreError> {
+        let mut cmd = spdk_nvme_cmd::default();
+        cmd.set_opc(NVME_ADMIN_DESTROY_SNAPSHOT.into());
+
+        let msg = NvmeSnapshotDestroyMessage::V1(
+            NvmeSnapshotDestroyMessageV1::new(snapshot_uuid.to_string()),
+        );
+        let encoded_msg = bincode::serialize(&msg)
+            .expect("Failed to serialize snapshot message");
+
+        let mut payload =
+            self.dma_malloc(encoded_msg.len() as u64).map_err(|_| {
+                CoreError::DmaAllocationFailed {
+                    size: encoded_msg.len() as u64,
+                }
+            })?;
+
+        payload
+            .as_mut_slice()
+            .clone_from_slice(encoded_msg.as_slice());
+        self.nvme_admin(&cmd, Some(&mut payload)).await
+    }
+
     async fn nvme_admin_custom(&self, opcode: u8) -> Result<(), CoreError> {
         let mut cmd = spdk_nvme_cmd::default();
         cmd.set_opc(opcode.into());
@@ -10,9 +10,16 @@ pub use controller_state::NvmeControllerState;
 pub use device::{lookup_by_name, open_by_name, NvmeBlockDevice};
 pub use handle::{nvme_io_ctx_pool_init, NvmeDeviceHandle};
 pub use namespace::NvmeNamespace;
+pub use path::{NvmePathInfo, NvmePathState};
 use poll_group::PollGroup;
 pub use qpair::{QPair, QPairState};
-pub use snapshot::{NvmeSnapshotMessage, NvmeSnapshotMessageV1};
+pub use snapshot::{
+    NvmeSnapshotDestroyMessage,
+    NvmeSnapshotDestroyMessageV1,
+    NvmeSnapshotMessage,
+    NvmeSnapshotMessageV1,
+    NVME_ADMIN_DESTROY_SNAPSHOT,
+};
 pub(crate) use uri::NvmfDeviceTemplate;
 
 use crate::{
@@ -27,6 +34,7 @@ mod controller_state;
 mod device;
 mod handle;
 mod namespace;
+mod path;
 mod poll_group;
 mod qpair;
 mod snapshot;
@@ -2,6 +2,7 @@ use std::ptr::NonNull;
 
 use spdk_rs::libspdk::{
     spdk_nvme_ns,
+    spdk_nvme_ns_get_ana_state,
     spdk_nvme_ns_get_extended_sector_size,
     spdk_nvme_ns_get_flags,
     spdk_nvme_ns_get_md_size,
@@ -14,6 +15,8 @@ use spdk_rs::libspdk::{
     SPDK_NVME_NS_WRITE_ZEROES_SUPPORTED,
 };
 
+use crate::bdev::nexus::NvmeAnaState;
+
 #[derive(Debug)]
 pub struct NvmeNamespace(NonNull<spdk_nvme_ns>);
 
@@ -69,6 +72,14 @@ impl NvmeNamespace {
         unsafe { spdk_nvme_ns_get_md_size(self.0.as_ptr()) as u64 }
     }
 
+    /// ANA state of the namespace as last reported by the controller, only
+    /// meaningful if the controller supports ANA reporting.
+    pub fn ana_state(&self) -> NvmeAnaState {
+        let state = unsafe { spdk_nvme_ns_get_ana_state(self.0.as_ptr()) };
+        NvmeAnaState::from_i32(state as i32)
+            .unwrap_or(NvmeAnaState::InvalidState)
+    }
+
     pub fn from_ptr(ns: *mut spdk_nvme_ns) -> NvmeNamespace {
         NonNull::new(ns)
             .map(NvmeNamespace)
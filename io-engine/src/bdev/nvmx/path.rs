@@ -0,0 +1,138 @@
+//!
+//! Paths of a multipath NVMe controller.
+//!
+//! A controller may be reached over several transport addresses of the same
+//! subsystem. Only one path carries I/O at a time, the others are kept on
+//! standby and the controller fails over to one of them once the active path
+//! is lost or the target reports the namespace as inaccessible over it.
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+use crate::bdev::nexus::NvmeAnaState;
+
+use super::controller::transport::NvmeTransportId;
+
+/// State of a controller path.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum NvmePathState {
+    /// I/O is served over this path.
+    Active,
+    /// The path can be failed over to.
+    Standby,
+    /// Connecting over this path failed, or the connection was lost.
+    Failed,
+    /// The target reports the namespace as inaccessible over this path.
+    Inaccessible,
+}
+
+impl ToString for NvmePathState {
+    fn to_string(&self) -> String {
+        match self {
+            NvmePathState::Active => "Active",
+            NvmePathState::Standby => "Standby",
+            NvmePathState::Failed => "Failed",
+            NvmePathState::Inaccessible => "Inaccessible",
+        }
+        .to_string()
+    }
+}
+
+/// Status of a controller path.
+#[derive(Debug, Clone)]
+pub struct NvmePathInfo {
+    /// Transport address of the path.
+    pub address: String,
+    /// Transport service id (port) of the path.
+    pub port: String,
+    /// State of the path.
+    pub state: NvmePathState,
+    /// ANA state of the namespace, as last seen over this path.
+    pub ana_state: NvmeAnaState,
+}
+
+/// A path to the target subsystem.
+pub(crate) struct NvmePath {
+    trid: NvmeTransportId,
+    state: NvmePathState,
+    ana_state: NvmeAnaState,
+}
+
+impl std::fmt::Debug for NvmePath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NvmePath")
+            .field("traddr", &self.trid.traddr())
+            .field("svcid", &self.trid.svcid())
+            .field("state", &self.state)
+            .field("ana_state", &self.ana_state)
+            .finish()
+    }
+}
+
+impl NvmePath {
+    pub(crate) fn new(trid: NvmeTransportId, state: NvmePathState) -> Self {
+        Self {
+            trid,
+            state,
+            ana_state: NvmeAnaState::InvalidState,
+        }
+    }
+
+    /// Get the transport id of the path.
+    pub(crate) fn trid(&self) -> &NvmeTransportId {
+        &self.trid
+    }
+
+    /// Get the state of the path.
+    pub(crate) fn state(&self) -> NvmePathState {
+        self.state
+    }
+
+    /// Modify the state of the path.
+    pub(crate) fn set_state(&mut self, state: NvmePathState) {
+        self.state = state;
+    }
+
+    /// Modify the ANA state seen over the path.
+    pub(crate) fn set_ana_state(&mut self, ana_state: NvmeAnaState) {
+        self.ana_state = ana_state;
+    }
+
+    /// Get the status of the path.
+    pub(crate) fn info(&self) -> NvmePathInfo {
+        NvmePathInfo {
+            address: self.trid.traddr(),
+            port: self.trid.svcid(),
+            state: self.state,
+            ana_state: self.ana_state,
+        }
+    }
+}
+
+/// Check whether I/O may be served for a namespace in the given ANA state.
+/// The state is invalid when the target does not report ANA states at all.
+pub(crate) fn ana_accessible(ana_state: NvmeAnaState) -> bool {
+    !matches!(
+        ana_state,
+        NvmeAnaState::InaccessibleState | NvmeAnaState::PersistentLossState
+    )
+}
+
+/// Get the order in which paths are tried when failing over from the active
+/// path: standby paths first, then the ones which failed or were
+/// inaccessible before and the active path last, as its failure may well be
+/// transient.
+pub(crate) fn failover_order(
+    paths: &[NvmePath],
+    active: usize,
+) -> VecDeque<usize> {
+    let others = (1 .. paths.len()).map(|i| (active + i) % paths.len());
+    let (standby, failed): (Vec<usize>, Vec<usize>) =
+        others.partition(|i| paths[*i].state == NvmePathState::Standby);
+
+    standby
+        .into_iter()
+        .chain(failed)
+        .chain(std::iter::once(active))
+        .collect()
+}
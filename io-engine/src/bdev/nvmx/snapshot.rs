@@ -25,3 +25,31 @@ impl NvmeSnapshotMessageV1 {
 pub enum NvmeSnapshotMessage {
     V1(NvmeSnapshotMessageV1),
 }
+
+/// Vendor specific NVMe admin opcode to destroy a snapshot of a replica, the
+/// two lowest bits select a host to controller data transfer.
+pub const NVME_ADMIN_DESTROY_SNAPSHOT: u8 = 0xc5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NvmeSnapshotDestroyMessageV1 {
+    snapshot_uuid: String,
+}
+
+impl NvmeSnapshotDestroyMessageV1 {
+    /// Create a V1 snapshot destruction message.
+    pub fn new(snapshot_uuid: String) -> Self {
+        Self {
+            snapshot_uuid,
+        }
+    }
+
+    /// Get the UUID of the snapshot to destroy.
+    pub fn snapshot_uuid(&self) -> &str {
+        &self.snapshot_uuid
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum NvmeSnapshotDestroyMessage {
+    V1(NvmeSnapshotDestroyMessageV1),
+}
@@ -39,6 +39,7 @@ use crate::{
         nvmx::{
             controller,
             controller_inner::SpdkNvmeController,
+            path::{NvmePath, NvmePathState},
             NvmeControllerState,
             NVME_CONTROLLERS,
         },
@@ -113,13 +114,16 @@ pub struct NvmfDeviceTemplate {
     uuid: Option<uuid::Uuid>,
     /// The HostNqn to connect to the nvmf target with.
     hostnqn: Option<String>,
+    /// alternate paths (host, port) to the same subsystem, which the
+    /// controller fails over to when the current path is lost
+    paths: Vec<(String, u16)>,
 }
 
 impl TryFrom<&Url> for NvmfDeviceTemplate {
     type Error = BdevError;
 
     fn try_from(url: &Url) -> Result<Self, Self::Error> {
-        let host = url.host_str().ok_or_else(|| BdevError::InvalidUri {
+        let host = uri::host(url).ok_or_else(|| BdevError::InvalidUri {
             uri: url.to_string(),
             message: String::from("missing host"),
         })?;
@@ -178,16 +182,36 @@ impl TryFrom<&Url> for NvmfDeviceTemplate {
 
         let hostnqn = parameters.remove("hostnqn");
 
+        let port = url.port().unwrap_or(DEFAULT_NVMF_PORT);
+
+        // Alternate paths are given as a comma separated list of host[:port]
+        // values, the port of the URI being the default.
+        let mut paths: Vec<(String, u16)> = Vec::new();
+        if let Some(value) = parameters.remove("paths") {
+            for path in value.split(',').filter(|p| !p.is_empty()) {
+                let path = uri::host_port(path, port).ok_or_else(|| {
+                    BdevError::InvalidUri {
+                        uri: url.to_string(),
+                        message: format!("invalid path '{path}'"),
+                    }
+                })?;
+                if path != (host.clone(), port) && !paths.contains(&path) {
+                    paths.push(path);
+                }
+            }
+        }
+
         Ok(NvmfDeviceTemplate {
             name: url[url::Position::BeforeHost .. url::Position::AfterPath]
                 .to_string(),
             alias: url.to_string(),
-            host: host.to_string(),
-            port: url.port().unwrap_or(DEFAULT_NVMF_PORT),
+            host,
+            port,
             subnqn: segments[0].to_string(),
             prchk_flags,
             uuid,
             hostnqn,
+            paths,
         })
     }
 }
@@ -207,15 +231,29 @@ pub(crate) struct NvmeControllerContext<'probe> {
     receiver: Option<oneshot::Receiver<Result<(), Errno>>>,
     poller: Option<Poller<'probe>>,
     attached: bool,
+    paths: Vec<NvmePath>,
 }
 
 impl<'probe> NvmeControllerContext<'probe> {
     pub fn new(template: &NvmfDeviceTemplate) -> NvmeControllerContext {
-        let trid = controller::transport::Builder::new()
-            .with_subnqn(&template.subnqn)
-            .with_svcid(&template.port.to_string())
-            .with_traddr(&template.host)
-            .build();
+        let make_trid = |host: &str, port: u16| {
+            controller::transport::Builder::new()
+                .with_subnqn(&template.subnqn)
+                .with_svcid(&port.to_string())
+                .with_traddr(host)
+                .build()
+        };
+        let trid = make_trid(&template.host, template.port);
+
+        // The controller initially connects over the first path.
+        let paths = std::iter::once(NvmePath::new(
+            make_trid(&template.host, template.port),
+            NvmePathState::Active,
+        ))
+        .chain(template.paths.iter().map(|(host, port)| {
+            NvmePath::new(make_trid(host, *port), NvmePathState::Standby)
+        }))
+        .collect();
 
         // setting the HOSTNQN allows tracking who is connected to what. These
         // makes debugging connections easier in certain cases. If no
@@ -258,6 +296,7 @@ impl<'probe> NvmeControllerContext<'probe> {
             receiver: Some(receiver),
             poller: None,
             attached: false,
+            paths,
         }
     }
 
@@ -273,6 +312,11 @@ impl<'probe> NvmeControllerContext<'probe> {
     pub fn sender(&mut self) -> Sender<Result<(), Errno>> {
         self.sender.take().expect("no sender available")
     }
+
+    /// Take the paths to the target subsystem.
+    pub(crate) fn take_paths(&mut self) -> Vec<NvmePath> {
+        std::mem::take(&mut self.paths)
+    }
 }
 #[async_trait(?Send)]
 impl CreateDestroy for NvmfDeviceTemplate {
@@ -4,6 +4,7 @@ use spdk_rs::libspdk::spdk_nvme_cpl;
 enum NvmeStatusCodeType {
     Generic = 0x0,
     MediaError = 0x2,
+    Path = 0x3,
 }
 
 #[derive(Debug, PartialEq)]
@@ -16,6 +17,7 @@ pub enum NvmeMediaErrorStatusCode {
 #[derive(Debug, PartialEq)]
 enum NvmeGenericCommandStatusCode {
     Success = 0x0,
+    AbortedSubmissionQueueDeleted = 0x8,
 }
 
 #[derive(Debug, PartialEq)]
@@ -31,6 +33,7 @@ pub enum NvmeAerType {
 #[derive(Debug, PartialEq)]
 pub enum NvmeAerInfoNotice {
     AttrChanged = 0x0,
+    AnaChange = 0x3,
 }
 
 #[derive(Debug, PartialEq)]
@@ -80,6 +83,29 @@ pub(crate) fn nvme_cpl_succeeded(cpl: *const spdk_nvme_cpl) -> bool {
         && sc == NvmeGenericCommandStatusCode::Success as u16
 }
 
+/// Check if the request failed because of the path it was submitted on
+/// rather than because of the namespace, so that it may succeed when
+/// submitted on another path: any path related status (including ANA
+/// inaccessible, persistent loss and transition) or an abort due to the
+/// deletion of the submission queue, as seen when the connection is lost.
+#[inline]
+pub(crate) fn nvme_cpl_is_path_error(cpl: *const spdk_nvme_cpl) -> bool {
+    let sct;
+    let sc;
+
+    unsafe {
+        let cplr = &(*cpl);
+        sct = cplr.__bindgen_anon_1.status.sct();
+        sc = cplr.__bindgen_anon_1.status.sc();
+    }
+
+    sct == NvmeStatusCodeType::Path as u16
+        || (sct == NvmeStatusCodeType::Generic as u16
+            && sc
+                == NvmeGenericCommandStatusCode::AbortedSubmissionQueueDeleted
+                    as u16)
+}
+
 /* Bit set of attributes for DATASET MANAGEMENT commands. */
 #[allow(dead_code)]
 pub enum NvmeDsmAttribute {
@@ -0,0 +1,705 @@
+//! Sparse file devices: `aio` and `uring` devices over regular files, which
+//! io-engine creates or extends itself.
+//!
+//! An `aio` or `uring` URI with a `size` or a `create` parameter makes a
+//! sparse file device. Its backing file is created if missing and `create` is
+//! set, and extended to `size` if smaller, without allocating its blocks. The
+//! SPDK `aio` or `uring` bdev over the file is wrapped by a bdev of this
+//! module, which has the name of the file and handles unmaps and write zeroes
+//! itself, by punching holes into the file, so that pools on files stay thin.
+//!
+//! The file can later be grown with `sparse_file_resize`, which raises the
+//! `DeviceResized` event on the device.
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fmt::{Debug, Formatter},
+    fs::{File, OpenOptions},
+    marker::PhantomData,
+    os::unix::{fs::FileTypeExt, io::AsRawFd},
+    pin::Pin,
+    sync::Arc,
+};
+
+use byte_unit::Byte;
+use futures::channel::oneshot;
+use libc::c_void;
+use nix::{
+    errno::Errno,
+    fcntl::{fallocate, FallocateFlags},
+};
+use snafu::ResultExt;
+use spdk_rs::{
+    libspdk::{
+        bdev_aio_delete,
+        bdev_aio_rescan,
+        bdev_uring_rescan,
+        create_aio_bdev,
+        create_uring_bdev,
+        delete_uring_bdev,
+        spdk_bdev_io,
+        spdk_bdev_notify_blockcnt_change,
+    },
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevOps,
+    IoChannel,
+    IoDevice,
+    IoType,
+    WithModuleInit,
+};
+use url::Url;
+
+use crate::{
+    bdev::{
+        device_open,
+        stacked::{self, StackedDevice},
+        util::uri,
+    },
+    bdev_api::{self, BdevError},
+    core::{
+        runtime,
+        BlockDevice,
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        CoreError,
+        DeviceEventListener,
+        DeviceEventSink,
+        DeviceEventType,
+        IoCompletionStatus,
+        Reactors,
+        UntypedBdev,
+        VerboseError,
+    },
+    ffihelper::{cb_arg, done_errno_cb, ErrnoResult},
+};
+
+/// Name of the sparse file bdev module, which is the driver name of its
+/// bdevs.
+pub(crate) const SPARSE_FILE_MODULE_NAME: &str = "sparse_file";
+
+/// Product name of the sparse file bdevs.
+const SPARSE_FILE_PRODUCT_ID: &str = "Sparse File";
+
+/// SPDK bdev module doing the I/Os to the backing file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum SparseFileKind {
+    Aio,
+    Uring,
+}
+
+/// Sparse file parameters of an `aio` or `uring` URI.
+#[derive(Debug, Default, Clone)]
+pub(super) struct SparseFileParams {
+    /// Minimum size of the backing file, in bytes.
+    size: Option<u64>,
+    /// Creates the backing file if missing.
+    create: bool,
+}
+
+impl SparseFileParams {
+    /// Takes the sparse file parameters out of the parameters of a URI.
+    pub(super) fn from_parameters(
+        url: &Url,
+        parameters: &mut HashMap<String, String>,
+        blk_size: u32,
+    ) -> Result<Self, BdevError> {
+        let size = match parameters.remove("size") {
+            Some(value) => match Byte::from_str(&value) {
+                Ok(size) if size.get_bytes() % blk_size as u128 == 0 => {
+                    Some(size.get_bytes() as u64)
+                }
+                _ => {
+                    return Err(BdevError::InvalidUri {
+                        uri: url.to_string(),
+                        message: format!(
+                            "'size' must be a multiple of the block size, \
+                            '{value}' is given"
+                        ),
+                    })
+                }
+            },
+            None => None,
+        };
+
+        let create = match parameters.remove("create") {
+            Some(value) => uri::boolean(&value, true).context(
+                bdev_api::BoolParamParseFailed {
+                    uri: url.to_string(),
+                    parameter: String::from("create"),
+                    value: value.to_string(),
+                },
+            )?,
+            None => false,
+        };
+
+        if create && size.is_none() {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from("'create' requires 'size'"),
+            });
+        }
+
+        Ok(Self {
+            size,
+            create,
+        })
+    }
+
+    /// Checks if the URI describes a sparse file device.
+    pub(super) fn is_sparse(&self) -> bool {
+        self.size.is_some() || self.create
+    }
+
+    /// Creates the backing file if needed, and extends it to the requested
+    /// size if smaller.
+    fn prepare(&self, path: &str) -> Result<(), BdevError> {
+        let err = |e: std::io::Error| BdevError::BackingFileFailed {
+            source: Errno::from_i32(e.raw_os_error().unwrap_or(libc::EIO)),
+            path: path.to_string(),
+        };
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(self.create)
+            .open(path)
+            .map_err(err)?;
+        let meta = file.metadata().map_err(err)?;
+        if !meta.file_type().is_file() {
+            return Err(BdevError::BackingFileFailed {
+                source: if meta.file_type().is_block_device() {
+                    Errno::ENOTSUP
+                } else {
+                    Errno::EINVAL
+                },
+                path: path.to_string(),
+            });
+        }
+
+        if let Some(size) = self.size {
+            if meta.len() < size {
+                info!(
+                    "Extending backing file '{path}' from {} to {size} bytes",
+                    meta.len()
+                );
+                file.set_len(size).map_err(err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Per-core channel of a sparse file device.
+pub(crate) struct SparseFileChannel {
+    handle: Option<Box<dyn BlockDeviceHandle>>,
+}
+
+/// A sparse file device.
+pub(crate) struct SparseFileDevice<'f> {
+    name: String,
+    kind: SparseFileKind,
+    /// Name of the SPDK bdev over the file.
+    base: String,
+    base_desc: Option<Box<dyn BlockDeviceDescriptor>>,
+    /// Backing file, to punch holes into and to extend.
+    file: Arc<File>,
+    block_len: u64,
+    event_sink: Option<DeviceEventSink>,
+    _f: PhantomData<&'f ()>,
+}
+
+impl Debug for SparseFileDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sparse file '{}' ({:?})", self.name, self.kind)
+    }
+}
+
+impl<'f> SparseFileDevice<'f> {
+    /// Completes an I/O passed to the SPDK bdev.
+    fn completion(
+        _dev: &dyn BlockDevice,
+        status: IoCompletionStatus,
+        ctx: *mut c_void,
+    ) {
+        let bio = BdevIo::<SparseFileDevice<'f>>::legacy_from_ptr(
+            ctx as *mut spdk_bdev_io,
+        );
+        if status == IoCompletionStatus::Success {
+            bio.ok();
+        } else {
+            bio.fail();
+        }
+    }
+
+    /// Deallocates the blocks of an unmap or a write zeroes I/O, which then
+    /// read back as zeroes. The range is zeroed instead if the file system
+    /// does not support punching holes.
+    fn punch_hole(&self, bio: BdevIo<Self>) {
+        let file = self.file.clone();
+        let offset = (bio.offset() * self.block_len) as i64;
+        let len = (bio.num_blocks() * self.block_len) as i64;
+
+        // The I/O completes on the reactor of the current core, the one it
+        // was submitted on.
+        let ptr = bio.legacy_as_ptr();
+        Reactors::current().send_future(async move {
+            let res = runtime::spawn_blocking(move || {
+                let fd = file.as_raw_fd();
+                let keep = FallocateFlags::FALLOC_FL_KEEP_SIZE;
+                match fallocate(
+                    fd,
+                    FallocateFlags::FALLOC_FL_PUNCH_HOLE | keep,
+                    offset,
+                    len,
+                ) {
+                    Err(Errno::EOPNOTSUPP) => fallocate(
+                        fd,
+                        FallocateFlags::FALLOC_FL_ZERO_RANGE | keep,
+                        offset,
+                        len,
+                    ),
+                    r => r,
+                }
+            })
+            .await;
+
+            let bio = BdevIo::<SparseFileDevice>::legacy_from_ptr(ptr);
+            match res {
+                Ok(Ok(_)) => bio.ok(),
+                Ok(Err(e)) => {
+                    error!("Sparse file: fallocate failed: {e}");
+                    bio.fail();
+                }
+                Err(e) => {
+                    error!("Sparse file: fallocate task failed: {e}");
+                    bio.fail();
+                }
+            }
+        });
+    }
+
+    /// Updates the number of blocks of the device after its SPDK bdev has
+    /// been resized, which raises the resize event on the device.
+    fn sync_block_count(&self) {
+        let num_blocks = match self.base_desc.as_ref() {
+            Some(d) => d.get_device().num_blocks(),
+            None => return,
+        };
+        let mut bdev = match UntypedBdev::lookup_by_name(&self.name) {
+            Some(bdev) => bdev,
+            None => return,
+        };
+        if bdev.num_blocks() == num_blocks {
+            return;
+        }
+
+        info!(
+            "{self:?}: resizing from {} to {num_blocks} blocks",
+            bdev.num_blocks()
+        );
+        let rc = unsafe {
+            spdk_bdev_notify_blockcnt_change(
+                bdev.unsafe_inner_mut_ptr(),
+                num_blocks,
+            )
+        };
+        if rc != 0 {
+            error!("{self:?}: failed to resize: {}", Errno::from_i32(rc.abs()));
+        }
+    }
+}
+
+impl StackedDevice for SparseFileDevice<'_> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl DeviceEventListener for SparseFileDevice<'_> {
+    fn handle_device_event(&self, evt: DeviceEventType, dev_name: &str) {
+        if dev_name != self.base {
+            return;
+        }
+
+        if evt == DeviceEventType::DeviceResized {
+            self.sync_block_count();
+        } else if stacked::is_removal(evt) {
+            let name = self.name.clone();
+            stacked::destroy_removed(self, dev_name, async move {
+                sparse_file_destroy(&name).await
+            });
+        }
+    }
+
+    fn get_listener_name(&self) -> String {
+        self.name.clone()
+    }
+}
+
+impl<'f> IoDevice for SparseFileDevice<'f> {
+    type ChannelData = SparseFileChannel;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        SparseFileChannel {
+            handle: stacked::base_io_handle(&*self, self.base_desc.as_deref()),
+        }
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'f> BdevOps for SparseFileDevice<'f> {
+    type ChannelData = SparseFileChannel;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        stacked::destruct(self, |s| {
+            s.event_sink = None;
+            s.base_desc = None;
+        });
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        bio: BdevIo<Self>,
+    ) {
+        if matches!(bio.io_type(), IoType::Unmap | IoType::WriteZeros) {
+            self.punch_hole(bio);
+            return;
+        }
+
+        let hdl = match chan.channel_data().handle.as_deref() {
+            Some(hdl) => hdl,
+            None => {
+                bio.fail();
+                return;
+            }
+        };
+
+        let ctx = bio.legacy_as_ptr().cast();
+        let res = match bio.io_type() {
+            IoType::Read => hdl.readv_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Write => hdl.writev_blocks(
+                bio.iovs(),
+                bio.iov_count(),
+                bio.offset(),
+                bio.num_blocks(),
+                Self::completion,
+                ctx,
+            ),
+            IoType::Flush => hdl.flush_io(Self::completion, ctx),
+            IoType::Reset => hdl.reset(Self::completion, ctx),
+            _ => Err(CoreError::NotSupported {
+                source: Errno::EOPNOTSUPP,
+            }),
+        };
+
+        if let Err(e) = res {
+            error!("{self:?}: I/O submission failed: {e}", e = e.verbose());
+            bio.fail();
+        }
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        match io_type {
+            IoType::Read
+            | IoType::Write
+            | IoType::Unmap
+            | IoType::WriteZeros => true,
+            IoType::Flush | IoType::Reset => self
+                .base_desc
+                .as_ref()
+                .map_or(false, |d| d.get_device().io_type_supported(io_type)),
+            _ => false,
+        }
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// Sparse file bdev module.
+pub(crate) struct SparseFileModule {}
+
+impl SparseFileModule {
+    /// Returns the sparse file bdev module instance.
+    /// Panics if the sparse file module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(SPARSE_FILE_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for SparseFileModule {
+    fn module_init() -> i32 {
+        info!("Initializing Sparse File Module");
+        0
+    }
+}
+
+impl BdevModuleBuild for SparseFileModule {}
+
+pub fn register() {
+    SparseFileModule::builder(SPARSE_FILE_MODULE_NAME)
+        .with_module_init()
+        .register();
+}
+
+/// Name of the SPDK bdev over the backing file of a sparse file device.
+fn base_name(name: &str) -> String {
+    format!("{name}:base")
+}
+
+/// Creates the SPDK bdev over a backing file.
+fn base_create(
+    kind: SparseFileKind,
+    name: &str,
+    path: &str,
+    blk_size: u32,
+) -> Result<(), BdevError> {
+    let cname = CString::new(name).unwrap();
+    let cpath = CString::new(path).unwrap();
+
+    let errno = match kind {
+        SparseFileKind::Aio => unsafe {
+            create_aio_bdev(cname.as_ptr(), cpath.as_ptr(), blk_size, false)
+        },
+        SparseFileKind::Uring => {
+            match UntypedBdev::checked_from_ptr(unsafe {
+                create_uring_bdev(cname.as_ptr(), cpath.as_ptr(), blk_size)
+            }) {
+                Some(_) => 0,
+                None => -libc::ENODEV,
+            }
+        }
+    };
+
+    if errno != 0 {
+        return Err(BdevError::CreateBdevFailed {
+            source: Errno::from_i32(errno.abs()),
+            name: name.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Destroys the SPDK bdev over a backing file.
+async fn base_destroy(
+    kind: SparseFileKind,
+    name: &str,
+) -> Result<(), BdevError> {
+    let bdev = match UntypedBdev::lookup_by_name(name) {
+        Some(bdev) => bdev,
+        None => {
+            return Err(BdevError::BdevNotFound {
+                name: name.to_string(),
+            })
+        }
+    };
+
+    let (sender, receiver) = oneshot::channel::<ErrnoResult<()>>();
+    unsafe {
+        let bdev_name = (*bdev.unsafe_inner_ptr()).name;
+        match kind {
+            SparseFileKind::Aio => {
+                bdev_aio_delete(bdev_name, Some(done_errno_cb), cb_arg(sender))
+            }
+            SparseFileKind::Uring => delete_uring_bdev(
+                bdev_name,
+                Some(done_errno_cb),
+                cb_arg(sender),
+            ),
+        }
+    }
+    receiver
+        .await
+        .context(bdev_api::BdevCommandCanceled {
+            name: name.to_string(),
+        })?
+        .context(bdev_api::DestroyBdevFailed {
+            name: name.to_string(),
+        })
+}
+
+/// Creates a sparse file device over the file `name`.
+pub(super) async fn sparse_file_create(
+    kind: SparseFileKind,
+    name: &str,
+    blk_size: u32,
+    params: &SparseFileParams,
+) -> Result<(), BdevError> {
+    params.prepare(name)?;
+
+    let base = base_name(name);
+    base_create(kind, &base, name, blk_size)?;
+
+    let res = sparse_file_register(kind, name, &base);
+    if res.is_err() {
+        if let Err(e) = base_destroy(kind, &base).await {
+            error!(
+                "Sparse file '{name}': failed to destroy '{base}': {e}",
+                e = e.verbose()
+            );
+        }
+    }
+    res
+}
+
+/// Registers a sparse file device over the SPDK bdev of its file.
+fn sparse_file_register(
+    kind: SparseFileKind,
+    name: &str,
+    base: &str,
+) -> Result<(), BdevError> {
+    let file = OpenOptions::new().write(true).open(name).map_err(|e| {
+        BdevError::BackingFileFailed {
+            source: Errno::from_i32(e.raw_os_error().unwrap_or(libc::EIO)),
+            path: name.to_string(),
+        }
+    })?;
+
+    let desc = device_open(base, true).map_err(|e| {
+        error!(
+            "Sparse file '{name}': failed to open '{base}': {e}",
+            e = e.verbose()
+        );
+        BdevError::BdevNotFound {
+            name: base.to_string(),
+        }
+    })?;
+    let base_dev = desc.get_device();
+
+    let dev = SparseFileDevice {
+        name: name.to_string(),
+        kind,
+        base: base.to_string(),
+        base_desc: Some(desc),
+        file: Arc::new(file),
+        block_len: base_dev.block_len(),
+        event_sink: None,
+        _f: Default::default(),
+    };
+
+    let mut bdev = SparseFileModule::current()
+        .bdev_builder()
+        .with_name(name)
+        .with_product_name(SPARSE_FILE_PRODUCT_ID)
+        .with_block_length(base_dev.block_len() as u32)
+        .with_block_count(base_dev.num_blocks())
+        .with_required_alignment(9)
+        .with_data(dev)
+        .build();
+
+    unsafe {
+        let d = bdev.data_mut().get_unchecked_mut();
+        d.event_sink = Some(DeviceEventSink::new(bdev.data()));
+    }
+    if let Some(sink) = bdev.data().event_sink.clone() {
+        if let Err(e) = base_dev.add_event_listener(sink) {
+            warn!(
+                "Sparse file '{name}': failed to listen to events of \
+                '{base}': {e}",
+                e = e.verbose()
+            );
+        }
+    }
+
+    bdev.data().register_io_device(Some(name));
+
+    if let Err(err) = bdev.register_bdev() {
+        error!(
+            "Sparse file '{name}': bdev registration failed: {}",
+            err.verbose()
+        );
+        return Err(BdevError::CreateBdevFailed {
+            source: err,
+            name: name.to_string(),
+        });
+    }
+
+    info!("{:?}: created", bdev.data());
+    Ok(())
+}
+
+/// Destroys a sparse file device and the SPDK bdev of its file.
+pub(super) async fn sparse_file_destroy(name: &str) -> Result<(), BdevError> {
+    let bdev =
+        stacked::lookup::<SparseFileDevice>(SparseFileModule::current(), name)?;
+    let kind = bdev.data().kind;
+    let base = bdev.data().base.clone();
+
+    stacked::unregister(bdev).await?;
+    base_destroy(kind, &base).await
+}
+
+/// Checks if a bdev is a sparse file device.
+pub(super) fn is_sparse_file(name: &str) -> bool {
+    UntypedBdev::lookup_by_name(name)
+        .map_or(false, |b| b.driver() == SPARSE_FILE_MODULE_NAME)
+}
+
+/// Grows the backing file of a sparse file device to `size` bytes, and
+/// resizes the device, which raises its `DeviceResized` event.
+pub fn sparse_file_resize(name: &str, size: u64) -> Result<(), BdevError> {
+    let bdev =
+        stacked::lookup::<SparseFileDevice>(SparseFileModule::current(), name)
+            .map_err(|e| match UntypedBdev::lookup_by_name(name) {
+                Some(_) => BdevError::ResizeBdevInvalid {
+                    name: name.to_string(),
+                    message: String::from("not a sparse file device"),
+                },
+                None => e,
+            })?;
+    let dev = bdev.data();
+
+    let cur_size = bdev.num_blocks() * dev.block_len;
+    if size % dev.block_len != 0 || size < cur_size {
+        return Err(BdevError::ResizeBdevInvalid {
+            name: name.to_string(),
+            message: format!(
+                "the size must be a multiple of the block size and not \
+                below {cur_size} bytes, {size} is given"
+            ),
+        });
+    }
+    if size == cur_size {
+        return Ok(());
+    }
+
+    dev.file
+        .set_len(size)
+        .map_err(|e| BdevError::BackingFileFailed {
+            source: Errno::from_i32(e.raw_os_error().unwrap_or(libc::EIO)),
+            path: name.to_string(),
+        })?;
+
+    let cname = CString::new(dev.base.as_str()).unwrap();
+    let rc = match dev.kind {
+        SparseFileKind::Aio => unsafe { bdev_aio_rescan(cname.as_ptr()) },
+        SparseFileKind::Uring => unsafe { bdev_uring_rescan(cname.as_ptr()) },
+    };
+    if rc != 0 {
+        return Err(BdevError::ResizeBdevFailed {
+            source: Errno::from_i32(rc.abs()),
+            name: name.to_string(),
+        });
+    }
+
+    // The resize event of the SPDK bdev may be delivered later: resize the
+    // device now, so that callers see the new size.
+    dev.sync_block_count();
+    Ok(())
+}
@@ -0,0 +1,170 @@
+//! Helpers shared by the bdevs stacked over other block devices, which open
+//! their base devices, pass I/Os to them, and are destroyed once one of
+//! them is removed.
+use std::{collections::HashMap, fmt::Debug, future::Future, pin::Pin};
+
+use nix::errno::Errno;
+use snafu::ResultExt;
+use spdk_rs::{BdevModule, BdevModuleIter, BdevOps, IoDevice};
+use url::Url;
+
+use crate::{
+    bdev::util::uri,
+    bdev_api::{self, BdevError},
+    core::{
+        BlockDeviceDescriptor,
+        BlockDeviceHandle,
+        DeviceEventType,
+        Reactors,
+        VerboseError,
+    },
+};
+
+/// A bdev stacked over other block devices.
+pub(crate) trait StackedDevice: BdevOps + Debug {
+    /// Returns the name of the stacked device.
+    fn name(&self) -> &str;
+}
+
+/// Parameters common to the URIs of the stacked devices:
+/// ```ignore
+///     <scheme>:///<base_bdev>[?name=<name>][&uuid=<uuid>]
+/// ```
+pub(crate) struct StackedUri {
+    /// Name of the base device.
+    pub(crate) base: String,
+    /// Name of the stacked device, `<scheme>-<base_bdev>` by default.
+    pub(crate) name: String,
+    /// UUID of the stacked device.
+    pub(crate) uuid: Option<uuid::Uuid>,
+    /// The parameters specific to the device.
+    pub(crate) parameters: HashMap<String, String>,
+}
+
+impl StackedUri {
+    /// Parses the common parameters of the URI of a stacked device.
+    pub(crate) fn parse(url: &Url) -> Result<Self, BdevError> {
+        let segments = uri::segments(url);
+        if segments.is_empty() {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from("no path segments"),
+            });
+        }
+
+        let mut parameters: HashMap<String, String> =
+            url.query_pairs().into_owned().collect();
+
+        let base = segments.join("/");
+        let name = parameters
+            .remove("name")
+            .unwrap_or_else(|| format!("{}-{base}", url.scheme()));
+        if name == base {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from("the name is the base device name"),
+            });
+        }
+
+        let uuid = uri::uuid(parameters.remove("uuid")).context(
+            bdev_api::UuidParamParseFailed {
+                uri: url.to_string(),
+            },
+        )?;
+
+        Ok(Self {
+            base,
+            name,
+            uuid,
+            parameters,
+        })
+    }
+}
+
+/// Checks if an event tells that a device was removed.
+pub(crate) fn is_removal(evt: DeviceEventType) -> bool {
+    matches!(
+        evt,
+        DeviceEventType::DeviceRemoved | DeviceEventType::LoopbackRemoved
+    )
+}
+
+/// Destroys a stacked device on the master reactor, with `destroy`, as its
+/// base device `base` was removed.
+pub(crate) fn destroy_removed(
+    dev: &impl Debug,
+    base: &str,
+    destroy: impl Future<Output = Result<(), BdevError>> + 'static,
+) {
+    warn!("{dev:?}: base device '{base}' removed, destroying");
+
+    let dev = format!("{dev:?}");
+    Reactors::master().send_future(async move {
+        if let Err(e) = destroy.await {
+            error!("{dev}: failed to destroy: {e}", e = e.verbose());
+        }
+    });
+}
+
+/// Gets a handle to a base device for a new I/O channel of a stacked
+/// device. The I/Os of a channel without a handle fail.
+pub(crate) fn base_io_handle(
+    dev: &impl Debug,
+    desc: Option<&dyn BlockDeviceDescriptor>,
+) -> Option<Box<dyn BlockDeviceHandle>> {
+    match desc.map(|d| d.get_io_handle()) {
+        Some(Ok(h)) => Some(h),
+        Some(Err(e)) => {
+            error!("{dev:?}: failed to get I/O handle: {e}", e = e.verbose());
+            None
+        }
+        None => None,
+    }
+}
+
+/// Destructs a stacked device: unregisters its I/O device, and then lets
+/// `close` drop its event sink and the descriptors of its base devices.
+pub(crate) fn destruct<T>(mut dev: Pin<&mut T>, close: impl FnOnce(&mut T))
+where
+    T: IoDevice + Debug,
+{
+    info!("{dev:?}: destructing");
+
+    dev.as_mut().unregister_io_device();
+    close(unsafe { dev.get_unchecked_mut() });
+}
+
+/// Looks up a stacked device of a bdev module by its name.
+pub(crate) fn lookup<T: StackedDevice>(
+    module: BdevModule,
+    name: &str,
+) -> Result<spdk_rs::Bdev<T>, BdevError> {
+    let mut iter: BdevModuleIter<T> = module.iter_bdevs();
+    iter.find(|b| b.data().name() == name).ok_or_else(|| {
+        BdevError::BdevNotFound {
+            name: name.to_string(),
+        }
+    })
+}
+
+/// Unregisters a stacked device.
+pub(crate) async fn unregister<T: StackedDevice>(
+    mut bdev: spdk_rs::Bdev<T>,
+) -> Result<(), BdevError> {
+    let name = bdev.data().name().to_string();
+    bdev.unregister_bdev_async().await.map_err(|_| {
+        BdevError::DestroyBdevFailed {
+            source: Errno::EIO,
+            name,
+        }
+    })
+}
+
+/// Looks up a stacked device of a bdev module by its name, and unregisters
+/// it.
+pub(crate) async fn destroy<T: StackedDevice>(
+    module: BdevModule,
+    name: &str,
+) -> Result<(), BdevError> {
+    unregister(lookup::<T>(module, name)?).await
+}
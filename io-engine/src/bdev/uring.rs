@@ -8,7 +8,25 @@ use url::Url;
 use spdk_rs::libspdk::{create_uring_bdev, delete_uring_bdev};
 
 use crate::{
-    bdev::{dev::reject_unknown_parameters, util::uri, CreateDestroy, GetName},
+    bdev::{
+        dev::reject_unknown_parameters,
+        sparse_file::{
+            is_sparse_file,
+            sparse_file_create,
+            sparse_file_destroy,
+            SparseFileKind,
+            SparseFileParams,
+        },
+        uring_ng::{
+            is_uring_ng,
+            uring_ng_create,
+            uring_ng_destroy,
+            UringOptions,
+        },
+        util::uri,
+        CreateDestroy,
+        GetName,
+    },
     bdev_api::{self, BdevError},
     core::UntypedBdev,
     ffihelper::{cb_arg, done_errno_cb, ErrnoResult},
@@ -20,6 +38,8 @@ pub(super) struct Uring {
     alias: String,
     blk_size: u32,
     uuid: Option<uuid::Uuid>,
+    sparse: SparseFileParams,
+    options: Option<UringOptions>,
 }
 
 /// Convert a URI to an Uring "object"
@@ -56,6 +76,20 @@ impl TryFrom<&Url> for Uring {
             },
         )?;
 
+        let sparse =
+            SparseFileParams::from_parameters(url, &mut parameters, blk_size)?;
+
+        let options = UringOptions::from_parameters(url, &mut parameters)?;
+        if options.is_some() && sparse.is_sparse() {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: String::from(
+                    "io_uring options cannot be combined with 'size' or \
+                    'create'",
+                ),
+            });
+        }
+
         reject_unknown_parameters(url, parameters)?;
 
         Ok(Uring {
@@ -63,6 +97,8 @@ impl TryFrom<&Url> for Uring {
             alias: url.to_string(),
             blk_size,
             uuid,
+            sparse,
+            options,
         })
     }
 }
@@ -85,11 +121,27 @@ impl CreateDestroy for Uring {
             });
         }
 
-        let cname = CString::new(self.get_name()).unwrap();
+        let bdev = if self.sparse.is_sparse() {
+            sparse_file_create(
+                SparseFileKind::Uring,
+                &self.name,
+                self.blk_size,
+                &self.sparse,
+            )
+            .await?;
+            UntypedBdev::lookup_by_name(&self.name)
+        } else if let Some(options) = &self.options {
+            uring_ng_create(&self.name, self.blk_size, options)?;
+            UntypedBdev::lookup_by_name(&self.name)
+        } else {
+            let cname = CString::new(self.get_name()).unwrap();
+
+            UntypedBdev::checked_from_ptr(unsafe {
+                create_uring_bdev(cname.as_ptr(), cname.as_ptr(), self.blk_size)
+            })
+        };
 
-        if let Some(mut bdev) = UntypedBdev::checked_from_ptr(unsafe {
-            create_uring_bdev(cname.as_ptr(), cname.as_ptr(), self.blk_size)
-        }) {
+        if let Some(mut bdev) = bdev {
             if let Some(uuid) = self.uuid {
                 unsafe { bdev.set_raw_uuid(uuid.into()) };
             }
@@ -112,6 +164,20 @@ impl CreateDestroy for Uring {
 
     /// Destroy the given uring bdev
     async fn destroy(self: Box<Self>) -> Result<(), Self::Error> {
+        if is_sparse_file(&self.name) {
+            if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+                bdev.remove_alias(&self.alias);
+            }
+            return sparse_file_destroy(&self.name).await;
+        }
+
+        if is_uring_ng(&self.name) {
+            if let Some(mut bdev) = UntypedBdev::lookup_by_name(&self.name) {
+                bdev.remove_alias(&self.alias);
+            }
+            return uring_ng_destroy(&self.name).await;
+        }
+
         match UntypedBdev::lookup_by_name(&self.name) {
             Some(mut bdev) => {
                 bdev.remove_alias(&self.alias);
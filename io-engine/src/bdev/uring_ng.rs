@@ -0,0 +1,710 @@
+//! A bdev doing its I/Os with io_uring, with the optional modes the SPDK
+//! uring bdev does not offer: submission queue polling by a kernel thread,
+//! a registered file descriptor and registered DMA buffers, and a queue depth
+//! set per device.
+//!
+//! A `uring` URI with any of the `sqpoll`, `fixed_files`, `fixed_buffers` or
+//! `queue_depth` parameters creates a device of this module, other `uring`
+//! URIs create an SPDK uring bdev:
+//! ```ignore
+//!     uring:///dev/sdb?sqpoll=true&fixed_files=true&fixed_buffers=true
+//! ```
+//!
+//! Each I/O channel has its own ring, with its own submission queue polling
+//! thread in the kernel if enabled. The requested modes the kernel refuses
+//! are disabled when the device is created, and the accepted modes are
+//! logged and returned by `uring_bdev_modes`.
+//!
+//! The registered buffers are the memory regions registered with SPDK, which
+//! DMA buffers are allocated from. Reads and writes of a single buffer within
+//! these regions use the fixed buffer operations, all other reads and writes
+//! use vectored operations.
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    fmt::{Debug, Formatter},
+    fs::{File, OpenOptions},
+    io::{Seek, SeekFrom},
+    marker::PhantomData,
+    os::unix::{
+        fs::OpenOptionsExt,
+        io::{AsRawFd, RawFd},
+    },
+    pin::Pin,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use io_uring::{opcode, squeue, types, IoUring};
+use libc::c_void;
+use nix::errno::Errno;
+use once_cell::sync::{Lazy, OnceCell};
+use parking_lot::Mutex;
+use snafu::ResultExt;
+use spdk_rs::{
+    libspdk::{
+        spdk_bdev_io,
+        spdk_mem_map,
+        spdk_mem_map_alloc,
+        spdk_mem_map_notify_action,
+        spdk_mem_map_ops,
+        SPDK_MEM_MAP_NOTIFY_REGISTER,
+        SPDK_MEM_MAP_NOTIFY_UNREGISTER,
+    },
+    BdevIo,
+    BdevModule,
+    BdevModuleBuild,
+    BdevModuleIter,
+    BdevOps,
+    IoChannel,
+    IoDevice,
+    IoType,
+    Poller,
+    PollerBuilder,
+    WithModuleInit,
+};
+use url::Url;
+
+use crate::{
+    bdev::util::{uri, uring::UringModes},
+    bdev_api::{self, BdevError},
+    core::UntypedBdev,
+};
+
+/// Name of the io_uring bdev module, which is the driver name of its bdevs.
+/// SPDK has its own "uring" module already.
+pub(crate) const URING_NG_MODULE_NAME: &str = "uring_ng";
+
+/// Product name of the io_uring bdevs.
+const URING_NG_PRODUCT_ID: &str = "io_uring Device";
+
+/// Default queue depth of a ring, as SPDK_URING_QUEUE_DEPTH.
+const DEFAULT_QUEUE_DEPTH: u32 = 512;
+
+/// Default idle time of a submission queue polling thread, before it sleeps.
+const DEFAULT_SQPOLL_IDLE_MS: u32 = 1000;
+
+/// Maximum size of a registered buffer.
+const MAX_FIXED_BUFFER_LEN: usize = 1 << 30;
+
+/// io_uring options of a `uring` URI.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct UringOptions {
+    /// Requested modes.
+    modes: UringModes,
+    /// Idle time of the submission queue polling thread, in milliseconds.
+    sqpoll_idle_ms: u32,
+    /// Number of entries of the submission queue of a ring, which is also
+    /// the maximum number of I/Os in flight on a channel.
+    queue_depth: u32,
+}
+
+impl UringOptions {
+    /// Takes the io_uring options out of the parameters of a URI. Returns
+    /// `None` if the URI has none.
+    pub(super) fn from_parameters(
+        url: &Url,
+        parameters: &mut HashMap<String, String>,
+    ) -> Result<Option<Self>, BdevError> {
+        let mut given = false;
+
+        let mut boolean = |param: &str| -> Result<bool, BdevError> {
+            match parameters.remove(param) {
+                Some(value) => {
+                    given = true;
+                    uri::boolean(&value, true).context(
+                        bdev_api::BoolParamParseFailed {
+                            uri: url.to_string(),
+                            parameter: param.to_string(),
+                            value: value.to_string(),
+                        },
+                    )
+                }
+                None => Ok(false),
+            }
+        };
+        let modes = UringModes {
+            sqpoll: boolean("sqpoll")?,
+            fixed_files: boolean("fixed_files")?,
+            fixed_buffers: boolean("fixed_buffers")?,
+        };
+
+        let mut int = |param: &str, default: u32| -> Result<u32, BdevError> {
+            match parameters.remove(param) {
+                Some(value) => {
+                    given = true;
+                    value.parse().context(bdev_api::IntParamParseFailed {
+                        uri: url.to_string(),
+                        parameter: param.to_string(),
+                        value: value.clone(),
+                    })
+                }
+                None => Ok(default),
+            }
+        };
+        let sqpoll_idle_ms = int("sqpoll_idle_ms", DEFAULT_SQPOLL_IDLE_MS)?;
+        let queue_depth = int("queue_depth", DEFAULT_QUEUE_DEPTH)?;
+
+        if !given {
+            return Ok(None);
+        }
+
+        if queue_depth == 0 || !queue_depth.is_power_of_two() {
+            return Err(BdevError::InvalidUri {
+                uri: url.to_string(),
+                message: format!(
+                    "'queue_depth' must be a power of two, {queue_depth} is \
+                    given"
+                ),
+            });
+        }
+
+        Ok(Some(Self {
+            modes,
+            sqpoll_idle_ms,
+            queue_depth,
+        }))
+    }
+}
+
+/// Memory regions registered with SPDK, as sorted start and end addresses.
+static DMA_REGIONS: Lazy<Mutex<Vec<(usize, usize)>>> =
+    Lazy::new(Default::default);
+
+/// Number of memory regions unregistered from SPDK. Rings stop using their
+/// registered buffers once a region is unregistered, as its addresses may
+/// later map other memory.
+static DMA_UNREGISTERED: AtomicU64 = AtomicU64::new(0);
+
+/// SPDK memory map which notifies the memory regions registered with SPDK.
+static DMA_MEM_MAP: OnceCell<usize> = OnceCell::new();
+
+/// Keeps track of the memory regions registered with SPDK.
+extern "C" fn dma_region_notify(
+    _cb_ctx: *mut c_void,
+    _map: *mut spdk_mem_map,
+    action: spdk_mem_map_notify_action,
+    vaddr: *mut c_void,
+    size: usize,
+) -> i32 {
+    let (start, end) = (vaddr as usize, vaddr as usize + size);
+    let mut regions = DMA_REGIONS.lock();
+
+    match action {
+        SPDK_MEM_MAP_NOTIFY_REGISTER => {
+            let idx = regions.partition_point(|r| r.0 < start);
+            regions.insert(idx, (start, end));
+        }
+        SPDK_MEM_MAP_NOTIFY_UNREGISTER => {
+            DMA_UNREGISTERED.fetch_add(1, Ordering::SeqCst);
+            let mut kept = Vec::with_capacity(regions.len());
+            for &(s, e) in regions.iter() {
+                if e <= start || s >= end {
+                    kept.push((s, e));
+                    continue;
+                }
+                if s < start {
+                    kept.push((s, start));
+                }
+                if e > end {
+                    kept.push((end, e));
+                }
+            }
+            *regions = kept;
+        }
+        _ => {}
+    }
+    0
+}
+
+static DMA_MEM_MAP_OPS: spdk_mem_map_ops = spdk_mem_map_ops {
+    notify_cb: Some(dma_region_notify),
+    are_contiguous: None,
+};
+
+/// Returns the memory regions registered with SPDK, merged when contiguous
+/// and split into buffers the kernel can register.
+fn dma_buffers() -> Vec<(usize, usize)> {
+    DMA_MEM_MAP.get_or_init(|| unsafe {
+        spdk_mem_map_alloc(0, &DMA_MEM_MAP_OPS, std::ptr::null_mut()) as usize
+    });
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    for &(s, e) in DMA_REGIONS.lock().iter() {
+        match merged.last_mut() {
+            Some(last) if last.1 == s => last.1 = e,
+            _ => merged.push((s, e)),
+        }
+    }
+
+    merged
+        .into_iter()
+        .flat_map(|(s, e)| {
+            (s .. e)
+                .step_by(MAX_FIXED_BUFFER_LEN)
+                .map(move |b| (b, e.min(b + MAX_FIXED_BUFFER_LEN)))
+        })
+        .collect()
+}
+
+/// Builds a submission queue entry on the registered or the plain file
+/// descriptor of a ring.
+macro_rules! sqe {
+    ($ring:expr, |$fd:ident| $op:expr) => {
+        if $ring.fixed_files {
+            let $fd = types::Fixed(0);
+            $op
+        } else {
+            let $fd = types::Fd($ring.fd);
+            $op
+        }
+    };
+}
+
+/// An io_uring instance of a channel.
+struct UringRing {
+    ring: IoUring,
+    fd: RawFd,
+    block_len: u64,
+    queue_depth: u32,
+    fixed_files: bool,
+    /// Registered buffers, as sorted start and end addresses.
+    buffers: Vec<(usize, usize)>,
+    /// Value of `DMA_UNREGISTERED` when the buffers were registered.
+    buffers_gen: u64,
+    /// I/Os not yet submitted to the ring.
+    pending: VecDeque<*mut spdk_bdev_io>,
+    inflight: u32,
+}
+
+impl UringRing {
+    /// Sets up a ring with the requested modes, and returns it with the
+    /// modes the kernel accepted.
+    fn new(
+        file: &File,
+        block_len: u64,
+        opts: &UringOptions,
+    ) -> std::io::Result<(Self, UringModes)> {
+        let mut modes = opts.modes;
+
+        let ring = if modes.sqpoll {
+            match IoUring::builder()
+                .setup_sqpoll(opts.sqpoll_idle_ms)
+                .build(opts.queue_depth)
+            {
+                Ok(ring) => Some(ring),
+                Err(e) => {
+                    warn!("io_uring: submission queue polling refused: {e}");
+                    modes.sqpoll = false;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let ring = match ring {
+            Some(ring) => ring,
+            None => IoUring::new(opts.queue_depth)?,
+        };
+
+        let fd = file.as_raw_fd();
+        if modes.fixed_files {
+            if let Err(e) = ring.submitter().register_files(&[fd]) {
+                warn!("io_uring: registering the file refused: {e}");
+                modes.fixed_files = false;
+            }
+        }
+
+        let buffers_gen = DMA_UNREGISTERED.load(Ordering::SeqCst);
+        let mut buffers = Vec::new();
+        if modes.fixed_buffers {
+            buffers = dma_buffers();
+            let iovs = buffers
+                .iter()
+                .map(|&(s, e)| libc::iovec {
+                    iov_base: s as *mut c_void,
+                    iov_len: e - s,
+                })
+                .collect::<Vec<_>>();
+            if let Err(e) = unsafe { ring.submitter().register_buffers(&iovs) }
+            {
+                warn!("io_uring: registering DMA buffers refused: {e}");
+                modes.fixed_buffers = false;
+                buffers.clear();
+            }
+        }
+
+        Ok((
+            Self {
+                ring,
+                fd,
+                block_len,
+                queue_depth: opts.queue_depth,
+                fixed_files: modes.fixed_files,
+                buffers,
+                buffers_gen,
+                pending: VecDeque::new(),
+                inflight: 0,
+            },
+            modes,
+        ))
+    }
+
+    /// Returns the index of the registered buffer holding a whole I/O
+    /// buffer.
+    fn buffer_index(&self, base: usize, len: usize) -> Option<u16> {
+        if self.buffers.is_empty()
+            || DMA_UNREGISTERED.load(Ordering::Relaxed) != self.buffers_gen
+        {
+            return None;
+        }
+
+        let idx = self.buffers.partition_point(|b| b.0 <= base);
+        let (_, end) = *self.buffers.get(idx.checked_sub(1)?)?;
+        if base + len <= end {
+            u16::try_from(idx - 1).ok()
+        } else {
+            None
+        }
+    }
+
+    /// Builds the submission queue entry of an I/O.
+    fn entry(&self, ptr: *mut spdk_bdev_io) -> squeue::Entry {
+        let bio = BdevIo::<UringDevice>::legacy_from_ptr(ptr);
+        let offset = (bio.offset() * self.block_len) as _;
+        let iovs = bio.iovs() as *const libc::iovec;
+        let iovcnt = bio.iov_count() as u32;
+
+        let fixed = if iovcnt == 1 {
+            let iov = unsafe { &*iovs };
+            self.buffer_index(iov.iov_base as usize, iov.iov_len)
+                .map(|idx| (iov.iov_base as *mut u8, iov.iov_len as u32, idx))
+        } else {
+            None
+        };
+
+        let entry = match (bio.io_type(), fixed) {
+            (IoType::Read, Some((buf, len, idx))) => sqe!(self, |fd| {
+                opcode::ReadFixed::new(fd, buf, len, idx)
+                    .offset(offset)
+                    .build()
+            }),
+            (IoType::Read, None) => sqe!(self, |fd| {
+                opcode::Readv::new(fd, iovs, iovcnt).offset(offset).build()
+            }),
+            (IoType::Write, Some((buf, len, idx))) => sqe!(self, |fd| {
+                opcode::WriteFixed::new(fd, buf as _, len, idx)
+                    .offset(offset)
+                    .build()
+            }),
+            (IoType::Write, None) => sqe!(self, |fd| {
+                opcode::Writev::new(fd, iovs, iovcnt).offset(offset).build()
+            }),
+            _ => sqe!(self, |fd| opcode::Fsync::new(fd).build()),
+        };
+        entry.user_data(ptr as u64)
+    }
+
+    /// Submits the pending I/Os the queue depth allows.
+    fn submit(&mut self) {
+        let mut pushed = 0;
+        while self.inflight < self.queue_depth {
+            let ptr = match self.pending.pop_front() {
+                Some(ptr) => ptr,
+                None => break,
+            };
+            let entry = self.entry(ptr);
+            if unsafe { self.ring.submission().push(&entry) }.is_err() {
+                self.pending.push_front(ptr);
+                break;
+            }
+            self.inflight += 1;
+            pushed += 1;
+        }
+
+        if pushed > 0 {
+            if let Err(e) = self.ring.submit() {
+                error!("io_uring: submission failed: {e}");
+            }
+        }
+    }
+
+    /// Takes the completed I/Os, with their results.
+    fn reap(&mut self) -> Vec<(*mut spdk_bdev_io, i32)> {
+        let done = self
+            .ring
+            .completion()
+            .map(|cqe| (cqe.user_data() as *mut spdk_bdev_io, cqe.result()))
+            .collect::<Vec<_>>();
+        self.inflight -= done.len() as u32;
+        done
+    }
+}
+
+/// Ring of a channel, shared by the channel and its poller.
+struct UringQueue {
+    ring: Mutex<Option<UringRing>>,
+    block_len: u64,
+}
+
+// Required because `UringRing.pending` contains raw pointers, which are not
+// Send.
+unsafe impl Send for UringQueue {}
+
+/// Per-core channel of an io_uring device.
+pub(crate) struct UringChannel<'u> {
+    poller: Poller<'u, UringQueue>,
+}
+
+impl<'u> UringChannel<'u> {
+    fn new(ring: Option<UringRing>, block_len: u64) -> Self {
+        let poller = PollerBuilder::new()
+            .with_data(UringQueue {
+                ring: Mutex::new(ring),
+                block_len,
+            })
+            .with_poll_fn(|q| {
+                let done = match q.ring.lock().as_mut() {
+                    Some(ring) => {
+                        let done = ring.reap();
+                        ring.submit();
+                        done
+                    }
+                    None => return 0,
+                };
+
+                done.iter().for_each(|&(ptr, res)| {
+                    let bio = BdevIo::<UringDevice>::legacy_from_ptr(ptr);
+                    let expected = match bio.io_type() {
+                        IoType::Read | IoType::Write => {
+                            bio.num_blocks() * q.block_len
+                        }
+                        _ => 0,
+                    };
+                    if res >= 0 && res as u64 == expected {
+                        bio.ok();
+                    } else {
+                        if res < 0 {
+                            error!(
+                                "io_uring: {:?} I/O failed: {}",
+                                bio.io_type(),
+                                Errno::from_i32(-res)
+                            );
+                        }
+                        bio.fail();
+                    }
+                });
+                done.len() as i32
+            })
+            .build();
+
+        Self {
+            poller,
+        }
+    }
+}
+
+/// An io_uring device.
+pub(crate) struct UringDevice<'u> {
+    name: String,
+    file: File,
+    block_len: u64,
+    options: UringOptions,
+    /// Modes accepted by the kernel when the device was created.
+    modes: UringModes,
+    _u: PhantomData<&'u ()>,
+}
+
+impl Debug for UringDevice<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "io_uring '{}' ({})", self.name, self.modes)
+    }
+}
+
+impl<'u> IoDevice for UringDevice<'u> {
+    type ChannelData = UringChannel<'u>;
+
+    fn io_channel_create(self: Pin<&mut Self>) -> Self::ChannelData {
+        let mut opts = self.options;
+        opts.modes = self.modes;
+
+        let ring = match UringRing::new(&self.file, self.block_len, &opts) {
+            Ok((ring, modes)) => {
+                if modes != self.modes {
+                    warn!("{self:?}: channel ring modes: {modes}");
+                }
+                Some(ring)
+            }
+            Err(e) => {
+                error!("{self:?}: failed to set up a ring: {e}");
+                None
+            }
+        };
+
+        UringChannel::new(ring, self.block_len)
+    }
+
+    fn io_channel_destroy(self: Pin<&mut Self>, _chan: Self::ChannelData) {}
+}
+
+impl<'u> BdevOps for UringDevice<'u> {
+    type ChannelData = UringChannel<'u>;
+    type BdevData = Self;
+    type IoDev = Self;
+
+    fn destruct(self: Pin<&mut Self>) {
+        info!("{self:?}: destructing");
+        self.unregister_io_device();
+    }
+
+    fn submit_request(
+        &self,
+        chan: IoChannel<Self::ChannelData>,
+        bio: BdevIo<Self>,
+    ) {
+        let queue = chan.channel_data().poller.data();
+        match queue.ring.lock().as_mut() {
+            Some(ring) => ring.pending.push_back(bio.legacy_as_ptr()),
+            None => bio.fail(),
+        }
+    }
+
+    fn io_type_supported(&self, io_type: IoType) -> bool {
+        matches!(io_type, IoType::Read | IoType::Write | IoType::Flush)
+    }
+
+    fn get_io_device(&self) -> &Self::IoDev {
+        self
+    }
+}
+
+/// io_uring bdev module.
+pub(crate) struct UringModule {}
+
+impl UringModule {
+    /// Returns the io_uring bdev module instance.
+    /// Panics if the io_uring module was not registered.
+    fn current() -> BdevModule {
+        match BdevModule::find_by_name(URING_NG_MODULE_NAME) {
+            Ok(m) => m,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+impl WithModuleInit for UringModule {
+    fn module_init() -> i32 {
+        info!("Initializing io_uring Module");
+        0
+    }
+}
+
+impl BdevModuleBuild for UringModule {}
+
+pub fn register() {
+    UringModule::builder(URING_NG_MODULE_NAME)
+        .with_module_init()
+        .register();
+}
+
+/// Creates an io_uring device over the file or the disk `name`.
+pub(super) fn uring_ng_create(
+    name: &str,
+    blk_size: u32,
+    opts: &UringOptions,
+) -> Result<(), BdevError> {
+    let err = |e: std::io::Error| BdevError::CreateBdevFailed {
+        source: Errno::from_i32(e.raw_os_error().unwrap_or(libc::EIO)),
+        name: name.to_string(),
+    };
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(name)
+        .map_err(err)?;
+    let size = file.seek(SeekFrom::End(0)).map_err(err)?;
+    let block_len = blk_size as u64;
+    if size < block_len {
+        return Err(BdevError::CreateBdevInvalidParams {
+            source: Errno::EINVAL,
+            name: name.to_string(),
+        });
+    }
+
+    // Probe the modes the kernel accepts with a first ring.
+    let (_, modes) = UringRing::new(&file, block_len, opts).map_err(err)?;
+    if modes != opts.modes {
+        warn!(
+            "io_uring '{name}': requested modes: {}, accepted modes: {modes}",
+            opts.modes
+        );
+    }
+
+    let dev = UringDevice {
+        name: name.to_string(),
+        file,
+        block_len,
+        options: *opts,
+        modes,
+        _u: Default::default(),
+    };
+
+    let mut bdev = UringModule::current()
+        .bdev_builder()
+        .with_name(name)
+        .with_product_name(URING_NG_PRODUCT_ID)
+        .with_block_length(blk_size)
+        .with_block_count(size / block_len)
+        .with_required_alignment(9)
+        .with_data(dev)
+        .build();
+
+    bdev.data().register_io_device(Some(name));
+
+    if let Err(err) = bdev.register_bdev() {
+        error!("io_uring '{name}': bdev registration failed: {err}");
+        return Err(BdevError::CreateBdevFailed {
+            source: err,
+            name: name.to_string(),
+        });
+    }
+
+    info!("{:?}: created", bdev.data());
+    Ok(())
+}
+
+/// Destroys an io_uring device by its name.
+pub(super) async fn uring_ng_destroy(name: &str) -> Result<(), BdevError> {
+    let mut iter: BdevModuleIter<UringDevice> =
+        UringModule::current().iter_bdevs();
+
+    match iter.find(|b| b.data().name == name) {
+        Some(mut bdev) => bdev.unregister_bdev_async().await.map_err(|_| {
+            BdevError::DestroyBdevFailed {
+                source: Errno::EIO,
+                name: name.to_string(),
+            }
+        }),
+        None => Err(BdevError::BdevNotFound {
+            name: name.to_string(),
+        }),
+    }
+}
+
+/// Checks if a bdev is an io_uring device of this module.
+pub(super) fn is_uring_ng(name: &str) -> bool {
+    UntypedBdev::lookup_by_name(name)
+        .map_or(false, |b| b.driver() == URING_NG_MODULE_NAME)
+}
+
+/// Returns the io_uring modes the kernel accepted for a device.
+pub fn uring_bdev_modes(name: &str) -> Result<UringModes, BdevError> {
+    let mut iter: BdevModuleIter<UringDevice> =
+        UringModule::current().iter_bdevs();
+    iter.find(|b| b.data().name == name)
+        .map(|b| b.data().modes)
+        .ok_or_else(|| BdevError::BdevNotFound {
+            name: name.to_string(),
+        })
+}
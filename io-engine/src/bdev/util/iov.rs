@@ -0,0 +1,108 @@
+//! Helpers for the data described by I/O vectors.
+
+use spdk_rs::IoVec;
+
+/// A position within the data described by I/O vectors.
+#[derive(Clone, Copy)]
+pub(crate) struct IovCursor<'a> {
+    iovs: &'a [IoVec],
+    idx: usize,
+    off: usize,
+}
+
+impl<'a> IovCursor<'a> {
+    pub(crate) fn new(iovs: &'a [IoVec]) -> Self {
+        Self {
+            iovs,
+            idx: 0,
+            off: 0,
+        }
+    }
+
+    /// Returns the next `len` bytes if they are contiguous in the current
+    /// vector, and moves past them.
+    pub(crate) fn contiguous(&mut self, len: usize) -> Option<&'a mut [u8]> {
+        let iov = self.iovs.get(self.idx)?;
+        if iov.iov_len - self.off < len {
+            return None;
+        }
+
+        let data = unsafe {
+            std::slice::from_raw_parts_mut(
+                (iov.iov_base as *mut u8).add(self.off),
+                len,
+            )
+        };
+        self.advance(len);
+        Some(data)
+    }
+
+    /// Copies the next bytes into the buffer, and moves past them.
+    pub(crate) fn copy_to(&mut self, mut buf: &mut [u8]) {
+        while !buf.is_empty() {
+            let iov = &self.iovs[self.idx];
+            let n = buf.len().min(iov.iov_len - self.off);
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    (iov.iov_base as *const u8).add(self.off),
+                    buf.as_mut_ptr(),
+                    n,
+                );
+            }
+            buf = &mut buf[n ..];
+            self.advance(n);
+        }
+    }
+
+    /// Copies the buffer over the next bytes, and moves past them.
+    pub(crate) fn copy_from(&mut self, mut buf: &[u8]) {
+        while !buf.is_empty() {
+            let iov = &self.iovs[self.idx];
+            let n = buf.len().min(iov.iov_len - self.off);
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    buf.as_ptr(),
+                    (iov.iov_base as *mut u8).add(self.off),
+                    n,
+                );
+            }
+            buf = &buf[n ..];
+            self.advance(n);
+        }
+    }
+
+    /// Moves past the next bytes.
+    pub(crate) fn skip(&mut self, mut n: usize) {
+        while n > 0 {
+            let len = n.min(self.iovs[self.idx].iov_len - self.off);
+            self.advance(len);
+            n -= len;
+        }
+    }
+
+    /// Returns the vectors which describe the next bytes, and moves past
+    /// them.
+    pub(crate) fn take(&mut self, mut n: usize) -> Vec<IoVec> {
+        let mut iovs = Vec::new();
+        while n > 0 {
+            let iov = &self.iovs[self.idx];
+            let len = n.min(iov.iov_len - self.off);
+            iovs.push(IoVec {
+                iov_base: unsafe { (iov.iov_base as *mut u8).add(self.off) }
+                    .cast(),
+                iov_len: len,
+            });
+            self.advance(len);
+            n -= len;
+        }
+        iovs
+    }
+
+    fn advance(&mut self, n: usize) {
+        self.off += n;
+        if self.off == self.iovs[self.idx].iov_len {
+            self.idx += 1;
+            self.off = 0;
+        }
+    }
+}
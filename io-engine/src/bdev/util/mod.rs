@@ -1,2 +1,4 @@
+pub(super) mod iov;
+pub(super) mod region_lock;
 pub(super) mod uri;
 pub mod uring;
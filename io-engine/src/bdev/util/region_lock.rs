@@ -0,0 +1,101 @@
+//! Locks of the regions of a device, such as the rows of a parity nexus or
+//! the chunks of a compress device, taken by the I/Os of any core.
+
+use std::collections::{HashMap, VecDeque};
+
+use futures::channel::oneshot;
+
+use crate::core::{Cores, Reactors};
+
+/// Number of shards of the locks, which spread the regions locked by the
+/// cores over several mutexes.
+const REGION_LOCK_SHARDS: u64 = 64;
+
+/// A waiter for a locked region, woken up on its own core.
+struct RegionWaiter {
+    core: u32,
+    sender: oneshot::Sender<()>,
+}
+
+/// Locked regions of a shard of the locks, with their waiters.
+type LockedRegions = HashMap<u64, VecDeque<RegionWaiter>>;
+
+/// Locks of the regions of a device, with the waiters of each locked region,
+/// hashed by region over several shards.
+pub(crate) struct RegionLocks(Vec<parking_lot::Mutex<LockedRegions>>);
+
+impl Default for RegionLocks {
+    fn default() -> Self {
+        Self(
+            (0 .. REGION_LOCK_SHARDS)
+                .map(|_| Default::default())
+                .collect(),
+        )
+    }
+}
+
+impl RegionLocks {
+    /// Returns the shard of the locks which holds a region.
+    fn shard(&self, region: u64) -> &parking_lot::Mutex<LockedRegions> {
+        &self.0[(region % REGION_LOCK_SHARDS) as usize]
+    }
+
+    /// Locks a region, waiting for the I/Os in progress on it.
+    pub(crate) async fn lock(&self, region: u64) -> RegionGuard<'_> {
+        let wait = {
+            let mut locked = self.shard(region).lock();
+            match locked.get_mut(&region) {
+                Some(waiters) => {
+                    let (s, r) = oneshot::channel();
+                    waiters.push_back(RegionWaiter {
+                        core: Cores::current(),
+                        sender: s,
+                    });
+                    Some(r)
+                }
+                None => {
+                    locked.insert(region, VecDeque::new());
+                    None
+                }
+            }
+        };
+        if let Some(r) = wait {
+            r.await.ok();
+        }
+
+        RegionGuard {
+            locks: self,
+            region,
+        }
+    }
+}
+
+/// Lock on a region, released when dropped.
+pub(crate) struct RegionGuard<'a> {
+    locks: &'a RegionLocks,
+    region: u64,
+}
+
+impl Drop for RegionGuard<'_> {
+    fn drop(&mut self) {
+        let mut locked = self.locks.shard(self.region).lock();
+        let waiters = locked.get_mut(&self.region).unwrap();
+        // Hand the lock over to the next waiter still waiting. A waiter on
+        // another core is woken up on its core, as the futures of a core are
+        // only polled there. The I/Os taking these locks run to completion,
+        // so such a waiter still waits.
+        while let Some(w) = waiters.pop_front() {
+            if w.core == Cores::current() {
+                if w.sender.send(()).is_ok() {
+                    return;
+                }
+            } else if let Some(reactor) = Reactors::get_by_core(w.core) {
+                reactor.send_future(async move {
+                    w.sender.send(()).ok();
+                });
+                return;
+            }
+        }
+        locked.remove(&self.region);
+    }
+}
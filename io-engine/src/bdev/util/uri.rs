@@ -2,7 +2,7 @@
 
 use std::str::ParseBoolError;
 
-use url::Url;
+use url::{Host, Url};
 
 pub(crate) fn segments(url: &Url) -> Vec<&str> {
     if let Some(iter) = url.path_segments() {
@@ -18,6 +18,32 @@ pub(crate) fn segments(url: &Url) -> Vec<&str> {
     Vec::new()
 }
 
+/// Get the host of the URI, with IPv6 addresses stripped of their enclosing
+/// brackets as expected by transport addresses.
+pub(crate) fn host(url: &Url) -> Option<String> {
+    match url.host()? {
+        Host::Ipv6(addr) => Some(addr.to_string()),
+        Host::Ipv4(addr) => Some(addr.to_string()),
+        Host::Domain(domain) => Some(domain.to_string()),
+    }
+}
+
+/// Parse a `host[:port]` value, where IPv6 hosts are enclosed in brackets,
+/// into its host and port, using the default port if none is specified.
+pub(crate) fn host_port(
+    value: &str,
+    default_port: u16,
+) -> Option<(String, u16)> {
+    let url = Url::parse(&format!("tcp://{value}")).ok()?;
+    if !matches!(url.path(), "" | "/")
+        || url.query().is_some()
+        || !url.username().is_empty()
+    {
+        return None;
+    }
+    Some((host(&url)?, url.port().unwrap_or(default_port)))
+}
+
 /// Parse a value that represents a boolean
 /// Acceptable values are: true, false, yes, no, on, off
 /// Also accept an (unsigned) integer, where 0 represents false
@@ -46,3 +72,44 @@ pub(crate) fn uuid(
 ) -> Result<Option<uuid::Uuid>, uuid::Error> {
     value.map(|uuid| uuid::Uuid::parse_str(&uuid)).transpose()
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn host_ipv6() {
+        let url =
+            Url::parse("nvmf://[fd00::1]:8420/nqn.2019-05.io.openebs:disk0")
+                .unwrap();
+        assert_eq!(host(&url), Some("fd00::1".to_string()));
+        assert_eq!(url.port(), Some(8420));
+        assert_eq!(segments(&url), vec!["nqn.2019-05.io.openebs:disk0"]);
+
+        let url = Url::parse("nvmf://[fd00:0:0::1]/nqn").unwrap();
+        assert_eq!(host(&url), Some("fd00::1".to_string()));
+
+        let url = Url::parse("nvmf://10.0.0.1:8420/nqn").unwrap();
+        assert_eq!(host(&url), Some("10.0.0.1".to_string()));
+
+        assert!(Url::parse("nvmf://fd00::1:8420/nqn").is_err());
+    }
+
+    #[test]
+    fn host_port_ipv6() {
+        assert_eq!(
+            host_port("[fd00::1]:8420", 4420),
+            Some(("fd00::1".to_string(), 8420))
+        );
+        assert_eq!(
+            host_port("[fd00::1]", 4420),
+            Some(("fd00::1".to_string(), 4420))
+        );
+        assert_eq!(
+            host_port("10.0.0.1", 4420),
+            Some(("10.0.0.1".to_string(), 4420))
+        );
+        assert_eq!(host_port("fd00::1", 4420), None);
+        assert_eq!(host_port("[fd00::1]:8420/nqn", 4420), None);
+    }
+}
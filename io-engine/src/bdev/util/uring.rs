@@ -1,5 +1,11 @@
 //! Utility functions for io_uring support
 
+use std::{
+    fmt::{Display, Formatter},
+    fs::File,
+    os::unix::io::AsRawFd,
+};
+
 /// Returns true if the running kernel supports io_uring
 pub fn kernel_support() -> bool {
     // Match SPDK_URING_QUEUE_DEPTH
@@ -12,3 +18,70 @@ pub fn kernel_support() -> bool {
         }
     }
 }
+
+/// Optional io_uring modes, as requested for a device or as accepted by the
+/// kernel.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct UringModes {
+    /// Submission queue polling by a kernel thread.
+    pub sqpoll: bool,
+    /// Registered file descriptors.
+    pub fixed_files: bool,
+    /// Registered I/O buffers.
+    pub fixed_buffers: bool,
+}
+
+impl Display for UringModes {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let yes_no = |b| if b { "yes" } else { "no" };
+        write!(
+            f,
+            "sqpoll: {}, fixed files: {}, fixed buffers: {}",
+            yes_no(self.sqpoll),
+            yes_no(self.fixed_files),
+            yes_no(self.fixed_buffers)
+        )
+    }
+}
+
+/// Probes the optional io_uring modes the running kernel accepts.
+/// Submission queue polling may also require privileges.
+pub fn probe_modes() -> UringModes {
+    let sqpoll = match io_uring::IoUring::builder().setup_sqpoll(1000).build(8)
+    {
+        Ok(_ring) => true,
+        Err(e) => {
+            debug!("IoUring::new with SQPOLL: {}", e);
+            false
+        }
+    };
+
+    let ring = match io_uring::IoUring::new(8) {
+        Ok(ring) => ring,
+        Err(e) => {
+            debug!("IoUring::new: {}", e);
+            return UringModes::default();
+        }
+    };
+
+    let fixed_files = match File::open("/dev/null") {
+        Ok(file) => {
+            ring.submitter().register_files(&[file.as_raw_fd()]).is_ok()
+        }
+        Err(_) => false,
+    };
+
+    let mut buf = vec![0u8; 4096];
+    let iov = libc::iovec {
+        iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+        iov_len: buf.len(),
+    };
+    let fixed_buffers =
+        unsafe { ring.submitter().register_buffers(&[iov]) }.is_ok();
+
+    UringModes {
+        sqpoll,
+        fixed_files,
+        fixed_buffers,
+    }
+}
@@ -4,7 +4,17 @@ use snafu::Snafu;
 use std::{convert::TryFrom, num::ParseIntError, str::ParseBoolError};
 use url::ParseError;
 
-use crate::{bdev::uri, core::Bdev};
+use crate::{
+    bdev::{
+        compress_bdev::COMPRESS_MODULE_NAME,
+        delay_bdev::DELAY_MODULE_NAME,
+        error_bdev::ERROR_MODULE_NAME,
+        sparse_file::SPARSE_FILE_MODULE_NAME,
+        uri,
+        uring_ng::URING_NG_MODULE_NAME,
+    },
+    core::Bdev,
+};
 
 // parse URI and bdev create/destroy errors common for all types of bdevs
 #[derive(Debug, Snafu, Clone)]
@@ -88,6 +98,68 @@ pub enum BdevError {
     // Generic destruction failure.
     #[snafu(display("Failed to destroy a BDEV '{}'", name))]
     DestroyBdevFailed { source: Errno, name: String },
+    // Invalid encryption key.
+    #[snafu(display(
+        "Failed to create a BDEV '{}': invalid encryption key: {}",
+        name,
+        message
+    ))]
+    CryptKeyInvalid { name: String, message: String },
+    // Encryption key does not match the key of the device.
+    #[snafu(display(
+        "Failed to create a BDEV '{}': the encryption key does not match \
+            the key '{}' of the device",
+        name,
+        key_id
+    ))]
+    CryptKeyMismatch { name: String, key_id: String },
+    // Invalid base device of an encrypted BDEV.
+    #[snafu(display(
+        "Failed to create a BDEV '{}': invalid base device: {}",
+        name,
+        message
+    ))]
+    CryptBaseInvalid { name: String, message: String },
+    // Invalid latencies of a delay BDEV.
+    #[snafu(display(
+        "Invalid latencies for BDEV '{}': p99 latencies must not be below \
+            the average latencies",
+        name
+    ))]
+    DelayLatencyInvalid { name: String },
+    // Invalid error injection rule of an error BDEV.
+    #[snafu(display(
+        "Invalid error injection rule for BDEV '{}': {}",
+        name,
+        message
+    ))]
+    ErrorRuleInvalid { name: String, message: String },
+    // Error injection rule not found.
+    #[snafu(display(
+        "Error injection rule {} not found for BDEV '{}'",
+        id,
+        name
+    ))]
+    ErrorRuleNotFound { name: String, id: u64 },
+    // Failure to create or extend the backing file of a BDEV.
+    #[snafu(display("Failed to create or extend backing file '{}'", path))]
+    BackingFileFailed { source: Errno, path: String },
+    // Invalid resize of a BDEV.
+    #[snafu(display("Failed to resize BDEV '{}': {}", name, message))]
+    ResizeBdevInvalid { name: String, message: String },
+    // Generic resize failure.
+    #[snafu(display("Failed to resize BDEV '{}'", name))]
+    ResizeBdevFailed { source: Errno, name: String },
+    // Invalid local or source device of a copy-on-read BDEV.
+    #[snafu(display("Invalid copy-on-read BDEV '{}': {}", name, message))]
+    CorDeviceInvalid { name: String, message: String },
+    // Invalid base device of a compressed BDEV.
+    #[snafu(display(
+        "Failed to create a BDEV '{}': invalid compressed base device: {}",
+        name,
+        message
+    ))]
+    CompressBaseInvalid { name: String, message: String },
     // Command canceled.
     #[snafu(display("Command canceled for a BDEV '{}'", name))]
     BdevCommandCanceled { source: Canceled, name: String },
@@ -111,6 +183,23 @@ pub fn bdev_get_name(uri: &str) -> Result<String, BdevError> {
     Ok(uri::parse(uri)?.get_name())
 }
 
+/// Checks if a bdev driver is the one of a URI scheme.
+fn driver_eq(driver: &str, scheme: &str) -> bool {
+    match scheme {
+        "nvmf" | "pcie" => driver == "nvme",
+        "compress" => driver == COMPRESS_MODULE_NAME,
+        "delay" => driver == DELAY_MODULE_NAME,
+        "error" => driver == ERROR_MODULE_NAME,
+        "aio" => driver == scheme || driver == SPARSE_FILE_MODULE_NAME,
+        "uring" => {
+            driver == scheme
+                || driver == SPARSE_FILE_MODULE_NAME
+                || driver == URING_NG_MODULE_NAME
+        }
+        scheme => driver == scheme,
+    }
+}
+
 /// TODO
 pub fn bdev_uri_eq<T>(bdev: &Bdev<T>, uri: &url::Url) -> bool
 where
@@ -118,11 +207,7 @@ where
 {
     match uri::parse(uri.as_ref()) {
         Ok(device) if device.get_name() == bdev.name() => {
-            bdev.driver()
-                == match uri.scheme() {
-                    "nvmf" | "pcie" => "nvme",
-                    scheme => scheme,
-                }
+            driver_eq(bdev.driver(), uri.scheme())
         }
         _ => false,
     }
@@ -135,11 +220,7 @@ where
 {
     match uri::parse(uri.as_ref()) {
         Ok(device) if device.get_name() == bdev.name() => {
-            bdev.driver()
-                == match uri.scheme() {
-                    "nvmf" | "pcie" => "nvme",
-                    scheme => scheme,
-                }
+            driver_eq(bdev.driver(), uri.scheme())
         }
         _ => false,
     }
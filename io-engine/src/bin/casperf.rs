@@ -1,10 +1,16 @@
-use std::{cell::RefCell, os::raw::c_void, ptr::NonNull};
+use std::{
+    cell::RefCell,
+    os::raw::c_void,
+    ptr::NonNull,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 
 use clap::{value_t, App, AppSettings, Arg};
 use rand::Rng;
 
 use io_engine::{
-    bdev_api::bdev_create,
+    bdev_api::{bdev_create, bdev_destroy},
     core::{
         mayastor_env_stop,
         Cores,
@@ -16,6 +22,7 @@ use io_engine::{
         UntypedDescriptorGuard,
     },
     logger,
+    sleep::mayastor_sleep,
     subsys::Config,
 };
 use spdk_rs::{
@@ -46,6 +53,12 @@ enum IoType {
 const QD: u64 = 64;
 /// default io_size
 const IO_SIZE: u64 = 512;
+/// default number of seconds each device runs in compare mode
+const COMPARE_TIME: u64 = 10;
+
+/// set in compare mode, where the devices run one after another and the
+/// environment is stopped once all of them ran
+static COMPARE: AtomicBool = AtomicBool::new(false);
 
 /// a Job refers to a set of work typically defined by either time or size
 /// that drives IO to a bdev using its own channel.
@@ -118,7 +131,7 @@ impl Job {
             JOBLIST.with(|l| {
                 let mut list = l.borrow_mut();
                 list.retain(|this| job.bdev.name() != this.bdev.name());
-                if list.is_empty() {
+                if list.is_empty() && !COMPARE.load(Ordering::Relaxed) {
                     Reactors::master().send_future(async {
                         mayastor_env_stop(0);
                     });
@@ -280,8 +293,9 @@ fn sig_override() {
     let handler = || {
         Mthread::primary().send_msg((), |_| {
             PERF_TICK.with(|t| {
-                let ticker = t.borrow_mut().take().unwrap();
-                unsafe { spdk_poller_unregister(&mut ticker.as_ptr()) }
+                if let Some(ticker) = t.borrow_mut().take() {
+                    unsafe { spdk_poller_unregister(&mut ticker.as_ptr()) }
+                }
             });
 
             println!("Draining jobs....");
@@ -327,6 +341,59 @@ extern "C" fn perf_tick(_: *mut c_void) -> i32 {
     0
 }
 
+/// runs each of the given URIs alone for the given number of seconds, one
+/// after another, and prints their performance side by side
+async fn compare(
+    variants: Vec<(&str, String)>,
+    io_size: u64,
+    qd: u64,
+    secs: u64,
+) {
+    let mut results = Vec::new();
+
+    for (label, uri) in variants {
+        println!("Running {label} ({uri}) for {secs}s....");
+        let job = Job::new(&uri, io_size, qd).await;
+        let thread =
+            Mthread::new(job.bdev.name().to_string(), Cores::current())
+                .unwrap();
+        thread.send_msg(job, |job| {
+            job.run();
+        });
+
+        mayastor_sleep(Duration::from_secs(secs)).await.unwrap();
+
+        let n_io = JOBLIST.with(|l| {
+            let mut list = l.borrow_mut();
+            list.iter_mut().for_each(|j| j.drain = true);
+            list.iter().map(|j| j.n_io).sum::<u64>()
+        });
+
+        while JOBLIST.with(|l| !l.borrow().is_empty()) {
+            mayastor_sleep(Duration::from_millis(10)).await.unwrap();
+        }
+
+        if let Err(e) = bdev_destroy(&uri).await {
+            eprintln!("Failed to destroy {uri}: {e}");
+        }
+
+        let io_per_second = n_io / secs;
+        results.push((label, io_per_second, io_per_second * io_size));
+    }
+
+    println!("\r ==================================================== +");
+    for (label, io_per_second, bytes_per_second) in results {
+        println!(
+            "\r {:20}: {:10} IO/s {:10}: MB/s",
+            label,
+            io_per_second,
+            bytes_per_second / (1024 * 1024)
+        );
+    }
+
+    mayastor_env_stop(0);
+}
+
 fn main() {
     logger::init("INFO");
 
@@ -355,21 +422,38 @@ fn main() {
                 .help("queue depth")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("compare")
+                .value_name("path")
+                .long("compare")
+                .help("compare aio with the io_uring modes on a file or a disk")
+                .takes_value(true)
+                .conflicts_with("URI"),
+        )
+        .arg(
+            Arg::with_name("time")
+                .value_name("time")
+                .short("t")
+                .long("time")
+                .help("number of seconds each device runs in compare mode")
+                .takes_value(true)
+                .requires("compare"),
+        )
         .arg(
             Arg::with_name("URI")
                 .value_name("URI")
                 .help("storage URI's")
                 .index(1)
                 .multiple(true)
-                .takes_value(true),
+                .takes_value(true)
+                .required_unless("compare"),
         )
         .get_matches();
 
     let mut uris = matches
         .values_of("URI")
-        .unwrap()
-        .map(|u| u.to_string())
-        .collect::<Vec<_>>();
+        .map(|v| v.map(|u| u.to_string()).collect::<Vec<_>>())
+        .unwrap_or_default();
 
     let io_size = value_t!(matches.value_of("io_size"), u64).unwrap_or(IO_SIZE);
     let qd = value_t!(matches.value_of("queue_depth"), u64).unwrap_or(QD);
@@ -380,6 +464,38 @@ fn main() {
 
     MayastorEnvironment::new(args).init();
     sig_override();
+
+    if let Some(path) = matches.value_of("compare") {
+        let secs =
+            value_t!(matches.value_of("time"), u64).unwrap_or(COMPARE_TIME);
+        let variants = vec![
+            ("aio", format!("aio://{path}")),
+            ("uring", format!("uring://{path}")),
+            ("uring sqpoll", format!("uring://{path}?sqpoll=true")),
+            (
+                "uring fixed files",
+                format!("uring://{path}?fixed_files=true"),
+            ),
+            (
+                "uring fixed buffers",
+                format!("uring://{path}?fixed_buffers=true"),
+            ),
+            (
+                "uring all modes",
+                format!(
+                    "uring://{path}?sqpoll=true&fixed_files=true&\
+                    fixed_buffers=true"
+                ),
+            ),
+        ];
+
+        COMPARE.store(true, Ordering::Relaxed);
+        Reactors::master().send_future(compare(variants, io_size, qd, secs));
+        Reactors::master().running();
+        Reactors::master().poll_reactor();
+        return;
+    }
+
     Reactors::master().send_future(async move {
         let jobs = uris
             .iter_mut()
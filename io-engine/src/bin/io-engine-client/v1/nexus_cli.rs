@@ -114,8 +114,15 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true)
                 .required(false)
                 .help("NQN of hosts which are allowed to connect to the target"))
+        .arg(
+            Arg::with_name("listener")
+                .long("listener")
+                .takes_value(true)
+                .multiple(true)
+                .required(false)
+                .help("Target address to listen on, all of them if not given"))
         .arg(Arg::with_name("protocol").short("p").long("protocol").value_name("PROTOCOL")
-            .help("Name of a protocol (nvmf) used for publishing the nexus remotely"));
+            .help("Name of a protocol (nvmf, ublk) used for publishing the nexus"));
 
     let unpublish = SubCommand::with_name("unpublish")
         .about("unpublish the nexus")
@@ -228,6 +235,60 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .help("injection uri"),
         );
 
+    let trace = SubCommand::with_name("trace")
+        .settings(&[
+            AppSettings::SubcommandRequiredElseHelp,
+            AppSettings::ColoredHelp,
+            AppSettings::ColorAlways,
+        ])
+        .about("runtime I/O tracing of the nexus")
+        .subcommand(
+            SubCommand::with_name("start")
+                .about("start tracing, discarding any previous trace")
+                .arg(
+                    Arg::with_name("uuid")
+                        .required(true)
+                        .index(1)
+                        .help("uuid of nexus"),
+                )
+                .arg(
+                    Arg::with_name("records")
+                        .short("r")
+                        .long("records")
+                        .takes_value(true)
+                        .value_name("NUMBER")
+                        .default_value("0")
+                        .help(
+                            "number of latest records kept per core, 0 for \
+                            the default",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("stop").about("stop tracing").arg(
+                Arg::with_name("uuid")
+                    .required(true)
+                    .index(1)
+                    .help("uuid of nexus"),
+            ),
+        )
+        .subcommand(
+            SubCommand::with_name("dump")
+                .about("dump the trace records to a capture file on the node")
+                .arg(
+                    Arg::with_name("uuid")
+                        .required(true)
+                        .index(1)
+                        .help("uuid of nexus"),
+                )
+                .arg(
+                    Arg::with_name("path")
+                        .required(true)
+                        .index(2)
+                        .help("name of the capture file to create"),
+                ),
+        );
+
     SubCommand::with_name("nexus")
         .settings(&[
             AppSettings::SubcommandRequiredElseHelp,
@@ -246,6 +307,7 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
         .subcommand(list)
         .subcommand(children)
         .subcommand(inject)
+        .subcommand(trace)
         .subcommand(nexus_child_cli::subcommands())
 }
 
@@ -266,6 +328,7 @@ pub async fn handler(
         ("remove", Some(args)) => nexus_remove(ctx, args).await,
         ("child", Some(args)) => nexus_child_cli::handler(ctx, args).await,
         ("inject", Some(args)) => injections(ctx, args).await,
+        ("trace", Some(args)) => io_trace(ctx, args).await,
         (cmd, _) => {
             Err(Status::not_found(format!("command {cmd} does not exist")))
                 .context(GrpcStatus)
@@ -619,6 +682,7 @@ async fn nexus_publish(
     let protocol = match matches.value_of("protocol") {
         None => v1::common::ShareProtocol::Nvmf as i32,
         Some("nvmf") => v1::common::ShareProtocol::Nvmf as i32,
+        Some("ublk") => v1::common::ShareProtocol::Ublk as i32,
         Some(_) => {
             return Err(Status::new(
                 Code::Internal,
@@ -629,6 +693,7 @@ async fn nexus_publish(
     };
     let allowed_hosts =
         matches.values_of_lossy("allowed-host").unwrap_or_default();
+    let listeners = matches.values_of_lossy("listener").unwrap_or_default();
 
     let response = ctx
         .v1
@@ -638,6 +703,7 @@ async fn nexus_publish(
             key,
             share: protocol,
             allowed_hosts,
+            listeners,
         })
         .await
         .context(GrpcStatus)?;
@@ -904,6 +970,75 @@ async fn injections(
     Ok(())
 }
 
+async fn io_trace(
+    mut ctx: Context,
+    matches: &ArgMatches<'_>,
+) -> crate::Result<()> {
+    let response = match matches.subcommand() {
+        ("start", Some(args)) => {
+            let records_per_core = value_t!(args.value_of("records"), u32)
+                .unwrap_or_else(|e| e.exit());
+            ctx.v1
+                .nexus
+                .set_nexus_io_trace(v1::nexus::SetNexusIoTraceRequest {
+                    uuid: args.value_of("uuid").unwrap().to_string(),
+                    enable: true,
+                    records_per_core,
+                })
+                .await
+        }
+        ("stop", Some(args)) => {
+            ctx.v1
+                .nexus
+                .set_nexus_io_trace(v1::nexus::SetNexusIoTraceRequest {
+                    uuid: args.value_of("uuid").unwrap().to_string(),
+                    enable: false,
+                    records_per_core: 0,
+                })
+                .await
+        }
+        ("dump", Some(args)) => {
+            ctx.v1
+                .nexus
+                .dump_nexus_io_trace(v1::nexus::DumpNexusIoTraceRequest {
+                    uuid: args.value_of("uuid").unwrap().to_string(),
+                    path: args.value_of("path").unwrap().to_string(),
+                })
+                .await
+        }
+        (cmd, _) => {
+            Err(Status::not_found(format!("command {cmd} does not exist")))
+        }
+    }
+    .context(GrpcStatus)?;
+
+    match ctx.output {
+        OutputFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(response.get_ref())
+                    .unwrap()
+                    .to_colored_json_auto()
+                    .unwrap()
+            );
+        }
+        OutputFormat::Default => {
+            let trace = response.get_ref();
+            let state = if trace.enabled { "enabled" } else { "disabled" };
+            if trace.path.is_empty() {
+                println!("tracing {state}, {} records", trace.records);
+            } else {
+                println!(
+                    "tracing {state}, {} records written to {}",
+                    trace.records, trace.path
+                );
+            }
+        }
+    };
+
+    Ok(())
+}
+
 async fn list_nexus_injections(
     mut ctx: Context,
     uuid: &str,
@@ -48,6 +48,12 @@ pub fn subcommands<'a, 'b>() -> App<'a, 'b> {
                 .long("thin")
                 .takes_value(false)
                 .help("Whether replica is thin provisioned (default false)"))
+        .arg(
+            Arg::with_name("compression")
+                .long("compression")
+                .takes_value(true)
+                .value_name("CODEC")
+                .help("Compress the replica with the codec (lz4), which makes it thin provisioned"))
         .arg(
             Arg::with_name("allowed-host")
                 .long("allowed-host")
@@ -175,6 +181,7 @@ async fn replica_create(
     .map_err(|s| Status::invalid_argument(format!("Bad size '{s}'")))
     .context(GrpcStatus)?;
     let thin = matches.is_present("thin");
+    let compression = matches.value_of("compression").map(str::to_string);
     let share = parse_replica_protocol(matches.value_of("protocol"))
         .context(GrpcStatus)?;
     let allowed_hosts =
@@ -188,6 +195,7 @@ async fn replica_create(
         share,
         size: size.get_bytes() as u64,
         allowed_hosts,
+        compression,
     };
 
     let response = ctx
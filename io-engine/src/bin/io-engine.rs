@@ -26,8 +26,10 @@ use io_engine::{
         Mthread,
         Reactors,
     },
+    events::pool_space_monitor_loop,
     grpc,
     logger,
+    metrics,
     persistent_store::PersistentStore,
     subsys::Registration,
 };
@@ -56,6 +58,9 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
     let registration_addr = args.registration_endpoint.clone();
     let rpc_address = args.rpc_address.clone();
     let api_versions = args.api_versions.clone();
+    let grpc_server_options = args.grpc_server_options();
+    let registration_tls = args.registration_tls();
+    let metrics_endpoint = args.metrics_endpoint;
     let node_name = grpc::node_name(&args.node_name);
     let node_nqn = args.make_hostnqn();
 
@@ -96,12 +101,17 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
             let mut futures = Vec::new();
             PersistentStore::init(persistent_store_endpoint).await;
             runtime::spawn(device_monitor_loop());
+            runtime::spawn(pool_space_monitor_loop());
 
             // Launch reactor health monitor if diagnostics is enabled.
             if reactor_freeze_detection {
                 runtime::spawn(reactor_monitor_loop(reactor_freeze_timeout));
             }
 
+            if let Some(metrics_endpoint) = metrics_endpoint {
+                runtime::spawn(metrics::run_metrics_server(metrics_endpoint));
+            }
+
             futures.push(
                 grpc::MayastorGrpcServer::run(
                     &node_name,
@@ -109,18 +119,23 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
                     grpc_address,
                     rpc_address,
                     api_versions.clone(),
+                    grpc_server_options,
                 )
                 .boxed(),
             );
 
             if let Some(registration_addr) = registration_addr {
-                Registration::init(
+                if let Err(error) = Registration::init(
                     &node_name,
                     &node_nqn,
                     &grpc_address.to_string(),
                     registration_addr,
                     api_versions,
-                );
+                    registration_tls,
+                ) {
+                    error!("{}", error);
+                    std::process::exit(1);
+                }
                 futures.push(Registration::run().boxed());
             }
 
@@ -248,10 +263,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         };
 
+    let uring_support = uring::kernel_support();
     info!(
         "kernel io_uring support: {}",
-        if uring::kernel_support() { "yes" } else { "no" }
+        if uring_support { "yes" } else { "no" }
     );
+    if uring_support {
+        info!("kernel io_uring modes: {}", uring::probe_modes());
+    }
     info!("kernel nvme initiator multipath support: {}", nvme_mp);
 
     let ms = MayastorEnvironment::new(args.clone()).init();
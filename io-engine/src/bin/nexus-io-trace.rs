@@ -0,0 +1,120 @@
+//! Decodes the nexus I/O trace captures dumped with the `DumpNexusIoTrace`
+//! gRPC method, into text or JSON.
+
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use io_engine::bdev::nexus::{
+    IoTraceCapture,
+    IoTraceOp,
+    IoTraceRecord,
+    IoTraceStatus,
+    IO_TRACE_NEXUS,
+};
+use serde::Serialize;
+use structopt::StructOpt;
+use version_info::{package_description, version_info_str};
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = package_description!(),
+    version = version_info_str!(),
+    about = "Decodes nexus I/O trace captures",
+    setting = structopt::clap::AppSettings::ColoredHelp
+)]
+struct Opt {
+    /// Path of the capture file.
+    #[structopt(parse(from_os_str))]
+    capture: PathBuf,
+    /// Output the capture as JSON.
+    #[structopt(short, long)]
+    json: bool,
+    /// Only output the records of the nexus I/Os.
+    #[structopt(long)]
+    nexus_only: bool,
+}
+
+/// A trace record with its child resolved, for the JSON output.
+#[derive(Serialize)]
+struct Record<'a> {
+    timestamp_ns: u64,
+    op: IoTraceOp,
+    offset: u64,
+    num_blocks: u32,
+    child: Option<&'a str>,
+    status: IoTraceStatus,
+    latency_ns: u64,
+}
+
+fn child_name(
+    capture: &IoTraceCapture,
+    record: &IoTraceRecord,
+) -> Option<&str> {
+    if record.child == IO_TRACE_NEXUS {
+        return None;
+    }
+    capture.header.children.get(record.child as usize).map(|c| {
+        if c.uri.is_empty() {
+            c.device.as_str()
+        } else {
+            c.uri.as_str()
+        }
+    })
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let opt = Opt::from_args();
+
+    let file = File::open(&opt.capture)?;
+    let capture = IoTraceCapture::read_from(BufReader::new(file))?;
+    let records = capture
+        .records
+        .iter()
+        .filter(|r| !opt.nexus_only || r.child == IO_TRACE_NEXUS);
+
+    if opt.json {
+        let records = records
+            .map(|r| Record {
+                timestamp_ns: r.timestamp_ns,
+                op: r.op(),
+                offset: r.offset,
+                num_blocks: r.num_blocks,
+                child: child_name(&capture, r),
+                status: r.status(),
+                latency_ns: r.latency_ns,
+            })
+            .collect::<Vec<_>>();
+        let out = serde_json::json!({
+            "nexus": capture.header.nexus,
+            "uuid": capture.header.uuid,
+            "block_len": capture.header.block_len,
+            "start_time_ns": capture.header.start_time_ns,
+            "children": capture.header.children,
+            "records": records,
+        });
+        println!("{}", serde_json::to_string_pretty(&out)?);
+        return Ok(());
+    }
+
+    println!(
+        "# nexus {} ({}), block size {}, {} records",
+        capture.header.nexus,
+        capture.header.uuid,
+        capture.header.block_len,
+        capture.records.len()
+    );
+    println!("# timestamp_us op offset num_blocks target status latency_us");
+    for r in records {
+        println!(
+            "{:.3} {:?} {} {} {} {:?} {:.3}",
+            r.timestamp_ns as f64 / 1000.0,
+            r.op(),
+            r.offset,
+            r.num_blocks,
+            child_name(&capture, r).unwrap_or("nexus"),
+            r.status(),
+            r.latency_ns as f64 / 1000.0,
+        );
+    }
+
+    Ok(())
+}
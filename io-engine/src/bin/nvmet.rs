@@ -31,6 +31,7 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
     let grpc_endpoint = grpc::endpoint(args.grpc_endpoint.clone());
     let rpc_address = args.rpc_address.clone();
     let api_versions = args.api_versions.clone();
+    let grpc_server_options = args.grpc_server_options();
 
     Mthread::spawn_unaffinitized(move || {
         let rt = tokio::runtime::Builder::new_multi_thread()
@@ -47,6 +48,7 @@ fn start_tokio_runtime(args: &MayastorCliArgs) {
             grpc_endpoint,
             rpc_address,
             api_versions,
+            grpc_server_options,
         )
         .boxed_local()];
 
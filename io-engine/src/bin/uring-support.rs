@@ -1,18 +1,26 @@
 extern crate clap;
 
-use clap::App;
+use clap::{App, Arg};
 
 use io_engine::bdev::util::uring;
 use version_info::version_info_str;
 
 fn main() {
-    let _matches = App::new("Detect io_uring support")
+    let matches = App::new("Detect io_uring support")
         .version(version_info_str!())
         .author("Jonathan Teh <jonathan.teh@mayadata.io>")
         .about("Determines io_uring support")
+        .arg(
+            Arg::with_name("modes")
+                .long("modes")
+                .help("print the optional io_uring modes the kernel accepts"),
+        )
         .get_matches();
 
     let supported = uring::kernel_support();
+    if supported && matches.is_present("modes") {
+        println!("{}", uring::probe_modes());
+    }
 
     std::process::exit(!supported as i32)
 }
@@ -8,7 +8,12 @@ use async_trait::async_trait;
 use nix::errno::Errno;
 use snafu::ResultExt;
 
-use spdk_rs::libspdk::spdk_bdev;
+use spdk_rs::libspdk::{
+    spdk_bdev,
+    spdk_bdev_get_qd,
+    spdk_bdev_get_qd_sampling_period,
+    spdk_bdev_set_qd_sampling_period,
+};
 
 use crate::{
     bdev::bdev_event_callback,
@@ -157,6 +162,26 @@ where
         BdevIter::<T>::new().next()
     }
 
+    /// Get the queue depth of the bdev measured at the last sample, or None
+    /// if queue depth sampling is disabled.
+    pub fn queue_depth(&self) -> Option<u64> {
+        unsafe {
+            let bdev = self.unsafe_inner_ptr();
+            match spdk_bdev_get_qd_sampling_period(bdev) {
+                0 => None,
+                _ => Some(spdk_bdev_get_qd(bdev)),
+            }
+        }
+    }
+
+    /// Sets the period of the queue depth sampling of the bdev, 0 disabling
+    /// the sampling.
+    pub fn set_qd_sampling_period(&self, period_us: u64) {
+        unsafe {
+            spdk_bdev_set_qd_sampling_period(self.unsafe_inner_ptr(), period_us)
+        }
+    }
+
     /// TODO
     pub async fn stats_async(&self) -> Result<BlockDeviceIoStats, CoreError> {
         match self.inner.stats_async().await {
@@ -167,6 +192,10 @@ where
                 bytes_written: stat.bytes_written,
                 num_unmap_ops: stat.num_unmap_ops,
                 bytes_unmapped: stat.bytes_unmapped,
+                read_latency_ticks: stat.read_latency_ticks,
+                write_latency_ticks: stat.write_latency_ticks,
+                unmap_latency_ticks: stat.unmap_latency_ticks,
+                tick_rate: stat.ticks_rate,
             }),
             Err(err) => Err(CoreError::DeviceStatisticsFailed {
                 source: err,
@@ -209,7 +238,10 @@ where
             .await
             .context(ShareNvmf {})?;
 
-        subsystem.start().await.context(ShareNvmf {})
+        subsystem
+            .start_with_listeners(props.listeners())
+            .await
+            .context(ShareNvmf {})
     }
 
     async fn update_properties<P: Into<Option<UpdateProps>>>(
@@ -226,9 +258,15 @@ where
                         .set_allowed_hosts(props.allowed_hosts())
                         .await
                         .context(ShareNvmf {})?;
+                    if let Some(listeners) = props.listeners() {
+                        subsystem
+                            .set_listeners(listeners)
+                            .await
+                            .context(ShareNvmf {})?;
+                    }
                 }
             }
-            Some(Protocol::Off) | None => {}
+            _ => {}
         }
 
         Ok(())
@@ -243,7 +281,7 @@ where
                     ss.destroy();
                 }
             }
-            Some(Protocol::Off) | None => {}
+            _ => {}
         }
 
         Ok(())
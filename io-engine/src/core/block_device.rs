@@ -2,6 +2,7 @@ use super::{
     CoreError,
     DeviceEventSink,
     IoCompletionStatus,
+    IoLatencyStats,
     IoType,
     SnapshotParams,
 };
@@ -29,6 +30,18 @@ pub struct BlockDeviceIoStats {
     pub num_unmap_ops: u64,
     #[merge(strategy = merge::num::saturating_add)]
     pub bytes_unmapped: u64,
+    /// Total time spent on reads, in ticks of `tick_rate`.
+    #[merge(strategy = merge::num::saturating_add)]
+    pub read_latency_ticks: u64,
+    /// Total time spent on writes, in ticks of `tick_rate`.
+    #[merge(strategy = merge::num::saturating_add)]
+    pub write_latency_ticks: u64,
+    /// Total time spent on unmaps, in ticks of `tick_rate`.
+    #[merge(strategy = merge::num::saturating_add)]
+    pub unmap_latency_ticks: u64,
+    /// Number of ticks per second, 0 when latencies are not accounted.
+    #[merge(strategy = merge::num::overwrite_zero)]
+    pub tick_rate: u64,
 }
 
 /// Core trait that represents a block device.
@@ -65,6 +78,14 @@ pub trait BlockDevice {
     /// Obtains I/O statistics for the device.
     async fn io_stats(&self) -> Result<BlockDeviceIoStats, CoreError>;
 
+    /// Obtains I/O latency histograms for the device, if it records them.
+    fn io_latency(&self) -> Option<IoLatencyStats> {
+        None
+    }
+
+    /// Resets I/O latency histograms for the device.
+    fn reset_io_latency(&self) {}
+
     /// Checks if block device has been claimed.
     fn claimed_by(&self) -> Option<String>;
 
@@ -233,6 +254,16 @@ pub trait BlockDeviceHandle {
         params: SnapshotParams,
     ) -> Result<u64, CoreError>;
 
+    /// Destroy a snapshot of the device, given its UUID.
+    async fn destroy_snapshot(
+        &self,
+        _snapshot_uuid: &str,
+    ) -> Result<(), CoreError> {
+        Err(CoreError::NotSupported {
+            source: Errno::EOPNOTSUPP,
+        })
+    }
+
     /// TODO
     async fn nvme_resv_register(
         &self,
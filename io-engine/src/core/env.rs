@@ -1,7 +1,7 @@
 use std::{
     env,
     ffi::CString,
-    net::Ipv4Addr,
+    net::IpAddr,
     os::raw::{c_char, c_void},
     pin::Pin,
     sync::{
@@ -54,7 +54,7 @@ use crate::{
     persistent_store::PersistentStore,
     subsys::{
         self,
-        registration::registration_grpc::ApiVersion,
+        registration::registration_grpc::{ApiVersion, RegistrationTlsFiles},
         Config,
         PoolConfig,
         Registration,
@@ -91,9 +91,50 @@ pub struct MayastorCliArgs {
     #[structopt(short = "g", default_value = grpc::default_endpoint_str())]
     /// IP address and port (optional) for the gRPC server to listen on.
     pub grpc_endpoint: String,
+    #[structopt(
+        long,
+        env = "GRPC_TLS_CERT",
+        requires_all = &["grpc-tls-key", "grpc-tls-client-ca"]
+    )]
+    /// Path to the PEM certificate of the gRPC server. Enables TLS, with the
+    /// client certificates verified against the CA of --grpc-tls-client-ca.
+    pub grpc_tls_cert: Option<String>,
+    #[structopt(long, env = "GRPC_TLS_KEY", requires = "grpc-tls-cert")]
+    /// Path to the PEM private key of the gRPC server certificate.
+    pub grpc_tls_key: Option<String>,
+    #[structopt(long, env = "GRPC_TLS_CLIENT_CA", requires = "grpc-tls-cert")]
+    /// Path to the PEM CA certificate the gRPC client certificates must be
+    /// signed by.
+    pub grpc_tls_client_ca: Option<String>,
+    #[structopt(long, env = "GRPC_UDS_PATH")]
+    /// Path of a Unix domain socket to also serve the v1 gRPC services on.
+    pub grpc_uds_path: Option<String>,
+    #[structopt(long, env = "METRICS_ENDPOINT")]
+    /// IP address and port to serve the Prometheus metrics on, at the
+    /// /metrics path. The metrics are not served by default.
+    pub metrics_endpoint: Option<std::net::SocketAddr>,
     #[structopt(short = "R")]
     /// Registration grpc endpoint
     pub registration_endpoint: Option<Uri>,
+    #[structopt(long, env = "REGISTRATION_TLS_CA")]
+    /// Path to the PEM CA certificate the registration endpoint is verified
+    /// against. Enables TLS for the registration client.
+    pub registration_tls_ca: Option<String>,
+    #[structopt(
+        long,
+        env = "REGISTRATION_TLS_CERT",
+        requires_all = &["registration-tls-key", "registration-tls-ca"]
+    )]
+    /// Path to the PEM client certificate presented to the registration
+    /// endpoint.
+    pub registration_tls_cert: Option<String>,
+    #[structopt(
+        long,
+        env = "REGISTRATION_TLS_KEY",
+        requires = "registration-tls-cert"
+    )]
+    /// Path to the PEM private key of the registration client certificate.
+    pub registration_tls_key: Option<String>,
     #[structopt(short = "L")]
     /// Enable logging for sub components.
     pub log_components: Vec<String>,
@@ -122,6 +163,9 @@ pub struct MayastorCliArgs {
     #[structopt(long)]
     /// Path to persistence through power loss nvme reservation base directory.
     pub ptpl_dir: Option<String>,
+    #[structopt(long = "io-trace-dir", default_value = "/var/tmp/io-trace")]
+    /// Directory the nexus I/O trace captures are dumped into.
+    pub io_trace_dir: String,
     #[structopt(short = "P")]
     /// Path to pool config file.
     pub pool_config: Option<String>,
@@ -145,7 +189,9 @@ pub struct MayastorCliArgs {
     /// Number of entries in memory pool for NVMe controller I/O contexts
     pub nvme_ctl_io_ctx_pool_size: u64,
     #[structopt(short = "T", long = "tgt-iface", env = "NVMF_TGT_IFACE")]
-    /// NVMF target interface (ip, mac, name or subnet).
+    /// NVMF target interface (ip, mac, name or subnet), IPv4 or IPv6.
+    /// A comma separated list makes the target listen on several interfaces,
+    /// the first one being the primary.
     pub nvmf_tgt_interface: Option<String>,
     /// api Version
     #[structopt(
@@ -204,12 +250,21 @@ impl Default for MayastorCliArgs {
             log_format: None,
             mayastor_config: None,
             ptpl_dir: None,
+            io_trace_dir: "/var/tmp/io-trace".to_string(),
             pool_config: None,
             hugedir: None,
             core_list: None,
             bdev_io_ctx_pool_size: 65535,
             nvme_ctl_io_ctx_pool_size: 65535,
             registration_endpoint: None,
+            grpc_tls_cert: None,
+            grpc_tls_key: None,
+            grpc_tls_client_ca: None,
+            grpc_uds_path: None,
+            metrics_endpoint: None,
+            registration_tls_ca: None,
+            registration_tls_cert: None,
+            registration_tls_key: None,
             nvmf_tgt_interface: None,
             api_versions: vec![ApiVersion::V0, ApiVersion::V1],
             diagnose_stack: None,
@@ -224,6 +279,39 @@ impl MayastorCliArgs {
     pub fn make_hostnqn(&self) -> Option<String> {
         make_hostnqn(self.node_name.as_ref())
     }
+
+    /// Get the transport options of the gRPC server.
+    pub fn grpc_server_options(&self) -> grpc::GrpcServerOptions {
+        let tls = match (
+            &self.grpc_tls_cert,
+            &self.grpc_tls_key,
+            &self.grpc_tls_client_ca,
+        ) {
+            (Some(cert), Some(key), Some(client_ca)) => {
+                Some(grpc::GrpcTlsFiles {
+                    cert: cert.clone(),
+                    key: key.clone(),
+                    client_ca: client_ca.clone(),
+                })
+            }
+            _ => None,
+        };
+        grpc::GrpcServerOptions {
+            tls,
+            uds_path: self.grpc_uds_path.clone(),
+        }
+    }
+
+    /// Get the TLS files of the registration client, if TLS is enabled.
+    pub fn registration_tls(&self) -> Option<RegistrationTlsFiles> {
+        self.registration_tls_ca
+            .as_ref()
+            .map(|ca_cert| RegistrationTlsFiles {
+                ca_cert: ca_cert.clone(),
+                cert: self.registration_tls_cert.clone(),
+                key: self.registration_tls_key.clone(),
+            })
+    }
 }
 
 /// Global exit code of the program, initially set to -1 to capture double
@@ -275,9 +363,11 @@ pub struct MayastorEnvironment {
     node_nqn: Option<String>,
     pub grpc_endpoint: Option<std::net::SocketAddr>,
     pub registration_endpoint: Option<Uri>,
+    grpc_server_options: grpc::GrpcServerOptions,
     persistent_store_endpoint: Option<String>,
     mayastor_config: Option<String>,
     ptpl_dir: Option<String>,
+    io_trace_dir: String,
     pool_config: Option<String>,
     delay_subsystem_init: bool,
     enable_coredump: bool,
@@ -317,9 +407,11 @@ impl Default for MayastorEnvironment {
             node_nqn: None,
             grpc_endpoint: None,
             registration_endpoint: None,
+            grpc_server_options: Default::default(),
             persistent_store_endpoint: None,
             mayastor_config: None,
             ptpl_dir: None,
+            io_trace_dir: "/var/tmp/io-trace".into(),
             pool_config: None,
             delay_subsystem_init: false,
             enable_coredump: true,
@@ -438,9 +530,11 @@ static MAYASTOR_DEFAULT_ENV: OnceCell<MayastorEnvironment> = OnceCell::new();
 
 impl MayastorEnvironment {
     pub fn new(args: MayastorCliArgs) -> Self {
+        let grpc_server_options = args.grpc_server_options();
         Self {
             grpc_endpoint: Some(grpc::endpoint(args.grpc_endpoint)),
             registration_endpoint: args.registration_endpoint,
+            grpc_server_options,
             persistent_store_endpoint: args.persistent_store_endpoint,
             node_name: args.node_name.clone().unwrap_or_else(|| {
                 env::var("HOSTNAME").unwrap_or_else(|_| "mayastor-node".into())
@@ -452,6 +546,7 @@ impl MayastorEnvironment {
             ),
             mayastor_config: args.mayastor_config,
             ptpl_dir: args.ptpl_dir,
+            io_trace_dir: args.io_trace_dir,
             pool_config: args.pool_config,
             log_component: args.log_components,
             mem_size: args.mem_size,
@@ -475,6 +570,11 @@ impl MayastorEnvironment {
         self.ptpl_dir.clone()
     }
 
+    /// Get the directory the nexus I/O trace captures are dumped into.
+    pub fn io_trace_dir(&self) -> String {
+        self.io_trace_dir.clone()
+    }
+
     fn setup_static(self) -> Self {
         MAYASTOR_DEFAULT_ENV.get_or_init(|| self.clone());
         self
@@ -657,14 +757,29 @@ impl MayastorEnvironment {
         }
     }
 
-    /// Returns NVMF target's IP address.
+    /// Returns NVMF target's primary IP address.
     pub(crate) fn get_nvmf_tgt_ip() -> Result<String, String> {
-        static TGT_IP: OnceCell<String> = OnceCell::new();
-        TGT_IP
+        Self::get_nvmf_tgt_ips().map(|ips| ips[0].clone())
+    }
+
+    /// Returns all IP addresses the NVMF target listens on, one per
+    /// interface given in CLI arguments. The first one is the primary address.
+    pub(crate) fn get_nvmf_tgt_ips() -> Result<Vec<String>, String> {
+        static TGT_IPS: OnceCell<Vec<String>> = OnceCell::new();
+        TGT_IPS
             .get_or_try_init(|| {
                 match Self::global_or_default().nvmf_tgt_interface {
-                    Some(ref iface) => Self::detect_nvmf_tgt_iface_ip(iface),
-                    None => Self::detect_pod_ip(),
+                    Some(ref ifaces) => {
+                        let mut ips = Vec::new();
+                        for iface in ifaces.split(',').map(str::trim) {
+                            let ip = Self::detect_nvmf_tgt_iface_ip(iface)?;
+                            if !ips.contains(&ip) {
+                                ips.push(ip);
+                            }
+                        }
+                        Ok(ips)
+                    }
+                    None => Self::detect_pod_ip().map(|ip| vec![ip]),
                 }
             })
             .map(|s| s.clone())
@@ -684,20 +799,38 @@ impl MayastorEnvironment {
             None => ("name", iface),
         };
 
+        // An interface selected by an IPv6 address or subnet must listen on
+        // that address, or on its address within that subnet, even if it has
+        // an IPv4 address configured.
+        let mut ipv6_addr = None;
+        let mut ipv6_subnet = None;
+
         let pred: Box<dyn Fn(&nic::Interface) -> bool> = match cls {
             "name" => Box::new(|n| n.name == name),
             "mac" => {
                 let mac = Some(name.parse::<nic::MacAddr>()?);
                 Box::new(move |n| n.mac == mac)
             }
-            "ip" => {
-                let addr = Some(nic::parse_ipv4(name)?);
-                Box::new(move |n| n.inet.addr == addr)
-            }
-            "subnet" => {
-                let (subnet, mask) = nic::parse_ipv4_subnet(name)?;
-                Box::new(move |n| n.ipv4_subnet_eq(subnet, mask))
-            }
+            "ip" => match nic::parse_ipv4(name) {
+                Ok(addr) => Box::new(move |n| n.inet.addr == Some(addr)),
+                Err(_) => {
+                    let addr = nic::parse_ipv6(name)?;
+                    ipv6_addr = Some(addr);
+                    Box::new(move |n| n.has_ipv6_addr(addr))
+                }
+            },
+            "subnet" => match nic::parse_ipv4_subnet(name) {
+                Ok((subnet, mask)) => {
+                    Box::new(move |n| n.ipv4_subnet_eq(subnet, mask))
+                }
+                Err(_) => {
+                    let (subnet, prefix) = nic::parse_ipv6_subnet(name)?;
+                    ipv6_subnet = Some((subnet, prefix));
+                    Box::new(move |n| {
+                        n.ipv6_subnet_addr(subnet, prefix).is_some()
+                    })
+                }
+            },
             _ => {
                 return Err(format!(
                     "Invalid NVMF target interface: '{iface}'",
@@ -728,14 +861,20 @@ impl MayastorEnvironment {
             iface, res
         );
 
-        if res.inet.addr.is_none() {
-            return Err(format!(
-                "Network interface '{}' has no IPv4 address configured",
-                res.name
-            ));
-        }
+        let addr = match (ipv6_addr, ipv6_subnet) {
+            (Some(addr), _) => Some(addr.to_string()),
+            (None, Some((subnet, prefix))) => {
+                res.ipv6_subnet_addr(subnet, prefix).map(|a| a.to_string())
+            }
+            (None, None) => res.tgt_addr(),
+        };
 
-        Ok(res.inet.addr.unwrap().to_string())
+        addr.ok_or_else(|| {
+            format!(
+                "Network interface '{}' has no IPv4 or IPv6 address configured",
+                res.name
+            )
+        })
     }
 
     /// Detects pod IP address.
@@ -747,12 +886,12 @@ impl MayastorEnvironment {
                         for NVMF target network interface"
                 );
 
-                if val.parse::<Ipv4Addr>().is_ok() {
+                if val.parse::<IpAddr>().is_ok() {
                     Ok(val)
                 } else {
                     Err(format!(
                         "MY_POD_IP environment variable is set to an \
-                            invalid IPv4 address: '{val}'"
+                            invalid IP address: '{val}'"
                     ))
                 }
             }
@@ -918,6 +1057,7 @@ impl MayastorEnvironment {
         let grpc_endpoint = self.grpc_endpoint;
         let rpc_addr = self.rpc_addr.clone();
         let api_versions = self.api_versions.clone();
+        let grpc_server_options = self.grpc_server_options.clone();
         let persistent_store_endpoint = self.persistent_store_endpoint.clone();
         let ms = self.init();
 
@@ -937,6 +1077,7 @@ impl MayastorEnvironment {
                     grpc_endpoint,
                     rpc_addr,
                     api_versions,
+                    grpc_server_options,
                 )));
             }
             futures.push(Box::pin(subsys::Registration::run()));
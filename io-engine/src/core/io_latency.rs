@@ -0,0 +1,329 @@
+//!
+//! Latency histograms of the I/O operations of a device.
+//!
+//! Latencies are recorded into per-core sets of atomic buckets, so that the
+//! I/O completion paths never contend on a lock, and merged into
+//! `IoLatencyStats` on request. Buckets are log-linear: every power of two
+//! of nanoseconds is split into `SUB_BUCKETS` linear buckets, which bounds
+//! the error of a percentile to 25% of its value.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use merge::Merge;
+use spdk_rs::libspdk::{spdk_get_ticks, spdk_get_ticks_hz};
+
+use super::{Cores, IoType};
+
+/// Number of bits of a latency used for the linear sub-buckets.
+const SUB_BUCKET_BITS: u32 = 2;
+/// Number of linear buckets per power of two.
+const SUB_BUCKETS: usize = 1 << SUB_BUCKET_BITS;
+/// Latencies from 2^36 ns (about 68 seconds) are all counted in the last
+/// bucket.
+const MAX_EXPONENT: u32 = 36;
+/// Number of buckets of a histogram.
+pub const LATENCY_BUCKETS: usize =
+    (MAX_EXPONENT - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + SUB_BUCKETS;
+
+const NSEC_PER_SEC: u128 = 1_000_000_000;
+
+/// Returns the index of the bucket a latency in nanoseconds is counted in.
+#[inline]
+fn bucket_index(latency_ns: u64) -> usize {
+    if latency_ns < SUB_BUCKETS as u64 {
+        return latency_ns as usize;
+    }
+    let exp = 63 - latency_ns.leading_zeros();
+    if exp > MAX_EXPONENT {
+        return LATENCY_BUCKETS - 1;
+    }
+    let sub =
+        (latency_ns >> (exp - SUB_BUCKET_BITS)) as usize & (SUB_BUCKETS - 1);
+    (exp - SUB_BUCKET_BITS + 1) as usize * SUB_BUCKETS + sub
+}
+
+/// Returns the exclusive upper bound of a bucket, in nanoseconds.
+fn bucket_upper_bound(index: usize) -> u64 {
+    if index < SUB_BUCKETS {
+        return index as u64 + 1;
+    }
+    if index == LATENCY_BUCKETS - 1 {
+        return u64::MAX;
+    }
+    let exp = (index / SUB_BUCKETS) as u32 + SUB_BUCKET_BITS - 1;
+    let sub = (index % SUB_BUCKETS) as u64;
+    (SUB_BUCKETS as u64 + sub + 1) << (exp - SUB_BUCKET_BITS)
+}
+
+/// Returns the current time in ticks, to be passed to
+/// `IoLatencyRecorder::record` once the I/O completes.
+#[inline]
+pub fn io_start_ticks() -> u64 {
+    unsafe { spdk_get_ticks() }
+}
+
+/// Latency histogram of a type of I/O operation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LatencyHistogram {
+    buckets: Vec<u64>,
+    sum_ns: u64,
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; LATENCY_BUCKETS],
+            sum_ns: 0,
+        }
+    }
+}
+
+impl Merge for LatencyHistogram {
+    fn merge(&mut self, other: Self) {
+        for (b, o) in self.buckets.iter_mut().zip(other.buckets) {
+            *b = b.saturating_add(o);
+        }
+        self.sum_ns = self.sum_ns.saturating_add(other.sum_ns);
+    }
+}
+
+impl LatencyHistogram {
+    /// Number of recorded I/O operations.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Sum of the latencies of the recorded I/O operations, in nanoseconds.
+    pub fn sum_ns(&self) -> u64 {
+        self.sum_ns
+    }
+
+    /// Returns the non-empty buckets as pairs of their exclusive upper bound
+    /// in nanoseconds and their count, in increasing order of latency.
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.buckets
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(i, count)| (bucket_upper_bound(i), *count))
+    }
+
+    /// Returns the number of recorded I/O operations below each of the given
+    /// bounds in nanoseconds, which are expected in increasing order.
+    /// Operations are counted by bucket, so a bound which is not a bucket
+    /// boundary only counts the buckets below it.
+    pub fn cumulative_counts(&self, bounds_ns: &[u64]) -> Vec<u64> {
+        let mut seen = 0;
+        let mut buckets = self.buckets().peekable();
+        bounds_ns
+            .iter()
+            .map(|bound| {
+                while let Some((_, n)) =
+                    buckets.next_if(|(upper, _)| upper <= bound)
+                {
+                    seen += n;
+                }
+                seen
+            })
+            .collect()
+    }
+
+    /// Returns the upper bound in nanoseconds of the bucket holding the given
+    /// percentile, e.g. 99.9, or None if no I/O was recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((percentile / 100.0) * count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        self.buckets().find_map(|(bound, n)| {
+            seen += n;
+            (seen >= rank).then_some(bound)
+        })
+    }
+}
+
+/// Latency histograms of the I/O operations of a device.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Merge)]
+pub struct IoLatencyStats {
+    pub read: LatencyHistogram,
+    pub write: LatencyHistogram,
+    pub unmap: LatencyHistogram,
+    pub flush: LatencyHistogram,
+}
+
+/// Buckets of a type of I/O operation on a core.
+struct AtomicHistogram {
+    buckets: [AtomicU64; LATENCY_BUCKETS],
+    sum_ns: AtomicU64,
+}
+
+impl AtomicHistogram {
+    fn new() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            sum_ns: AtomicU64::new(0),
+        }
+    }
+
+    fn load_into(&self, hist: &mut LatencyHistogram) {
+        for (b, a) in hist.buckets.iter_mut().zip(&self.buckets) {
+            *b = b.saturating_add(a.load(Ordering::Relaxed));
+        }
+        hist.sum_ns = hist
+            .sum_ns
+            .saturating_add(self.sum_ns.load(Ordering::Relaxed));
+    }
+
+    fn reset(&self) {
+        self.buckets
+            .iter()
+            .for_each(|b| b.store(0, Ordering::Relaxed));
+        self.sum_ns.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Histograms of a core, aligned to a cache line to avoid false sharing
+/// between the cores.
+#[repr(align(64))]
+struct CoreHistograms {
+    read: AtomicHistogram,
+    write: AtomicHistogram,
+    unmap: AtomicHistogram,
+    flush: AtomicHistogram,
+}
+
+impl CoreHistograms {
+    fn new() -> Self {
+        Self {
+            read: AtomicHistogram::new(),
+            write: AtomicHistogram::new(),
+            unmap: AtomicHistogram::new(),
+            flush: AtomicHistogram::new(),
+        }
+    }
+
+    fn all(&self) -> [&AtomicHistogram; 4] {
+        [&self.read, &self.write, &self.unmap, &self.flush]
+    }
+}
+
+/// Records the latencies of the I/O operations of a device, per core.
+pub struct IoLatencyRecorder {
+    cores: Box<[CoreHistograms]>,
+    tick_rate: u64,
+}
+
+impl std::fmt::Debug for IoLatencyRecorder {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("IoLatencyRecorder")
+            .field("cores", &self.cores.len())
+            .finish()
+    }
+}
+
+impl Default for IoLatencyRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl IoLatencyRecorder {
+    /// Creates a recorder with histograms for every reactor core.
+    pub fn new() -> Self {
+        let num_cores = Cores::last().id() as usize + 1;
+        Self {
+            cores: (0 .. num_cores).map(|_| CoreHistograms::new()).collect(),
+            tick_rate: unsafe { spdk_get_ticks_hz() },
+        }
+    }
+
+    /// Records the latency of an I/O operation started at the given ticks,
+    /// as obtained from `io_start_ticks`. Only reads, writes, unmaps and
+    /// flushes are recorded.
+    #[inline]
+    pub fn record(&self, op: IoType, start_ticks: u64) {
+        let core = &self.cores[Cores::current() as usize % self.cores.len()];
+        let hist = match op {
+            IoType::Read => &core.read,
+            IoType::Write => &core.write,
+            IoType::Unmap => &core.unmap,
+            IoType::Flush => &core.flush,
+            _ => return,
+        };
+
+        let ticks = io_start_ticks().saturating_sub(start_ticks);
+        let latency_ns = (ticks as u128 * NSEC_PER_SEC
+            / self.tick_rate.max(1) as u128) as u64;
+        hist.buckets[bucket_index(latency_ns)].fetch_add(1, Ordering::Relaxed);
+        hist.sum_ns.fetch_add(latency_ns, Ordering::Relaxed);
+    }
+
+    /// Merges the histograms of all the cores.
+    pub fn stats(&self) -> IoLatencyStats {
+        let mut stats = IoLatencyStats::default();
+        for core in self.cores.iter() {
+            core.read.load_into(&mut stats.read);
+            core.write.load_into(&mut stats.write);
+            core.unmap.load_into(&mut stats.unmap);
+            core.flush.load_into(&mut stats.flush);
+        }
+        stats
+    }
+
+    /// Resets the histograms of all the cores. Operations completing during
+    /// the reset may or may not be accounted.
+    pub fn reset(&self) {
+        self.cores
+            .iter()
+            .flat_map(|core| core.all())
+            .for_each(AtomicHistogram::reset);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets_bound_their_latencies() {
+        let mut prev = 0;
+        for latency in (0 .. 10_000).chain([1 << 20, 12_345_678, 1 << 36]) {
+            let index = bucket_index(latency);
+            assert!(index >= prev);
+            assert!(latency < bucket_upper_bound(index));
+            if index > 0 {
+                assert!(latency >= bucket_upper_bound(index - 1));
+            }
+            prev = index;
+        }
+        assert_eq!(bucket_index(u64::MAX), LATENCY_BUCKETS - 1);
+    }
+
+    #[test]
+    fn percentiles() {
+        let mut hist = LatencyHistogram::default();
+        assert_eq!(hist.percentile(99.0), None);
+
+        for latency in 1 ..= 1000 {
+            hist.buckets[bucket_index(latency * 1000)] += 1;
+        }
+        let p50 = hist.percentile(50.0).unwrap();
+        assert!((500_000 .. 640_000).contains(&p50));
+        let p99 = hist.percentile(99.0).unwrap();
+        assert!((990_000 .. 1_280_000).contains(&p99));
+        assert_eq!(hist.count(), 1000);
+    }
+
+    #[test]
+    fn cumulative_counts() {
+        let mut hist = LatencyHistogram::default();
+        for latency in [100, 1000, 1000, 5000, 1 << 20] {
+            hist.buckets[bucket_index(latency)] += 1;
+        }
+        assert_eq!(
+            hist.cumulative_counts(&[64, 1 << 10, 1 << 13, 1 << 30]),
+            vec![0, 3, 4, 5]
+        );
+    }
+}
@@ -42,6 +42,12 @@ pub use env::{
 };
 pub use handle::{BdevHandle, UntypedBdevHandle};
 pub use io_device::IoDevice;
+pub use io_latency::{
+    io_start_ticks,
+    IoLatencyRecorder,
+    IoLatencyStats,
+    LatencyHistogram,
+};
 pub use logical_volume::LogicalVolume;
 pub use reactor::{
     reactor_monitor_loop,
@@ -82,6 +88,7 @@ mod env;
 mod handle;
 mod io_device;
 pub mod io_driver;
+mod io_latency;
 pub mod lock;
 pub mod logical_volume;
 pub mod mempool;
@@ -302,6 +309,10 @@ pub enum CoreError {
     SnapshotCreate {
         reason: String,
     },
+    #[snafu(display("Failed to destroy device snapshot: {}", reason))]
+    SnapshotDestroy {
+        reason: String,
+    },
 }
 
 /// Transform error into errno code.
@@ -122,8 +122,8 @@ pub struct Interface {
     pub name: String,
     /// IPv4 network address and netmask of this interface.
     pub inet: InetConfig<Ipv4Addr>,
-    /// IPv6 network address and netmask of this interface.
-    pub inet6: InetConfig<Ipv6Addr>,
+    /// IPv6 network addresses and netmasks of this interface.
+    pub inet6: Vec<InetConfig<Ipv6Addr>>,
     /// MAC address of this interface.
     pub mac: Option<MacAddr>,
 }
@@ -155,6 +155,59 @@ impl Interface {
 
         subnet == net_addr
     }
+
+    /// Tests if the given IPv6 address is configured on the interface.
+    pub fn has_ipv6_addr(&self, addr: Ipv6Addr) -> bool {
+        self.inet6.iter().any(|i| i.addr == Some(addr))
+    }
+
+    /// Returns the IPv6 address of the interface which belongs to the given
+    /// IPv6 subnet, if any.
+    pub fn ipv6_subnet_addr(
+        &self,
+        net_addr: Ipv6Addr,
+        prefix_len: u32,
+    ) -> Option<Ipv6Addr> {
+        self.inet6.iter().find_map(|inet6| {
+            let (addr, mask) = match (inet6.addr, inet6.netmask) {
+                (Some(addr), Some(mask)) => (addr, mask),
+                _ => return None,
+            };
+
+            let mask = u128::from(mask.to_std());
+            if mask.count_ones() != prefix_len {
+                return None;
+            }
+
+            let subnet = u128::from(addr.to_std()) & mask;
+            let subnet = Ipv6Addr::from_std(&std::net::Ipv6Addr::from(subnet));
+
+            (subnet == net_addr).then_some(addr)
+        })
+    }
+
+    /// Returns the IPv6 address of the interface: a global one if any, as an
+    /// interface typically has a link-local address besides its global ones.
+    pub fn ipv6_addr(&self) -> Option<Ipv6Addr> {
+        let mut addrs = self.inet6.iter().filter_map(|i| i.addr);
+        let first = addrs.clone().next();
+        addrs.find(|a| !is_ipv6_link_local(a)).or(first)
+    }
+
+    /// Returns the address the NVMF target should listen on: the IPv4 address
+    /// if one is configured, otherwise the IPv6 one.
+    pub fn tgt_addr(&self) -> Option<String> {
+        self.inet
+            .addr
+            .map(|a| a.to_string())
+            .or_else(|| self.ipv6_addr().map(|a| a.to_string()))
+    }
+}
+
+/// Tests if the IPv6 address is a link-local (fe80::/10) one, which can't be
+/// used without a scope and hence is not usable for NVMF.
+fn is_ipv6_link_local(addr: &Ipv6Addr) -> bool {
+    (addr.to_std().segments()[0] & 0xffc0) == 0xfe80
 }
 
 impl fmt::Display for Interface {
@@ -166,12 +219,22 @@ impl fmt::Display for Interface {
             }
         }
 
+        let inet6 = if self.inet6.is_empty() {
+            "-".to_string()
+        } else {
+            self.inet6
+                .iter()
+                .map(|i| i.to_string())
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+
         write!(
             f,
             "{}: inet {} inet6 {} mac {}",
             self.name,
             self.inet,
-            self.inet6,
+            inet6,
             fmt_opt(&self.mac)
         )
     }
@@ -186,11 +249,27 @@ pub fn find_all_nics() -> Vec<Interface> {
             .entry(addr.interface_name)
             .or_insert_with_key(|k| Interface::new(k));
 
+        let netmask = match addr.netmask {
+            Some(SockAddr::Inet(inet)) => Some(inet.ip()),
+            _ => None,
+        };
+
         if let Some(sock) = addr.address {
             match sock {
                 SockAddr::Inet(inet) => match inet.ip() {
-                    IpAddr::V4(v4) => nic.inet.addr = Some(v4),
-                    IpAddr::V6(v6) => nic.inet6.addr = Some(v6),
+                    IpAddr::V4(v4) => {
+                        nic.inet.addr = Some(v4);
+                        if let Some(IpAddr::V4(mask)) = netmask {
+                            nic.inet.netmask = Some(mask);
+                        }
+                    }
+                    IpAddr::V6(v6) => nic.inet6.push(InetConfig {
+                        addr: Some(v6),
+                        netmask: match netmask {
+                            Some(IpAddr::V6(mask)) => Some(mask),
+                            _ => None,
+                        },
+                    }),
                 },
                 SockAddr::Link(link) => {
                     nic.mac = Some(MacAddr::new(link.addr()))
@@ -198,13 +277,6 @@ pub fn find_all_nics() -> Vec<Interface> {
                 _ => {}
             }
         }
-
-        if let Some(SockAddr::Inet(inet)) = addr.netmask {
-            match inet.ip() {
-                IpAddr::V4(v4) => nic.inet.netmask = Some(v4),
-                IpAddr::V6(v6) => nic.inet6.netmask = Some(v6),
-            }
-        }
     }
 
     nics.into_values().collect()
@@ -242,3 +314,90 @@ pub fn parse_ipv4_subnet(addr_str: &str) -> Result<(Ipv4Addr, u32), String> {
     let subnet = Ipv4Addr::from_std(&std::net::Ipv4Addr::from(subnet));
     Ok((subnet, mask))
 }
+
+/// Utility to parse an IPv6 address string into a nix's Ipv6Addr.
+/// The address may be enclosed in brackets.
+pub fn parse_ipv6(addr: &str) -> Result<Ipv6Addr, String> {
+    let addr = addr
+        .strip_prefix('[')
+        .and_then(|a| a.strip_suffix(']'))
+        .unwrap_or(addr);
+    let res = addr
+        .parse::<std::net::Ipv6Addr>()
+        .map_err(|e| e.to_string())?;
+    Ok(Ipv6Addr::from_std(&res))
+}
+
+/// Utility to parse an IPv6 subnet string into a nix's Ipv6Addr and the
+/// prefix length.
+pub fn parse_ipv6_subnet(addr_str: &str) -> Result<(Ipv6Addr, u32), String> {
+    let (addr, bits) = match addr_str.split_once('/') {
+        Some(p) => p,
+        None => return Err(format!("Invalid subnet: '{addr_str}'")),
+    };
+
+    let addr = u128::from(parse_ipv6(addr)?.to_std());
+
+    let bits = bits
+        .parse::<u32>()
+        .map_err(|e| format!("Invalid subnet '{addr_str}': {e}"))?;
+
+    if bits > 128 {
+        return Err(format!("Invalid subnet '{addr_str}': suffix too large"));
+    }
+
+    let mask = (!0u128).checked_shl(128 - bits).unwrap_or(0);
+
+    let subnet = addr & mask;
+    let subnet = Ipv6Addr::from_std(&std::net::Ipv6Addr::from(subnet));
+    Ok((subnet, bits))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ipv6_subnet() {
+        let (subnet, prefix) = parse_ipv6_subnet("fd00:0:0:1::5/64").unwrap();
+        assert_eq!(subnet, parse_ipv6("fd00:0:0:1::").unwrap());
+        assert_eq!(prefix, 64);
+
+        let (subnet, _) = parse_ipv6_subnet("[fd00::1]/128").unwrap();
+        assert_eq!(subnet, parse_ipv6("fd00::1").unwrap());
+
+        assert!(parse_ipv6_subnet("fd00::1").is_err());
+        assert!(parse_ipv6_subnet("fd00::1/129").is_err());
+        assert!(parse_ipv6_subnet("10.0.0.0/8").is_err());
+    }
+
+    #[test]
+    fn ipv6_subnet_addr() {
+        let inet6 = |addr: &str, prefix: &str| InetConfig {
+            addr: Some(parse_ipv6(addr).unwrap()),
+            netmask: Some(parse_ipv6(prefix).unwrap()),
+        };
+        let mut nic = Interface::new("eth0");
+        nic.inet6 = vec![
+            inet6("fe80::1", "ffff:ffff:ffff:ffff::"),
+            inet6("fd00:0:0:1::5", "ffff:ffff:ffff:ffff::"),
+            inet6("fd00:0:0:2::5", "ffff:ffff:ffff:ffff::"),
+        ];
+
+        let (subnet, prefix) = parse_ipv6_subnet("fd00:0:0:2::/64").unwrap();
+        assert_eq!(
+            nic.ipv6_subnet_addr(subnet, prefix),
+            Some(parse_ipv6("fd00:0:0:2::5").unwrap())
+        );
+
+        let (subnet, prefix) = parse_ipv6_subnet("fd00:0:0:3::/64").unwrap();
+        assert_eq!(nic.ipv6_subnet_addr(subnet, prefix), None);
+
+        let (subnet, prefix) = parse_ipv6_subnet("fd00::/48").unwrap();
+        assert_eq!(nic.ipv6_subnet_addr(subnet, prefix), None);
+
+        assert!(nic.has_ipv6_addr(parse_ipv6("fd00:0:0:2::5").unwrap()));
+        assert_eq!(nic.ipv6_addr(), Some(parse_ipv6("fd00:0:0:1::5").unwrap()));
+        assert_eq!(nic.tgt_addr(), Some("fd00:0:0:1::5".to_string()));
+    }
+}
@@ -11,6 +11,8 @@ pub enum Protocol {
     Off,
     /// shared as NVMe-oF TCP
     Nvmf,
+    /// shared as a local ublk block device
+    Ublk,
 }
 
 impl TryFrom<i32> for Protocol {
@@ -21,6 +23,7 @@ impl TryFrom<i32> for Protocol {
             0 => Ok(Self::Off),
             1 => Ok(Self::Nvmf),
             // 2 was for iSCSI
+            3 => Ok(Self::Ublk),
             // the gRPC code does not validate enums so we have
             // to do it here
             _ => Err(LvsError::ReplicaShareProtocol {
@@ -35,6 +38,7 @@ impl Display for Protocol {
         let p = match self {
             Self::Off => "Not shared",
             Self::Nvmf => "NVMe-oF TCP",
+            Self::Ublk => "ublk",
         };
         write!(f, "{p}")
     }
@@ -69,6 +73,8 @@ pub struct ShareProps {
     allowed_hosts: Vec<String>,
     /// Persistent-Power-Loss settings.
     ptpl: Option<PtplProps>,
+    /// Target addresses to listen on, all of them if empty.
+    listeners: Vec<String>,
 }
 impl ShareProps {
     /// Returns a new `Self`.
@@ -119,6 +125,16 @@ impl ShareProps {
     pub fn ptpl(&self) -> &Option<PtplProps> {
         &self.ptpl
     }
+    /// Modify the target addresses the share listens on.
+    #[must_use]
+    pub fn with_listeners(mut self, listeners: Vec<String>) -> Self {
+        self.listeners = listeners;
+        self
+    }
+    /// Get the target addresses the share listens on, all if empty.
+    pub fn listeners(&self) -> &Vec<String> {
+        &self.listeners
+    }
 }
 impl From<Option<ShareProps>> for ShareProps {
     fn from(opts: Option<ShareProps>) -> Self {
@@ -134,6 +150,8 @@ impl From<Option<ShareProps>> for ShareProps {
 pub struct UpdateProps {
     /// Hosts allowed to connect.
     allowed_hosts: Vec<String>,
+    /// Target addresses to listen on, unchanged if `None`.
+    listeners: Option<Vec<String>>,
 }
 impl UpdateProps {
     /// Returns a new `Self`.
@@ -154,6 +172,16 @@ impl UpdateProps {
     pub fn allowed_hosts(&self) -> &Vec<String> {
         &self.allowed_hosts
     }
+    /// Modify the target addresses the share listens on, all if empty.
+    #[must_use]
+    pub fn with_listeners(mut self, listeners: Vec<String>) -> Self {
+        self.listeners = Some(listeners);
+        self
+    }
+    /// Target addresses the share listens on, if they are to be changed.
+    pub fn listeners(&self) -> Option<&Vec<String>> {
+        self.listeners.as_ref()
+    }
 }
 impl From<Option<UpdateProps>> for UpdateProps {
     fn from(opts: Option<UpdateProps>) -> Self {
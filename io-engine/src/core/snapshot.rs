@@ -132,6 +132,12 @@ pub trait SnapshotOps {
         snap_param: SnapshotParams,
     ) -> Result<Lvol, Self::Error>;
 
+    /// Destroy a snapshot of the volume, given its UUID.
+    async fn destroy_snapshot(
+        &self,
+        snapshot_uuid: &str,
+    ) -> Result<(), Self::Error>;
+
     // Get a Snapshot Iterator.
     async fn snapshot_iter(self) -> Self::SnapshotIter;
 
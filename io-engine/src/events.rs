@@ -0,0 +1,285 @@
+//!
+//! Events about the state changes of the engine objects.
+//!
+//! Every published event gets the next value of a monotonic sequence number
+//! and is kept in a bounded backlog, so that a watcher which reconnects can
+//! resume right after the last event it received, as long as that event is
+//! still in the backlog. The sequence restarts from 1 when the engine
+//! restarts.
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::channel::mpsc;
+use once_cell::sync::OnceCell;
+
+use crate::{
+    bdev::nexus::{nexus_lookup, FaultReason, NexusStatus},
+    core::{Reactor, Reactors, VerboseError},
+    lvs::Lvs,
+};
+
+/// Number of past events kept for the watchers resuming after a reconnect.
+const EVENT_BACKLOG: usize = 4096;
+
+/// Number of events queued for a watcher, beyond which the watcher is
+/// considered lagging and is dropped.
+const WATCHER_QUEUE_DEPTH: usize = 1024;
+
+/// Interval between the checks of the free space of all pools, which thin
+/// provisioned replicas consume as they are written to.
+const POOL_SPACE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Free space of a pool, in percent of its capacity, below which the pool is
+/// reported as low on space.
+const POOL_LOW_SPACE_PERCENT: u64 = 10;
+
+/// An event about a state change of an engine object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineEvent {
+    /// A nexus child has been faulted.
+    ChildFaulted {
+        nexus: String,
+        child: String,
+        reason: FaultReason,
+    },
+    /// A previously faulted or offlined nexus child has been onlined.
+    ChildOnlined { nexus: String, child: String },
+    /// The status of a nexus has changed.
+    NexusStatusChanged {
+        nexus: String,
+        uuid: String,
+        status: NexusStatus,
+    },
+    /// A rebuild of a nexus child has started, or resumed after a pause.
+    RebuildStarted {
+        nexus: String,
+        child: String,
+        src_uri: String,
+    },
+    /// A rebuild of a nexus child has completed successfully.
+    RebuildCompleted { nexus: String, child: String },
+    /// A rebuild of a nexus child has failed.
+    RebuildFailed {
+        nexus: String,
+        child: String,
+        error: String,
+    },
+    /// A pool has been created.
+    PoolCreated { name: String, uuid: String },
+    /// A pool has been destroyed.
+    PoolDestroyed { name: String, uuid: String },
+    /// The free space of a pool fell below the low space threshold.
+    PoolLowSpace {
+        name: String,
+        uuid: String,
+        capacity: u64,
+        available: u64,
+    },
+    /// A snapshot of a replica has been created.
+    SnapshotCreated {
+        pool_uuid: String,
+        source_uuid: String,
+        snapshot_uuid: String,
+        snapshot_name: String,
+    },
+}
+
+/// A published event.
+#[derive(Debug, Clone)]
+pub struct EventRecord {
+    /// Sequence number of the event.
+    pub sequence: u64,
+    /// Time the event was published at.
+    pub timestamp: DateTime<Utc>,
+    /// The event.
+    pub event: EngineEvent,
+}
+
+/// A watch on the published events.
+#[derive(Debug)]
+pub struct EventWatch {
+    /// Events published before the watch started, which the watcher has not
+    /// received yet.
+    pub backlog: Vec<EventRecord>,
+    /// Events published from now on, until the watcher lags behind by more
+    /// than `WATCHER_QUEUE_DEPTH` events.
+    pub receiver: mpsc::Receiver<EventRecord>,
+}
+
+/// Failure to resume a watch.
+#[derive(Debug, Clone, Copy)]
+pub struct EventsLost {
+    /// Sequence number of the oldest event in the backlog, if any.
+    pub oldest: Option<u64>,
+    /// Sequence number of the latest published event.
+    pub latest: u64,
+}
+
+/// Published events and their watchers.
+#[derive(Default)]
+struct EventBus {
+    /// Sequence number of the latest published event.
+    sequence: u64,
+    backlog: VecDeque<EventRecord>,
+    watchers: Vec<mpsc::Sender<EventRecord>>,
+    /// Last published status of each nexus.
+    nexus_status: HashMap<String, NexusStatus>,
+    /// Pools which have been reported as low on space.
+    low_space_pools: HashSet<String>,
+}
+
+impl EventBus {
+    /// Get the event bus, which can be used from any thread.
+    fn get<'a>() -> parking_lot::MutexGuard<'a, EventBus> {
+        static EVENT_BUS: OnceCell<parking_lot::Mutex<EventBus>> =
+            OnceCell::new();
+
+        EVENT_BUS
+            .get_or_init(|| parking_lot::Mutex::new(EventBus::default()))
+            .lock()
+    }
+
+    fn publish(&mut self, event: EngineEvent) {
+        self.sequence += 1;
+        let record = EventRecord {
+            sequence: self.sequence,
+            timestamp: Utc::now(),
+            event,
+        };
+        debug!(
+            sequence = record.sequence,
+            event = ?record.event,
+            "Publishing engine event"
+        );
+
+        self.watchers
+            .retain_mut(|w| match w.try_send(record.clone()) {
+                Ok(()) => true,
+                Err(error) => {
+                    if error.is_full() {
+                        warn!(
+                            sequence = record.sequence,
+                            "Dropping an event watcher which is lagging behind"
+                        );
+                    }
+                    false
+                }
+            });
+        if self.backlog.len() == EVENT_BACKLOG {
+            self.backlog.pop_front();
+        }
+        self.backlog.push_back(record);
+    }
+}
+
+impl EngineEvent {
+    /// Publishes the event to the watchers.
+    pub fn publish(self) {
+        EventBus::get().publish(self);
+    }
+}
+
+/// Starts watching the events, resuming right after the event with the
+/// given sequence number if given.
+/// Fails if some of the events to resume from are no longer in the backlog,
+/// or were published by a previous instance of the engine.
+/// The receiver is closed if the watcher lags behind, in which case the
+/// watch must be resumed after the last received event.
+pub fn watch_events(after: Option<u64>) -> Result<EventWatch, EventsLost> {
+    let mut bus = EventBus::get();
+
+    let backlog = match after {
+        None => Vec::new(),
+        Some(after) => {
+            let oldest = bus.backlog.front().map(|r| r.sequence);
+            if after > bus.sequence
+                || oldest.map_or(after < bus.sequence, |o| after + 1 < o)
+            {
+                return Err(EventsLost {
+                    oldest,
+                    latest: bus.sequence,
+                });
+            }
+            bus.backlog
+                .iter()
+                .filter(|r| r.sequence > after)
+                .cloned()
+                .collect()
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel(WATCHER_QUEUE_DEPTH);
+    bus.watchers.push(sender);
+    Ok(EventWatch {
+        backlog,
+        receiver,
+    })
+}
+
+/// Schedules a check of the status of the nexus, publishing an event if it
+/// changed since it was last published.
+/// The check is deferred to the master reactor, so that this can be called
+/// while the nexus or its children are being modified.
+pub(crate) fn nexus_status_check(nexus_name: &str) {
+    let nexus_name = nexus_name.to_string();
+    Reactors::master().send_future(async move {
+        let nexus = nexus_lookup(&nexus_name);
+        let mut bus = EventBus::get();
+        let Some(nexus) = nexus else {
+            bus.nexus_status.remove(&nexus_name);
+            return;
+        };
+
+        let status = nexus.status();
+        if bus.nexus_status.insert(nexus_name.clone(), status) != Some(status) {
+            bus.publish(EngineEvent::NexusStatusChanged {
+                nexus: nexus_name,
+                uuid: nexus.uuid().to_string(),
+                status,
+            });
+        }
+    });
+}
+
+/// Publishes an event if the free space of the pool fell below the low space
+/// threshold since the last check.
+pub(crate) fn pool_space_check(pool: &Lvs) {
+    let capacity = pool.capacity();
+    let available = pool.available();
+    let low = available * 100 < capacity * POOL_LOW_SPACE_PERCENT;
+
+    let mut bus = EventBus::get();
+    if !low {
+        bus.low_space_pools.remove(&pool.uuid());
+    } else if bus.low_space_pools.insert(pool.uuid()) {
+        bus.publish(EngineEvent::PoolLowSpace {
+            name: pool.name().to_string(),
+            uuid: pool.uuid(),
+            capacity,
+            available,
+        });
+    }
+}
+
+/// Periodically checks the free space of all pools, publishing an event for
+/// the pools which fell below the low space threshold.
+pub async fn pool_space_monitor_loop() {
+    let mut interval = tokio::time::interval(POOL_SPACE_CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        let rx = Reactor::spawn_at_primary(async {
+            Lvs::iter().for_each(|pool| pool_space_check(&pool));
+        });
+        match rx {
+            Ok(rx) => {
+                rx.await.ok();
+            }
+            Err(e) => {
+                error!("Failed to schedule pool space checks: {}", e.verbose())
+            }
+        }
+    }
+}
@@ -1,6 +1,11 @@
 use crate::{
-    bdev::{NvmeController, NvmeControllerState, NVME_CONTROLLERS},
-    core::{BlockDeviceIoStats, CoreError},
+    bdev::{
+        NvmeController,
+        NvmeControllerState,
+        NvmePathInfo,
+        NVME_CONTROLLERS,
+    },
+    core::{BlockDeviceIoStats, CoreError, IoLatencyStats},
     ffihelper::{cb_arg, done_cb},
 };
 use futures::channel::oneshot;
@@ -11,6 +16,7 @@ pub struct NvmeControllerInfo {
     pub state: NvmeControllerState,
     pub size: u64,
     pub blk_size: u32,
+    pub paths: Vec<NvmePathInfo>,
 }
 
 impl<'a> NvmeController<'a> {
@@ -24,6 +30,7 @@ impl<'a> NvmeController<'a> {
             state: self.get_state(),
             size,
             blk_size,
+            paths: self.path_info(),
         }
     }
 }
@@ -58,6 +65,25 @@ pub async fn controller_stats(
     }
 }
 
+/// Returns the I/O latency histograms of the given NVMe Controller,
+/// resetting them afterwards if requested.
+pub fn controller_io_latency(
+    controller_name: &str,
+    reset: bool,
+) -> Result<IoLatencyStats, CoreError> {
+    let ctrlr = NVME_CONTROLLERS
+        .lookup_by_name(controller_name)
+        .ok_or_else(|| CoreError::BdevNotFound {
+            name: controller_name.to_string(),
+        })?;
+    let io_latency = ctrlr.lock().io_latency();
+    let stats = io_latency.stats();
+    if reset {
+        io_latency.reset();
+    }
+    Ok(stats)
+}
+
 /// Lists all the NVMe Controllers
 pub async fn list_controllers() -> Vec<NvmeControllerInfo> {
     NVME_CONTROLLERS
@@ -6,12 +6,14 @@ use std::{
 };
 
 use futures::channel::oneshot::Receiver;
-pub use server::MayastorGrpcServer;
+pub use server::{GrpcServerOptions, GrpcTlsFiles, MayastorGrpcServer};
 use tonic::{Request, Response, Status};
 
 use crate::{
     bdev_api::BdevError,
     core::{CoreError, Reactor},
+    rebuild::RebuildError,
+    subsys::NvmfError,
 };
 
 impl From<BdevError> for tonic::Status {
@@ -26,6 +28,33 @@ impl From<BdevError> for tonic::Status {
             BdevError::InvalidUri {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            BdevError::CryptKeyInvalid {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            BdevError::CryptKeyMismatch {
+                ..
+            } => Status::permission_denied(e.to_string()),
+            BdevError::CryptBaseInvalid {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            BdevError::DelayLatencyInvalid {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            BdevError::ErrorRuleInvalid {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            BdevError::ErrorRuleNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            BdevError::ResizeBdevInvalid {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            BdevError::CorDeviceInvalid {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            BdevError::CompressBaseInvalid {
+                ..
+            } => Status::failed_precondition(e.to_string()),
             e => Status::internal(e.to_string()),
         }
     }
@@ -37,6 +66,50 @@ impl From<CoreError> for tonic::Status {
     }
 }
 
+impl From<NvmfError> for tonic::Status {
+    fn from(e: NvmfError) -> Self {
+        match e {
+            NvmfError::Subsystem {
+                source: nix::errno::Errno::ENOENT,
+                ..
+            } => Status::not_found(e.to_string()),
+            NvmfError::HostCstrNul {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            e => Status::internal(e.to_string()),
+        }
+    }
+}
+
+impl From<RebuildError> for tonic::Status {
+    fn from(e: RebuildError) -> Self {
+        match e {
+            RebuildError::JobNotFound {
+                ..
+            } => Status::not_found(e.to_string()),
+            RebuildError::JobAlreadyExists {
+                ..
+            } => Status::already_exists(e.to_string()),
+            RebuildError::InvalidParameters {
+                ..
+            }
+            | RebuildError::InvalidReplicationTarget {
+                ..
+            }
+            | RebuildError::SnapshotChanges {
+                ..
+            } => Status::invalid_argument(e.to_string()),
+            RebuildError::OpError {
+                ..
+            }
+            | RebuildError::StatePending {
+                ..
+            } => Status::failed_precondition(e.to_string()),
+            e => Status::internal(e.to_string()),
+        }
+    }
+}
+
 pub mod controller_grpc;
 mod server;
 pub mod v0 {
@@ -47,12 +120,14 @@ pub mod v0 {
 }
 pub mod v1 {
     pub mod bdev;
+    pub mod event;
     pub mod host;
     pub mod json;
     pub mod nexus;
     pub mod pool;
     pub mod replica;
     pub mod snapshot;
+    pub mod stats;
 }
 
 /// Default timeout for gRPC calls, in seconds. Should be enforced in case
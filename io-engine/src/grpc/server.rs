@@ -6,6 +6,7 @@ use super::{
     },
     v1::{
         bdev::BdevService,
+        event::EventService,
         host::HostService,
         json::JsonService,
         nexus::NexusService,
@@ -25,10 +26,23 @@ use mayastor_api::{
 };
 
 use crate::subsys::registration::registration_grpc::ApiVersion;
-use futures::{select, FutureExt, StreamExt};
+use futures::{future, select, FutureExt, StreamExt};
 use once_cell::sync::OnceCell;
-use std::{borrow::Cow, time::Duration};
-use tonic::transport::Server;
+use std::{
+    borrow::Cow,
+    fs,
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    time::Duration,
+};
+use tokio::net::UnixListener;
+use tokio_stream::wrappers::UnixListenerStream;
+use tonic::transport::{
+    server::Router,
+    Certificate,
+    Identity,
+    Server,
+    ServerTlsConfig,
+};
 use tracing::trace;
 
 static MAYASTOR_GRPC_SERVER: OnceCell<MayastorGrpcServer> = OnceCell::new();
@@ -41,6 +55,42 @@ pub struct MayastorGrpcServer {
     fini_chan: async_channel::Sender<()>,
 }
 
+/// Paths of the PEM files the gRPC server authenticates itself with, and
+/// verifies the certificates of its clients against.
+#[derive(Debug, Clone)]
+pub struct GrpcTlsFiles {
+    /// Certificate of the server.
+    pub cert: String,
+    /// Private key of the server certificate.
+    pub key: String,
+    /// CA certificate the client certificates must be signed by.
+    pub client_ca: String,
+}
+
+impl GrpcTlsFiles {
+    /// Loads the files into a TLS configuration requiring client
+    /// certificates.
+    fn server_config(&self) -> Result<ServerTlsConfig, String> {
+        let read = |path: &str| {
+            std::fs::read(path).map_err(|e| format!("{path}: {e}"))
+        };
+        let identity = Identity::from_pem(read(&self.cert)?, read(&self.key)?);
+        let client_ca = Certificate::from_pem(read(&self.client_ca)?);
+        Ok(ServerTlsConfig::new()
+            .identity(identity)
+            .client_ca_root(client_ca))
+    }
+}
+
+/// Transport options of the gRPC server.
+#[derive(Debug, Clone, Default)]
+pub struct GrpcServerOptions {
+    /// Serve over mutual TLS instead of plain TCP.
+    pub tls: Option<GrpcTlsFiles>,
+    /// Also serve the v1 services on a Unix domain socket at this path.
+    pub uds_path: Option<String>,
+}
+
 impl MayastorGrpcServer {
     /// Get or initialise the grpc server global instance.
     pub fn get_or_init() -> &'static MayastorGrpcServer {
@@ -63,6 +113,7 @@ impl MayastorGrpcServer {
         endpoint: std::net::SocketAddr,
         rpc_addr: String,
         api_versions: Vec<ApiVersion>,
+        options: GrpcServerOptions,
     ) -> Result<(), ()> {
         let mut rcv_chan = Self::get_or_init().rcv_chan.clone();
 
@@ -74,7 +125,93 @@ impl MayastorGrpcServer {
             "{:?} gRPC server configured at address {}",
             api_versions, endpoint
         );
-        let svc = Server::builder()
+
+        let mut server = Server::builder();
+        if let Some(tls) = &options.tls {
+            server = tls
+                .server_config()
+                .and_then(|cfg| {
+                    server.tls_config(cfg).map_err(|e| e.to_string())
+                })
+                .map_err(|error| {
+                    error!("Failed to configure gRPC server TLS: {}", error);
+                })?;
+            info!("gRPC server requires TLS client certificates");
+        }
+
+        let svc = Self::v1_router(
+            &mut server,
+            enable_v1,
+            node_name,
+            node_nqn,
+            endpoint,
+            &address,
+            &api_versions,
+        )
+        .add_optional_service(enable_v0.map(|_| {
+            MayastorRpcServer::new(MayastorSvc::new(Duration::from_millis(4)))
+        }))
+        .add_optional_service(
+            enable_v0
+                .map(|_| JsonRpcServer::new(JsonRpcSvc::new(address.clone()))),
+        )
+        .add_optional_service(
+            enable_v0.map(|_| BdevRpcServer::new(BdevSvc::new())),
+        )
+        .serve(endpoint);
+
+        let uds_svc = match (&options.uds_path, enable_v1) {
+            (Some(path), Some(_)) => {
+                let incoming = Self::uds_incoming(path).map_err(|error| {
+                    error!(
+                        "Failed to bind gRPC server to socket {}: {}",
+                        path, error
+                    );
+                })?;
+                info!("v1 gRPC server configured at socket {}", path);
+                Self::v1_router(
+                    &mut Server::builder(),
+                    enable_v1,
+                    node_name,
+                    node_nqn,
+                    endpoint,
+                    &address,
+                    &api_versions,
+                )
+                .serve_with_incoming(incoming)
+                .boxed()
+            }
+            (Some(path), None) => {
+                warn!(
+                    "Not serving gRPC on socket {} as the v1 API is disabled",
+                    path
+                );
+                future::pending().boxed()
+            }
+            (None, _) => future::pending().boxed(),
+        };
+
+        select! {
+            result = svc.fuse() => Self::served(result),
+            result = uds_svc.fuse() => Self::served(result),
+            _ = rcv_chan.next().fuse() => {
+                info!("Shutting down grpc server");
+                Ok(())
+            }
+        }
+    }
+
+    /// Creates a router of the server with the v1 services, if enabled.
+    fn v1_router(
+        server: &mut Server,
+        enable_v1: Option<bool>,
+        node_name: &str,
+        node_nqn: &Option<String>,
+        endpoint: std::net::SocketAddr,
+        address: &Cow<'static, str>,
+        api_versions: &[ApiVersion],
+    ) -> Router {
+        server
             .add_optional_service(
                 enable_v1
                     .map(|_| v1::bdev::BdevRpcServer::new(BdevService::new())),
@@ -97,7 +234,7 @@ impl MayastorGrpcServer {
                     node_name,
                     node_nqn,
                     endpoint,
-                    api_versions,
+                    api_versions.to_vec(),
                 ))
             }))
             .add_optional_service(
@@ -105,38 +242,56 @@ impl MayastorGrpcServer {
                     v1::nexus::NexusRpcServer::new(NexusService::new())
                 }),
             )
-            .add_optional_service(enable_v0.map(|_| {
-                MayastorRpcServer::new(MayastorSvc::new(Duration::from_millis(
-                    4,
-                )))
-            }))
             .add_optional_service(
-                enable_v0.map(|_| {
-                    JsonRpcServer::new(JsonRpcSvc::new(address.clone()))
+                enable_v1.map(|_| {
+                    v1::event::EventRpcServer::new(EventService::new())
                 }),
             )
-            .add_optional_service(
-                enable_v0.map(|_| BdevRpcServer::new(BdevSvc::new())),
-            )
-            .serve(endpoint);
+    }
 
-        select! {
-            result = svc.fuse() => {
-                match result {
-                    Ok(result) => {
-                        trace!(?result);
-                        Ok(())
-                    }
-                    Err(e) => {
-                        error!("gRPC server failed with error: {}", e);
-                        Err(())
-                    }
-                }
-            },
-            _ = rcv_chan.next().fuse() => {
-                info!("Shutting down grpc server");
+    /// Binds a Unix domain socket at the given path, replacing a stale
+    /// socket left behind by a previous instance. Only the user the engine
+    /// runs as can connect to the socket.
+    fn uds_incoming(path: &str) -> std::io::Result<UnixListenerStream> {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                return Err(e)
+            }
+            _ => {}
+        }
+
+        // Bind within a directory only we can access, and move the socket
+        // in place once its permissions are restricted, so that there is no
+        // window for others to connect.
+        let private_dir = format!("{path}.d");
+        fs::remove_dir_all(&private_dir).ok();
+        fs::DirBuilder::new().mode(0o700).create(&private_dir)?;
+
+        let private_path = format!("{private_dir}/socket");
+        let result = UnixListener::bind(&private_path).and_then(|listener| {
+            fs::set_permissions(
+                &private_path,
+                fs::Permissions::from_mode(0o600),
+            )?;
+            fs::rename(&private_path, path)?;
+            Ok(listener)
+        });
+        fs::remove_dir_all(&private_dir).ok();
+
+        Ok(UnixListenerStream::new(result?))
+    }
+
+    /// Logs the result of a server which stopped serving.
+    fn served(result: Result<(), tonic::transport::Error>) -> Result<(), ()> {
+        match result {
+            Ok(result) => {
+                trace!(?result);
                 Ok(())
             }
+            Err(e) => {
+                error!("gRPC server failed with error: {}", e);
+                Err(())
+            }
         }
     }
 }
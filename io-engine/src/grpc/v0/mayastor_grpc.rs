@@ -250,9 +250,19 @@ impl From<LvsError> for tonic::Status {
             LvsError::ReplicaShareProtocol {
                 ..
             } => Status::invalid_argument(e.to_string()),
+            LvsError::ReplicaImport {
+                source, ..
+            } => match source {
+                Errno::EINVAL => Status::invalid_argument(e.to_string()),
+                Errno::ENOMEDIUM => Status::failed_precondition(e.to_string()),
+                _ => Status::internal(e.to_string()),
+            },
             LvsError::Destroy {
                 source, ..
             } => source.into(),
+            LvsError::Compression {
+                source, ..
+            } => source.into(),
             LvsError::Invalid {
                 source, ..
             } => match source {
@@ -289,6 +299,7 @@ impl From<Protocol> for i32 {
         match p {
             Protocol::Off => 0,
             Protocol::Nvmf => 1,
+            Protocol::Ublk => 3,
         }
     }
 }
@@ -338,6 +349,7 @@ impl From<LvolSpaceUsage> for ReplicaSpaceUsage {
             cluster_size: u.cluster_size,
             num_clusters: u.num_clusters,
             num_allocated_clusters: u.num_allocated_clusters,
+            compression_ratio: u.compression_ratio,
         }
     }
 }
@@ -993,6 +1005,11 @@ impl mayastor_server::Mayastor for MayastorSvc {
                                 Protocol::Off => {
                                     lvol.as_mut().unshare().await?;
                                 }
+                                Protocol::Ublk => {
+                                    return Err(LvsError::ReplicaShareProtocol {
+                                        value: args.share,
+                                    });
+                                }
                                 Protocol::Nvmf => {
                                     let props = ShareProps::new()
                                         .with_allowed_hosts(args.allowed_hosts)
@@ -1100,6 +1117,7 @@ impl mayastor_server::Mayastor for MayastorSvc {
                     },
                     &args.children,
                     nexus_info_key,
+                    nexus::NexusLayout::Mirror,
                 )
                 .await?;
                 let nexus = nexus_lookup(&args.name)?;
@@ -1427,7 +1445,12 @@ impl mayastor_server::Mayastor for MayastorSvc {
                 };
 
                 let device_uri = nexus_lookup(&args.uuid)?
-                    .share_ext(share_protocol, key, args.allowed_hosts.clone())
+                    .share_ext(
+                        share_protocol,
+                        key,
+                        args.allowed_hosts.clone(),
+                        vec![],
+                    )
                     .await?;
 
                 info!(
@@ -1,10 +1,27 @@
 use crate::{
+    bdev::{
+        cor_bdev::{cor_bdev_progress, CorProgress, CorState},
+        delay_bdev::{
+            delay_bdev_set_latency,
+            DelayLatency,
+            DelayLatencyUpdate,
+        },
+        error_bdev::{
+            error_bdev_add_rule,
+            error_bdev_list_rules,
+            error_bdev_remove_rule,
+            ErrorRule,
+            ErrorRuleState,
+        },
+        sparse_file::sparse_file_resize,
+    },
     bdev_api::{bdev_create, bdev_destroy, BdevError},
     core,
     core::{CoreError, Protocol, Share, ShareProps},
     grpc::{rpc_submit, GrpcResult},
 };
 use mayastor_api::v1::bdev::{
+    AddErrorRuleRequest,
     Bdev,
     BdevRpc,
     BdevShareRequest,
@@ -13,10 +30,20 @@ use mayastor_api::v1::bdev::{
     CreateBdevRequest,
     CreateBdevResponse,
     DestroyBdevRequest,
+    ErrorInjectionRule,
+    GetPopulateProgressRequest,
     ListBdevOptions,
     ListBdevResponse,
+    ListErrorRulesRequest,
+    ListErrorRulesResponse,
+    PopulateProgress,
+    RemoveErrorRuleRequest,
+    ResizeBdevRequest,
+    ResizeBdevResponse,
+    SetDelayLatencyRequest,
+    SetDelayLatencyResponse,
 };
-use std::{convert::TryFrom, pin::Pin};
+use std::{convert::TryFrom, pin::Pin, time::Duration};
 use tonic::{Request, Response, Status};
 use url::Url;
 
@@ -40,6 +67,51 @@ where
     }
 }
 
+/// Makes the response to a latency change of a delay bdev.
+fn delay_latency_response(
+    name: String,
+    lat: DelayLatency,
+) -> SetDelayLatencyResponse {
+    SetDelayLatencyResponse {
+        name,
+        read_avg_us: lat.read_avg.as_micros() as u64,
+        read_p99_us: lat.read_p99.as_micros() as u64,
+        write_avg_us: lat.write_avg.as_micros() as u64,
+        write_p99_us: lat.write_p99.as_micros() as u64,
+    }
+}
+
+impl From<ErrorRuleState> for ErrorInjectionRule {
+    fn from(r: ErrorRuleState) -> Self {
+        Self {
+            id: r.id,
+            op: r.rule.op.to_string(),
+            status: r.rule.status.to_string(),
+            offset: r.rule.offset,
+            num_blocks: r.rule.num_blocks,
+            count: r.rule.count,
+            probability: r.rule.prob,
+            hits: r.hits,
+        }
+    }
+}
+
+/// Makes the population progress of a copy-on-read bdev.
+fn populate_progress(name: String, p: CorProgress) -> PopulateProgress {
+    PopulateProgress {
+        name,
+        source: p.source,
+        state: p.state.to_string(),
+        error: match p.state {
+            CorState::Failed(error) => error,
+            _ => String::new(),
+        },
+        region_size: p.region_size,
+        total_regions: p.total_regions,
+        populated_regions: p.populated_regions,
+    }
+}
+
 /// RPC service for spdk bdev operations
 #[derive(Debug)]
 pub struct BdevService {}
@@ -183,4 +255,147 @@ impl BdevRpc for BdevService {
             .map_err(Status::from)
             .map(Response::new)
     }
+
+    #[tracing::instrument(skip(self))]
+    async fn set_delay_latency(
+        &self,
+        request: Request<SetDelayLatencyRequest>,
+    ) -> GrpcResult<SetDelayLatencyResponse> {
+        let args = request.into_inner();
+
+        let rx = rpc_submit::<_, _, BdevError>(async move {
+            let update = DelayLatencyUpdate {
+                read_avg: args.read_avg_us.map(Duration::from_micros),
+                read_p99: args.read_p99_us.map(Duration::from_micros),
+                write_avg: args.write_avg_us.map(Duration::from_micros),
+                write_p99: args.write_p99_us.map(Duration::from_micros),
+            };
+            let lat = delay_bdev_set_latency(&args.name, update)?;
+            Ok(delay_latency_response(args.name, lat))
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn add_error_rule(
+        &self,
+        request: Request<AddErrorRuleRequest>,
+    ) -> GrpcResult<ErrorInjectionRule> {
+        let args = request.into_inner();
+
+        let rx = rpc_submit::<_, _, BdevError>(async move {
+            let invalid = |message| BdevError::ErrorRuleInvalid {
+                name: args.name.clone(),
+                message,
+            };
+            let mut rule = ErrorRule::default();
+            if !args.op.is_empty() {
+                rule.op = args.op.parse().map_err(invalid)?;
+            }
+            if !args.status.is_empty() {
+                rule.status = args.status.parse().map_err(invalid)?;
+            }
+            rule.offset = args.offset;
+            rule.num_blocks = args.num_blocks;
+            rule.count = args.count;
+            if let Some(prob) = args.probability {
+                rule.prob = prob;
+            }
+
+            Ok(error_bdev_add_rule(&args.name, rule)?.into())
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn remove_error_rule(
+        &self,
+        request: Request<RemoveErrorRuleRequest>,
+    ) -> GrpcResult<()> {
+        let args = request.into_inner();
+
+        let rx = rpc_submit::<_, _, BdevError>(async move {
+            error_bdev_remove_rule(&args.name, args.id)
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn list_error_rules(
+        &self,
+        request: Request<ListErrorRulesRequest>,
+    ) -> GrpcResult<ListErrorRulesResponse> {
+        let args = request.into_inner();
+
+        let rx = rpc_submit::<_, _, BdevError>(async move {
+            let rules = error_bdev_list_rules(&args.name)?;
+            Ok(ListErrorRulesResponse {
+                rules: rules
+                    .into_iter()
+                    .map(ErrorInjectionRule::from)
+                    .collect(),
+            })
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn resize(
+        &self,
+        request: Request<ResizeBdevRequest>,
+    ) -> GrpcResult<ResizeBdevResponse> {
+        let args = request.into_inner();
+
+        let rx = rpc_submit::<_, _, BdevError>(async move {
+            sparse_file_resize(&args.name, args.size)?;
+
+            match core::UntypedBdev::lookup_by_name(&args.name) {
+                Some(bdev) => Ok(ResizeBdevResponse {
+                    bdev: Some(bdev.into()),
+                }),
+                None => Err(BdevError::BdevNotFound {
+                    name: args.name,
+                }),
+            }
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    #[tracing::instrument(skip(self))]
+    async fn get_populate_progress(
+        &self,
+        request: Request<GetPopulateProgressRequest>,
+    ) -> GrpcResult<PopulateProgress> {
+        let args = request.into_inner();
+
+        let rx = rpc_submit::<_, _, BdevError>(async move {
+            let progress = cor_bdev_progress(&args.name)?;
+            Ok(populate_progress(args.name, progress))
+        })?;
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
 }
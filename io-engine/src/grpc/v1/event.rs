@@ -0,0 +1,188 @@
+use crate::{
+    events::{watch_events, EngineEvent, EventRecord},
+    grpc::{v1::nexus::map_fault_reason, GrpcResult},
+};
+use futures::{stream, Stream, StreamExt};
+use mayastor_api::v1::{
+    event::{
+        engine_event::Event,
+        ChildFaulted,
+        ChildOnlined,
+        EventRpc,
+        NexusStatusChanged,
+        PoolCreated,
+        PoolDestroyed,
+        PoolLowSpace,
+        RebuildCompleted,
+        RebuildFailed,
+        RebuildStarted,
+        SnapshotCreated,
+        WatchEventsRequest,
+    },
+    nexus::NexusState,
+};
+use std::pin::Pin;
+use tonic::{Request, Response, Status};
+
+impl From<EngineEvent> for Event {
+    fn from(event: EngineEvent) -> Self {
+        match event {
+            EngineEvent::ChildFaulted {
+                nexus,
+                child,
+                reason,
+            } => Event::ChildFaulted(ChildFaulted {
+                nexus_name: nexus,
+                child_uri: child,
+                reason: map_fault_reason(reason) as i32,
+            }),
+            EngineEvent::ChildOnlined {
+                nexus,
+                child,
+            } => Event::ChildOnlined(ChildOnlined {
+                nexus_name: nexus,
+                child_uri: child,
+            }),
+            EngineEvent::NexusStatusChanged {
+                nexus,
+                uuid,
+                status,
+            } => Event::NexusStatusChanged(NexusStatusChanged {
+                nexus_name: nexus,
+                nexus_uuid: uuid,
+                state: NexusState::from(status) as i32,
+            }),
+            EngineEvent::RebuildStarted {
+                nexus,
+                child,
+                src_uri,
+            } => Event::RebuildStarted(RebuildStarted {
+                nexus_name: nexus,
+                child_uri: child,
+                src_uri,
+            }),
+            EngineEvent::RebuildCompleted {
+                nexus,
+                child,
+            } => Event::RebuildCompleted(RebuildCompleted {
+                nexus_name: nexus,
+                child_uri: child,
+            }),
+            EngineEvent::RebuildFailed {
+                nexus,
+                child,
+                error,
+            } => Event::RebuildFailed(RebuildFailed {
+                nexus_name: nexus,
+                child_uri: child,
+                error,
+            }),
+            EngineEvent::PoolCreated {
+                name,
+                uuid,
+            } => Event::PoolCreated(PoolCreated {
+                name,
+                uuid,
+            }),
+            EngineEvent::PoolDestroyed {
+                name,
+                uuid,
+            } => Event::PoolDestroyed(PoolDestroyed {
+                name,
+                uuid,
+            }),
+            EngineEvent::PoolLowSpace {
+                name,
+                uuid,
+                capacity,
+                available,
+            } => Event::PoolLowSpace(PoolLowSpace {
+                name,
+                uuid,
+                capacity,
+                available,
+            }),
+            EngineEvent::SnapshotCreated {
+                pool_uuid,
+                source_uuid,
+                snapshot_uuid,
+                snapshot_name,
+            } => Event::SnapshotCreated(SnapshotCreated {
+                pool_uuid,
+                source_uuid,
+                snapshot_uuid,
+                snapshot_name,
+            }),
+        }
+    }
+}
+
+impl From<EventRecord> for mayastor_api::v1::event::EngineEvent {
+    fn from(record: EventRecord) -> Self {
+        Self {
+            sequence: record.sequence,
+            timestamp: Some(record.timestamp.into()),
+            event: Some(record.event.into()),
+        }
+    }
+}
+
+/// RPC service for watching the engine events.
+#[derive(Debug)]
+pub struct EventService {}
+
+impl EventService {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl Default for EventService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[tonic::async_trait]
+impl EventRpc for EventService {
+    type WatchEventsStream = Pin<
+        Box<
+            dyn Stream<
+                    Item = Result<mayastor_api::v1::event::EngineEvent, Status>,
+                > + Send,
+        >,
+    >;
+
+    async fn watch_events(
+        &self,
+        request: Request<WatchEventsRequest>,
+    ) -> GrpcResult<Self::WatchEventsStream> {
+        let args = request.into_inner();
+        info!("{:?}", args);
+
+        // Events are published from any thread, so the watch is not
+        // submitted to the reactor.
+        let watch = watch_events(args.after_sequence).map_err(|lost| {
+            Status::out_of_range(format!(
+                "Events after {} are no longer available: oldest event is \
+                {:?}, latest event is {}",
+                args.after_sequence.unwrap_or_default(),
+                lost.oldest,
+                lost.latest
+            ))
+        })?;
+
+        // The receiver only closes when the watcher lags behind.
+        let lagged = stream::once(async {
+            Err(Status::resource_exhausted(
+                "Event watcher lagged behind, resume the watch after the \
+                last received event",
+            ))
+        });
+        let events = stream::iter(watch.backlog)
+            .chain(watch.receiver)
+            .map(|record| Ok(record.into()))
+            .chain(lagged);
+        Ok(Response::new(Box::pin(events)))
+    }
+}
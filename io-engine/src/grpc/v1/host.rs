@@ -1,8 +1,9 @@
 use crate::{
-    bdev::{nexus, NvmeControllerState},
+    bdev::{nexus, NvmeControllerState, NvmePathInfo, NvmePathState},
     core::{BlockDeviceIoStats, CoreError, MayastorFeatures},
     grpc::{
         controller_grpc::{
+            controller_io_latency,
             controller_stats,
             list_controllers,
             NvmeControllerInfo,
@@ -13,11 +14,22 @@ use crate::{
         Serializer,
     },
     host::{blk_device, resource},
-    subsys::{registration::registration_grpc::ApiVersion, Registration},
+    subsys::{
+        registration::registration_grpc::ApiVersion,
+        NvmfError,
+        NvmfHostController,
+        NvmfSubsystem,
+        Registration,
+        SubType,
+    },
 };
 use ::function_name::named;
 use futures::FutureExt;
-use mayastor_api::v1::{host as host_rpc, registration::RegisterRequest};
+use mayastor_api::v1::{
+    host as host_rpc,
+    nexus as nexus_rpc,
+    registration::RegisterRequest,
+};
 use std::panic::AssertUnwindSafe;
 use tonic::{Request, Response, Status};
 use version_info::raw_version_string;
@@ -170,10 +182,82 @@ impl From<NvmeControllerInfo> for host_rpc::NvmeController {
             state: host_rpc::NvmeControllerState::from(n.state) as i32,
             size: n.size,
             blk_size: n.blk_size,
+            paths: n.paths.into_iter().map(host_rpc::NvmePath::from).collect(),
+        }
+    }
+}
+
+impl From<NvmePathInfo> for host_rpc::NvmePath {
+    fn from(p: NvmePathInfo) -> Self {
+        Self {
+            address: p.address,
+            port: p.port,
+            state: host_rpc::NvmePathState::from(p.state) as i32,
+            ana_state: nexus_rpc::NvmeAnaState::from_i32(p.ana_state as i32)
+                .unwrap_or(nexus_rpc::NvmeAnaState::NvmeAnaInvalidState)
+                as i32,
+        }
+    }
+}
+
+impl From<NvmePathState> for host_rpc::NvmePathState {
+    fn from(state: NvmePathState) -> Self {
+        match state {
+            NvmePathState::Active => host_rpc::NvmePathState::Active,
+            NvmePathState::Standby => host_rpc::NvmePathState::Standby,
+            NvmePathState::Failed => host_rpc::NvmePathState::Failed,
+            NvmePathState::Inaccessible => {
+                host_rpc::NvmePathState::Inaccessible
+            }
+        }
+    }
+}
+
+impl From<NvmfHostController> for host_rpc::NvmfHost {
+    fn from(c: NvmfHostController) -> Self {
+        Self {
+            host_nqn: c.host_nqn,
+            controller_id: c.cntlid as u32,
+            io_qpairs: c.io_qpairs,
+            connect_time: c.connect_time.map(Into::into),
+            keep_alive_timeout_ms: c.keep_alive_timeout_ms,
+            last_keep_alive_ms: c.last_keep_alive_ms,
         }
     }
 }
 
+impl From<NvmfSubsystem> for host_rpc::NvmfSubsystemHosts {
+    fn from(s: NvmfSubsystem) -> Self {
+        Self {
+            nqn: s.get_nqn(),
+            bdev: s.bdev().map(|b| b.name().to_string()).unwrap_or_default(),
+            hosts: s
+                .controllers()
+                .into_iter()
+                .map(host_rpc::NvmfHost::from)
+                .collect(),
+        }
+    }
+}
+
+/// Get the NVMe subsystems, or only the one with the given NQN.
+fn nvmf_subsystems(nqn: Option<&str>) -> Result<Vec<NvmfSubsystem>, NvmfError> {
+    match nqn {
+        Some(nqn) => NvmfSubsystem::lookup_by_nqn(nqn)
+            .map(|s| vec![s])
+            .ok_or_else(|| NvmfError::Subsystem {
+                source: nix::errno::Errno::ENOENT,
+                nqn: nqn.to_string(),
+                msg: "subsystem not found".to_string(),
+            }),
+        None => Ok(NvmfSubsystem::first()
+            .into_iter()
+            .flat_map(|s| s.into_iter())
+            .filter(|s| s.subtype() == SubType::Nvme)
+            .collect()),
+    }
+}
+
 impl From<NvmeControllerState> for host_rpc::NvmeControllerState {
     fn from(state: NvmeControllerState) -> Self {
         match state {
@@ -206,6 +290,7 @@ impl From<BlockDeviceIoStats> for host_rpc::NvmeControllerIoStats {
             bytes_written: b.bytes_written,
             num_unmap_ops: b.num_unmap_ops,
             bytes_unmapped: b.bytes_unmapped,
+            latency: None,
         }
     }
 }
@@ -300,6 +385,89 @@ impl host_rpc::HostRpc for HostService {
         .await
     }
 
+    #[named]
+    async fn list_nvmf_hosts(
+        &self,
+        request: Request<host_rpc::ListNvmfHostsRequest>,
+    ) -> GrpcResult<host_rpc::ListNvmfHostsResponse> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                let rx = rpc_submit::<_, _, NvmfError>(async move {
+                    let subsystems =
+                        nvmf_subsystems(args.subsystem_nqn.as_deref())?
+                            .into_iter()
+                            .map(host_rpc::NvmfSubsystemHosts::from)
+                            .collect();
+                    Ok(host_rpc::ListNvmfHostsResponse {
+                        subsystems,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn disconnect_nvmf_host(
+        &self,
+        request: Request<host_rpc::DisconnectNvmfHostRequest>,
+    ) -> GrpcResult<host_rpc::DisconnectNvmfHostResponse> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                let rx = rpc_submit::<_, _, NvmfError>(async move {
+                    let subsystems =
+                        nvmf_subsystems(args.subsystem_nqn.as_deref())?
+                            .into_iter()
+                            .filter(|s| s.is_host_connected(&args.host_nqn))
+                            .collect::<Vec<_>>();
+
+                    if subsystems.is_empty() {
+                        return Err(NvmfError::Subsystem {
+                            source: nix::errno::Errno::ENOENT,
+                            nqn: args.subsystem_nqn.unwrap_or_default(),
+                            msg: format!(
+                                "host '{}' is not connected",
+                                args.host_nqn
+                            ),
+                        });
+                    }
+
+                    // note that the host may well connect again, unless it is
+                    // removed from the allowed hosts as well
+                    let mut nqns = Vec::with_capacity(subsystems.len());
+                    for s in subsystems {
+                        s.disconnect_host(&args.host_nqn).await?;
+                        info!(
+                            "Disconnected host '{}' from subsystem '{}'",
+                            args.host_nqn,
+                            s.get_nqn()
+                        );
+                        nqns.push(s.get_nqn());
+                    }
+
+                    Ok(host_rpc::DisconnectNvmfHostResponse {
+                        subsystems: nqns,
+                    })
+                })?;
+
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
     #[named]
     async fn stat_nvme_controller(
         &self,
@@ -310,16 +478,15 @@ impl host_rpc::HostRpc for HostService {
             async move {
                 let args = request.into_inner();
                 let rx = rpc_submit::<_, _, CoreError>(async move {
-                    controller_stats(&args.name)
-                        .await
-                        .map(|blk_stat| {
-                            Some(host_rpc::NvmeControllerIoStats::from(
-                                blk_stat,
-                            ))
-                        })
-                        .map(|ctrl_stat| host_rpc::StatNvmeControllerResponse {
-                            stats: ctrl_stat,
-                        })
+                    let blk_stat = controller_stats(&args.name).await?;
+                    let latency =
+                        controller_io_latency(&args.name, args.reset_latency)?;
+                    Ok(host_rpc::StatNvmeControllerResponse {
+                        stats: Some(host_rpc::NvmeControllerIoStats {
+                            latency: Some(latency.into()),
+                            ..blk_stat.into()
+                        }),
+                    })
                 })?;
                 rx.await
                     .map_err(|_| Status::cancelled("cancelled"))?
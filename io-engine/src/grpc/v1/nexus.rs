@@ -11,8 +11,11 @@ use crate::{
     },
     core::{
         lock::{ProtectedSubsystems, ResourceLockManager},
+        BlockDevice,
+        MayastorEnvironment,
         Protocol,
         Share,
+        UntypedBdev,
     },
     grpc::{rpc_submit, GrpcClientContext, GrpcResult},
     rebuild::{HistoryRecord, RebuildState, RebuildStats},
@@ -22,11 +25,15 @@ use std::{
     convert::{From, TryFrom, TryInto},
     fmt::Debug,
     ops::Deref,
+    path::{Component, Path, PathBuf},
     pin::Pin,
 };
 use tonic::{Request, Response, Status};
 
-use mayastor_api::v1::nexus::*;
+use mayastor_api::v1::{
+    nexus::*,
+    stats::{IoLatencyHistograms, IoStats},
+};
 
 #[derive(Debug)]
 struct UnixStream(tokio::net::UnixStream);
@@ -35,6 +42,23 @@ use crate::bdev::{dev::device_name, nexus::NexusPtpl, PtplFileOps};
 use ::function_name::named;
 use std::panic::AssertUnwindSafe;
 
+/// Returns the path of an I/O trace capture file within the configured trace
+/// directory. Only a plain file name is accepted, so that a client cannot
+/// write elsewhere on the node.
+fn io_trace_path(name: &str) -> Result<PathBuf, Status> {
+    let mut components = Path::new(name).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(file)), None) => Ok(Path::new(
+            &MayastorEnvironment::global_or_default().io_trace_dir(),
+        )
+        .join(file)),
+        _ => Err(Status::invalid_argument(format!(
+            "Invalid I/O trace file name '{name}': expected a file name \
+            without a directory"
+        ))),
+    }
+}
+
 /// RPC service for mayastor nexus operations
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -131,7 +155,7 @@ impl From<NexusStatus> for NexusState {
     }
 }
 
-fn map_fault_reason(r: FaultReason) -> ChildStateReason {
+pub(crate) fn map_fault_reason(r: FaultReason) -> ChildStateReason {
     use ChildStateReason::*;
 
     match r {
@@ -281,6 +305,27 @@ impl TryFrom<NvmePreemptionConv> for nexus::NexusNvmePreemption {
         }
     }
 }
+struct NexusLayoutConv(i32, u64, u32);
+impl TryFrom<NexusLayoutConv> for nexus::NexusLayout {
+    type Error = tonic::Status;
+    fn try_from(value: NexusLayoutConv) -> Result<Self, Self::Error> {
+        match NexusLayout::from_i32(value.0) {
+            Some(NexusLayout::Mirror) => Ok(nexus::NexusLayout::Mirror),
+            Some(NexusLayout::Stripe) => Ok(nexus::NexusLayout::Stripe {
+                stripe_size: value.1,
+            }),
+            Some(NexusLayout::Concat) => Ok(nexus::NexusLayout::Concat),
+            Some(NexusLayout::Parity) => Ok(nexus::NexusLayout::Parity {
+                parity_children: value.2,
+                stripe_size: value.1,
+            }),
+            None => Err(tonic::Status::invalid_argument(format!(
+                "Invalid nexus layout {}",
+                value.0
+            ))),
+        }
+    }
+}
 
 /// Look up a nexus by uuid
 pub fn nexus_lookup<'n>(
@@ -357,6 +402,42 @@ impl<'n> nexus::Nexus<'n> {
             allowed_hosts: self.allowed_hosts(),
         }
     }
+
+    /// Get the I/O statistics and latency histograms of the nexus and its
+    /// children, resetting the latency histograms afterwards if requested.
+    async fn io_stats_grpc(&self, reset_latency: bool) -> NexusIoStats {
+        let io = match UntypedBdev::lookup_by_name(&self.bdev_name()) {
+            Some(bdev) => bdev.stats_async().await.ok().map(IoStats::from),
+            None => None,
+        };
+
+        let mut children = Vec::with_capacity(self.children_iter().count());
+        for child in self.children_iter() {
+            let (io, latency) = match child.get_device() {
+                Ok(device) => {
+                    let io = device.io_stats().await.ok().map(IoStats::from);
+                    let latency = device.io_latency();
+                    if reset_latency {
+                        device.reset_io_latency();
+                    }
+                    (io, latency.map(IoLatencyHistograms::from))
+                }
+                Err(_) => (None, None),
+            };
+            children.push(ChildIoStats {
+                uri: child.uri().to_string(),
+                io,
+                latency,
+            });
+        }
+
+        NexusIoStats {
+            uuid: self.uuid().to_string(),
+            io,
+            latency: Some(self.io_latency(reset_latency).into()),
+            children,
+        }
+    }
 }
 
 /// Add child to nexus. Normally this would have been part of grpc method
@@ -399,6 +480,12 @@ impl NexusRpc for NexusService {
             let resv_type = NvmeReservationConv(args.resv_type).try_into()?;
             let preempt_policy =
                 NvmePreemptionConv(args.preempt_policy).try_into()?;
+            let layout = NexusLayoutConv(
+                args.layout,
+                args.stripe_size,
+                args.parity_children,
+            )
+            .try_into()?;
             let rx = rpc_submit::<_, _, nexus::Error>(async move {
                 // check for nexus exists, uuid & name
                 if let Some(_n) = nexus::nexus_lookup(&args.name) {
@@ -438,6 +525,7 @@ impl NexusRpc for NexusService {
                     },
                     &args.children,
                     nexus_info_key,
+                    layout,
                 )
                 .await?;
                 let nexus = nexus_lookup(&args.uuid)?;
@@ -776,19 +864,27 @@ impl NexusRpc for NexusService {
                 };
 
                 // error out if nbd or iscsi
-                if !matches!(share_protocol, Protocol::Off | Protocol::Nvmf) {
+                if !matches!(
+                    share_protocol,
+                    Protocol::Off | Protocol::Nvmf | Protocol::Ublk
+                ) {
                     return Err(nexus::Error::InvalidShareProtocol {
                         sp_value: args.share,
                     });
                 }
 
                 let device_uri = nexus_lookup(&args.uuid)?
-                    .share_ext(share_protocol, key, args.allowed_hosts.clone())
+                    .share_ext(
+                        share_protocol,
+                        key,
+                        args.allowed_hosts.clone(),
+                        args.listeners.clone(),
+                    )
                     .await?;
 
                 info!(
-                    "Published nexus {} under {} for {:?}",
-                    args.uuid, device_uri, args.allowed_hosts
+                    "Published nexus {} under {} for {:?} on {:?}",
+                    args.uuid, device_uri, args.allowed_hosts, args.listeners
                 );
 
                 let nexus = nexus_lookup(&args.uuid)?.into_grpc().await;
@@ -1119,6 +1215,132 @@ impl NexusRpc for NexusService {
         .await
     }
 
+    #[named]
+    async fn stat_nexus(
+        &self,
+        request: Request<StatNexusRequest>,
+    ) -> GrpcResult<StatNexusResponse> {
+        let ctx = GrpcClientContext::new(&request, function_name!());
+        let args = request.into_inner();
+
+        self.serialized(ctx, args.uuid.clone(), false, async move {
+            trace!("{:?}", args);
+            let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                let stats = nexus_lookup(&args.uuid)?
+                    .io_stats_grpc(args.reset_latency)
+                    .await;
+                Ok(StatNexusResponse {
+                    stats: Some(stats),
+                })
+            })?;
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
+    #[named]
+    async fn set_nexus_io_trace(
+        &self,
+        request: Request<SetNexusIoTraceRequest>,
+    ) -> GrpcResult<NexusIoTraceResponse> {
+        let ctx = GrpcClientContext::new(&request, function_name!());
+        let args = request.into_inner();
+
+        self.serialized(ctx, args.uuid.clone(), false, async move {
+            info!("{:?}", args);
+            let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                let nexus = nexus_lookup(&args.uuid)?;
+                nexus.set_io_trace(args.enable, args.records_per_core as usize);
+                let (enabled, records) = nexus.io_trace_status();
+                Ok(NexusIoTraceResponse {
+                    uuid: args.uuid.clone(),
+                    enabled,
+                    records: records as u64,
+                    path: String::new(),
+                })
+            })?;
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
+    #[named]
+    async fn dump_nexus_io_trace(
+        &self,
+        request: Request<DumpNexusIoTraceRequest>,
+    ) -> GrpcResult<NexusIoTraceResponse> {
+        let ctx = GrpcClientContext::new(&request, function_name!());
+        let args = request.into_inner();
+
+        let path = io_trace_path(&args.path)?;
+        self.serialized(ctx, args.uuid.clone(), false, async move {
+            info!("{:?}", args);
+            let uuid = args.uuid.clone();
+            let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                let nexus = nexus_lookup(&uuid)?;
+                Ok((nexus.io_trace_status().0, nexus.io_trace_capture()))
+            })?;
+            let (enabled, capture) = rx
+                .await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)?;
+
+            // The capture is written off the reactors, as it may be large.
+            // An existing file is never overwritten.
+            let path = path.display().to_string();
+            let records = capture.records.len() as u64;
+            let path = tokio::task::spawn_blocking(move || {
+                if let Some(dir) = Path::new(&path).parent() {
+                    std::fs::create_dir_all(dir).map_err(|error| {
+                        Status::internal(format!(
+                            "Failed to create I/O trace directory \
+                            '{}': {error}",
+                            dir.display()
+                        ))
+                    })?;
+                }
+                let file = std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+                    .map_err(|error| match error.kind() {
+                        std::io::ErrorKind::AlreadyExists => {
+                            Status::already_exists(format!(
+                                "I/O trace file '{path}' already exists"
+                            ))
+                        }
+                        _ => Status::internal(format!(
+                            "Failed to create I/O trace file '{path}': {error}"
+                        )),
+                    })?;
+                capture
+                    .write_to(std::io::BufWriter::new(file))
+                    .map_err(|error| {
+                        Status::internal(format!(
+                            "Failed to write I/O trace file '{path}': {error}"
+                        ))
+                    })
+                    .map(|_| path)
+            })
+            .await
+            .map_err(|_| Status::cancelled("cancelled"))??;
+
+            Ok(Response::new(NexusIoTraceResponse {
+                uuid: args.uuid,
+                enabled,
+                records,
+                path,
+            }))
+        })
+        .await
+    }
+
     #[named]
     async fn get_rebuild_history(
         &self,
@@ -76,6 +76,7 @@ impl From<LvolSpaceUsage> for ReplicaSpaceUsage {
             cluster_size: u.cluster_size,
             num_clusters: u.num_clusters,
             num_allocated_clusters: u.num_allocated_clusters,
+            compression_ratio: u.compression_ratio,
         }
     }
 }
@@ -149,9 +150,20 @@ impl ReplicaRpc for ReplicaService {
                         }
                     }
                 };
+                // a compressed replica is always thin provisioned
+                let created = match args.compression.as_deref() {
+                    Some(codec) => {
+                        let codec = codec.parse().map_err(|msg| LvsError::Invalid {
+                            source: Errno::EINVAL,
+                            msg,
+                        })?;
+                        lvs.create_compressed_lvol(&args.name, args.size, Some(&args.uuid), codec).await
+                    }
+                    None => lvs.create_lvol(&args.name, args.size, Some(&args.uuid), args.thin).await,
+                };
                 // if pooltype is not Lvs, the provided replica uuid need to be added as
                 // a metadata on the volume.
-                match lvs.create_lvol(&args.name, args.size, Some(&args.uuid), args.thin).await {
+                match created {
                     Ok(mut lvol)
                     if Protocol::try_from(args.share)? == Protocol::Nvmf => {
                         let props = ShareProps::new()
@@ -354,6 +366,11 @@ impl ReplicaRpc for ReplicaService {
                                             .to_string(),
                                     })
                                 }
+                                Protocol::Ublk => {
+                                    return Err(LvsError::ReplicaShareProtocol {
+                                        value: args.share,
+                                    })
+                                }
                                 Protocol::Nvmf => {
                                     let props = ShareProps::new()
                                         .with_allowed_hosts(args.allowed_hosts)
@@ -1,7 +1,11 @@
 use crate::{
     bdev::{
         nexus,
-        nexus::{NexusReplicaSnapshotDescriptor, NexusReplicaSnapshotStatus},
+        nexus::{
+            NexusGroupSnapshotDescriptor,
+            NexusReplicaSnapshotDescriptor,
+            NexusReplicaSnapshotStatus,
+        },
     },
     core::{
         lock::ProtectedSubsystems,
@@ -15,6 +19,7 @@ use crate::{
         },
         ResourceLockManager,
         UntypedBdev,
+        UntypedBdevHandle,
     },
     grpc::{
         rpc_submit,
@@ -24,17 +29,40 @@ use crate::{
         Serializer,
     },
     lvs::{Error as LvsError, Lvol, Lvs, LvsLvol},
+    rebuild::{RebuildError, ReplicationHistoryRecord, SnapshotReplicationJob},
     spdk_rs::ffihelper::IntoCString,
 };
 use ::function_name::named;
 use core::ffi::{c_char, c_void};
-use futures::FutureExt;
-use mayastor_api::v1::snapshot::*;
+use futures::{channel::mpsc, FutureExt, SinkExt, Stream, StreamExt};
+use mayastor_api::v1::{
+    nexus::{RebuildHistoryRecord, RebuildJobState},
+    replica::Replica,
+    snapshot::*,
+};
 use nix::errno::Errno;
 use spdk_rs::libspdk::spdk_blob_get_xattr_value;
-use std::{convert::TryFrom, panic::AssertUnwindSafe};
+use std::{
+    convert::TryFrom,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    time::Duration,
+};
 use strum::IntoEnumIterator;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
+
+/// Default maximum time the nexuses may take to pause for a group snapshot.
+const GROUP_SNAPSHOT_MAX_PAUSE: Duration = Duration::from_secs(5);
+
+/// Version of the snapshot stream format, see doc/snapshot-stream.md.
+const SNAPSHOT_STREAM_VERSION: u32 = 1;
+
+/// Maximum amount of data carried by a single chunk of a snapshot stream.
+const SNAPSHOT_STREAM_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// Number of snapshot stream messages buffered between the reactor and the
+/// gRPC stream.
+const SNAPSHOT_STREAM_DEPTH: usize = 4;
 
 #[derive(Debug)]
 #[allow(dead_code)]
@@ -164,6 +192,18 @@ impl From<VolumeSnapshotDescriptor> for SnapshotInfo {
         }
     }
 }
+/// Generate SnapshotReplicationHistoryRecord for the replication history.
+impl From<ReplicationHistoryRecord> for SnapshotReplicationHistoryRecord {
+    fn from(r: ReplicationHistoryRecord) -> Self {
+        Self {
+            snapshot_uuid: r.snapshot_uuid,
+            base_snapshot_uuid: r.base_snapshot_uuid,
+            target_uri: r.target_uri,
+            record: Some(RebuildHistoryRecord::from(&r.record)),
+        }
+    }
+}
+
 #[async_trait::async_trait]
 impl<F, T> Serializer<F, T> for SnapshotService
 where
@@ -278,8 +318,335 @@ impl SnapshotService {
     }
 }
 
+/// Look up a snapshot of a replica and optionally an older base snapshot,
+/// both among the snapshots taken from that replica.
+fn replica_snapshots(
+    replica_uuid: &str,
+    snapshot_uuid: &str,
+    base_snapshot_uuid: Option<&str>,
+) -> Result<(Lvol, Option<Lvol>), LvsError> {
+    let replica = match UntypedBdev::lookup_by_uuid_str(replica_uuid) {
+        Some(bdev) => Lvol::try_from(bdev)?,
+        None => {
+            return Err(LvsError::Invalid {
+                source: Errno::ENOENT,
+                msg: format!("Replica {replica_uuid} not found"),
+            })
+        }
+    };
+
+    let snapshots = replica.list_snapshot_by_source_uuid();
+    let find = |uuid: &str| {
+        snapshots
+            .iter()
+            .map(|s| s.snapshot_lvol())
+            .find(|l| l.uuid() == uuid)
+            .cloned()
+            .ok_or_else(|| LvsError::Invalid {
+                source: Errno::ENOENT,
+                msg: format!(
+                    "Snapshot {uuid} of replica {replica_uuid} not found"
+                ),
+            })
+    };
+
+    let snapshot = find(snapshot_uuid)?;
+    let base = base_snapshot_uuid.map(find).transpose()?;
+    Ok((snapshot, base))
+}
+
+type SnapshotStreamSender = mpsc::Sender<Result<SnapshotStreamMessage, Status>>;
+
+/// Send a message of a snapshot stream, failing once the receiving end has
+/// gone away.
+async fn send_stream_message(
+    sender: &mut SnapshotStreamSender,
+    snapshot: &Lvol,
+    message: snapshot_stream_message::Message,
+) -> Result<(), LvsError> {
+    sender
+        .send(Ok(SnapshotStreamMessage {
+            message: Some(message),
+        }))
+        .await
+        .map_err(|_| LvsError::SnapshotExport {
+            source: Errno::ECONNRESET,
+            name: snapshot.uuid(),
+            msg: "stream receiver has gone away".to_string(),
+        })
+}
+
+/// Stream the data of a snapshot which changed since the base snapshot, or
+/// all its data without a base snapshot. The stream starts with a header,
+/// carries the data in chunks of at most `SNAPSHOT_STREAM_CHUNK_SIZE` bytes
+/// in increasing offset order and ends with a trailer.
+async fn export_snapshot_stream(
+    args: ExportSnapshotRequest,
+    mut sender: SnapshotStreamSender,
+) -> Result<(), LvsError> {
+    let (snapshot, base) = replica_snapshots(
+        &args.replica_uuid,
+        &args.snapshot_uuid,
+        args.base_snapshot_uuid.as_deref(),
+    )?;
+    let ranges = snapshot.changed_ranges(base.as_ref())?;
+    let bdev = snapshot.as_bdev();
+
+    let export_error = |source: Errno, msg: String| LvsError::SnapshotExport {
+        source,
+        name: snapshot.uuid(),
+        msg,
+    };
+    let handle = UntypedBdevHandle::open_with_bdev(&bdev, false)
+        .map_err(|e| export_error(Errno::ENODEV, e.to_string()))?;
+
+    info!(
+        snapshot = snapshot.uuid(),
+        base = ?args.base_snapshot_uuid,
+        ranges = ranges.len(),
+        "Exporting snapshot"
+    );
+
+    send_stream_message(
+        &mut sender,
+        &snapshot,
+        snapshot_stream_message::Message::Header(SnapshotStreamHeader {
+            version: SNAPSHOT_STREAM_VERSION,
+            snapshot_uuid: snapshot.uuid(),
+            base_snapshot_uuid: base.as_ref().map(|b| b.uuid()),
+            size: snapshot.size(),
+            cluster_size: snapshot.usage().cluster_size,
+            block_size: bdev.block_len(),
+        }),
+    )
+    .await?;
+
+    let mut trailer = SnapshotStreamTrailer::default();
+    for range in ranges {
+        let mut offset = range.offset;
+        while offset < range.end() {
+            let len = SNAPSHOT_STREAM_CHUNK_SIZE.min(range.end() - offset);
+            let mut buf = handle
+                .dma_malloc(len)
+                .map_err(|e| export_error(Errno::ENOMEM, e.to_string()))?;
+            handle
+                .read_at(offset, &mut buf)
+                .await
+                .map_err(|e| export_error(Errno::EIO, e.to_string()))?;
+
+            let data = buf.as_slice().to_vec();
+            let crc32 = crc::crc32::checksum_ieee(&data);
+            send_stream_message(
+                &mut sender,
+                &snapshot,
+                snapshot_stream_message::Message::Chunk(SnapshotStreamChunk {
+                    offset,
+                    data,
+                    crc32,
+                }),
+            )
+            .await?;
+
+            trailer.chunks += 1;
+            trailer.bytes += len;
+            offset += len;
+        }
+    }
+
+    send_stream_message(
+        &mut sender,
+        &snapshot,
+        snapshot_stream_message::Message::Trailer(trailer),
+    )
+    .await
+}
+
+/// Create a new thin replica and write a full snapshot stream into it. The
+/// replica is destroyed again if the stream turns out to be incomplete or
+/// corrupted.
+async fn import_replica_stream(
+    params: ImportReplicaParams,
+    mut receiver: mpsc::Receiver<SnapshotStreamMessage>,
+) -> Result<Replica, LvsError> {
+    let import_error = |source: Errno, msg: &str| LvsError::ReplicaImport {
+        source,
+        name: params.name.clone(),
+        msg: msg.to_string(),
+    };
+
+    let header = match receiver.next().await.and_then(|m| m.message) {
+        Some(snapshot_stream_message::Message::Header(header)) => header,
+        _ => {
+            return Err(import_error(
+                Errno::EINVAL,
+                "stream does not start with a header",
+            ))
+        }
+    };
+    if header.version != SNAPSHOT_STREAM_VERSION {
+        return Err(import_error(
+            Errno::EINVAL,
+            &format!("unsupported stream version {}", header.version),
+        ));
+    }
+    if header.base_snapshot_uuid.is_some() {
+        return Err(import_error(
+            Errno::EINVAL,
+            "incremental streams can not be imported into a new replica",
+        ));
+    }
+
+    let lvs = Lvs::lookup_by_uuid(&params.pooluuid)
+        .or_else(|| Lvs::lookup(&params.pooluuid))
+        .ok_or_else(|| {
+            import_error(
+                Errno::ENOMEDIUM,
+                &format!("pool {} not found", params.pooluuid),
+            )
+        })?;
+
+    // The stream only carries the ranges holding data, so the replica must
+    // be thin for the other ranges to read back as zeroes.
+    let lvol = lvs
+        .create_lvol(&params.name, header.size, Some(&params.uuid), true)
+        .await?;
+
+    info!(
+        replica = lvol.uuid(),
+        snapshot = header.snapshot_uuid,
+        "Importing replica from snapshot stream"
+    );
+
+    match write_replica_stream(&lvol, &mut receiver).await {
+        Ok(()) => Ok(Replica::from(lvol)),
+        Err(e) => {
+            error!(?lvol, ?e, "Failed to import replica, destroying it");
+            if let Err(error) = lvol.destroy().await {
+                error!(?error, "Failed to destroy imported replica");
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Write the chunks of a snapshot stream into the replica until the trailer
+/// is received.
+async fn write_replica_stream(
+    lvol: &Lvol,
+    receiver: &mut mpsc::Receiver<SnapshotStreamMessage>,
+) -> Result<(), LvsError> {
+    let import_error = |source: Errno, msg: String| LvsError::ReplicaImport {
+        source,
+        name: lvol.name(),
+        msg,
+    };
+
+    let bdev = lvol.as_bdev();
+    let block_len = bdev.block_len() as u64;
+    let handle = UntypedBdevHandle::open_with_bdev(&bdev, true)
+        .map_err(|e| import_error(Errno::ENODEV, e.to_string()))?;
+
+    let mut received = SnapshotStreamTrailer::default();
+    while let Some(message) = receiver.next().await {
+        match message.message {
+            Some(snapshot_stream_message::Message::Chunk(chunk)) => {
+                let len = chunk.data.len() as u64;
+                if chunk.offset % block_len != 0
+                    || len % block_len != 0
+                    || chunk.offset + len > lvol.size()
+                {
+                    return Err(import_error(
+                        Errno::EINVAL,
+                        format!(
+                            "invalid chunk at offset {} of {len} bytes",
+                            chunk.offset
+                        ),
+                    ));
+                }
+                if crc::crc32::checksum_ieee(&chunk.data) != chunk.crc32 {
+                    return Err(import_error(
+                        Errno::EINVAL,
+                        format!(
+                            "bad checksum of chunk at offset {}",
+                            chunk.offset
+                        ),
+                    ));
+                }
+
+                let mut buf = handle
+                    .dma_malloc(len)
+                    .map_err(|e| import_error(Errno::ENOMEM, e.to_string()))?;
+                buf.as_mut_slice().copy_from_slice(&chunk.data);
+                handle
+                    .write_at(chunk.offset, &buf)
+                    .await
+                    .map_err(|e| import_error(Errno::EIO, e.to_string()))?;
+
+                received.chunks += 1;
+                received.bytes += len;
+            }
+            Some(snapshot_stream_message::Message::Trailer(trailer)) => {
+                return if trailer == received {
+                    Ok(())
+                } else {
+                    Err(import_error(
+                        Errno::EINVAL,
+                        format!(
+                            "received {} chunks of {} bytes, expected {} chunks of {} bytes",
+                            received.chunks,
+                            received.bytes,
+                            trailer.chunks,
+                            trailer.bytes
+                        ),
+                    ))
+                };
+            }
+            _ => {
+                return Err(import_error(
+                    Errno::EINVAL,
+                    "unexpected stream message".to_string(),
+                ))
+            }
+        }
+    }
+
+    Err(import_error(
+        Errno::EINVAL,
+        "stream ended without a trailer".to_string(),
+    ))
+}
+
+/// Generate the SnapshotReplication status of a replication job.
+async fn snapshot_replication(
+    job: &SnapshotReplicationJob,
+) -> SnapshotReplication {
+    SnapshotReplication {
+        snapshot_uuid: job.snapshot_uuid().to_string(),
+        base_snapshot_uuid: job.base_snapshot_uuid().map(String::from),
+        target_uri: job.target_uri().to_string(),
+        state: RebuildJobState::from(job.state()) as i32,
+        stats: Some(job.stats().await.into()),
+        error: job.error_desc(),
+    }
+}
+
+/// Apply an operation to the replication job of the given target and return
+/// its status.
+async fn snapshot_replication_op(
+    target_uri: String,
+    op: fn(&SnapshotReplicationJob) -> Result<(), RebuildError>,
+) -> Result<SnapshotReplication, RebuildError> {
+    let job = SnapshotReplicationJob::lookup(&target_uri)?;
+    op(&job)?;
+    Ok(snapshot_replication(&job).await)
+}
+
 #[tonic::async_trait]
 impl SnapshotRpc for SnapshotService {
+    type ExportSnapshotStream = Pin<
+        Box<dyn Stream<Item = Result<SnapshotStreamMessage, Status>> + Send>,
+    >;
+
     #[named]
     async fn create_nexus_snapshot(
         &self,
@@ -331,6 +698,83 @@ impl SnapshotRpc for SnapshotService {
         })
         .await
     }
+    #[named]
+    async fn create_group_snapshot(
+        &self,
+        request: Request<CreateGroupSnapshotRequest>,
+    ) -> GrpcResult<CreateGroupSnapshotResponse> {
+        let ctx = GrpcClientContext::new(&request, function_name!());
+        let args = request.into_inner();
+
+        // Several nexuses are involved, so serialize against all other
+        // global operations.
+        self.serialized(ctx, args.group_id.clone(), true, async move {
+            trace!("{:?}", args);
+            let rx = rpc_submit::<_, _, nexus::Error>(async move {
+                let max_pause = args
+                    .max_pause_ms
+                    .map(|ms| Duration::from_millis(ms as u64))
+                    .unwrap_or(GROUP_SNAPSHOT_MAX_PAUSE);
+
+                let nexuses = args
+                    .nexuses
+                    .into_iter()
+                    .map(|n| NexusGroupSnapshotDescriptor {
+                        snapshot: SnapshotParams::new(
+                            Some(n.entity_id),
+                            Some(n.nexus_uuid.clone()),
+                            Some(args.group_id.clone()),
+                            Some(n.snapshot_name),
+                            None, // Snapshot UUID is handled per replica.
+                        ),
+                        nexus_uuid: n.nexus_uuid,
+                        replicas: n
+                            .replicas
+                            .into_iter()
+                            .map(NexusReplicaSnapshotDescriptor::from)
+                            .collect(),
+                    })
+                    .collect::<Vec<_>>();
+
+                let res = nexus::create_group_snapshot(
+                    &args.group_id,
+                    nexuses,
+                    max_pause,
+                )
+                .await?;
+
+                let mut nexuses = Vec::with_capacity(res.nexuses.len());
+                for (uuid, status) in res.nexuses {
+                    let nexus = nexus_lookup(&uuid)?;
+                    nexuses.push(NexusCreateSnapshotResponse {
+                        nexus: Some(nexus.into_grpc().await),
+                        snapshot_timestamp: Some(
+                            status.snapshot_timestamp.into(),
+                        ),
+                        replicas_done: status
+                            .replicas_done
+                            .into_iter()
+                            .map(NexusCreateSnapshotReplicaStatus::from)
+                            .collect(),
+                        replicas_skipped: status.replicas_skipped,
+                    });
+                }
+
+                Ok(CreateGroupSnapshotResponse {
+                    group_id: res.group_id,
+                    snapshot_timestamp: Some(res.snapshot_timestamp.into()),
+                    nexuses,
+                })
+            })?;
+
+            rx.await
+                .map_err(|_| Status::cancelled("cancelled"))?
+                .map_err(Status::from)
+                .map(Response::new)
+        })
+        .await
+    }
+
     #[named]
     async fn create_replica_snapshot(
         &self,
@@ -411,63 +855,58 @@ impl SnapshotRpc for SnapshotService {
                 info!("{:?}", args);
                 let rx = rpc_submit(async move {
                     // if snapshot_uuid is input, get specific snapshot result
-                    if let Some(snapshot_uuid) = args.snapshot_uuid {
-                        let lvol = match UntypedBdev::lookup_by_uuid_str(
-                            &snapshot_uuid,
-                        ) {
-                            Some(bdev) => Lvol::try_from(bdev)?,
-                            None => {
-                                return Err(LvsError::Invalid {
-                                    source: Errno::ENOENT,
-                                    msg: format!(
-                                        "Replica {snapshot_uuid} not found",
-                                    ),
-                                })
-                            }
-                        };
-                        let snapshots = lvol
-                            .list_snapshot_by_snapshot_uuid()
-                            .into_iter()
-                            .map(SnapshotInfo::from)
-                            .collect();
-                        Ok(ListSnapshotsResponse {
-                            snapshots,
-                        })
-                    } else if let Some(replica_uuid) = args.source_uuid {
-                        // if replica_uuid is valid, filter snapshot based
-                        // on source_uuid
-                        let lvol = match UntypedBdev::lookup_by_uuid_str(
-                            &replica_uuid,
-                        ) {
-                            Some(bdev) => Lvol::try_from(bdev)?,
-                            None => {
-                                return Err(LvsError::Invalid {
-                                    source: Errno::ENOENT,
-                                    msg: format!(
-                                        "Replica {replica_uuid} not found",
-                                    ),
-                                })
-                            }
+                    let snapshots =
+                        if let Some(snapshot_uuid) = args.snapshot_uuid {
+                            let lvol = match UntypedBdev::lookup_by_uuid_str(
+                                &snapshot_uuid,
+                            ) {
+                                Some(bdev) => Lvol::try_from(bdev)?,
+                                None => {
+                                    return Err(LvsError::Invalid {
+                                        source: Errno::ENOENT,
+                                        msg: format!(
+                                            "Replica {snapshot_uuid} not found",
+                                        ),
+                                    })
+                                }
+                            };
+                            lvol.list_snapshot_by_snapshot_uuid()
+                        } else if let Some(replica_uuid) = args.source_uuid {
+                            // if replica_uuid is valid, filter snapshot based
+                            // on source_uuid
+                            let lvol = match UntypedBdev::lookup_by_uuid_str(
+                                &replica_uuid,
+                            ) {
+                                Some(bdev) => Lvol::try_from(bdev)?,
+                                None => {
+                                    return Err(LvsError::Invalid {
+                                        source: Errno::ENOENT,
+                                        msg: format!(
+                                            "Replica {replica_uuid} not found",
+                                        ),
+                                    })
+                                }
+                            };
+                            lvol.list_snapshot_by_source_uuid()
+                        } else {
+                            // if source_uuid is not input, list all snapshot
+                            // present in system
+                            Lvol::list_all_snapshots()
                         };
-                        let snapshots = lvol
-                            .list_snapshot_by_source_uuid()
-                            .into_iter()
-                            .map(SnapshotInfo::from)
-                            .collect();
-                        Ok(ListSnapshotsResponse {
-                            snapshots,
-                        })
-                    } else {
-                        // if source_uuid is not input, list all snapshot
-                        // present in system
-                        let snapshots = Lvol::list_all_snapshots()
-                            .into_iter()
-                            .map(SnapshotInfo::from)
-                            .collect();
-                        Ok(ListSnapshotsResponse {
-                            snapshots,
+
+                    // snapshots of a group share the group ID as their
+                    // transaction ID
+                    let snapshots = snapshots
+                        .into_iter()
+                        .filter(|s| {
+                            args.group_id.is_none()
+                                || s.snapshot_params().txn_id() == args.group_id
                         })
-                    }
+                        .map(SnapshotInfo::from)
+                        .collect();
+                    Ok(ListSnapshotsResponse {
+                        snapshots,
+                    })
                 })?;
                 rx.await
                     .map_err(|_| Status::cancelled("cancelled"))?
@@ -565,4 +1004,266 @@ impl SnapshotRpc for SnapshotService {
         )
         .await
     }
+
+    #[named]
+    async fn list_snapshot_changes(
+        &self,
+        request: Request<ListSnapshotChangesRequest>,
+    ) -> GrpcResult<ListSnapshotChangesResponse> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(async move {
+                    let (snapshot, base) = replica_snapshots(
+                        &args.replica_uuid,
+                        &args.snapshot_uuid,
+                        args.base_snapshot_uuid.as_deref(),
+                    )?;
+                    let ranges = snapshot
+                        .changed_ranges(base.as_ref())?
+                        .into_iter()
+                        .map(|r| SnapshotRange {
+                            offset: r.offset,
+                            length: r.len,
+                        })
+                        .collect();
+
+                    Ok(ListSnapshotChangesResponse {
+                        snapshot_uuid: snapshot.uuid(),
+                        base_snapshot_uuid: base.map(|b| b.uuid()),
+                        cluster_size: snapshot.usage().cluster_size,
+                        ranges,
+                    })
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    async fn export_snapshot(
+        &self,
+        request: Request<ExportSnapshotRequest>,
+    ) -> GrpcResult<Self::ExportSnapshotStream> {
+        let args = request.into_inner();
+        info!("{:?}", args);
+
+        // The export runs on the reactor for as long as the client keeps
+        // reading the stream, so it is not serialized with other calls.
+        let (sender, receiver) = mpsc::channel(SNAPSHOT_STREAM_DEPTH);
+        let rx = rpc_submit(export_snapshot_stream(args, sender.clone()))?;
+
+        // Terminate the stream with the error the export failed with.
+        let mut sender = sender;
+        tokio::spawn(async move {
+            let result = rx
+                .await
+                .map_err(|_| Status::cancelled("cancelled"))
+                .and_then(|r| r.map_err(Status::from));
+            if let Err(status) = result {
+                let _ = sender.send(Err(status)).await;
+            }
+        });
+
+        Ok(Response::new(Box::pin(receiver)))
+    }
+
+    async fn import_replica(
+        &self,
+        request: Request<Streaming<ImportReplicaRequest>>,
+    ) -> GrpcResult<Replica> {
+        let mut stream = request.into_inner();
+        let params = match stream.message().await? {
+            Some(ImportReplicaRequest {
+                message: Some(import_replica_request::Message::Params(params)),
+            }) => params,
+            _ => {
+                return Err(Status::invalid_argument(
+                    "The first message must carry the replica parameters",
+                ))
+            }
+        };
+        info!("{:?}", params);
+
+        let (mut sender, receiver) = mpsc::channel(SNAPSHOT_STREAM_DEPTH);
+        let rx = rpc_submit(import_replica_stream(params, receiver))?;
+
+        // Feed the snapshot stream to the import running on the reactor.
+        // Anything else than stream messages is passed on as an empty message
+        // for the import to reject it.
+        while let Some(request) = stream.message().await? {
+            let message = match request.message {
+                Some(import_replica_request::Message::Stream(message)) => {
+                    message
+                }
+                _ => SnapshotStreamMessage::default(),
+            };
+            if sender.send(message).await.is_err() {
+                // The import has failed, its result tells why.
+                break;
+            }
+        }
+        drop(sender);
+
+        rx.await
+            .map_err(|_| Status::cancelled("cancelled"))?
+            .map_err(Status::from)
+            .map(Response::new)
+    }
+
+    #[named]
+    async fn start_snapshot_replication(
+        &self,
+        request: Request<StartSnapshotReplicationRequest>,
+    ) -> GrpcResult<SnapshotReplication> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit::<_, _, Status>(async move {
+                    let (snapshot, base) = replica_snapshots(
+                        &args.replica_uuid,
+                        &args.snapshot_uuid,
+                        args.base_snapshot_uuid.as_deref(),
+                    )?;
+                    let job = SnapshotReplicationJob::start(
+                        &snapshot,
+                        base.as_ref(),
+                        &args.target_uri,
+                    )
+                    .await?;
+                    Ok(snapshot_replication(&job).await)
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn stop_snapshot_replication(
+        &self,
+        request: Request<SnapshotReplicationRequest>,
+    ) -> GrpcResult<SnapshotReplication> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(snapshot_replication_op(
+                    args.target_uri,
+                    SnapshotReplicationJob::stop,
+                ))?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn pause_snapshot_replication(
+        &self,
+        request: Request<SnapshotReplicationRequest>,
+    ) -> GrpcResult<SnapshotReplication> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(snapshot_replication_op(
+                    args.target_uri,
+                    SnapshotReplicationJob::pause,
+                ))?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn resume_snapshot_replication(
+        &self,
+        request: Request<SnapshotReplicationRequest>,
+    ) -> GrpcResult<SnapshotReplication> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let args = request.into_inner();
+                info!("{:?}", args);
+                let rx = rpc_submit(snapshot_replication_op(
+                    args.target_uri,
+                    SnapshotReplicationJob::resume,
+                ))?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map_err(Status::from)
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn list_snapshot_replications(
+        &self,
+        request: Request<ListSnapshotReplicationsRequest>,
+    ) -> GrpcResult<ListSnapshotReplicationsResponse> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let rx = rpc_submit::<_, _, Status>(async move {
+                    let mut replications = Vec::new();
+                    for job in SnapshotReplicationJob::list() {
+                        replications.push(snapshot_replication(&job).await);
+                    }
+                    Ok(ListSnapshotReplicationsResponse {
+                        replications,
+                    })
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
+
+    #[named]
+    async fn get_snapshot_replication_history(
+        &self,
+        request: Request<SnapshotReplicationHistoryRequest>,
+    ) -> GrpcResult<SnapshotReplicationHistoryResponse> {
+        self.locked(
+            GrpcClientContext::new(&request, function_name!()),
+            async move {
+                let rx = rpc_submit::<_, _, Status>(async move {
+                    let records = SnapshotReplicationJob::history()
+                        .into_iter()
+                        .map(SnapshotReplicationHistoryRecord::from)
+                        .collect();
+                    Ok(SnapshotReplicationHistoryResponse {
+                        records,
+                    })
+                })?;
+                rx.await
+                    .map_err(|_| Status::cancelled("cancelled"))?
+                    .map(Response::new)
+            },
+        )
+        .await
+    }
 }
@@ -0,0 +1,42 @@
+use crate::core::{BlockDeviceIoStats, IoLatencyStats, LatencyHistogram};
+use mayastor_api::v1::stats;
+
+impl From<BlockDeviceIoStats> for stats::IoStats {
+    fn from(b: BlockDeviceIoStats) -> Self {
+        Self {
+            num_read_ops: b.num_read_ops,
+            num_write_ops: b.num_write_ops,
+            bytes_read: b.bytes_read,
+            bytes_written: b.bytes_written,
+            num_unmap_ops: b.num_unmap_ops,
+            bytes_unmapped: b.bytes_unmapped,
+        }
+    }
+}
+
+impl From<&LatencyHistogram> for stats::LatencyHistogram {
+    fn from(h: &LatencyHistogram) -> Self {
+        Self {
+            count: h.count(),
+            sum_ns: h.sum_ns(),
+            buckets: h
+                .buckets()
+                .map(|(upper_bound_ns, count)| stats::LatencyBucket {
+                    upper_bound_ns,
+                    count,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<IoLatencyStats> for stats::IoLatencyHistograms {
+    fn from(l: IoLatencyStats) -> Self {
+        Self {
+            read: Some((&l.read).into()),
+            write: Some((&l.write).into()),
+            unmap: Some((&l.unmap).into()),
+            flush: Some((&l.flush).into()),
+        }
+    }
+}
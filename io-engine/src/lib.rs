@@ -17,11 +17,13 @@ pub mod delay;
 pub use spdk_rs::ffihelper;
 pub mod bdev_api;
 pub mod constants;
+pub mod events;
 pub mod grpc;
 pub mod host;
 pub mod jsonrpc;
 pub mod logger;
 pub mod lvs;
+pub mod metrics;
 pub mod persistent_store;
 pub mod pool_backend;
 pub mod rebuild;
@@ -44,4 +46,11 @@ pub extern "C" fn cps_init() {
     subsys::register_subsystem();
     bdev::nexus::register_module();
     bdev::null_ng::register();
+    bdev::crypt::register();
+    bdev::delay_bdev::register();
+    bdev::error_bdev::register();
+    bdev::sparse_file::register();
+    bdev::uring_ng::register();
+    bdev::cor_bdev::register();
+    bdev::compress_bdev::register();
 }
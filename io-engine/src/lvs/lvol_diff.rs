@@ -0,0 +1,153 @@
+//!
+//! Allocation based differences between snapshots of an lvol.
+//!
+//! The blobs of a snapshot chain only hold the clusters which were written
+//! while they were the writable head of the chain, so the clusters which
+//! changed between an older and a newer snapshot of the same lvol are the
+//! clusters allocated by the newer snapshot and by every snapshot in between.
+//! These are found from the blob allocation metadata alone, without reading
+//! any data.
+use nix::errno::Errno;
+
+use spdk_rs::libspdk::{
+    spdk_blob_get_id,
+    spdk_blob_get_next_allocated_io_unit,
+    spdk_blob_get_next_unallocated_io_unit,
+    spdk_blob_get_num_clusters,
+    spdk_blob_get_parent_snapshot,
+    spdk_blob_id,
+    spdk_bs_get_cluster_size,
+    spdk_bs_get_io_unit_size,
+};
+
+use super::{Error, Lvol, LvsLvol};
+use crate::core::LogicalVolume;
+
+/// Blob id returned by the blobstore when there is no such blob.
+const SPDK_BLOBID_INVALID: spdk_blob_id = u64::MAX;
+
+/// A byte range of an lvol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LvolRange {
+    /// Offset of the range in bytes.
+    pub offset: u64,
+    /// Length of the range in bytes.
+    pub len: u64,
+}
+
+impl LvolRange {
+    /// Get the offset right past the end of the range.
+    pub fn end(&self) -> u64 {
+        self.offset + self.len
+    }
+}
+
+impl Lvol {
+    /// Get the id of the blob backing the lvol.
+    pub(crate) fn blob_id(&self) -> spdk_blob_id {
+        unsafe { spdk_blob_get_id(self.blob_checked()) }
+    }
+
+    /// Get the snapshot the lvol depends on, which for a snapshot is the
+    /// snapshot taken before it and for a replica the latest snapshot.
+    pub fn parent_snapshot(&self) -> Option<Lvol> {
+        let parent = unsafe {
+            spdk_blob_get_parent_snapshot(
+                self.lvs().blob_store(),
+                self.blob_id(),
+            )
+        };
+        if parent == SPDK_BLOBID_INVALID {
+            return None;
+        }
+        self.lvs().lvols()?.find(|l| l.blob_id() == parent)
+    }
+
+    /// Get the ranges allocated by the lvol's own blob, leaving out the ones
+    /// it shares with the snapshots it depends on.
+    pub fn allocated_ranges(&self) -> Vec<LvolRange> {
+        let bs = self.lvs().blob_store();
+        let blob = self.blob_checked();
+        let (io_unit_size, num_io_units) = unsafe {
+            let io_unit_size = spdk_bs_get_io_unit_size(bs);
+            let num_io_units = spdk_blob_get_num_clusters(blob)
+                * spdk_bs_get_cluster_size(bs)
+                / io_unit_size;
+            (io_unit_size, num_io_units)
+        };
+
+        let mut ranges = Vec::new();
+        let mut io_unit = 0;
+        while io_unit < num_io_units {
+            let start =
+                unsafe { spdk_blob_get_next_allocated_io_unit(blob, io_unit) };
+            if start >= num_io_units {
+                break;
+            }
+            let end =
+                unsafe { spdk_blob_get_next_unallocated_io_unit(blob, start) }
+                    .min(num_io_units);
+
+            ranges.push(LvolRange {
+                offset: start * io_unit_size,
+                len: (end - start) * io_unit_size,
+            });
+            io_unit = end;
+        }
+        ranges
+    }
+
+    /// Get the ranges of this snapshot which changed since the `base`
+    /// snapshot was taken, which must be an older snapshot of the same lvol.
+    /// Without a base, all the ranges holding data are returned.
+    /// The ranges are sorted by offset and do not overlap.
+    pub fn changed_ranges(
+        &self,
+        base: Option<&Lvol>,
+    ) -> Result<Vec<LvolRange>, Error> {
+        let base_id = base.map(|b| b.blob_id());
+        if base_id == Some(self.blob_id()) {
+            return Ok(Vec::new());
+        }
+
+        let mut ranges = Vec::new();
+        let mut current = Some(self.clone());
+        while let Some(lvol) = current {
+            ranges.extend(lvol.allocated_ranges());
+            current = lvol.parent_snapshot();
+            if base_id.is_some()
+                && current.as_ref().map(|l| l.blob_id()) == base_id
+            {
+                return Ok(merge_ranges(ranges));
+            }
+        }
+
+        match base {
+            Some(base) => Err(Error::Invalid {
+                source: Errno::EINVAL,
+                msg: format!(
+                    "Snapshot {} is not an ancestor of {}",
+                    base.uuid(),
+                    self.uuid()
+                ),
+            }),
+            None => Ok(merge_ranges(ranges)),
+        }
+    }
+}
+
+/// Sort the ranges and merge the adjacent and overlapping ones.
+fn merge_ranges(mut ranges: Vec<LvolRange>) -> Vec<LvolRange> {
+    ranges.sort_by_key(|r| r.offset);
+
+    let mut merged: Vec<LvolRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.offset <= last.end() => {
+                last.len = last.end().max(range.end()) - last.offset;
+            }
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
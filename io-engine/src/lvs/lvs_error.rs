@@ -58,6 +58,16 @@ pub enum Error {
         name: String,
         msg: String,
     },
+    #[snafu(display("errno: {} failed to resize lvol {}", source, name))]
+    RepResize {
+        source: Errno,
+        name: String,
+    },
+    #[snafu(display("compressed bdev of lvol {} failed: {}", name, source))]
+    Compression {
+        source: BdevError,
+        name: String,
+    },
     #[snafu(display("bdev {} is not a lvol", name))]
     NotALvol {
         source: Errno,
@@ -128,4 +138,16 @@ pub enum Error {
         name: String,
         msg: String,
     },
+    #[snafu(display("Failed to export snapshot {}: {}", name, msg))]
+    SnapshotExport {
+        source: Errno,
+        name: String,
+        msg: String,
+    },
+    #[snafu(display("Failed to import replica {}: {}", name, msg))]
+    ReplicaImport {
+        source: Errno,
+        name: String,
+        msg: String,
+    },
 }
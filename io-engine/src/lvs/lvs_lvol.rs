@@ -30,6 +30,7 @@ use spdk_rs::libspdk::{
     vbdev_lvol_create_snapshot_ext,
     vbdev_lvol_destroy,
     vbdev_lvol_get_from_bdev,
+    vbdev_lvol_resize,
     LVS_CLEAR_WITH_UNMAP,
     SPDK_BDEV_LARGE_BUF_MAX_SIZE,
 };
@@ -37,7 +38,18 @@ use spdk_rs::libspdk::{
 use super::{Error, Lvs};
 
 use crate::{
-    bdev::PtplFileOps,
+    bdev::{
+        compress_bdev::{
+            compress_bdev_stats,
+            compress_destroy,
+            compress_resize,
+            compress_resize_backing_size,
+            CompressionCodec,
+            COMPRESS_MODULE_NAME,
+        },
+        PtplFileOps,
+    },
+    bdev_api::{bdev_create, BdevError},
     core::{
         logical_volume::LogicalVolume,
         snapshot::{SnapshotDescriptor, VolumeSnapshotDescriptor},
@@ -52,6 +64,7 @@ use crate::{
         UntypedBdev,
         UpdateProps,
     },
+    events::{pool_space_check, EngineEvent},
     ffihelper::{
         cb_arg,
         errno_result_from_i32,
@@ -75,6 +88,7 @@ pub(crate) const WIPE_SUPER_LEN: u64 = (1 << 20) * 8;
 pub enum PropValue {
     Shared(bool),
     AllowedHosts(Vec<String>),
+    Compression(String),
 }
 
 #[derive(Debug)]
@@ -82,6 +96,7 @@ pub enum PropValue {
 pub enum PropName {
     Shared,
     AllowedHosts,
+    Compression,
 }
 
 impl From<&PropValue> for PropName {
@@ -89,6 +104,7 @@ impl From<&PropValue> for PropName {
         match v {
             PropValue::Shared(_) => Self::Shared,
             PropValue::AllowedHosts(_) => Self::AllowedHosts,
+            PropValue::Compression(_) => Self::Compression,
         }
     }
 }
@@ -103,6 +119,7 @@ impl Display for PropName {
         let name = match self {
             PropName::Shared => "shared",
             PropName::AllowedHosts => "allowed-hosts",
+            PropName::Compression => "compression",
         };
         write!(f, "{name}")
     }
@@ -121,6 +138,9 @@ pub struct LvolSpaceUsage {
     pub num_clusters: u64,
     /// Number of actually allocated clusters.
     pub num_allocated_clusters: u64,
+    /// Ratio of the size of the data written to the size it takes once
+    /// compressed, 1.0 for uncompressed lvols.
+    pub compression_ratio: f64,
 }
 #[derive(Clone)]
 /// struct representing an lvol
@@ -177,7 +197,7 @@ impl Share for Lvol {
             .as_ref()
             .map(|s| s.allowed_hosts().clone())
             .unwrap_or_default();
-        let share = Pin::new(&mut self.as_bdev())
+        let share = Pin::new(&mut self.data_bdev())
             .share_nvmf(props)
             .await
             .map_err(|e| Error::LvolShare {
@@ -197,7 +217,7 @@ impl Share for Lvol {
         self: Pin<&mut Self>,
         props: P,
     ) -> Result<(), Self::Error> {
-        Pin::new(&mut self.as_bdev())
+        Pin::new(&mut self.data_bdev())
             .update_properties(props)
             .await
             .map_err(|e| Error::UpdateShareProperties {
@@ -209,12 +229,13 @@ impl Share for Lvol {
 
     /// unshare the nvmf target
     async fn unshare(mut self: Pin<&mut Self>) -> Result<(), Self::Error> {
-        Pin::new(&mut self.as_bdev()).unshare().await.map_err(|e| {
-            Error::LvolUnShare {
+        Pin::new(&mut self.data_bdev())
+            .unshare()
+            .await
+            .map_err(|e| Error::LvolUnShare {
                 source: e,
                 name: self.name(),
-            }
-        })?;
+            })?;
 
         self.as_mut().set(PropValue::Shared(false)).await?;
 
@@ -224,7 +245,7 @@ impl Share for Lvol {
 
     /// return the protocol this bdev is shared under
     fn shared(&self) -> Option<Protocol> {
-        self.as_bdev().shared()
+        self.data_bdev().shared()
     }
 
     /// returns the share URI this lvol is shared as
@@ -232,12 +253,12 @@ impl Share for Lvol {
     /// uniquely identify a replica as the replica UUID is currently set to its
     /// name, which is *NOT* unique and in MOAC's use case, is the volume UUID
     fn share_uri(&self) -> Option<String> {
-        let uri_no_uuid = self.as_bdev().share_uri();
+        let uri_no_uuid = self.data_bdev().share_uri();
         uri_no_uuid.map(|uri| format!("{}?uuid={}", uri, self.uuid()))
     }
 
     fn allowed_hosts(&self) -> Vec<String> {
-        self.as_bdev().allowed_hosts()
+        self.data_bdev().allowed_hosts()
     }
 
     /// returns the URI that is used to construct the bdev. This is always None
@@ -274,7 +295,7 @@ impl Lvol {
 
     /// TODO
     #[inline(always)]
-    fn blob_checked(&self) -> *mut spdk_blob {
+    pub(super) fn blob_checked(&self) -> *mut spdk_blob {
         let blob = self.as_inner_ref().blob;
         assert!(!blob.is_null());
         blob
@@ -352,6 +373,142 @@ impl Lvol {
         LvolPtpl::from(self)
     }
 
+    /// Returns the name of the compressed bdev of this lvol.
+    fn compressed_name(&self) -> String {
+        format!("{}-compressed", self.name())
+    }
+
+    /// Returns the compressed bdev of this lvol, if the lvol is compressed.
+    pub fn compressed_bdev(&self) -> Option<UntypedBdev> {
+        UntypedBdev::lookup_by_name(&self.compressed_name())
+            .filter(|b| b.driver() == COMPRESS_MODULE_NAME)
+    }
+
+    /// Returns the codec of this lvol, as its compression property, if the
+    /// lvol is compressed.
+    fn compression_codec(&self) -> Option<String> {
+        let name = PropName::Compression.to_string().into_cstring();
+        let mut value: *const libc::c_char = std::ptr::null::<libc::c_char>();
+        let mut value_len: u64 = 0;
+        let rc = unsafe {
+            spdk_blob_get_xattr_value(
+                self.blob_checked(),
+                name.as_ptr(),
+                &mut value as *mut *const c_char as *mut *const c_void,
+                &mut value_len,
+            )
+        };
+        if rc != 0 {
+            return None;
+        }
+        unsafe { CStr::from_ptr(value) }
+            .to_str()
+            .ok()
+            .map(String::from)
+    }
+
+    /// Returns the bdev which holds the data of this lvol: its compressed
+    /// bdev if the lvol is compressed, or else its own bdev.
+    pub fn data_bdev(&self) -> UntypedBdev {
+        self.compressed_bdev().unwrap_or_else(|| self.as_bdev())
+    }
+
+    /// Opens the compressed bdev of this lvol. The compressed bdev is
+    /// formatted to hold `size` bytes when it is opened for the first time.
+    pub(crate) async fn open_compressed(
+        &self,
+        codec: CompressionCodec,
+        size: Option<u64>,
+    ) -> Result<UntypedBdev, Error> {
+        let mut uri = format!(
+            "compress:///{}?name={}&codec={codec}",
+            self.name(),
+            self.compressed_name()
+        );
+        if let Some(size) = size {
+            uri.push_str(&format!("&size={size}"));
+        }
+
+        bdev_create(&uri)
+            .await
+            .map_err(|source| Error::Compression {
+                source,
+                name: self.name(),
+            })?;
+        self.compressed_bdev().ok_or_else(|| Error::Compression {
+            source: BdevError::BdevNotFound {
+                name: self.compressed_name(),
+            },
+            name: self.name(),
+        })
+    }
+
+    /// Closes the compressed bdev of this lvol, if it is open.
+    pub(crate) async fn close_compressed(&self) -> Result<(), Error> {
+        if self.compressed_bdev().is_some() {
+            compress_destroy(&self.compressed_name()).await.map_err(
+                |source| Error::Compression {
+                    source,
+                    name: self.name(),
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Grows this lvol to `size` bytes. A compressed lvol is grown to hold
+    /// `size` bytes of data along with its compressed bdev, which cannot
+    /// grow beyond the room its map was created with.
+    pub async fn resize(&self, size: u64) -> Result<(), Error> {
+        extern "C" fn resize_cb(sender: *mut c_void, errno: i32) {
+            let sender =
+                unsafe { Box::from_raw(sender as *mut oneshot::Sender<i32>) };
+            sender.send(errno).ok();
+        }
+
+        let compressed = self.compressed_bdev().map(|b| b.name().to_string());
+        let lvol_size =
+            match &compressed {
+                Some(name) => compress_resize_backing_size(name, size)
+                    .map_err(|source| Error::Compression {
+                        source,
+                        name: self.name(),
+                    })?,
+                None => size,
+            };
+
+        if lvol_size > self.size() {
+            let (s, r) = pair::<i32>();
+            unsafe {
+                vbdev_lvol_resize(
+                    self.as_inner_ptr(),
+                    lvol_size,
+                    Some(resize_cb),
+                    cb_arg(s),
+                )
+            };
+            r.await
+                .expect("lvol resize callback is gone")
+                .to_result(|e| Error::RepResize {
+                    source: Errno::from_i32(e),
+                    name: self.name(),
+                })?;
+        }
+
+        if let Some(name) = compressed {
+            compress_resize(&name, size).await.map_err(|source| {
+                Error::Compression {
+                    source,
+                    name: self.name(),
+                }
+            })?;
+        }
+
+        info!("{:?}: resized to {} bytes", self, size);
+        pool_space_check(&self.lvs());
+        Ok(())
+    }
+
     /// TODO:
     fn prepare_snapshot_xattrs(
         &self,
@@ -435,6 +592,21 @@ impl Lvol {
             &mut cstrs,
         )?;
 
+        // The snapshot of a compressed lvol holds the compressed data, and
+        // is compressed with the same codec.
+        let mut attr_descrs = attr_descrs.to_vec();
+        if let Some(codec) = self.compression_codec() {
+            let attr_name = PropName::Compression.to_string().into_cstring();
+            let attr_val = codec.into_cstring();
+            attr_descrs.push(spdk_xattr_descriptor {
+                name: attr_name.as_ptr() as *mut c_char,
+                value: attr_val.as_ptr() as *mut c_void,
+                value_len: attr_val.as_bytes_with_nul().len() as c_ushort,
+            });
+            cstrs.push(attr_val);
+            cstrs.push(attr_name);
+        }
+
         let c_snapshot_name = snap_param.name().unwrap().into_cstring();
 
         // No need to flush blob's buffers explicitly as SPDK always
@@ -444,7 +616,7 @@ impl Lvol {
                 self.as_inner_ptr(),
                 c_snapshot_name.as_ptr(),
                 attr_descrs.as_mut_ptr(),
-                SnapshotXattrs::COUNT as u32,
+                attr_descrs.len() as u32,
                 Some(done_cb),
                 done_cb_arg,
             )
@@ -809,17 +981,20 @@ impl LogicalVolume for Lvol {
     fn usage(&self) -> LvolSpaceUsage {
         let bs = self.lvs().blob_store();
         let blob = self.blob_checked();
+        let compressed = compress_bdev_stats(&self.compressed_name()).ok();
         unsafe {
             let cluster_size = spdk_bs_get_cluster_size(bs);
             let num_clusters = spdk_blob_get_num_clusters(blob);
             let num_allocated_clusters = spdk_blob_calc_used_clusters(blob);
 
             LvolSpaceUsage {
-                capacity_bytes: self.size(),
+                capacity_bytes: compressed
+                    .map_or_else(|| self.size(), |s| s.logical_size),
                 allocated_bytes: cluster_size * num_allocated_clusters,
                 cluster_size,
                 num_clusters,
                 num_allocated_clusters,
+                compression_ratio: compressed.map_or(1.0, |s| s.ratio()),
             }
         }
     }
@@ -908,6 +1083,32 @@ impl LvsLvol for Lvol {
                     }),
                 }
             }
+            PropName::Compression => {
+                let name = prop.to_string().into_cstring();
+                let mut value: *const libc::c_char =
+                    std::ptr::null::<libc::c_char>();
+                let mut value_len: u64 = 0;
+                unsafe {
+                    spdk_blob_get_xattr_value(
+                        blob,
+                        name.as_ptr(),
+                        &mut value as *mut *const c_char as *mut *const c_void,
+                        &mut value_len,
+                    )
+                }
+                .to_result(|e| Error::GetProperty {
+                    source: Errno::from_i32(e),
+                    prop,
+                    name: self.name(),
+                })?;
+                match unsafe { CStr::from_ptr(value).to_str() } {
+                    Ok(codec) => Ok(PropValue::Compression(codec.to_string())),
+                    _ => Err(Error::Property {
+                        source: Errno::EINVAL,
+                        name: self.name(),
+                    }),
+                }
+            }
         }
     }
 
@@ -927,6 +1128,7 @@ impl LvsLvol for Lvol {
 
         // we must always unshare before destroying bdev
         let _ = Pin::new(&mut self).unshare().await;
+        self.close_compressed().await?;
 
         let name = self.name();
         let ptpl = self.ptpl();
@@ -1007,6 +1209,23 @@ impl LvsLvol for Lvol {
                     name: self.name(),
                 })?;
             }
+            PropValue::Compression(codec) => {
+                let name = PropName::from(&prop).to_string().into_cstring();
+                let value = codec.into_cstring();
+                unsafe {
+                    spdk_blob_set_xattr(
+                        blob,
+                        name.as_ptr(),
+                        value.as_bytes_with_nul().as_ptr() as *const _,
+                        value.as_bytes_with_nul().len() as u16,
+                    )
+                }
+                .to_result(|e| Error::SetProperty {
+                    source: Errno::from_i32(e),
+                    prop: prop.into(),
+                    name: self.name(),
+                })?;
+            }
         }
         Ok(())
     }
@@ -1087,6 +1306,7 @@ impl LvsLvol for Lvol {
                 volume = self.name(),
                 "Failed to create remote snapshot"
             );
+            nvmf_req.complete(0x06); // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
         }
     }
 
@@ -1171,15 +1391,64 @@ impl SnapshotOps for Lvol {
 
         let (s, r) = oneshot::channel::<(i32, *mut spdk_lvol)>();
 
-        let create_snapshot = self
+        let snapshot = self
             .do_create_snapshot(
                 snap_param,
                 snapshot_create_done_cb,
                 cb_arg(s),
                 r,
             )
-            .await;
-        create_snapshot
+            .await?;
+
+        // The snapshot of a compressed lvol is read through its own
+        // compressed bdev, which is read-only as the snapshot is.
+        if let Some(codec) = snapshot.compression_codec() {
+            let res = match codec.parse::<CompressionCodec>() {
+                Ok(codec) => snapshot.open_compressed(codec, None).await,
+                Err(msg) => Err(Error::Invalid {
+                    source: Errno::EINVAL,
+                    msg,
+                }),
+            };
+            if let Err(e) = res {
+                error!(
+                    "{:?}: failed to open compressed snapshot: {}",
+                    snapshot,
+                    e.to_string()
+                );
+            }
+        }
+
+        EngineEvent::SnapshotCreated {
+            pool_uuid: self.pool_uuid(),
+            source_uuid: self.uuid(),
+            snapshot_uuid: snapshot.uuid(),
+            snapshot_name: snapshot.name(),
+        }
+        .publish();
+        pool_space_check(&self.lvs());
+
+        Ok(snapshot)
+    }
+    /// Destroy a snapshot of the volume, which must be in the same pool.
+    async fn destroy_snapshot(&self, snapshot_uuid: &str) -> Result<(), Error> {
+        let snapshot = UntypedBdev::lookup_by_uuid_str(snapshot_uuid)
+            .filter(|b| b.driver() == "lvol")
+            .and_then(|b| Lvol::try_from(b).ok())
+            .filter(|s| s.is_snapshot() && s.lvs().uuid() == self.lvs().uuid())
+            .ok_or_else(|| Error::Invalid {
+                source: Errno::ENOENT,
+                msg: format!(
+                    "Snapshot {snapshot_uuid} of {} not found",
+                    self.name()
+                ),
+            })?;
+
+        info!(
+            volume = self.name(),
+            snapshot_uuid, "Destroying a volume snapshot"
+        );
+        snapshot.destroy().await.map(|_| ())
     }
     /// Get a Snapshot Iterator.
     async fn snapshot_iter(self) -> LvolSnapshotIter {
@@ -36,7 +36,15 @@ use url::Url;
 use super::{Error, Lvol, LvsIter, PropName, PropValue};
 
 use crate::{
-    bdev::{uri, PtplFileOps},
+    bdev::{
+        compress_bdev::{
+            compress_backing_size,
+            CompressionCodec,
+            DEFAULT_CHUNK_SIZE,
+        },
+        uri,
+        PtplFileOps,
+    },
     bdev_api::{bdev_destroy, BdevError},
     core::{
         logical_volume::LogicalVolume,
@@ -47,6 +55,7 @@ use crate::{
         ShareProps,
         UntypedBdev,
     },
+    events::{pool_space_check, EngineEvent},
     ffihelper::{cb_arg, pair, AsStr, ErrnoResult, FfiResult, IntoCString},
     lvs::lvs_lvol::{LvsLvol, WIPE_SUPER_LEN},
     pool_backend::PoolArgs,
@@ -290,6 +299,7 @@ impl Lvs {
                 name: pool_name,
             })
         } else {
+            lvs.open_all_compressed().await;
             lvs.share_all().await;
             info!("{:?}: existing lvs imported successfully", lvs);
             Ok(lvs)
@@ -417,6 +427,11 @@ impl Lvs {
         match Self::lookup(name) {
             Some(pool) => {
                 info!("{:?}: new lvs created successfully", pool);
+                EngineEvent::PoolCreated {
+                    name: pool.name().to_string(),
+                    uuid: pool.uuid(),
+                }
+                .publish();
                 Ok(pool)
             }
             None => Err(Error::PoolCreate {
@@ -543,15 +558,46 @@ impl Lvs {
         Ok(())
     }
 
-    /// unshare all lvols prior to export or destroy
+    /// unshare all lvols prior to export or destroy, and close the
+    /// compressed bdevs of the compressed lvols
     async fn unshare_all(&self) {
         for l in self.lvols().unwrap() {
             // notice we dont use the unshare impl of the bdev
             // here. we do this to avoid the on disk persistence
-            let mut bdev = l.as_bdev();
+            let mut bdev = l.data_bdev();
             if let Err(e) = Pin::new(&mut bdev).unshare().await {
                 error!("{:?}: failed to unshare: {}", l, e.to_string())
             }
+            if let Err(e) = l.close_compressed().await {
+                error!("{:?}: failed to close: {}", l, e.to_string())
+            }
+        }
+    }
+
+    /// open the compressed bdevs of all lvols which have the compression
+    /// property set
+    async fn open_all_compressed(&self) {
+        if let Some(lvols) = self.lvols() {
+            for l in lvols {
+                let codec = match l.get(PropName::Compression).await {
+                    Ok(PropValue::Compression(codec)) => codec,
+                    _ => continue,
+                };
+                let res = match codec.parse::<CompressionCodec>() {
+                    Ok(codec) => l.open_compressed(codec, None).await,
+                    Err(msg) => Err(Error::Invalid {
+                        source: Errno::EINVAL,
+                        msg,
+                    }),
+                };
+                if let Err(e) = res {
+                    error!(
+                        "{:?}: failed to open compressed lvol: {}",
+                        l,
+                        e.to_string()
+                    );
+                }
+            }
         }
     }
 
@@ -603,6 +649,7 @@ impl Lvs {
 
         let ptpl = self.ptpl();
         let pool = self.name().to_string();
+        let pool_uuid = self.uuid();
         let (s, r) = pair::<i32>();
 
         // when destroying a pool unshare all volumes
@@ -626,6 +673,11 @@ impl Lvs {
             })?;
 
         info!("{}: lvs destroyed successfully", self_str);
+        EngineEvent::PoolDestroyed {
+            name: pool.clone(),
+            uuid: pool_uuid,
+        }
+        .publish();
 
         bdev_destroy(&base_bdev.bdev_uri_original().unwrap())
             .await
@@ -801,6 +853,43 @@ impl Lvs {
         }
 
         info!("{:?}: created", lvol);
+        pool_space_check(self);
+        Ok(lvol)
+    }
+
+    /// create a new compressed lvol on this pool, which holds `size` bytes
+    /// of data compressed with `codec`; the lvol is thin provisioned and
+    /// only allocates the space the compressed data takes
+    pub async fn create_compressed_lvol(
+        &self,
+        name: &str,
+        size: u64,
+        uuid: Option<&str>,
+        codec: CompressionCodec,
+    ) -> Result<Lvol, Error> {
+        let backing = compress_backing_size(size, DEFAULT_CHUNK_SIZE);
+        let mut lvol = self.create_lvol(name, backing, uuid, true).await?;
+
+        let res = match Pin::new(&mut lvol)
+            .set(PropValue::Compression(codec.to_string()))
+            .await
+        {
+            Ok(()) => lvol.open_compressed(codec, Some(size)).await,
+            Err(e) => Err(e),
+        };
+        if let Err(error) = res {
+            let lvol_uuid = lvol.uuid();
+            if let Err(error) = lvol.destroy().await {
+                warn!(
+                    "uuid/{}: failed to destroy lvol after failing to open \
+                    it compressed: {:?}",
+                    lvol_uuid, error
+                );
+            }
+            return Err(error);
+        }
+
+        info!("{:?}: compressed with {}", lvol, codec);
         Ok(lvol)
     }
 
@@ -1,3 +1,4 @@
+pub use lvol_diff::LvolRange;
 pub use lvol_snapshot_iter::LvolSnapshotIter;
 pub use lvs_bdev::LvsBdev;
 pub use lvs_error::Error;
@@ -5,6 +6,7 @@ pub use lvs_iter::{LvsBdevIter, LvsIter};
 pub use lvs_lvol::{Lvol, LvolSpaceUsage, LvsLvol, PropName, PropValue};
 pub use lvs_store::Lvs;
 
+mod lvol_diff;
 mod lvol_snapshot_iter;
 mod lvs_bdev;
 mod lvs_error;
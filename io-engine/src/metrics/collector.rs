@@ -0,0 +1,402 @@
+//!
+//! Collection of the metrics of the engine objects.
+//! The collection must run on the primary SPDK thread.
+use std::{collections::BTreeMap, os::raw::c_void};
+
+use futures::channel::oneshot;
+use spdk_rs::libspdk::{
+    spdk_for_each_thread,
+    spdk_get_ticks_hz,
+    spdk_thread_get_stats,
+    spdk_thread_stats,
+};
+
+use super::encoder::{MetricKind, MetricsEncoder};
+use crate::{
+    bdev::nexus::nexus_iter,
+    core::{
+        BlockDeviceIoStats,
+        Cores,
+        IoLatencyStats,
+        LogicalVolume,
+        UntypedBdev,
+    },
+    grpc::controller_grpc::{
+        controller_io_latency,
+        controller_stats,
+        list_controllers,
+    },
+    lvs::{Lvs, LvsLvol},
+};
+
+/// Upper bounds of the exported latency histogram buckets, every power of
+/// four from about 1us to 17s. They are boundaries of the recorded
+/// histogram buckets, so the exported counts are exact.
+const LATENCY_BOUNDS_NS: [u64; 13] = [
+    1 << 10,
+    1 << 12,
+    1 << 14,
+    1 << 16,
+    1 << 18,
+    1 << 20,
+    1 << 22,
+    1 << 24,
+    1 << 26,
+    1 << 28,
+    1 << 30,
+    1 << 32,
+    1 << 34,
+];
+
+const NSEC_PER_SEC: f64 = 1_000_000_000.0;
+
+/// Collects the metrics of all the engine objects and encodes them.
+pub(super) async fn collect() -> String {
+    let mut enc = MetricsEncoder::default();
+    collect_nexuses(&mut enc).await;
+    collect_replicas(&mut enc).await;
+    collect_pools(&mut enc);
+    collect_controllers(&mut enc).await;
+    collect_reactors(&mut enc).await;
+    enc.encode()
+}
+
+/// Adds the samples of the I/O statistics of a device.
+fn io_stats(
+    enc: &mut MetricsEncoder,
+    prefix: Prefix,
+    labels: &[(&str, &str)],
+    stats: &BlockDeviceIoStats,
+) {
+    use MetricKind::Counter;
+
+    let ops = [
+        ("read", stats.num_read_ops, stats.bytes_read),
+        ("write", stats.num_write_ops, stats.bytes_written),
+        ("unmap", stats.num_unmap_ops, stats.bytes_unmapped),
+    ];
+    for (op, num_ops, bytes) in ops {
+        let labels = [labels, &[("op", op)]].concat();
+        enc.sample(
+            prefix.ops,
+            "Number of completed I/O operations.",
+            Counter,
+            &labels,
+            num_ops as f64,
+        );
+        enc.sample(
+            prefix.bytes,
+            "Number of bytes transferred by the completed I/O operations.",
+            Counter,
+            &labels,
+            bytes as f64,
+        );
+    }
+
+    if stats.tick_rate == 0 {
+        return;
+    }
+    let latencies = [
+        ("read", stats.read_latency_ticks),
+        ("write", stats.write_latency_ticks),
+        ("unmap", stats.unmap_latency_ticks),
+    ];
+    for (op, ticks) in latencies {
+        enc.sample(
+            prefix.latency,
+            "Total time spent on the completed I/O operations.",
+            Counter,
+            &[labels, &[("op", op)]].concat(),
+            ticks as f64 / stats.tick_rate as f64,
+        );
+    }
+}
+
+/// Adds the latency histograms of the I/O operations of a device.
+fn io_latency(
+    enc: &mut MetricsEncoder,
+    name: &'static str,
+    labels: &[(&str, &str)],
+    stats: &IoLatencyStats,
+) {
+    let ops = [
+        ("read", &stats.read),
+        ("write", &stats.write),
+        ("unmap", &stats.unmap),
+        ("flush", &stats.flush),
+    ];
+    for (op, hist) in ops {
+        let buckets = LATENCY_BOUNDS_NS
+            .iter()
+            .zip(hist.cumulative_counts(&LATENCY_BOUNDS_NS))
+            .map(|(bound, count)| (*bound as f64 / NSEC_PER_SEC, count))
+            .collect::<Vec<_>>();
+        enc.histogram(
+            name,
+            "Latency of the completed I/O operations.",
+            &[labels, &[("op", op)]].concat(),
+            &buckets,
+            hist.sum_ns() as f64 / NSEC_PER_SEC,
+            hist.count(),
+        );
+    }
+}
+
+/// Names of the I/O metrics of an object type.
+#[derive(Clone, Copy)]
+struct Prefix {
+    ops: &'static str,
+    bytes: &'static str,
+    latency: &'static str,
+}
+
+const NEXUS: Prefix = Prefix {
+    ops: "io_engine_nexus_io_ops_total",
+    bytes: "io_engine_nexus_io_bytes_total",
+    latency: "io_engine_nexus_io_latency_seconds_total",
+};
+
+const CHILD: Prefix = Prefix {
+    ops: "io_engine_nexus_child_io_ops_total",
+    bytes: "io_engine_nexus_child_io_bytes_total",
+    latency: "io_engine_nexus_child_io_latency_seconds_total",
+};
+
+const REPLICA: Prefix = Prefix {
+    ops: "io_engine_replica_io_ops_total",
+    bytes: "io_engine_replica_io_bytes_total",
+    latency: "io_engine_replica_io_latency_seconds_total",
+};
+
+const CONTROLLER: Prefix = Prefix {
+    ops: "io_engine_nvme_controller_io_ops_total",
+    bytes: "io_engine_nvme_controller_io_bytes_total",
+    latency: "io_engine_nvme_controller_io_latency_seconds_total",
+};
+
+/// Adds the queue depth sample of a bdev, if its queue depth sampling is
+/// enabled.
+fn queue_depth(
+    enc: &mut MetricsEncoder,
+    name: &'static str,
+    labels: &[(&str, &str)],
+    bdev: &UntypedBdev,
+) {
+    if let Some(qd) = bdev.queue_depth() {
+        enc.sample(
+            name,
+            "Number of outstanding I/O operations at the last sample.",
+            MetricKind::Gauge,
+            labels,
+            qd as f64,
+        );
+    }
+}
+
+async fn collect_nexuses(enc: &mut MetricsEncoder) {
+    for nexus in nexus_iter() {
+        let uuid = nexus.uuid().to_string();
+        let status = nexus.status().to_string();
+        let labels = [("nexus", nexus.name.as_str()), ("uuid", uuid.as_str())];
+
+        enc.sample(
+            "io_engine_nexus_status",
+            "Status of the nexus, 1 for the current status.",
+            MetricKind::Gauge,
+            &[&labels[..], &[("status", status.as_str())]].concat(),
+            1.0,
+        );
+
+        if let Some(bdev) = UntypedBdev::lookup_by_name(&nexus.bdev_name()) {
+            if let Ok(stats) = bdev.stats_async().await {
+                io_stats(enc, NEXUS, &labels, &stats);
+            }
+            queue_depth(enc, "io_engine_nexus_queue_depth", &labels, &bdev);
+        }
+        io_latency(
+            enc,
+            "io_engine_nexus_io_latency_seconds",
+            &labels,
+            &nexus.io_latency(false),
+        );
+
+        for child in nexus.children_iter() {
+            let labels =
+                [("nexus", nexus.name.as_str()), ("child", child.uri())];
+
+            if let Ok(device) = child.get_device() {
+                if let Ok(stats) = device.io_stats().await {
+                    io_stats(enc, CHILD, &labels, &stats);
+                }
+                if let Some(stats) = device.io_latency() {
+                    io_latency(
+                        enc,
+                        "io_engine_nexus_child_io_latency_seconds",
+                        &labels,
+                        &stats,
+                    );
+                }
+            }
+
+            if let Some(job) = child.rebuild_job() {
+                enc.sample(
+                    "io_engine_nexus_child_rebuild_progress_percent",
+                    "Progress of the running rebuild of the child.",
+                    MetricKind::Gauge,
+                    &labels,
+                    job.stats().await.progress as f64,
+                );
+            }
+        }
+    }
+}
+
+async fn collect_replicas(enc: &mut MetricsEncoder) {
+    for pool in Lvs::iter() {
+        let Some(lvols) = pool.lvols() else {
+            continue;
+        };
+
+        for lvol in lvols.filter(|l| !l.is_snapshot()) {
+            let name = lvol.name();
+            let uuid = lvol.uuid();
+            let labels = [
+                ("pool", pool.name()),
+                ("replica", name.as_str()),
+                ("uuid", uuid.as_str()),
+            ];
+
+            let bdev = lvol.as_bdev();
+            if let Ok(stats) = bdev.stats_async().await {
+                io_stats(enc, REPLICA, &labels, &stats);
+            }
+            queue_depth(enc, "io_engine_replica_queue_depth", &labels, &bdev);
+
+            let usage = lvol.usage();
+            enc.sample(
+                "io_engine_replica_capacity_bytes",
+                "Size of the replica.",
+                MetricKind::Gauge,
+                &labels,
+                usage.capacity_bytes as f64,
+            );
+            enc.sample(
+                "io_engine_replica_allocated_bytes",
+                "Space allocated by the replica in its pool.",
+                MetricKind::Gauge,
+                &labels,
+                usage.allocated_bytes as f64,
+            );
+        }
+    }
+}
+
+fn collect_pools(enc: &mut MetricsEncoder) {
+    for pool in Lvs::iter() {
+        let uuid = pool.uuid();
+        let labels = [("pool", pool.name()), ("uuid", uuid.as_str())];
+
+        let sizes = [
+            (
+                "io_engine_pool_capacity_bytes",
+                "Capacity of the pool.",
+                pool.capacity(),
+            ),
+            (
+                "io_engine_pool_used_bytes",
+                "Space allocated in the pool.",
+                pool.used(),
+            ),
+            (
+                "io_engine_pool_committed_bytes",
+                "Sum of the sizes of the replicas of the pool.",
+                pool.committed(),
+            ),
+        ];
+        for (name, help, value) in sizes {
+            enc.sample(name, help, MetricKind::Gauge, &labels, value as f64);
+        }
+    }
+}
+
+async fn collect_controllers(enc: &mut MetricsEncoder) {
+    for ctrlr in list_controllers().await {
+        let labels = [("controller", ctrlr.name.as_str())];
+        if let Ok(stats) = controller_stats(&ctrlr.name).await {
+            io_stats(enc, CONTROLLER, &labels, &stats);
+        }
+        if let Ok(stats) = controller_io_latency(&ctrlr.name, false) {
+            io_latency(
+                enc,
+                "io_engine_nvme_controller_io_latency_seconds",
+                &labels,
+                &stats,
+            );
+        }
+    }
+}
+
+async fn collect_reactors(enc: &mut MetricsEncoder) {
+    let hz = unsafe { spdk_get_ticks_hz() } as f64;
+    for (core, (busy, idle)) in reactor_stats().await {
+        let core = core.to_string();
+        enc.sample(
+            "io_engine_reactor_busy_seconds_total",
+            "Time the SPDK threads of the reactor spent doing work.",
+            MetricKind::Counter,
+            &[("core", core.as_str())],
+            busy as f64 / hz,
+        );
+        enc.sample(
+            "io_engine_reactor_idle_seconds_total",
+            "Time the SPDK threads of the reactor spent idle.",
+            MetricKind::Counter,
+            &[("core", core.as_str())],
+            idle as f64 / hz,
+        );
+    }
+}
+
+/// Busy and idle ticks of the SPDK threads, summed up per core.
+type ReactorStats = BTreeMap<u32, (u64, u64)>;
+
+/// Gets the busy and idle ticks of the SPDK threads of every reactor, by
+/// visiting every SPDK thread in turn.
+async fn reactor_stats() -> ReactorStats {
+    struct Ctx {
+        stats: ReactorStats,
+        sender: Option<oneshot::Sender<ReactorStats>>,
+    }
+
+    extern "C" fn visit(arg: *mut c_void) {
+        let ctx = unsafe { &mut *(arg as *mut Ctx) };
+        let mut stats = spdk_thread_stats::default();
+        if unsafe { spdk_thread_get_stats(&mut stats) } == 0 {
+            let entry = ctx.stats.entry(Cores::current()).or_default();
+            entry.0 += stats.busy_tsc;
+            entry.1 += stats.idle_tsc;
+        }
+    }
+
+    extern "C" fn done(arg: *mut c_void) {
+        let mut ctx = unsafe { Box::from_raw(arg as *mut Ctx) };
+        let stats = std::mem::take(&mut ctx.stats);
+        if let Some(sender) = ctx.sender.take() {
+            sender.send(stats).ok();
+        }
+    }
+
+    let (s, r) = oneshot::channel();
+    let ctx = Box::new(Ctx {
+        stats: Default::default(),
+        sender: Some(s),
+    });
+    unsafe {
+        spdk_for_each_thread(
+            Some(visit),
+            Box::into_raw(ctx).cast(),
+            Some(done),
+        );
+    }
+    r.await.unwrap_or_default()
+}
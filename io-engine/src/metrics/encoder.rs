@@ -0,0 +1,238 @@
+//!
+//! Encoding of metrics into the Prometheus text exposition format.
+use std::fmt::Write;
+
+/// Kind of a metric family.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricKind {
+    /// A value which only ever increases, e.g. a number of I/Os.
+    Counter,
+    /// A value which can go up and down, e.g. a queue depth.
+    Gauge,
+    /// Cumulative counts of observations per bucket, with their sum and
+    /// count, e.g. I/O latencies.
+    Histogram,
+}
+
+impl MetricKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+            Self::Histogram => "histogram",
+        }
+    }
+}
+
+/// A sample of a metric family: the suffix of its name, e.g. `_bucket` for
+/// histograms, its encoded labels and its value.
+#[derive(Debug)]
+struct Sample {
+    suffix: &'static str,
+    labels: String,
+    value: f64,
+}
+
+/// A metric family: the samples of a metric for all its label values.
+#[derive(Debug)]
+struct MetricFamily {
+    name: &'static str,
+    help: &'static str,
+    kind: MetricKind,
+    samples: Vec<Sample>,
+}
+
+/// Accumulates the samples of the metrics, grouped by family, and encodes
+/// them in the order the families were first sampled in.
+#[derive(Debug, Default)]
+pub struct MetricsEncoder {
+    families: Vec<MetricFamily>,
+}
+
+impl MetricsEncoder {
+    /// Adds a sample of the named metric with the given labels.
+    pub fn sample(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        kind: MetricKind,
+        labels: &[(&str, &str)],
+        value: f64,
+    ) {
+        self.family(name, help, kind).push(Sample {
+            suffix: "",
+            labels: encode_labels(labels),
+            value,
+        });
+    }
+
+    /// Adds a histogram of the named metric with the given labels, given
+    /// the cumulative counts of its buckets by increasing upper bound, and
+    /// the sum of all the observations. The `+Inf` bucket is added.
+    pub fn histogram(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        labels: &[(&str, &str)],
+        buckets: &[(f64, u64)],
+        sum: f64,
+        count: u64,
+    ) {
+        let samples = self.family(name, help, MetricKind::Histogram);
+        let infinity = (f64::INFINITY, count);
+        for (le, cumulative) in buckets.iter().chain(std::iter::once(&infinity))
+        {
+            let le = if le.is_infinite() {
+                "+Inf".to_string()
+            } else {
+                le.to_string()
+            };
+            samples.push(Sample {
+                suffix: "_bucket",
+                labels: encode_labels(
+                    &[labels, &[("le", le.as_str())]].concat(),
+                ),
+                value: *cumulative as f64,
+            });
+        }
+        samples.push(Sample {
+            suffix: "_sum",
+            labels: encode_labels(labels),
+            value: sum,
+        });
+        samples.push(Sample {
+            suffix: "_count",
+            labels: encode_labels(labels),
+            value: count as f64,
+        });
+    }
+
+    /// Gets the samples of the named family, adding it if needed.
+    fn family(
+        &mut self,
+        name: &'static str,
+        help: &'static str,
+        kind: MetricKind,
+    ) -> &mut Vec<Sample> {
+        let idx = match self.families.iter().position(|f| f.name == name) {
+            Some(idx) => idx,
+            None => {
+                self.families.push(MetricFamily {
+                    name,
+                    help,
+                    kind,
+                    samples: Vec::new(),
+                });
+                self.families.len() - 1
+            }
+        };
+        &mut self.families[idx].samples
+    }
+
+    /// Encodes all the samples into the text exposition format.
+    pub fn encode(&self) -> String {
+        let mut out = String::new();
+        for family in &self.families {
+            writeln!(out, "# HELP {} {}", family.name, family.help).ok();
+            writeln!(out, "# TYPE {} {}", family.name, family.kind.as_str())
+                .ok();
+            for sample in &family.samples {
+                writeln!(
+                    out,
+                    "{}{}{} {}",
+                    family.name, sample.suffix, sample.labels, sample.value
+                )
+                .ok();
+            }
+        }
+        out
+    }
+}
+
+/// Encodes the labels of a sample, escaping the label values.
+fn encode_labels(labels: &[(&str, &str)]) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{name}=\"{value}\"")
+        })
+        .collect::<Vec<_>>();
+    format!("{{{}}}", labels.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn families_are_grouped() {
+        let mut enc = MetricsEncoder::default();
+        enc.sample(
+            "ops_total",
+            "Ops.",
+            MetricKind::Counter,
+            &[("op", "r")],
+            1.0,
+        );
+        enc.sample("depth", "Depth.", MetricKind::Gauge, &[], 2.5);
+        enc.sample(
+            "ops_total",
+            "Ops.",
+            MetricKind::Counter,
+            &[("op", "w")],
+            3.0,
+        );
+
+        assert_eq!(
+            enc.encode(),
+            "# HELP ops_total Ops.\n\
+             # TYPE ops_total counter\n\
+             ops_total{op=\"r\"} 1\n\
+             ops_total{op=\"w\"} 3\n\
+             # HELP depth Depth.\n\
+             # TYPE depth gauge\n\
+             depth 2.5\n"
+        );
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(
+            encode_labels(&[("a", "x\"y"), ("b", "c:\\d\ne")]),
+            "{a=\"x\\\"y\",b=\"c:\\\\d\\ne\"}"
+        );
+        assert_eq!(encode_labels(&[]), "");
+    }
+
+    #[test]
+    fn histograms() {
+        let mut enc = MetricsEncoder::default();
+        enc.histogram(
+            "latency_seconds",
+            "Latency.",
+            &[("op", "read")],
+            &[(0.001, 2), (0.5, 5)],
+            1.25,
+            6,
+        );
+
+        assert_eq!(
+            enc.encode(),
+            "# HELP latency_seconds Latency.\n\
+             # TYPE latency_seconds histogram\n\
+             latency_seconds_bucket{op=\"read\",le=\"0.001\"} 2\n\
+             latency_seconds_bucket{op=\"read\",le=\"0.5\"} 5\n\
+             latency_seconds_bucket{op=\"read\",le=\"+Inf\"} 6\n\
+             latency_seconds_sum{op=\"read\"} 1.25\n\
+             latency_seconds_count{op=\"read\"} 6\n"
+        );
+    }
+}
@@ -0,0 +1,76 @@
+//!
+//! HTTP endpoint exporting the metrics of the engine in the Prometheus text
+//! exposition format, so that they can be scraped without any external
+//! collector. The metrics and their labels are documented in
+//! `doc/metrics.md`.
+use std::{convert::Infallible, net::SocketAddr};
+
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body,
+    Method,
+    Request,
+    Response,
+    Server,
+    StatusCode,
+};
+
+use crate::core::Reactor;
+
+mod collector;
+mod encoder;
+
+pub use encoder::{MetricKind, MetricsEncoder};
+
+/// Path the metrics are served at.
+const METRICS_PATH: &str = "/metrics";
+
+/// Content type of the Prometheus text exposition format.
+const METRICS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+/// Serves the metrics over HTTP at the given address until the runtime
+/// stops.
+pub async fn run_metrics_server(endpoint: SocketAddr) {
+    info!("Metrics server configured at address {}", endpoint);
+
+    let make_svc = make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(handle_request))
+    });
+    if let Err(error) = Server::bind(&endpoint).serve(make_svc).await {
+        error!("Metrics server failed with error: {}", error);
+    }
+}
+
+async fn handle_request(
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if request.method() != Method::GET || request.uri().path() != METRICS_PATH {
+        return Ok(response(StatusCode::NOT_FOUND, "Not found\n".into()));
+    }
+
+    // The engine objects can only be accessed from the primary SPDK thread.
+    let metrics = match Reactor::spawn_at_primary(collector::collect()) {
+        Ok(rx) => rx.await.ok(),
+        Err(_) => None,
+    };
+    Ok(match metrics {
+        Some(metrics) => {
+            let mut response = response(StatusCode::OK, metrics);
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, METRICS_CONTENT_TYPE.parse().unwrap());
+            response
+        }
+        None => response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "Failed to collect the metrics\n".into(),
+        ),
+    })
+}
+
+fn response(status: StatusCode, body: String) -> Response<Body> {
+    let mut response = Response::new(Body::from(body));
+    *response.status_mut() = status;
+    response
+}
@@ -6,6 +6,7 @@ mod rebuild_map;
 mod rebuild_state;
 mod rebuild_stats;
 mod rebuild_task;
+mod snapshot_replication;
 
 use rebuild_descriptor::RebuildDescriptor;
 pub(crate) use rebuild_error::RebuildError;
@@ -22,6 +23,10 @@ use rebuild_state::RebuildStates;
 pub(crate) use rebuild_stats::HistoryRecord;
 pub use rebuild_stats::RebuildStats;
 use rebuild_task::{RebuildTask, RebuildTasks, TaskResult};
+pub use snapshot_replication::{
+    ReplicationHistoryRecord,
+    SnapshotReplicationJob,
+};
 
 /// Number of concurrent copy tasks per rebuild job
 const SEGMENT_TASKS: usize = 16;
@@ -26,8 +26,17 @@ pub(super) struct RebuildDescriptor {
     /// Pre-opened descriptor for destination block device.
     #[allow(clippy::non_send_fields_in_send_ty)]
     pub(super) dst_descriptor: Box<dyn BlockDeviceDescriptor>,
+    /// Pre-opened descriptors for the other sources of a reconstruction, the
+    /// segments of the destination being the XOR of the segments of all the
+    /// sources.
+    #[allow(clippy::non_send_fields_in_send_ty)]
+    pub(super) parity_descriptors: Vec<Box<dyn BlockDeviceDescriptor>>,
+    /// Stripe unit size in blocks and number of data units of the rows of a
+    /// parity nexus, to lock the rows of the segments being reconstructed.
+    pub(super) parity_rows: Option<(u64, u64)>,
     /// Nexus Descriptor so we can lock its ranges when rebuilding a segment.
-    pub(super) nexus_descriptor: DescriptorGuard<()>,
+    /// Jobs copying devices which are not part of a nexus have none.
+    pub(super) nexus_descriptor: Option<DescriptorGuard<()>>,
     /// Start time of this rebuild.
     pub(super) start_time: DateTime<Utc>,
     /// Rebuild map.
@@ -44,6 +53,25 @@ impl RebuildDescriptor {
         self.segment_size_blks
     }
 
+    /// Returns the offset and the number of blocks of the range of the nexus
+    /// whose data is held by the given blocks of the children: the same
+    /// blocks of the data partition for a mirror, and the rows of the blocks
+    /// for a parity nexus.
+    pub(super) fn nexus_range(&self, blk: u64, len: u64) -> (u64, u64) {
+        let start = blk - self.range.start;
+        match self.parity_rows {
+            None => (start, len),
+            Some((unit, data_units)) => {
+                let first = start / unit;
+                let last = (start + len + unit - 1) / unit;
+                (
+                    first * unit * data_units,
+                    (last - first) * unit * data_units,
+                )
+            }
+        }
+    }
+
     /// Get a `BlockDeviceHandle` for the source.
     pub(super) async fn src_io_handle(
         &self,
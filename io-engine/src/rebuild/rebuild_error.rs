@@ -67,4 +67,21 @@ pub enum RebuildError {
     BackendGone,
     #[snafu(display("The rebuild task pool channel is unexpectedly closed with {} active tasks", active))]
     RebuildTasksChannel { active: usize },
+    #[snafu(display(
+        "Invalid snapshot replication target {}: {}",
+        uri,
+        reason
+    ))]
+    InvalidReplicationTarget { uri: String, reason: String },
+    #[snafu(display(
+        "Failed to connect to snapshot replication target {}",
+        uri
+    ))]
+    ReplicationTargetCreate { source: BdevError, uri: String },
+    #[snafu(display(
+        "Failed to get the changed ranges of snapshot {}: {}",
+        snapshot,
+        reason
+    ))]
+    SnapshotChanges { snapshot: String, reason: String },
 }
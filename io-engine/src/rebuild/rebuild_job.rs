@@ -87,11 +87,60 @@ impl RebuildJob {
             nexus_name,
             src_uri,
             dst_uri,
-            range.clone(),
+            range,
+            true,
+            Some(notify_fn),
+        )
+        .await?;
+
+        Ok(Self::with_backend(backend).await)
+    }
+
+    /// Creates a new RebuildJob which reconstructs the target URI of a
+    /// parity nexus from the source URIs, all the other children of the
+    /// nexus, from start to end (of the data partition). `parity_rows` are
+    /// the size of the stripe units in blocks and the number of data units
+    /// of the rows of the nexus.
+    pub(crate) async fn new_reconstruct(
+        nexus_name: &str,
+        src_uris: &[String],
+        dst_uri: &str,
+        range: Range<u64>,
+        parity_rows: (u64, u64),
+        notify_fn: fn(String, String) -> (),
+    ) -> Result<Self, RebuildError> {
+        let backend = RebuildJobBackend::new_reconstruct(
+            nexus_name,
+            src_uris,
+            dst_uri,
+            range,
+            parity_rows,
             notify_fn,
         )
         .await?;
 
+        Ok(Self::with_backend(backend).await)
+    }
+
+    /// Creates a new RebuildJob which copies from source URI to target URI
+    /// from start to end, where neither device is part of a nexus. The job
+    /// is identified by `name` in its logs, and has no notify callback: its
+    /// completion is awaited instead.
+    pub(crate) async fn new_detached(
+        name: &str,
+        src_uri: &str,
+        dst_uri: &str,
+        range: Range<u64>,
+    ) -> Result<Self, RebuildError> {
+        let backend =
+            RebuildJobBackend::new(name, src_uri, dst_uri, range, false, None)
+                .await?;
+
+        Ok(Self::with_backend(backend).await)
+    }
+
+    /// Creates the frontend of the given backend and schedules the backend.
+    async fn with_backend(backend: RebuildJobBackend) -> Self {
         let frontend = Self {
             nexus_name: backend.nexus_name.clone(),
             src_uri: backend.src_uri.clone(),
@@ -106,7 +155,7 @@ impl RebuildJob {
         // commands.
         backend.schedule().await;
 
-        Ok(frontend)
+        frontend
     }
 
     /// Returns number of all rebuild jobs on the system.
@@ -33,7 +33,7 @@ use super::{
 use crate::{
     bdev::device_open,
     bdev_api::bdev_get_name,
-    core::{BlockDevice, Reactors, UntypedBdev},
+    core::{BlockDevice, BlockDeviceDescriptor, Reactors, UntypedBdev},
 };
 
 /// Request between frontend and backend.
@@ -95,8 +95,8 @@ pub(super) struct RebuildJobBackend {
     pub(super) next: u64,
     /// A pool of tasks which perform the actual data rebuild.
     pub(super) task_pool: RebuildTasks,
-    /// Notification as a `fn` callback.
-    pub(super) notify_fn: fn(String, String) -> (),
+    /// Notification as a `fn` callback, if any.
+    pub(super) notify_fn: Option<fn(String, String) -> ()>,
     /// Channel used to signal rebuild update.
     pub notify_chan: (Sender<RebuildState>, Receiver<RebuildState>),
     /// Current state of the rebuild job.
@@ -128,12 +128,17 @@ impl Display for RebuildJobBackend {
         write!(
             f,
             "Rebuild job #{s} ({state}{done}) '{src}' -> '{dst}' \
-            on nexus '{nex}'",
+            {on} '{nex}'",
             s = self.serial,
             state = self.state(),
             done = if self.state().done() { ": done" } else { "" },
             src = self.src_uri,
             dst = self.dst_uri,
+            on = if self.descriptor.nexus_descriptor.is_some() {
+                "on nexus"
+            } else {
+                "for"
+            },
             nex = self.nexus_name
         )
     }
@@ -141,37 +146,21 @@ impl Display for RebuildJobBackend {
 
 impl RebuildJobBackend {
     /// Creates a new RebuildJob which rebuilds from source URI to target URI
-    /// from start to end (of the data partition); notify_fn callback is called
-    /// when the rebuild state is updated - with the nexus and destination
-    /// URI as arguments.
+    /// from start to end (of the data partition); notify_fn callback, if any,
+    /// is called when the rebuild state is updated - with the nexus and
+    /// destination URI as arguments.
+    /// Unless `lock_nexus` is set, the devices are not part of the nexus and
+    /// its ranges are not locked while copied.
     pub async fn new(
         nexus_name: &str,
         src_uri: &str,
         dst_uri: &str,
         range: std::ops::Range<u64>,
-        notify_fn: fn(String, String) -> (),
+        lock_nexus: bool,
+        notify_fn: Option<fn(String, String) -> ()>,
     ) -> Result<Self, RebuildError> {
-        let src_descriptor = device_open(
-            &bdev_get_name(src_uri).context(BdevInvalidUri {
-                uri: src_uri.to_string(),
-            })?,
-            false,
-        )
-        .map_err(|e| RebuildError::BdevNotFound {
-            source: e,
-            bdev: src_uri.to_string(),
-        })?;
-
-        let dst_descriptor = device_open(
-            &bdev_get_name(dst_uri).context(BdevInvalidUri {
-                uri: dst_uri.to_string(),
-            })?,
-            true,
-        )
-        .map_err(|e| RebuildError::BdevNotFound {
-            source: e,
-            bdev: dst_uri.to_string(),
-        })?;
+        let src_descriptor = Self::open_device(src_uri, false)?;
+        let dst_descriptor = Self::open_device(dst_uri, true)?;
 
         let source_hdl = RebuildDescriptor::io_handle(&*src_descriptor).await?;
         let destination_hdl =
@@ -211,10 +200,15 @@ impl RebuildJobBackend {
             });
         }
 
-        let nexus_descriptor = UntypedBdev::open_by_name(nexus_name, false)
-            .context(BdevNotFound {
-                bdev: nexus_name.to_string(),
-            })?;
+        let nexus_descriptor = if lock_nexus {
+            Some(UntypedBdev::open_by_name(nexus_name, false).context(
+                BdevNotFound {
+                    bdev: nexus_name.to_string(),
+                },
+            )?)
+        } else {
+            None
+        };
 
         // Job serial numbers.
         static SERIAL: AtomicU64 = AtomicU64::new(1);
@@ -240,6 +234,8 @@ impl RebuildJobBackend {
                 segment_size_blks,
                 src_descriptor,
                 dst_descriptor,
+                parity_descriptors: Vec::new(),
+                parity_rows: None,
                 nexus_descriptor,
                 start_time: Utc::now(),
                 rebuild_map: Arc::new(parking_lot::Mutex::new(None)),
@@ -252,6 +248,68 @@ impl RebuildJobBackend {
         Ok(be)
     }
 
+    /// Creates a new RebuildJob which reconstructs the target URI of a
+    /// parity nexus from all the other children, the source URIs, from start
+    /// to end (of the data partition). The rows of the nexus held by the
+    /// segments being reconstructed are locked, given the size of the stripe
+    /// units in blocks and the number of data units of a row.
+    pub async fn new_reconstruct(
+        nexus_name: &str,
+        src_uris: &[String],
+        dst_uri: &str,
+        range: std::ops::Range<u64>,
+        parity_rows: (u64, u64),
+        notify_fn: fn(String, String) -> (),
+    ) -> Result<Self, RebuildError> {
+        let Some((src_uri, other_uris)) = src_uris.split_first() else {
+            return Err(RebuildError::InvalidParameters {});
+        };
+        let mut be = Self::new(
+            nexus_name,
+            src_uri,
+            dst_uri,
+            range.clone(),
+            true,
+            Some(notify_fn),
+        )
+        .await?;
+
+        let mut parity_descriptors = Vec::with_capacity(other_uris.len());
+        for uri in other_uris {
+            let descriptor = Self::open_device(uri, false)?;
+            let hdl = RebuildDescriptor::io_handle(&*descriptor).await?;
+            let destination = be.descriptor.dst_descriptor.get_device();
+            if !Self::validate(hdl.get_device(), &*destination, &range) {
+                return Err(RebuildError::InvalidParameters {});
+            }
+            parity_descriptors.push(descriptor);
+        }
+
+        // The descriptor is not shared with the tasks until the job starts.
+        let descriptor = Arc::get_mut(&mut be.descriptor).unwrap();
+        descriptor.parity_descriptors = parity_descriptors;
+        descriptor.parity_rows = Some(parity_rows);
+
+        Ok(be)
+    }
+
+    /// Opens the device of the given URI.
+    fn open_device(
+        uri: &str,
+        read_write: bool,
+    ) -> Result<Box<dyn BlockDeviceDescriptor>, RebuildError> {
+        device_open(
+            &bdev_get_name(uri).context(BdevInvalidUri {
+                uri: uri.to_string(),
+            })?,
+            read_write,
+        )
+        .map_err(|e| RebuildError::BdevNotFound {
+            source: e,
+            bdev: uri.to_string(),
+        })
+    }
+
     /// State of the rebuild job
     fn state(&self) -> RebuildState {
         self.states.read().current
@@ -386,7 +444,9 @@ impl RebuildJobBackend {
     /// Calls the job's registered notify fn callback and notify sender channel
     fn send_notify(&mut self) {
         // should this return a status before we notify the sender channel?
-        (self.notify_fn)(self.nexus_name.clone(), self.dst_uri.clone());
+        if let Some(notify_fn) = self.notify_fn {
+            notify_fn(self.nexus_name.clone(), self.dst_uri.clone());
+        }
         if let Err(e) = self.notify_chan.0.send(self.state()) {
             error!(
                 "{self}: failed to send complete via the unbound channel \
@@ -46,7 +46,8 @@ pub(super) struct RebuildTask {
 impl RebuildTask {
     /// Copies one segment worth of data from source into destination. During
     /// this time the LBA range being copied is locked so that there cannot be
-    /// front end I/O to the same LBA range.
+    /// front end I/O to the same LBA range, unless the job is not copying
+    /// devices of a nexus.
     ///
     /// # Safety
     ///
@@ -66,38 +67,43 @@ impl RebuildTask {
             return Ok(false);
         }
 
+        let Some(nexus_descriptor) = &descriptor.nexus_descriptor else {
+            let result = self.copy_one(blk, descriptor).await;
+            if result.is_ok() {
+                descriptor.blk_synced(blk);
+            }
+            return result.map(|_| true);
+        };
+
         let len = descriptor.get_segment_size_blks(blk);
         // The nexus children have metadata and data partitions, whereas the
         // nexus has a data partition only. Because we are locking the range on
         // the nexus, we need to calculate the offset from the start of the data
         // partition.
-        let r = LbaRange::new(blk - descriptor.range.start, len);
+        let (offset, num_blocks) = descriptor.nexus_range(blk, len);
+        let r = LbaRange::new(offset, num_blocks);
 
         // Wait for LBA range to be locked.
         // This prevents other I/Os being issued to this LBA range whilst it is
         // being rebuilt.
-        let lock = descriptor
-            .nexus_descriptor
-            .lock_lba_range(r)
-            .await
-            .context(RangeLockFailed {
+        let lock = nexus_descriptor.lock_lba_range(r).await.context(
+            RangeLockFailed {
                 blk,
                 len,
-            })?;
+            },
+        )?;
 
         // Perform the copy
         let result = self.copy_one(blk, descriptor).await;
 
         // Wait for the LBA range to be unlocked.
         // This allows others I/Os to be issued to this LBA range once again.
-        descriptor
-            .nexus_descriptor
-            .unlock_lba_range(lock)
-            .await
-            .context(RangeUnlockFailed {
+        nexus_descriptor.unlock_lba_range(lock).await.context(
+            RangeUnlockFailed {
                 blk,
                 len,
-            })?;
+            },
+        )?;
 
         // In the case of success, mark the segment as already transferred.
         if result.is_ok() {
@@ -113,6 +119,10 @@ impl RebuildTask {
         blk: u64,
         descriptor: &RebuildDescriptor,
     ) -> Result<(), RebuildError> {
+        if !descriptor.parity_descriptors.is_empty() {
+            return self.reconstruct_one(blk, descriptor).await;
+        }
+
         let mut copy_buffer: DmaBuf;
         let mut source_hdl = descriptor.src_io_handle().await?;
         let destination_hdl = descriptor.dst_io_handle().await?;
@@ -161,6 +171,47 @@ impl RebuildTask {
 
         Ok(())
     }
+
+    /// Reconstructs one segment worth of data of the destination, as the XOR
+    /// of the segments of all the sources.
+    async fn reconstruct_one(
+        &mut self,
+        blk: u64,
+        descriptor: &RebuildDescriptor,
+    ) -> Result<(), RebuildError> {
+        let destination_hdl = descriptor.dst_io_handle().await?;
+        let len = descriptor.get_segment_size_blks(blk) * descriptor.block_size;
+        let offset = blk * descriptor.block_size;
+
+        let mut data =
+            destination_hdl.dma_malloc(len).context(NoCopyBuffer {})?;
+        let mut source_data =
+            destination_hdl.dma_malloc(len).context(NoCopyBuffer {})?;
+        data.fill(0);
+
+        let sources = std::iter::once(&descriptor.src_descriptor)
+            .chain(descriptor.parity_descriptors.iter());
+        for source in sources {
+            let source_hdl = RebuildDescriptor::io_handle(&**source).await?;
+            source_hdl.read_at(offset, &mut source_data).await.context(
+                ReadIoFailed {
+                    bdev: source.device_name(),
+                },
+            )?;
+            data.as_mut_slice()
+                .iter_mut()
+                .zip(source_data.as_slice())
+                .for_each(|(d, s)| *d ^= s);
+        }
+
+        destination_hdl.write_at(offset, &data).await.context(
+            WriteIoFailed {
+                bdev: &descriptor.dst_uri,
+            },
+        )?;
+
+        Ok(())
+    }
 }
 
 /// Pool of rebuild tasks and progress tracking.
@@ -0,0 +1,362 @@
+//!
+//! Replication of lvol snapshots into lvols of other nodes.
+//!
+//! A replication job copies the data of a local snapshot into a remote lvol
+//! reached over NVMe-oF, using the tasks and segments of a rebuild job.
+//! In incremental mode only the clusters which changed since a previously
+//! replicated base snapshot are copied, so the remote lvol must already hold
+//! the data of that base snapshot. Otherwise all the data of the snapshot is
+//! copied, and the remote lvol is expected to be a new thin lvol so that the
+//! ranges without data read back as zeroes.
+use std::{collections::HashMap, sync::Arc};
+
+use once_cell::sync::OnceCell;
+use snafu::ResultExt;
+use spdk_rs::Thread;
+
+use super::{
+    rebuild_error::ReplicationTargetCreate,
+    HistoryRecord,
+    RebuildError,
+    RebuildJob,
+    RebuildMap,
+    RebuildState,
+    RebuildStats,
+    SEGMENT_SIZE,
+};
+
+use crate::{
+    bdev_api::{bdev_create, bdev_destroy},
+    core::{LogicalVolume, Reactors, SegmentMap, VerboseError},
+    lvs::{Lvol, LvolRange, LvsLvol},
+};
+
+/// Maximum number of finished replication jobs kept in the history, the
+/// oldest records are dropped first.
+const MAX_REPLICATION_HISTORY: usize = 64;
+
+/// Running replication jobs by the URI of their remote lvol. A job being
+/// started has no entry yet, which reserves its URI.
+type ReplicationInstances =
+    HashMap<String, Option<Arc<SnapshotReplicationJob>>>;
+
+/// A job replicating a local snapshot into a remote lvol.
+#[derive(Debug)]
+pub struct SnapshotReplicationJob {
+    /// UUID of the replicated snapshot.
+    snapshot_uuid: String,
+    /// UUID of the previously replicated snapshot, in incremental mode.
+    base_snapshot_uuid: Option<String>,
+    /// URI of the remote lvol.
+    target_uri: String,
+    /// Rebuild job copying the snapshot.
+    job: RebuildJob,
+}
+
+/// A record of a finished snapshot replication.
+#[derive(Debug, Clone)]
+pub struct ReplicationHistoryRecord {
+    /// UUID of the replicated snapshot.
+    pub snapshot_uuid: String,
+    /// UUID of the previously replicated snapshot, in incremental mode.
+    pub base_snapshot_uuid: Option<String>,
+    /// URI of the remote lvol.
+    pub target_uri: String,
+    /// Record of the rebuild job which copied the snapshot.
+    pub record: HistoryRecord,
+}
+
+impl SnapshotReplicationJob {
+    /// Starts replicating the snapshot into the remote lvol at the given
+    /// `nvmf://` URI, incrementally if a base snapshot, which must be an
+    /// older snapshot of the same lvol, is given.
+    pub async fn start(
+        snapshot: &Lvol,
+        base: Option<&Lvol>,
+        target_uri: &str,
+    ) -> Result<Arc<Self>, RebuildError> {
+        match url::Url::parse(target_uri) {
+            Ok(url) if url.scheme() == "nvmf" => {}
+            Ok(_) => {
+                return Err(RebuildError::InvalidReplicationTarget {
+                    uri: target_uri.to_string(),
+                    reason: "only nvmf targets are supported".to_string(),
+                })
+            }
+            Err(e) => {
+                return Err(RebuildError::InvalidReplicationTarget {
+                    uri: target_uri.to_string(),
+                    reason: e.to_string(),
+                })
+            }
+        }
+        let reservation = TargetReservation::new(target_uri)?;
+
+        let ranges = snapshot.changed_ranges(base).map_err(|e| {
+            RebuildError::SnapshotChanges {
+                snapshot: snapshot.uuid(),
+                reason: e.to_string(),
+            }
+        })?;
+
+        info!(
+            snapshot = snapshot.uuid(),
+            base = ?base.map(|b| b.uuid()),
+            target_uri,
+            ranges = ranges.len(),
+            "Starting snapshot replication"
+        );
+
+        bdev_create(target_uri)
+            .await
+            .context(ReplicationTargetCreate {
+                uri: target_uri.to_string(),
+            })?;
+
+        let (job, map) =
+            match Self::create_job(snapshot, target_uri, &ranges).await {
+                Ok(r) => r,
+                Err(e) => {
+                    Self::destroy_target(target_uri).await;
+                    return Err(e);
+                }
+            };
+
+        let job = Arc::new(Self {
+            snapshot_uuid: snapshot.uuid(),
+            base_snapshot_uuid: base.map(|b| b.uuid()),
+            target_uri: target_uri.to_string(),
+            job,
+        });
+        reservation.register(job.clone());
+
+        let complete = match job.job.start(Some(map)).await {
+            Ok(complete) => complete,
+            Err(e) => {
+                job.job.terminate().await.ok();
+                job.finish().await;
+                return Err(e);
+            }
+        };
+
+        let finished = job.clone();
+        Reactors::master().send_future(async move {
+            // The channel is closed once the job is done.
+            complete.await.ok();
+            finished.finish().await;
+        });
+
+        Ok(job)
+    }
+
+    /// Creates the rebuild job copying the given ranges of the snapshot,
+    /// along with the map of the segments to copy.
+    async fn create_job(
+        snapshot: &Lvol,
+        target_uri: &str,
+        ranges: &[LvolRange],
+    ) -> Result<(RebuildJob, RebuildMap), RebuildError> {
+        let bdev = snapshot.as_bdev();
+        let num_blocks = bdev.num_blocks();
+        let block_len = bdev.block_len() as u64;
+
+        let job = RebuildJob::new_detached(
+            &snapshot.uuid(),
+            &format!("bdev:///{}", bdev.name()),
+            target_uri,
+            0 .. num_blocks,
+        )
+        .await?;
+
+        let segment_blks = SEGMENT_SIZE / block_len;
+        let mut segments = SegmentMap::new(num_blocks, block_len, SEGMENT_SIZE);
+        for range in ranges {
+            let end = range.end() / block_len;
+            let mut blk = range.offset / block_len;
+            blk -= blk % segment_blks;
+            while blk < end {
+                segments.set(blk, 1, true);
+                blk += segment_blks;
+            }
+        }
+
+        Ok((job, RebuildMap::new(&bdev.name(), segments)))
+    }
+
+    /// Removes the finished job, records it in the history and disconnects
+    /// from the remote lvol.
+    async fn finish(&self) {
+        Self::get_instances().remove(&self.target_uri);
+
+        match self.job.history_record() {
+            Some(record) => {
+                info!(
+                    snapshot = self.snapshot_uuid,
+                    target_uri = self.target_uri,
+                    state = ?record.state,
+                    "Snapshot replication finished"
+                );
+                let mut history = Self::get_history();
+                if history.len() >= MAX_REPLICATION_HISTORY {
+                    let excess = history.len() + 1 - MAX_REPLICATION_HISTORY;
+                    history.drain(.. excess);
+                }
+                history.push(ReplicationHistoryRecord {
+                    snapshot_uuid: self.snapshot_uuid.clone(),
+                    base_snapshot_uuid: self.base_snapshot_uuid.clone(),
+                    target_uri: self.target_uri.clone(),
+                    record,
+                });
+            }
+            None => error!(
+                target_uri = self.target_uri,
+                "Snapshot replication finished without final stats"
+            ),
+        }
+
+        Self::destroy_target(&self.target_uri).await;
+    }
+
+    /// Disconnects from the remote lvol.
+    async fn destroy_target(target_uri: &str) {
+        if let Err(error) = bdev_destroy(target_uri).await {
+            error!(
+                target_uri,
+                error = error.verbose(),
+                "Failed to disconnect from snapshot replication target"
+            );
+        }
+    }
+
+    /// Lookup a replication job by the URI of its remote lvol.
+    pub fn lookup(target_uri: &str) -> Result<Arc<Self>, RebuildError> {
+        Self::get_instances()
+            .get(target_uri)
+            .cloned()
+            .flatten()
+            .ok_or_else(|| RebuildError::JobNotFound {
+                job: target_uri.to_string(),
+            })
+    }
+
+    /// Get all the running replication jobs.
+    pub fn list() -> Vec<Arc<Self>> {
+        Self::get_instances().values().flatten().cloned().collect()
+    }
+
+    /// Get the records of the finished replication jobs.
+    pub fn history() -> Vec<ReplicationHistoryRecord> {
+        Self::get_history().clone()
+    }
+
+    /// Stops the job.
+    pub fn stop(&self) -> Result<(), RebuildError> {
+        self.job.stop()
+    }
+
+    /// Pauses the job which can then be later resumed.
+    pub fn pause(&self) -> Result<(), RebuildError> {
+        self.job.pause()
+    }
+
+    /// Resumes a previously paused job.
+    pub fn resume(&self) -> Result<(), RebuildError> {
+        self.job.resume()
+    }
+
+    /// Gets the current state of the job.
+    pub fn state(&self) -> RebuildState {
+        self.job.state()
+    }
+
+    /// Get the job stats.
+    pub async fn stats(&self) -> RebuildStats {
+        self.job.stats().await
+    }
+
+    /// Get the last error description.
+    pub fn error_desc(&self) -> String {
+        self.job.error_desc()
+    }
+
+    /// Get the UUID of the replicated snapshot.
+    pub fn snapshot_uuid(&self) -> &str {
+        &self.snapshot_uuid
+    }
+
+    /// Get the UUID of the base snapshot, in incremental mode.
+    pub fn base_snapshot_uuid(&self) -> Option<&str> {
+        self.base_snapshot_uuid.as_deref()
+    }
+
+    /// Get the URI of the remote lvol.
+    pub fn target_uri(&self) -> &str {
+        &self.target_uri
+    }
+
+    /// Get the replication job instances, we ensure that this can only ever
+    /// be called on a properly allocated thread.
+    fn get_instances<'a>() -> parking_lot::MutexGuard<'a, ReplicationInstances>
+    {
+        assert!(Thread::is_spdk_thread(), "not called from SPDK thread");
+
+        static REPLICATION_INSTANCES: OnceCell<
+            parking_lot::Mutex<ReplicationInstances>,
+        > = OnceCell::new();
+
+        REPLICATION_INSTANCES
+            .get_or_init(|| parking_lot::Mutex::new(HashMap::new()))
+            .lock()
+    }
+
+    /// Get the replication history.
+    fn get_history<'a>(
+    ) -> parking_lot::MutexGuard<'a, Vec<ReplicationHistoryRecord>> {
+        static REPLICATION_HISTORY: OnceCell<
+            parking_lot::Mutex<Vec<ReplicationHistoryRecord>>,
+        > = OnceCell::new();
+
+        REPLICATION_HISTORY
+            .get_or_init(|| parking_lot::Mutex::new(Vec::new()))
+            .lock()
+    }
+}
+
+/// Reservation of the URI of a remote lvol while its replication job is
+/// being started, released when dropped unless the job was registered.
+struct TargetReservation<'a> {
+    target_uri: Option<&'a str>,
+}
+
+impl<'a> TargetReservation<'a> {
+    /// Reserves the URI, unless a job already replicates into it.
+    fn new(target_uri: &'a str) -> Result<Self, RebuildError> {
+        let mut instances = SnapshotReplicationJob::get_instances();
+        if instances.contains_key(target_uri) {
+            return Err(RebuildError::JobAlreadyExists {
+                job: target_uri.to_string(),
+            });
+        }
+        instances.insert(target_uri.to_string(), None);
+
+        Ok(Self {
+            target_uri: Some(target_uri),
+        })
+    }
+
+    /// Registers the started job under the reserved URI.
+    fn register(mut self, job: Arc<SnapshotReplicationJob>) {
+        if let Some(target_uri) = self.target_uri.take() {
+            SnapshotReplicationJob::get_instances()
+                .insert(target_uri.to_string(), Some(job));
+        }
+    }
+}
+
+impl Drop for TargetReservation<'_> {
+    fn drop(&mut self) {
+        if let Some(target_uri) = self.target_uri.take() {
+            SnapshotReplicationJob::get_instances().remove(target_uri);
+        }
+    }
+}
@@ -12,6 +12,7 @@ pub use nvmf::{
     set_snapshot_time,
     Error as NvmfError,
     NvmeCpl,
+    NvmfHostController,
     NvmfReq,
     NvmfSubsystem,
     SubType,
@@ -7,8 +7,17 @@ use std::{
     time::{SystemTime, UNIX_EPOCH},
 };
 
+use serde::de::DeserializeOwned;
+
 use crate::{
-    bdev::{nexus, nvmx::NvmeSnapshotMessage},
+    bdev::{
+        nexus,
+        nvmx::{
+            NvmeSnapshotDestroyMessage,
+            NvmeSnapshotMessage,
+            NVME_ADMIN_DESTROY_SNAPSHOT,
+        },
+    },
     core::{
         logical_volume::LogicalVolume,
         snapshot::SnapshotOps,
@@ -106,10 +115,10 @@ pub fn set_snapshot_time(cmd: &mut spdk_nvme_cmd) -> u64 {
     now
 }
 
-/// Decode snapshot information from incoming NVMe admin command data.
-fn decode_snapshot_params(
+/// Decode a message from incoming NVMe admin command data.
+fn decode_admin_message<T: DeserializeOwned>(
     req: *mut spdk_nvmf_request,
-) -> Option<SnapshotParams> {
+) -> Option<T> {
     let encoded_msg = unsafe {
         let mut val = std::ptr::null_mut();
         let mut size: u32 = 0;
@@ -130,18 +139,21 @@ fn decode_snapshot_params(
         std::slice::from_raw_parts(val as *const u8, size as usize)
     };
 
+    match bincode::deserialize::<T>(encoded_msg) {
+        Err(e) => {
+            error!("Failed to deserialize admin command message: {}", e);
+            None
+        }
+        Ok(msg) => Some(msg),
+    }
+}
+
+/// Decode snapshot information from incoming NVMe admin command data.
+fn decode_snapshot_params(
+    req: *mut spdk_nvmf_request,
+) -> Option<SnapshotParams> {
     // Decode versioned snapshot creation request.
-    let decoded_msg =
-        match bincode::deserialize::<NvmeSnapshotMessage>(encoded_msg) {
-            Err(e) => {
-                error!(
-                    "Failed to deserialize snapshot creation message: {}",
-                    e
-                );
-                return None;
-            }
-            Ok(msg) => msg,
-        };
+    let decoded_msg = decode_admin_message::<NvmeSnapshotMessage>(req)?;
 
     let snapshot_params = match decoded_msg {
         NvmeSnapshotMessage::V1(v1) => v1.params().clone(),
@@ -150,29 +162,23 @@ fn decode_snapshot_params(
     Some(snapshot_params)
 }
 
-/// NVMf custom command handler for opcode c1h
-/// Called from nvmf_ctrlr_process_admin_cmd
-/// Return: <0 for any error, caller handles it as unsupported opcode
-extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+/// Get the bdev, descriptor and channel of the only namespace of the
+/// subsystem the request was received on.
+fn request_bdev(
+    req: *mut spdk_nvmf_request,
+) -> Option<(*mut spdk_bdev, *mut spdk_bdev_desc, *mut spdk_io_channel)> {
     let subsys = unsafe { spdk_nvmf_request_get_subsystem(req) };
     if subsys.is_null() {
         debug!("subsystem is null");
-        return -1;
+        return None;
     }
 
     /* Only process this request if it has exactly one namespace */
     if unsafe { spdk_nvmf_subsystem_get_max_nsid(subsys) } != 1 {
         debug!("multiple namespaces");
-        return -1;
+        return None;
     }
 
-    /* Get snapshot parameters from NVMe request */
-    let snapshot_params = match decode_snapshot_params(req) {
-        None => return -1,
-        Some(v) => v,
-    };
-
-    /* Forward to first namespace if it supports NVME admin commands */
     let mut bdev: *mut spdk_bdev = std::ptr::null_mut();
     let mut desc: *mut spdk_bdev_desc = std::ptr::null_mut();
     let mut ch: *mut spdk_io_channel = std::ptr::null_mut();
@@ -182,9 +188,27 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     if rc != 0 {
         /* No bdev found for this namespace. Continue. */
         debug!("no bdev found");
-        return -1;
+        return None;
     }
 
+    Some((bdev, desc, ch))
+}
+
+/// NVMf custom command handler for opcode c1h
+/// Called from nvmf_ctrlr_process_admin_cmd
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    /* Forward to first namespace if it supports NVME admin commands */
+    let Some((bdev, desc, ch)) = request_bdev(req) else {
+        return -1;
+    };
+
+    /* Get snapshot parameters from NVMe request */
+    let snapshot_params = match decode_snapshot_params(req) {
+        None => return -1,
+        Some(v) => v,
+    };
+
     let bd = Bdev::checked_from_ptr(bdev).unwrap();
     if bd.driver() == nexus::NEXUS_MODULE_NAME {
         // Received command on a published Nexus
@@ -208,6 +232,49 @@ extern "C" fn nvmf_create_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
     }
 }
 
+/// NVMf custom command handler for opcode c5h, which destroys a snapshot of a
+/// shared replica.
+/// Return: <0 for any error, caller handles it as unsupported opcode
+extern "C" fn nvmf_destroy_snapshot_hdlr(req: *mut spdk_nvmf_request) -> i32 {
+    let Some((bdev, _, _)) = request_bdev(req) else {
+        return -1;
+    };
+
+    let snapshot_uuid =
+        match decode_admin_message::<NvmeSnapshotDestroyMessage>(req) {
+            None => return -1,
+            Some(NvmeSnapshotDestroyMessage::V1(v1)) => {
+                v1.snapshot_uuid().to_string()
+            }
+        };
+
+    let bd = Bdev::checked_from_ptr(bdev).unwrap();
+    let Ok(lvol) = Lvol::try_from(bd) else {
+        debug!("unsupported bdev driver");
+        return -1;
+    };
+
+    let nvmf_req = NvmfReq(NonNull::new(req).unwrap());
+
+    // Blobfs operations must be on md_thread
+    Reactors::master().send_future(async move {
+        let sc = match lvol.destroy_snapshot(&snapshot_uuid).await {
+            Ok(()) => 0,
+            Err(error) => {
+                error!(
+                    ?error,
+                    volume = lvol.name(),
+                    snapshot_uuid,
+                    "Failed to destroy remote snapshot"
+                );
+                0x06 // SPDK_NVME_SC_INTERNAL_DEVICE_ERROR
+            }
+        };
+        nvmf_req.complete(sc);
+    });
+    1 // SPDK_NVMF_REQUEST_EXEC_STATUS_ASYNCHRONOUS
+}
+
 pub fn create_snapshot(
     lvol: Lvol,
     cmd: &spdk_nvme_cmd,
@@ -238,12 +305,16 @@ pub fn create_snapshot(
     });
 }
 
-/// Register custom NVMe admin command handler
+/// Register custom NVMe admin command handlers
 pub fn setup_create_snapshot_hdlr() {
     unsafe {
         spdk_nvmf_set_custom_admin_cmd_hdlr(
             nvme_admin_opc::CREATE_SNAPSHOT,
             Some(nvmf_create_snapshot_hdlr),
         );
+        spdk_nvmf_set_custom_admin_cmd_hdlr(
+            NVME_ADMIN_DESTROY_SNAPSHOT,
+            Some(nvmf_destroy_snapshot_hdlr),
+        );
     }
 }
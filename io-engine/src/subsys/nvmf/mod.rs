@@ -20,7 +20,7 @@ use spdk_rs::libspdk::{
     spdk_subsystem_fini_next,
     spdk_subsystem_init_next,
 };
-pub use subsystem::{NvmfSubsystem, SubType};
+pub use subsystem::{NvmfHostController, NvmfSubsystem, SubType};
 pub use target::Target;
 
 use crate::{
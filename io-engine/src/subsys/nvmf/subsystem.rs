@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     ffi::{c_void, CString},
     fmt::{self, Debug, Display, Formatter},
     mem::size_of,
@@ -6,8 +7,11 @@ use std::{
     sync::atomic::Ordering,
 };
 
+use chrono::{DateTime, Utc};
 use futures::channel::oneshot;
 use nix::errno::Errno;
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 
 use crate::bdev::{
     nexus::{nexus_lookup, nexus_lookup_mut},
@@ -19,6 +23,9 @@ use spdk_rs::libspdk::{
     nvmf_subsystem_set_ana_state,
     nvmf_subsystem_set_cntlid_range,
     spdk_bdev_nvme_opts,
+    spdk_bit_array_count_set,
+    spdk_get_ticks,
+    spdk_get_ticks_hz,
     spdk_nvmf_ctrlr,
     spdk_nvmf_ns_get_bdev,
     spdk_nvmf_ns_opts,
@@ -40,6 +47,7 @@ use spdk_rs::libspdk::{
     spdk_nvmf_subsystem_listener_get_trid,
     spdk_nvmf_subsystem_pause,
     spdk_nvmf_subsystem_remove_host,
+    spdk_nvmf_subsystem_remove_listener,
     spdk_nvmf_subsystem_resume,
     spdk_nvmf_subsystem_set_allow_any_host,
     spdk_nvmf_subsystem_set_ana_reporting,
@@ -69,7 +77,11 @@ use crate::{
     ffihelper::{cb_arg, done_cb, AsStr, FfiResult, IntoCString},
     subsys::{
         make_subsystem_serial,
-        nvmf::{transport::TransportId, Error, NVMF_TGT},
+        nvmf::{
+            transport::{get_ip_addresses, same_address, TransportId},
+            Error,
+            NVMF_TGT,
+        },
         Config,
     },
 };
@@ -95,11 +107,74 @@ pub struct NvmfSubsystemIterator(*mut spdk_nvmf_subsystem);
 #[repr(C)]
 pub struct SpdkNvmfController(pub(crate) NonNull<spdk_nvmf_ctrlr>);
 
+/// Times at which the host controllers connected, keyed by the subsystem NQN
+/// and the controller ID.
+static CONNECT_TIMES: Lazy<Mutex<HashMap<(String, u16), DateTime<Utc>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// A host controller connected to a subsystem.
+#[derive(Debug, Clone)]
+pub struct NvmfHostController {
+    /// NQN of the connected host.
+    pub host_nqn: String,
+    /// Controller ID.
+    pub cntlid: u16,
+    /// Number of connected I/O qpairs.
+    pub io_qpairs: u32,
+    /// Time at which the host connected, if it is known.
+    pub connect_time: Option<DateTime<Utc>>,
+    /// Keep alive timeout requested by the host, 0 if disabled.
+    pub keep_alive_timeout_ms: u32,
+    /// Time elapsed since the last keep alive received from the host.
+    pub last_keep_alive_ms: u64,
+}
+
 impl SpdkNvmfController {
     /// Get the hostnqn from the controller
     fn hostnqn(&self) -> String {
         unsafe { self.0.as_ref().hostnqn.as_str().to_string() }
     }
+
+    /// Get the controller ID.
+    fn cntlid(&self) -> u16 {
+        unsafe { self.0.as_ref().cntlid }
+    }
+
+    /// Get the number of I/O qpairs connected to the controller.
+    fn io_qpairs(&self) -> u32 {
+        let qpairs =
+            unsafe { spdk_bit_array_count_set(self.0.as_ref().qpair_mask) };
+        // the admin qpair is accounted for as well
+        qpairs.saturating_sub(1)
+    }
+
+    /// Get the keep alive timeout, 0 if disabled.
+    fn keep_alive_timeout_ms(&self) -> u32 {
+        unsafe { self.0.as_ref().feat.keep_alive_timer.raw }
+    }
+
+    /// Get the time elapsed since the last keep alive.
+    fn last_keep_alive_ms(&self) -> u64 {
+        let (now, hz) = unsafe { (spdk_get_ticks(), spdk_get_ticks_hz()) };
+        let last = unsafe { self.0.as_ref().last_keep_alive_tick };
+        now.saturating_sub(last) * 1000 / hz
+    }
+
+    /// Get the status of the controller connected to the given subsystem.
+    fn info(&self, nqn: &str) -> NvmfHostController {
+        let cntlid = self.cntlid();
+        NvmfHostController {
+            host_nqn: self.hostnqn(),
+            cntlid,
+            io_qpairs: self.io_qpairs(),
+            connect_time: CONNECT_TIMES
+                .lock()
+                .get(&(nqn.to_string(), cntlid))
+                .cloned(),
+            keep_alive_timeout_ms: self.keep_alive_timeout_ms(),
+            last_keep_alive_ms: self.last_keep_alive_ms(),
+        }
+    }
 }
 impl From<*mut spdk_nvmf_ctrlr> for SpdkNvmfController {
     fn from(s: *mut spdk_nvmf_ctrlr) -> Self {
@@ -346,6 +421,17 @@ impl NvmfSubsystem {
             host controler: {spdk_ctrlr:?}"
         );
 
+        let key = (subsys_nqn.clone(), spdk_ctrlr.cntlid());
+        match event {
+            SPDK_NVMF_SS_INIATOR_CONNECT => {
+                CONNECT_TIMES.lock().insert(key, Utc::now());
+            }
+            SPDK_NVMF_SS_INIATOR_DISCONNECT => {
+                CONNECT_TIMES.lock().remove(&key);
+            }
+            _ => {}
+        }
+
         let nexus_name = match extract_nexus_name(&subsys_nqn) {
             Some(value) => value,
             None => {
@@ -542,6 +628,8 @@ impl NvmfSubsystem {
                 warn!("Subsystem destruction already started");
                 return -libc::EALREADY;
             }
+            let nqn = self.get_nqn();
+            CONNECT_TIMES.lock().retain(|(ss, _), _| ss != &nqn);
             spdk_nvmf_subsystem_destroy(
                 self.0.as_ptr(),
                 None,
@@ -707,6 +795,25 @@ impl NvmfSubsystem {
         })
     }
 
+    /// Get the host controllers connected to the subsystem.
+    pub fn controllers(&self) -> Vec<NvmfHostController> {
+        let nqn = self.get_nqn();
+        let mut controllers = Vec::new();
+
+        let mut ctrlr = unsafe { self.0.as_ref().ctrlrs.tqh_first };
+        while let Some(c) = NonNull::new(ctrlr) {
+            controllers.push(SpdkNvmfController(c).info(&nqn));
+            ctrlr = unsafe { c.as_ref().link.tqe_next };
+        }
+
+        controllers
+    }
+
+    /// Check whether the given host is connected to the subsystem.
+    pub fn is_host_connected(&self, host: &str) -> bool {
+        self.controllers().iter().any(|c| c.host_nqn == host)
+    }
+
     /// enable Asymmetric Namespace Access (ANA) reporting
     pub fn set_ana_reporting(&self, enable: bool) -> Result<(), Error> {
         match std::env::var("NEXUS_NVMF_ANA_ENABLE") {
@@ -751,24 +858,18 @@ impl NvmfSubsystem {
         Ok(())
     }
 
-    // we currently allow all listeners to the subsystem
-    async fn add_listener(&self) -> Result<(), Error> {
+    /// Add a listener for the given transport ID to the subsystem.
+    async fn add_listener(&self, trid: &TransportId) -> Result<(), Error> {
         extern "C" fn listen_cb(arg: *mut c_void, status: i32) {
             let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
             s.send(status).unwrap();
         }
 
-        let cfg = Config::get();
-
-        // dont yet enable both ports, IOW just add one transportID now
-
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-
         let (s, r) = oneshot::channel::<i32>();
         unsafe {
             spdk_nvmf_subsystem_add_listener(
                 self.0.as_ptr(),
-                trid_replica.as_ptr(),
+                trid.as_ptr(),
                 Some(listen_cb),
                 cb_arg(s),
             );
@@ -777,11 +878,113 @@ impl NvmfSubsystem {
         r.await.expect("listener callback gone").to_result(|e| {
             Error::Transport {
                 source: Errno::from_i32(e),
-                msg: "Failed to add listener".to_string(),
+                msg: format!("Failed to add listener {trid}"),
             }
         })
     }
 
+    /// Remove the listener for the given transport ID from the subsystem.
+    /// The subsystem must be in paused or inactive state.
+    fn remove_listener(&self, trid: &TransportId) -> Result<(), Error> {
+        unsafe {
+            spdk_nvmf_subsystem_remove_listener(self.0.as_ptr(), trid.as_ptr())
+        }
+        .to_result(|e| Error::Transport {
+            source: Errno::from_i32(e),
+            msg: format!("Failed to remove listener {trid}"),
+        })
+    }
+
+    /// Transport IDs of the given target addresses, or of all of them if none
+    /// are given.
+    fn listener_trids(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<TransportId>, Error> {
+        let cfg = Config::get();
+
+        // dont yet enable both ports, IOW just add one transportID now
+        let port = cfg.nexus_opts.nvmf_replica_port;
+
+        if addresses.is_empty() {
+            return Ok(TransportId::all(port));
+        }
+
+        let tgt_addresses = get_ip_addresses()?;
+        addresses
+            .iter()
+            .map(|address| {
+                match tgt_addresses.iter().find(|a| same_address(a, address)) {
+                    Some(address) => {
+                        Ok(TransportId::with_address(address, port))
+                    }
+                    None => Err(Error::Listener {
+                        nqn: self.get_nqn(),
+                        trid: address.to_string(),
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    /// Add listeners on the given target addresses, or on all of them if none
+    /// are given.
+    async fn add_listeners(&self, addresses: &[String]) -> Result<(), Error> {
+        for trid in &self.listener_trids(addresses)? {
+            self.add_listener(trid).await?;
+        }
+        Ok(())
+    }
+
+    /// Listen on exactly the given target addresses, or on all of them if
+    /// none are given, adding and removing listeners as needed.
+    /// The subsystem is paused while the listeners change, and new listeners
+    /// get the ANA state of the existing ones. Hosts connected through a
+    /// removed listener stay connected until they disconnect.
+    pub async fn set_listeners(
+        &self,
+        addresses: &[String],
+    ) -> Result<(), Error> {
+        let wanted = self.listener_trids(addresses)?;
+        let current = self.listeners_to_vec().unwrap_or_default();
+
+        let contains = |trids: &[TransportId], trid: &TransportId| {
+            trids.iter().any(|t| t.same_endpoint(trid))
+        };
+        let to_add = wanted
+            .iter()
+            .filter(|trid| !contains(&current, trid))
+            .collect::<Vec<_>>();
+        let to_remove = current
+            .iter()
+            .filter(|trid| !contains(&wanted, trid))
+            .collect::<Vec<_>>();
+
+        if to_add.is_empty() && to_remove.is_empty() {
+            return Ok(());
+        }
+
+        let ana_state = self.get_ana_state().await.ok();
+
+        self.pause().await?;
+        let result = async {
+            for trid in to_add {
+                self.add_listener(trid).await?;
+            }
+            for trid in to_remove {
+                self.remove_listener(trid)?;
+            }
+            if let Some(ana_state) = ana_state {
+                self.set_ana_state(ana_state).await?;
+            }
+            Ok::<(), Error>(())
+        }
+        .await;
+        self.resume().await?;
+
+        result
+    }
+
     /// TODO
     async fn change_state(
         &self,
@@ -858,11 +1061,30 @@ impl NvmfSubsystem {
         res
     }
 
-    /// start the subsystem previously created -- note that we destroy it on
-    /// failure to ensure the state is not in limbo and to avoid leaking
-    /// resources
+    /// start the subsystem previously created, listening on all target
+    /// addresses -- note that we destroy it on failure to ensure the state is
+    /// not in limbo and to avoid leaking resources
     pub async fn start(self) -> Result<String, Error> {
-        self.add_listener().await?;
+        self.start_with_listeners(&[]).await
+    }
+
+    /// start the subsystem previously created, listening only on the given
+    /// target addresses (all of them if none are given)
+    pub async fn start_with_listeners(
+        self,
+        addresses: &[String],
+    ) -> Result<String, Error> {
+        if let Err(e) = self.add_listeners(addresses).await {
+            error!(
+                "Failed to add listeners to subsystem '{}': {}; destroying it",
+                self.get_nqn(),
+                e.to_string(),
+            );
+
+            self.destroy();
+
+            return Err(e);
+        }
 
         if let Err(e) = self
             .change_state("start", |ss, cb, arg| unsafe {
@@ -909,10 +1131,15 @@ impl NvmfSubsystem {
         .await
     }
 
-    /// get ANA state
+    /// get ANA state, which is the same for all listeners
     pub async fn get_ana_state(&self) -> Result<u32, Error> {
         let cfg = Config::get();
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
+        let trid_replica = self
+            .listeners_to_vec()
+            .and_then(|trids| trids.into_iter().next())
+            .unwrap_or_else(|| {
+                TransportId::new(cfg.nexus_opts.nvmf_replica_port)
+            });
         let listener = unsafe {
             nvmf_subsystem_find_listener(self.0.as_ptr(), trid_replica.as_ptr())
         };
@@ -926,36 +1153,47 @@ impl NvmfSubsystem {
         }
     }
 
-    /// set ANA state: optimized, non_optimized, inaccessible
+    /// set ANA state of all listeners: optimized, non_optimized, inaccessible
     /// subsystem must be in paused or inactive state
     pub async fn set_ana_state(&self, ana_state: u32) -> Result<(), Error> {
         extern "C" fn set_ana_state_cb(arg: *mut c_void, status: i32) {
             let s = unsafe { Box::from_raw(arg as *mut oneshot::Sender<i32>) };
             s.send(status).unwrap();
         }
-        let cfg = Config::get();
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
 
-        let (s, r) = oneshot::channel::<i32>();
+        let Some(trids) = self.listeners_to_vec() else {
+            return Err(Error::Listener {
+                nqn: self.get_nqn(),
+                trid: "none".to_string(),
+            });
+        };
 
-        unsafe {
-            nvmf_subsystem_set_ana_state(
-                self.0.as_ptr(),
-                trid_replica.as_ptr(),
-                ana_state,
-                0,
-                Some(set_ana_state_cb),
-                cb_arg(s),
-            );
+        for trid in trids {
+            let (s, r) = oneshot::channel::<i32>();
+
+            unsafe {
+                nvmf_subsystem_set_ana_state(
+                    self.0.as_ptr(),
+                    trid.as_ptr(),
+                    ana_state,
+                    0,
+                    Some(set_ana_state_cb),
+                    cb_arg(s),
+                );
+            }
+
+            r.await
+                .expect("Cancellation is not supported")
+                .to_result(|e| Error::Subsystem {
+                    source: Errno::from_i32(-e),
+                    nqn: self.get_nqn(),
+                    msg: format!(
+                        "failed to set_ana_state of the subsystem for {trid}"
+                    ),
+                })?;
         }
 
-        r.await
-            .expect("Cancellation is not supported")
-            .to_result(|e| Error::Subsystem {
-                source: Errno::from_i32(-e),
-                nqn: self.get_nqn(),
-                msg: "failed to set_ana_state of the subsystem".to_string(),
-            })
+        Ok(())
     }
 
     /// destroy all subsystems associated with our target, subsystems must be in
@@ -1013,6 +1251,13 @@ impl NvmfSubsystem {
             .find(|s| s.get_nqn() == nqn)
     }
 
+    /// lookup a subsystem by its NQN
+    pub fn lookup_by_nqn(nqn: &str) -> Option<NvmfSubsystem> {
+        NvmfSubsystem::first()?
+            .into_iter()
+            .find(|s| s.get_nqn() == nqn)
+    }
+
     /// get the bdev associated with this subsystem -- we implicitly assume the
     /// first namespace
     pub fn bdev(&self) -> Option<UntypedBdev> {
@@ -34,7 +34,7 @@ use crate::{
             poll_groups::PollGroup,
             subsystem::NvmfSubsystem,
             transport,
-            transport::{get_ipv4_address, TransportId},
+            transport::{get_ip_addresses, TransportId},
             Error,
             NVMF_PGS,
         },
@@ -220,11 +220,10 @@ impl Target {
         });
     }
 
-    /// Listen for incoming connections by default we only listen on the replica
-    /// port
+    /// Listen for incoming connections on every target address; by default we
+    /// only listen on the replica port
     fn listen(&mut self) -> Result<()> {
         let cfg = Config::get();
-        let trid_nexus = TransportId::new(cfg.nexus_opts.nvmf_nexus_port);
         let mut opts = spdk_nvmf_listen_opts::default();
         unsafe {
             spdk_nvmf_listen_opts_init(
@@ -232,39 +231,44 @@ impl Target {
                 std::mem::size_of::<spdk_nvmf_listen_opts>() as u64,
             );
         }
-        let rc = unsafe {
-            spdk_nvmf_tgt_listen_ext(
-                self.tgt.as_ptr(),
-                trid_nexus.as_ptr(),
-                &mut opts,
-            )
-        };
 
-        if rc != 0 {
-            return Err(Error::CreateTarget {
-                msg: "failed to back target".into(),
-            });
+        for trid_nexus in TransportId::all(cfg.nexus_opts.nvmf_nexus_port) {
+            let rc = unsafe {
+                spdk_nvmf_tgt_listen_ext(
+                    self.tgt.as_ptr(),
+                    trid_nexus.as_ptr(),
+                    &mut opts,
+                )
+            };
+
+            if rc != 0 {
+                return Err(Error::CreateTarget {
+                    msg: format!("failed to back target on {trid_nexus}"),
+                });
+            }
         }
 
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
-        let rc = unsafe {
-            spdk_nvmf_tgt_listen_ext(
-                self.tgt.as_ptr(),
-                trid_replica.as_ptr(),
-                &mut opts,
-            )
-        };
+        for trid_replica in TransportId::all(cfg.nexus_opts.nvmf_replica_port) {
+            let rc = unsafe {
+                spdk_nvmf_tgt_listen_ext(
+                    self.tgt.as_ptr(),
+                    trid_replica.as_ptr(),
+                    &mut opts,
+                )
+            };
 
-        if rc != 0 {
-            return Err(Error::CreateTarget {
-                msg: "failed to front target".into(),
-            });
+            if rc != 0 {
+                return Err(Error::CreateTarget {
+                    msg: format!("failed to front target on {trid_replica}"),
+                });
+            }
         }
+
         info!(
-            "nvmf target listening on {}:({},{})",
-            get_ipv4_address().unwrap(),
-            trid_nexus.trsvcid.as_str(),
-            trid_replica.trsvcid.as_str(),
+            "nvmf target listening on {:?}:({},{})",
+            get_ip_addresses().unwrap(),
+            cfg.nexus_opts.nvmf_nexus_port,
+            cfg.nexus_opts.nvmf_replica_port,
         );
         self.next_state();
         Ok(())
@@ -374,16 +378,24 @@ impl Target {
         }
 
         let cfg = Config::get();
-        let trid_nexus = TransportId::new(cfg.nexus_opts.nvmf_nexus_port);
-        let trid_replica = TransportId::new(cfg.nexus_opts.nvmf_replica_port);
 
-        unsafe {
-            spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid_replica.as_ptr())
-        };
+        for trid_replica in TransportId::all(cfg.nexus_opts.nvmf_replica_port) {
+            unsafe {
+                spdk_nvmf_tgt_stop_listen(
+                    self.tgt.as_ptr(),
+                    trid_replica.as_ptr(),
+                )
+            };
+        }
 
-        unsafe {
-            spdk_nvmf_tgt_stop_listen(self.tgt.as_ptr(), trid_nexus.as_ptr())
-        };
+        for trid_nexus in TransportId::all(cfg.nexus_opts.nvmf_nexus_port) {
+            unsafe {
+                spdk_nvmf_tgt_stop_listen(
+                    self.tgt.as_ptr(),
+                    trid_nexus.as_ptr(),
+                )
+            };
+        }
 
         unsafe {
             spdk_nvmf_tgt_destroy(
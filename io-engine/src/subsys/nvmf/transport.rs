@@ -16,6 +16,7 @@ use spdk_rs::{
         spdk_nvmf_transport_create,
         SPDK_NVME_TRANSPORT_TCP,
         SPDK_NVMF_ADRFAM_IPV4,
+        SPDK_NVMF_ADRFAM_IPV6,
         SPDK_NVMF_TRSVCID_MAX_LEN,
     },
 };
@@ -78,12 +79,31 @@ impl DerefMut for TransportId {
 }
 
 impl TransportId {
+    /// Transport ID of the primary target address and the given port.
     pub fn new(port: u16) -> Self {
-        let address = get_ipv4_address().unwrap();
+        Self::with_address(&get_ip_address().unwrap(), port)
+    }
+
+    /// Transport IDs of all target addresses and the given port.
+    pub fn all(port: u16) -> Vec<Self> {
+        get_ip_addresses()
+            .unwrap()
+            .iter()
+            .map(|address| Self::with_address(address, port))
+            .collect()
+    }
+
+    /// Transport ID of the given target address and port.
+    pub fn with_address(address: &str, port: u16) -> Self {
+        let adrfam = if is_ipv6(&address) {
+            SPDK_NVMF_ADRFAM_IPV6
+        } else {
+            SPDK_NVMF_ADRFAM_IPV4
+        };
 
         let mut trid = spdk_nvme_transport_id {
             trtype: SPDK_NVME_TRANSPORT_TCP,
-            adrfam: SPDK_NVMF_ADRFAM_IPV4,
+            adrfam,
             ..Default::default()
         };
 
@@ -91,12 +111,24 @@ impl TransportId {
         assert!(port.len() < SPDK_NVMF_TRSVCID_MAX_LEN as usize);
 
         copy_cstr_with_null(&TCP_TRANSPORT, &mut trid.trstring);
-        copy_str_with_null(&address, &mut trid.traddr);
+        copy_str_with_null(address, &mut trid.traddr);
         copy_str_with_null(&port, &mut trid.trsvcid);
 
         Self(trid)
     }
 
+    /// Get the transport address.
+    pub fn address(&self) -> &str {
+        self.0.traddr.as_str()
+    }
+
+    /// Tests if both transport IDs have the same address and port,
+    /// whichever way their addresses are written.
+    pub fn same_endpoint(&self, other: &Self) -> bool {
+        same_address(self.address(), other.address())
+            && self.0.trsvcid.as_str() == other.0.trsvcid.as_str()
+    }
+
     pub fn as_ptr(&self) -> *mut spdk_nvme_transport_id {
         &self.0 as *const _ as *mut spdk_nvme_transport_id
     }
@@ -104,12 +136,22 @@ impl TransportId {
 
 impl Display for TransportId {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "nvmf://{}:{}",
-            self.0.traddr.as_str(),
-            self.0.trsvcid.as_str()
-        )
+        // IPv6 addresses must be enclosed in brackets within URIs
+        if self.0.adrfam == SPDK_NVMF_ADRFAM_IPV6 {
+            write!(
+                f,
+                "nvmf://[{}]:{}",
+                self.0.traddr.as_str(),
+                self.0.trsvcid.as_str()
+            )
+        } else {
+            write!(
+                f,
+                "nvmf://{}:{}",
+                self.0.traddr.as_str(),
+                self.0.trsvcid.as_str()
+            )
+        }
     }
 }
 
@@ -124,7 +166,28 @@ impl Debug for TransportId {
     }
 }
 
-pub(crate) fn get_ipv4_address() -> Result<String, Error> {
+/// Tests if the given target address is an IPv6 address.
+pub(crate) fn is_ipv6(address: &str) -> bool {
+    address.parse::<std::net::Ipv6Addr>().is_ok()
+}
+
+/// Tests if two target addresses are the same IP address, whichever way
+/// they are written: `[fd00::1]` and `fd00:0:0::1` are the same address.
+pub(crate) fn same_address(a: &str, b: &str) -> bool {
+    let parse = |address: &str| {
+        address
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse::<std::net::IpAddr>()
+    };
+    match (parse(a), parse(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+/// Get the IPv4 or IPv6 address the target listens on.
+pub(crate) fn get_ip_address() -> Result<String, Error> {
     match MayastorEnvironment::get_nvmf_tgt_ip() {
         Ok(val) => Ok(val),
         Err(msg) => Err(Error::CreateTarget {
@@ -132,3 +195,10 @@ pub(crate) fn get_ipv4_address() -> Result<String, Error> {
         }),
     }
 }
+
+/// Get all IPv4 or IPv6 addresses the target listens on.
+pub(crate) fn get_ip_addresses() -> Result<Vec<String>, Error> {
+    MayastorEnvironment::get_nvmf_tgt_ips().map_err(|msg| Error::CreateTarget {
+        msg,
+    })
+}
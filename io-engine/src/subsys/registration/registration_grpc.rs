@@ -9,6 +9,7 @@ use mayastor_api::v1::registration::{
 };
 use once_cell::sync::OnceCell;
 use std::{env, str::FromStr, time::Duration};
+use tonic::transport::{Certificate, ClientTlsConfig, Identity};
 
 /// Mayastor sends registration messages in this interval (kind of heart-beat)
 const HB_INTERVAL_SEC: Duration = Duration::from_secs(5);
@@ -40,6 +41,35 @@ impl FromStr for ApiVersion {
     }
 }
 
+/// Paths of the PEM files the registration client verifies the registration
+/// endpoint with, and optionally authenticates itself with.
+#[derive(Clone, Debug)]
+pub struct RegistrationTlsFiles {
+    /// CA certificate the registration endpoint certificate is signed by.
+    pub ca_cert: String,
+    /// Client certificate presented to the registration endpoint.
+    pub cert: Option<String>,
+    /// Private key of the client certificate.
+    pub key: Option<String>,
+}
+
+impl RegistrationTlsFiles {
+    /// Loads the files into a client TLS configuration.
+    fn client_config(&self) -> Result<ClientTlsConfig, String> {
+        let read = |path: &str| {
+            std::fs::read(path).map_err(|e| format!("{path}: {e}"))
+        };
+        let config = ClientTlsConfig::new()
+            .ca_certificate(Certificate::from_pem(read(&self.ca_cert)?));
+        Ok(match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => {
+                config.identity(Identity::from_pem(read(cert)?, read(key)?))
+            }
+            _ => config,
+        })
+    }
+}
+
 #[derive(Clone)]
 struct Configuration {
     /// Id of the node that mayastor is running on
@@ -81,26 +111,33 @@ impl Registration {
         grpc_endpoint: &str,
         registration_addr: Uri,
         api_versions: Vec<ApiVersion>,
-    ) {
-        GRPC_REGISTRATION.get_or_init(|| {
-            Registration::new(
-                node,
-                node_nqn,
-                grpc_endpoint,
-                registration_addr,
-                api_versions,
-            )
-        });
+        tls: Option<RegistrationTlsFiles>,
+    ) -> Result<(), String> {
+        GRPC_REGISTRATION
+            .get_or_try_init(|| {
+                Registration::new(
+                    node,
+                    node_nqn,
+                    grpc_endpoint,
+                    registration_addr,
+                    api_versions,
+                    tls,
+                )
+            })
+            .map(|_| ())
     }
 
-    /// Create a new registration instance
+    /// Create a new registration instance, connecting over TLS if the TLS
+    /// files are given.
+    /// Fails if the TLS files cannot be loaded.
     pub fn new(
         node: &str,
         node_nqn: &Option<String>,
         grpc_endpoint: &str,
         registration_addr: Uri,
         api_versions: Vec<ApiVersion>,
-    ) -> Self {
+        tls: Option<RegistrationTlsFiles>,
+    ) -> Result<Self, String> {
         let (msg_sender, msg_receiver) = async_channel::unbounded::<()>();
         let config = Configuration {
             api_versions,
@@ -126,13 +163,24 @@ impl Registration {
             .timeout(config.hb_timeout_sec)
             .http2_keep_alive_interval(HTTP_KEEP_ALIVE_INTERVAL)
             .keep_alive_timeout(HTTP_KEEP_ALIVE_TIMEOUT);
+        let endpoint = match tls {
+            Some(tls) => tls
+                .client_config()
+                .and_then(|cfg| {
+                    endpoint.tls_config(cfg).map_err(|e| e.to_string())
+                })
+                .map_err(|error| {
+                    format!("Failed to configure registration TLS: {error}")
+                })?,
+            None => endpoint,
+        };
         let channel = endpoint.connect_lazy();
-        Self {
+        Ok(Self {
             config,
             client: registration_client::RegistrationClient::new(channel),
             rcv_chan: msg_receiver,
             fini_chan: msg_sender,
-        }
+        })
     }
 
     /// Get the instance uuid.
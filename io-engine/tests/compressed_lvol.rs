@@ -0,0 +1,191 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev::compress_bdev::CompressionCodec,
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{
+        LogicalVolume,
+        MayastorCliArgs,
+        SnapshotOps,
+        SnapshotParams,
+        UntypedBdevHandle,
+    },
+    lvs::{Lvs, LvsLvol},
+    pool_backend::PoolArgs,
+};
+use uuid::Uuid;
+pub mod common;
+
+const DISK: &str = "/tmp/compressed_lvol.img";
+const SNAP_DISK: &str = "/tmp/compressed_lvol_snap.img";
+
+/// Checks a compressed lvol reads back what it wrote, reports its logical
+/// size and compression ratio, and keeps its data across a pool export and
+/// import.
+#[tokio::test]
+async fn compressed_lvol() {
+    common::truncate_file(DISK, 128 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        let args = PoolArgs {
+            name: "cmp_pool".into(),
+            disks: vec![format!("aio://{DISK}")],
+            uuid: None,
+        };
+        let pool = Lvs::create_or_import(args.clone()).await.unwrap();
+        let lvol = pool
+            .create_compressed_lvol(
+                "cmp_vol",
+                32 << 20,
+                None,
+                CompressionCodec::Lz4,
+            )
+            .await
+            .unwrap();
+        let bdev = lvol.compressed_bdev().unwrap();
+        assert_eq!(bdev.size_in_bytes(), 32 << 20);
+
+        let h = UntypedBdevHandle::open(bdev.name(), true, false).unwrap();
+        let mut buf = h.dma_malloc(1 << 20).unwrap();
+        for (i, b) in buf.as_mut_slice().iter_mut().enumerate() {
+            *b = (i / 512 % 4) as u8;
+        }
+        h.write_at(4 << 20, &buf).await.unwrap();
+        // A write within a chunk merges with the data of the chunk.
+        let mut small = h.dma_malloc(4096).unwrap();
+        small.fill(0xa5);
+        h.write_at((4 << 20) + 8192, &small).await.unwrap();
+        drop(h);
+
+        let usage = lvol.usage();
+        assert_eq!(usage.capacity_bytes, 32 << 20);
+        assert!(usage.compression_ratio > 3.0);
+        assert!(usage.allocated_bytes < 32 << 20);
+
+        pool.export().await.unwrap();
+        let pool = Lvs::create_or_import(args).await.unwrap();
+        let lvol = pool.lvols().unwrap().next().unwrap();
+        let bdev = lvol.compressed_bdev().unwrap();
+
+        let h = UntypedBdevHandle::open(bdev.name(), true, false).unwrap();
+        let mut read = h.dma_malloc(1 << 20).unwrap();
+        h.read_at(4 << 20, &mut read).await.unwrap();
+        for (i, b) in read.as_slice().iter().enumerate() {
+            let expected = if (8192 .. 12288).contains(&i) {
+                0xa5
+            } else {
+                (i / 512 % 4) as u8
+            };
+            assert_eq!(*b, expected);
+        }
+        h.read_at(0, &mut read).await.unwrap();
+        assert!(read.as_slice().iter().all(|b| *b == 0));
+        drop(h);
+
+        lvol.destroy().await.unwrap();
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[DISK.into()]);
+}
+
+/// Checks a snapshot of a compressed lvol reads back the data of the lvol
+/// when it was taken, and a compressed lvol grows but does not shrink.
+#[tokio::test]
+async fn compressed_lvol_snapshot_resize() {
+    common::truncate_file(SNAP_DISK, 128 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        let pool = Lvs::create_or_import(PoolArgs {
+            name: "cmp_snap_pool".into(),
+            disks: vec![format!("aio://{SNAP_DISK}")],
+            uuid: None,
+        })
+        .await
+        .unwrap();
+        let lvol = pool
+            .create_compressed_lvol(
+                "cmp_snap_vol",
+                16 << 20,
+                None,
+                CompressionCodec::Lz4,
+            )
+            .await
+            .unwrap();
+
+        let bdev = lvol.compressed_bdev().unwrap();
+        let h = UntypedBdevHandle::open(bdev.name(), true, false).unwrap();
+        let mut buf = h.dma_malloc(1 << 20).unwrap();
+        buf.fill(0x5a);
+        h.write_at(1 << 20, &buf).await.unwrap();
+
+        let snapshot = lvol
+            .create_snapshot(SnapshotParams::new(
+                Some("cmp_entity".into()),
+                Some(lvol.uuid()),
+                Some("cmp_txn".into()),
+                Some("cmp_snap".into()),
+                Some(Uuid::new_v4().to_string()),
+            ))
+            .await
+            .unwrap();
+
+        buf.fill(0xc3);
+        h.write_at(1 << 20, &buf).await.unwrap();
+        drop(h);
+
+        let snap_bdev = snapshot.compressed_bdev().unwrap();
+        let h =
+            UntypedBdevHandle::open(snap_bdev.name(), false, false).unwrap();
+        let mut read = h.dma_malloc(1 << 20).unwrap();
+        h.read_at(1 << 20, &mut read).await.unwrap();
+        assert!(read.as_slice().iter().all(|b| *b == 0x5a));
+        drop(h);
+
+        lvol.resize(32 << 20).await.unwrap();
+        let bdev = lvol.compressed_bdev().unwrap();
+        assert_eq!(bdev.size_in_bytes(), 32 << 20);
+        assert_eq!(lvol.usage().capacity_bytes, 32 << 20);
+
+        let h = UntypedBdevHandle::open(bdev.name(), true, false).unwrap();
+        buf.fill(0x3c);
+        h.write_at(24 << 20, &buf).await.unwrap();
+        h.read_at(24 << 20, &mut read).await.unwrap();
+        assert!(read.as_slice().iter().all(|b| *b == 0x3c));
+        h.read_at(1 << 20, &mut read).await.unwrap();
+        assert!(read.as_slice().iter().all(|b| *b == 0xc3));
+        drop(h);
+
+        assert!(lvol.resize(8 << 20).await.is_err());
+
+        pool.destroy().await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[SNAP_DISK.into()]);
+}
+
+/// Checks a compressed device is refused over a device which holds other
+/// data.
+#[tokio::test]
+async fn compressed_base_invalid() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        bdev_create("malloc:///cmp_base?size_mb=16").await.unwrap();
+        let h = UntypedBdevHandle::open("cmp_base", true, false).unwrap();
+        let mut buf = h.dma_malloc(4096).unwrap();
+        buf.fill(0xff);
+        h.write_at(0, &buf).await.unwrap();
+        drop(h);
+
+        assert!(matches!(
+            bdev_create("compress:///cmp_base").await,
+            Err(BdevError::CompressBaseInvalid { .. })
+        ));
+
+        bdev_destroy("malloc:///cmp_base?size_mb=16").await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use common::MayastorTest;
+use io_engine::{
+    bdev::cor_bdev::{cor_bdev_progress, CorState},
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{MayastorCliArgs, UntypedBdevHandle},
+    lvs::{Lvs, LvsLvol},
+    pool_backend::PoolArgs,
+    sleep::mayastor_sleep,
+};
+pub mod common;
+
+const SOURCE: &str = "malloc:///cor_src?size_mb=32";
+
+/// Checks a copy-on-read device reads the data of its source before its
+/// regions are populated, populates all of them in the background, and
+/// then reads and writes its local lvol.
+#[tokio::test]
+async fn cor_bdev() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        bdev_create(SOURCE).await.unwrap();
+        let h = UntypedBdevHandle::open("cor_src", true, false).unwrap();
+        let mut buf = h.dma_malloc(1 << 20).unwrap();
+        buf.fill(0xa5);
+        h.write_at(8 << 20, &buf).await.unwrap();
+        drop(h);
+
+        let pool = Lvs::create_or_import(PoolArgs {
+            name: "cor_pool".into(),
+            disks: vec!["malloc:///cor_mem?size_mb=64".into()],
+            uuid: None,
+        })
+        .await
+        .unwrap();
+        let lvol = pool
+            .create_lvol("cor_vol", 32 << 20, None, true)
+            .await
+            .unwrap();
+        let local = lvol.as_bdev().name().to_string();
+
+        let uri = format!(
+            "cor:///{local}?source=bdev%3A%2F%2F%2Fcor_src&name=cor_vol_cor"
+        );
+        assert_eq!(bdev_create(&uri).await.unwrap(), "cor_vol_cor");
+
+        let h = UntypedBdevHandle::open("cor_vol_cor", true, false).unwrap();
+        let mut buf = h.dma_malloc(4096).unwrap();
+        h.read_at((8 << 20) + 4096, &mut buf).await.unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0xa5));
+
+        let mut progress = cor_bdev_progress("cor_vol_cor").unwrap();
+        for _ in 0 .. 100 {
+            if progress.state != CorState::Populating {
+                break;
+            }
+            mayastor_sleep(Duration::from_millis(50)).await.unwrap();
+            progress = cor_bdev_progress("cor_vol_cor").unwrap();
+        }
+        assert_eq!(progress.state, CorState::Complete);
+        assert_eq!(progress.populated_regions, progress.total_regions);
+
+        // Only the region with data was written to the lvol.
+        assert_eq!(lvol.usage().num_allocated_clusters, 1);
+
+        buf.fill(0x5a);
+        h.write_at(0, &buf).await.unwrap();
+        buf.fill(0);
+        h.read_at(0, &mut buf).await.unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0x5a));
+        drop(h);
+
+        bdev_destroy(&uri).await.unwrap();
+        assert!(matches!(
+            cor_bdev_progress("cor_vol_cor"),
+            Err(BdevError::BdevNotFound { .. })
+        ));
+
+        pool.destroy().await.unwrap();
+        bdev_destroy(SOURCE).await.unwrap();
+    })
+    .await;
+}
+
+/// Checks a copy-on-read device is refused over a device which is not a thin
+/// lvol.
+#[tokio::test]
+async fn cor_bdev_not_lvol() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        bdev_create(SOURCE).await.unwrap();
+        bdev_create("malloc:///cor_local?size_mb=32").await.unwrap();
+
+        assert!(matches!(
+            bdev_create("cor:///cor_local?source=bdev%3A%2F%2F%2Fcor_src")
+                .await,
+            Err(BdevError::CorDeviceInvalid { .. })
+        ));
+
+        bdev_destroy("malloc:///cor_local?size_mb=32")
+            .await
+            .unwrap();
+        bdev_destroy(SOURCE).await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,82 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{MayastorCliArgs, UntypedBdev},
+};
+use spdk_rs::DmaBuf;
+pub mod common;
+
+const BASE: &str = "malloc:///crypt_base?blk_size=512&size_mb=64";
+const KEY_FILE: &str = "/tmp/crypt_bdev.key";
+const WRONG_KEY_FILE: &str = "/tmp/crypt_bdev_wrong.key";
+
+fn crypt_uri(key_file: &str) -> String {
+    format!("crypt:///crypt_base?key_file={key_file}&name=crypt0")
+}
+
+/// Writes through a crypt bdev, checks the data lands encrypted on the base
+/// device and reads back decrypted, and that a wrong key is refused.
+#[tokio::test]
+async fn crypt_bdev() {
+    let key = (0 .. 64u8).collect::<Vec<_>>();
+    std::fs::write(KEY_FILE, &key).unwrap();
+    std::fs::write(
+        WRONG_KEY_FILE,
+        key.iter().rev().cloned().collect::<Vec<_>>(),
+    )
+    .unwrap();
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        bdev_create(BASE).await.unwrap();
+        assert_eq!(bdev_create(&crypt_uri(KEY_FILE)).await.unwrap(), "crypt0");
+
+        let crypt = UntypedBdev::open_by_name("crypt0", true).unwrap();
+        let base = UntypedBdev::lookup_by_name("crypt_base").unwrap();
+        // The first 4 KiB of the base device hold the header.
+        assert_eq!(crypt.bdev().num_blocks(), base.num_blocks() - 8);
+
+        let h = crypt.into_handle().unwrap();
+        let mut buf = DmaBuf::new(8192, 9).unwrap();
+        buf.fill(0xa5);
+        h.write_at(4096, &buf).await.unwrap();
+
+        let mut rd = DmaBuf::new(8192, 9).unwrap();
+        h.read_at(4096, &mut rd).await.unwrap();
+        assert_eq!(rd.as_slice(), buf.as_slice());
+        drop(h);
+
+        let base = UntypedBdev::open_by_name("crypt_base", false)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        base.read_at(8192, &mut rd).await.unwrap();
+        assert_ne!(rd.as_slice(), buf.as_slice());
+        drop(base);
+
+        bdev_destroy(&crypt_uri(KEY_FILE)).await.unwrap();
+        assert!(UntypedBdev::lookup_by_name("crypt0").is_none());
+
+        assert!(matches!(
+            bdev_create(&crypt_uri(WRONG_KEY_FILE)).await,
+            Err(BdevError::CryptKeyMismatch { .. })
+        ));
+
+        // The data survives reopening with the right key.
+        bdev_create(&crypt_uri(KEY_FILE)).await.unwrap();
+        let h = UntypedBdev::open_by_name("crypt0", false)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        h.read_at(4096, &mut rd).await.unwrap();
+        assert_eq!(rd.as_slice(), buf.as_slice());
+        drop(h);
+
+        bdev_destroy(&crypt_uri(KEY_FILE)).await.unwrap();
+        bdev_destroy(BASE).await.unwrap();
+    })
+    .await;
+
+    std::fs::remove_file(KEY_FILE).unwrap();
+    std::fs::remove_file(WRONG_KEY_FILE).unwrap();
+}
@@ -0,0 +1,84 @@
+use std::time::{Duration, Instant};
+
+use common::MayastorTest;
+use io_engine::{
+    bdev::delay_bdev::{
+        delay_bdev_latency,
+        delay_bdev_set_latency,
+        DelayLatencyUpdate,
+    },
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{MayastorCliArgs, UntypedBdev},
+};
+use spdk_rs::DmaBuf;
+pub mod common;
+
+const BASE: &str = "malloc:///delay_base?size_mb=64";
+const DELAY: &str = "delay:///delay_base?name=delay0&write_avg_us=20000";
+
+/// Checks the writes to a delay bdev take the configured latency, and that
+/// the latency can be changed while the device is open.
+#[tokio::test]
+async fn delay_bdev() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        bdev_create(BASE).await.unwrap();
+        assert_eq!(bdev_create(DELAY).await.unwrap(), "delay0");
+
+        let lat = delay_bdev_latency("delay0").unwrap();
+        assert_eq!(lat.write_avg, Duration::from_millis(20));
+        assert_eq!(lat.write_p99, Duration::from_millis(20));
+        assert_eq!(lat.read_avg, Duration::ZERO);
+
+        let h = UntypedBdev::open_by_name("delay0", true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = DmaBuf::new(4096, 9).unwrap();
+        buf.fill(0x5a);
+
+        let start = Instant::now();
+        h.write_at(0, &buf).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(20));
+
+        let lat = delay_bdev_set_latency(
+            "delay0",
+            DelayLatencyUpdate {
+                read_avg: Some(Duration::from_millis(30)),
+                read_p99: Some(Duration::from_millis(30)),
+                write_avg: Some(Duration::ZERO),
+                write_p99: Some(Duration::ZERO),
+            },
+        )
+        .unwrap();
+        assert_eq!(lat.read_avg, Duration::from_millis(30));
+
+        let start = Instant::now();
+        let mut rd = DmaBuf::new(4096, 9).unwrap();
+        h.read_at(0, &mut rd).await.unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(30));
+        assert_eq!(rd.as_slice(), buf.as_slice());
+
+        let start = Instant::now();
+        h.write_at(0, &buf).await.unwrap();
+        assert!(start.elapsed() < Duration::from_millis(20));
+        drop(h);
+
+        // The p99 latency can not be below the average latency.
+        assert!(matches!(
+            delay_bdev_set_latency(
+                "delay0",
+                DelayLatencyUpdate {
+                    write_avg: Some(Duration::from_millis(10)),
+                    ..Default::default()
+                },
+            ),
+            Err(BdevError::DelayLatencyInvalid { .. })
+        ));
+
+        bdev_destroy(DELAY).await.unwrap();
+        assert!(UntypedBdev::lookup_by_name("delay0").is_none());
+        bdev_destroy(BASE).await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,248 @@
+use once_cell::sync::OnceCell;
+use std::time::Duration;
+
+pub mod common;
+
+use common::{
+    compose::{
+        rpc::v1::{
+            event::{self, engine_event::Event, WatchEventsRequest},
+            GrpcConnect,
+        },
+        Binary,
+        Builder,
+    },
+    file_io::DataSize,
+    nvmf::test_write_to_nvmf,
+    pool::PoolBuilder,
+    replica::ReplicaBuilder,
+    MayastorTest,
+};
+
+use io_engine::{
+    core::{LogicalVolume, MayastorCliArgs, SnapshotOps, SnapshotParams},
+    events::{watch_events, EngineEvent},
+    lvs::Lvs,
+    pool_backend::PoolArgs,
+};
+use tonic::{Code, Streaming};
+use uuid::Uuid;
+
+static MAYASTOR: OnceCell<MayastorTest> = OnceCell::new();
+
+fn get_ms() -> &'static MayastorTest<'static> {
+    MAYASTOR.get_or_init(|| MayastorTest::new(MayastorCliArgs::default()))
+}
+
+const POOL_NAME: &str = "events_pool";
+const BDEV_NAME: &str = "malloc:///mem0?size_mb=64";
+const LVOL_NAME: &str = "events_lvol";
+
+/// Watches the events published while a pool, a replica and a snapshot are
+/// created and the pool destroyed, then resumes the watch from the middle.
+#[tokio::test]
+async fn engine_events_pool_and_snapshot() {
+    common::composer_init();
+
+    let mut watch = watch_events(None).unwrap();
+
+    let (pool_uuid, lvol_uuid, snapshot_uuid) = get_ms()
+        .spawn(async {
+            let pool = Lvs::create_or_import(PoolArgs {
+                name: POOL_NAME.to_string(),
+                disks: vec![BDEV_NAME.to_string()],
+                uuid: None,
+            })
+            .await
+            .unwrap();
+
+            let lvol = pool
+                .create_lvol(LVOL_NAME, 8 * 1024 * 1024, None, true)
+                .await
+                .unwrap();
+            let snapshot_uuid = Uuid::new_v4().to_string();
+            lvol.create_snapshot(SnapshotParams::new(
+                Some(lvol.name()),
+                Some(lvol.uuid()),
+                Some(Uuid::new_v4().to_string()),
+                Some("events_snap".to_string()),
+                Some(snapshot_uuid.clone()),
+            ))
+            .await
+            .unwrap();
+
+            let ids = (pool.uuid(), lvol.uuid(), snapshot_uuid);
+            pool.destroy().await.unwrap();
+            ids
+        })
+        .await;
+
+    let mut events = Vec::new();
+    while let Ok(Some(record)) = watch.receiver.try_next() {
+        events.push(record);
+    }
+    assert!(events.windows(2).all(|w| w[0].sequence < w[1].sequence));
+
+    let created = events
+        .iter()
+        .position(|r| {
+            r.event
+                == EngineEvent::PoolCreated {
+                    name: POOL_NAME.to_string(),
+                    uuid: pool_uuid.clone(),
+                }
+        })
+        .expect("no pool created event");
+    assert!(events.iter().any(|r| r.event
+        == EngineEvent::SnapshotCreated {
+            pool_uuid: pool_uuid.clone(),
+            source_uuid: lvol_uuid.clone(),
+            snapshot_uuid: snapshot_uuid.clone(),
+            snapshot_name: "events_snap".to_string(),
+        }));
+    assert!(events.iter().any(|r| r.event
+        == EngineEvent::PoolDestroyed {
+            name: POOL_NAME.to_string(),
+            uuid: pool_uuid.clone(),
+        }));
+
+    // Resuming after the pool creation replays the following events.
+    let resumed = watch_events(Some(events[created].sequence)).unwrap();
+    assert_eq!(
+        resumed
+            .backlog
+            .iter()
+            .map(|r| r.sequence)
+            .collect::<Vec<_>>(),
+        events[created + 1 ..]
+            .iter()
+            .map(|r| r.sequence)
+            .collect::<Vec<_>>()
+    );
+
+    // Events which have not been published yet cannot be resumed from.
+    let latest = events.last().unwrap().sequence;
+    assert!(watch_events(Some(latest + 1)).is_err());
+}
+
+/// Waits for the next event of the gRPC stream.
+async fn next_event(
+    events: &mut Streaming<event::EngineEvent>,
+) -> event::EngineEvent {
+    tokio::time::timeout(Duration::from_secs(30), events.message())
+        .await
+        .expect("Timed out waiting for an event")
+        .expect("Event stream failed")
+        .expect("Event stream closed")
+}
+
+/// Watches the events over gRPC while a thin provisioned replica fills its
+/// pool up with data, then resumes the watch from the pool creation.
+#[tokio::test]
+async fn engine_events_grpc_pool_low_space() {
+    common::composer_init();
+
+    let test = Builder::new()
+        .name("cargo-test")
+        .network("10.1.0.0/16")
+        .unwrap()
+        .add_container_bin(
+            "ms",
+            Binary::from_dbg("io-engine").with_args(vec!["-l", "1"]),
+        )
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    let conn = GrpcConnect::new(&test);
+    let ms = conn.grpc_handle_shared("ms").await.unwrap();
+
+    let mut events = ms
+        .lock()
+        .await
+        .event
+        .watch_events(WatchEventsRequest {
+            after_sequence: None,
+        })
+        .await
+        .unwrap()
+        .into_inner();
+
+    let mut pool = PoolBuilder::new(ms.clone())
+        .with_name("pool0")
+        .with_new_uuid()
+        .with_malloc("mem0", 100);
+    let capacity = pool.create().await.unwrap().capacity;
+
+    let created = next_event(&mut events).await;
+    assert_eq!(
+        created.event,
+        Some(Event::PoolCreated(event::PoolCreated {
+            name: pool.name(),
+            uuid: pool.uuid(),
+        }))
+    );
+
+    // A thin provisioned replica as large as the pool only consumes space
+    // when written to.
+    let mut repl = ReplicaBuilder::new(ms.clone())
+        .with_pool(&pool)
+        .with_name("repl0")
+        .with_new_uuid()
+        .with_size_kb(capacity / 1024)
+        .with_thin(true);
+    repl.create().await.unwrap();
+    repl.share().await.unwrap();
+
+    test_write_to_nvmf(
+        &repl.nvmf_location(),
+        DataSize::from_bytes(0),
+        (capacity / (1024 * 1024)) as usize,
+        DataSize::from_mb(1),
+    )
+    .await
+    .unwrap();
+
+    // The pool space is checked periodically.
+    let low_space = loop {
+        let record = next_event(&mut events).await;
+        if let Some(Event::PoolLowSpace(low_space)) = &record.event {
+            break (record.sequence, low_space.clone());
+        }
+    };
+    assert_eq!(low_space.1.uuid, pool.uuid());
+    assert_eq!(low_space.1.capacity, capacity);
+    assert!(low_space.1.available * 10 < capacity);
+
+    // Resuming after the pool creation replays the following events.
+    let mut resumed = ms
+        .lock()
+        .await
+        .event
+        .watch_events(WatchEventsRequest {
+            after_sequence: Some(created.sequence),
+        })
+        .await
+        .unwrap()
+        .into_inner();
+    let mut sequence = created.sequence;
+    while sequence < low_space.0 {
+        let record = next_event(&mut resumed).await;
+        assert_eq!(record.sequence, sequence + 1);
+        sequence = record.sequence;
+    }
+
+    // Events which have not been published yet cannot be resumed from.
+    let status = ms
+        .lock()
+        .await
+        .event
+        .watch_events(WatchEventsRequest {
+            after_sequence: Some(u64::MAX / 2),
+        })
+        .await
+        .err()
+        .expect("Resumed a watch from the future");
+    assert_eq!(status.code(), Code::OutOfRange);
+}
@@ -0,0 +1,114 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev::error_bdev::{
+        error_bdev_add_rule,
+        error_bdev_list_rules,
+        error_bdev_remove_rule,
+        ErrorOp,
+        ErrorRule,
+        ErrorStatus,
+    },
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{MayastorCliArgs, UntypedBdev},
+};
+use spdk_rs::DmaBuf;
+pub mod common;
+
+const BASE: &str = "malloc:///error_base?blk_size=512&size_mb=64";
+const ERROR: &str = "error:///error_base?name=error0";
+
+/// Checks the I/Os matching the rules of an error bdev fail, and that the
+/// other I/Os reach the base device.
+#[tokio::test]
+async fn error_bdev() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        bdev_create(BASE).await.unwrap();
+        assert_eq!(bdev_create(ERROR).await.unwrap(), "error0");
+
+        let h = UntypedBdev::open_by_name("error0", true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = DmaBuf::new(4096, 9).unwrap();
+        buf.fill(0x42);
+        h.write_at(0, &buf).await.unwrap();
+
+        // Fail two reads of the first 8 blocks with a media error.
+        let state = error_bdev_add_rule(
+            "error0",
+            ErrorRule {
+                op: ErrorOp::Read,
+                status: "media".parse().unwrap(),
+                num_blocks: 8,
+                count: Some(2),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(state.rule.status, ErrorStatus::new(2, 0x82));
+
+        let mut rd = DmaBuf::new(4096, 9).unwrap();
+        assert!(h.read_at(0, &mut rd).await.is_err());
+        assert!(h.read_at(0, &mut rd).await.is_err());
+        h.read_at(0, &mut rd).await.unwrap();
+        assert_eq!(rd.as_slice(), buf.as_slice());
+
+        // Out of range and writes are not affected.
+        h.read_at(8192, &mut rd).await.unwrap();
+        h.write_at(0, &buf).await.unwrap();
+
+        let rules = error_bdev_list_rules("error0").unwrap();
+        assert_eq!(rules.len(), 1);
+        assert_eq!(rules[0].hits, 2);
+
+        // A rule with a null probability never fails an I/O.
+        let never = error_bdev_add_rule(
+            "error0",
+            ErrorRule {
+                op: ErrorOp::Write,
+                prob: 0.0,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        h.write_at(0, &buf).await.unwrap();
+
+        error_bdev_remove_rule("error0", Some(never.id)).unwrap();
+        assert!(matches!(
+            error_bdev_remove_rule("error0", Some(never.id)),
+            Err(BdevError::ErrorRuleNotFound { .. })
+        ));
+
+        // Any write fails until the rules are removed.
+        error_bdev_add_rule(
+            "error0",
+            ErrorRule {
+                op: ErrorOp::Write,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        assert!(h.write_at(4096, &buf).await.is_err());
+        error_bdev_remove_rule("error0", None).unwrap();
+        h.write_at(4096, &buf).await.unwrap();
+        assert!(error_bdev_list_rules("error0").unwrap().is_empty());
+
+        assert!(matches!(
+            error_bdev_add_rule(
+                "error0",
+                ErrorRule {
+                    prob: 2.0,
+                    ..Default::default()
+                },
+            ),
+            Err(BdevError::ErrorRuleInvalid { .. })
+        ));
+        drop(h);
+
+        bdev_destroy(ERROR).await.unwrap();
+        assert!(UntypedBdev::lookup_by_name("error0").is_none());
+        bdev_destroy(BASE).await.unwrap();
+    })
+    .await;
+}
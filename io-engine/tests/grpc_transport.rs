@@ -0,0 +1,118 @@
+use std::{os::unix::fs::PermissionsExt, path::Path, time::Duration};
+
+pub mod common;
+
+use common::{
+    compose::rpc::v1::pool::{ListPoolOptions, PoolRpcClient},
+    MayastorTest,
+};
+use io_engine::{
+    core::MayastorCliArgs,
+    grpc::{GrpcServerOptions, GrpcTlsFiles, MayastorGrpcServer},
+    subsys::{
+        registration::registration_grpc::{ApiVersion, RegistrationTlsFiles},
+        Registration,
+    },
+};
+use tokio::net::UnixStream;
+use tonic::transport::{Endpoint, Uri};
+use tower::service_fn;
+
+const UDS_PATH: &str = "/tmp/io-engine-grpc-test.sock";
+
+/// Serves the v1 services on a Unix domain socket, which only the owner of
+/// the engine process can connect to.
+#[tokio::test]
+async fn grpc_transport_uds() {
+    common::composer_init();
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    std::fs::remove_file(UDS_PATH).ok();
+
+    tokio::spawn(MayastorGrpcServer::run(
+        "grpc-test",
+        &None,
+        "127.0.0.1:10199".parse().unwrap(),
+        "/var/tmp/io-engine-grpc-test.rpc".to_string(),
+        vec![ApiVersion::V1],
+        GrpcServerOptions {
+            tls: None,
+            uds_path: Some(UDS_PATH.to_string()),
+        },
+    ));
+
+    let mut attempts = 50;
+    while !Path::new(UDS_PATH).exists() {
+        attempts -= 1;
+        assert!(attempts > 0, "socket {UDS_PATH} not created");
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    let mode = std::fs::metadata(UDS_PATH).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // the URI is ignored, the connector always connects to the socket
+    let channel = Endpoint::try_from("http://[::]:50051")
+        .unwrap()
+        .connect_with_connector(service_fn(|_: Uri| {
+            UnixStream::connect(UDS_PATH)
+        }))
+        .await
+        .unwrap();
+
+    let pools = PoolRpcClient::new(channel)
+        .list_pools(ListPoolOptions {
+            name: None,
+            pooltype: None,
+            uuid: None,
+        })
+        .await
+        .unwrap()
+        .into_inner()
+        .pools;
+    assert!(pools.is_empty());
+
+    MayastorGrpcServer::get_or_init().fini();
+    drop(ms);
+}
+
+/// The gRPC server refuses to start, and the registration client fails to
+/// be created, if their TLS files cannot be read.
+#[tokio::test]
+async fn grpc_transport_tls_files_missing() {
+    common::composer_init();
+
+    let result = MayastorGrpcServer::run(
+        "grpc-test",
+        &None,
+        "127.0.0.1:10198".parse().unwrap(),
+        "/var/tmp/io-engine-grpc-test.rpc".to_string(),
+        vec![ApiVersion::V1],
+        GrpcServerOptions {
+            tls: Some(GrpcTlsFiles {
+                cert: "/nonexistent/server.pem".to_string(),
+                key: "/nonexistent/server.key".to_string(),
+                client_ca: "/nonexistent/ca.pem".to_string(),
+            }),
+            uds_path: None,
+        },
+    )
+    .await;
+    assert!(result.is_err());
+
+    let error = Registration::new(
+        "grpc-test",
+        &None,
+        "127.0.0.1:10198",
+        "https://127.0.0.1:50051".parse().unwrap(),
+        vec![ApiVersion::V1],
+        Some(RegistrationTlsFiles {
+            ca_cert: "/nonexistent/ca.pem".to_string(),
+            cert: None,
+            key: None,
+        }),
+    )
+    .err()
+    .expect("registration created with a missing CA certificate");
+    assert!(error.contains("/nonexistent/ca.pem"));
+}
@@ -348,3 +348,98 @@ async fn nexus_fault_injection_range_based() {
     let children = nex_0.get_nexus().await.unwrap().children;
     assert_eq!(children[0].state, ChildState::Faulted as i32);
 }
+
+#[tokio::test]
+async fn nexus_fault_injection_media_status() {
+    test_injection_uri("op=write&offset=64&status=media").await;
+}
+
+#[tokio::test]
+async fn nexus_fault_injection_path_status() {
+    test_injection_uri("op=write&offset=64&status=path").await;
+}
+
+#[tokio::test]
+async fn nexus_fault_injection_every_nth() {
+    let test = create_compose_test().await;
+
+    let StorageBuilder {
+        pool_0: _,
+        pool_1: _,
+        repl_0: _,
+        repl_1: _,
+        nex_0,
+    } = create_test_storage(&test).await;
+
+    let children = nex_0.get_nexus().await.unwrap().children;
+    let dev_name = children[0].device_name.as_ref().unwrap();
+
+    // Create an injection that fails every third write to the child.
+    let inj_uri = format!("inject://{dev_name}?op=write&every=3");
+    nex_0.inject_nexus_fault(&inj_uri).await.unwrap();
+
+    // The first two writes must not fail.
+    for _ in 0 .. 2 {
+        test_write_to_nexus(
+            &nex_0,
+            DataSize::from_bytes(0),
+            1,
+            DataSize::from_kb(1),
+        )
+        .await
+        .unwrap();
+        let children = nex_0.get_nexus().await.unwrap().children;
+        assert_eq!(children[0].state, ChildState::Online as i32);
+    }
+
+    // The third write fails the child.
+    test_write_to_nexus(
+        &nex_0,
+        DataSize::from_bytes(0),
+        1,
+        DataSize::from_kb(1),
+    )
+    .await
+    .unwrap();
+    let children = nex_0.get_nexus().await.unwrap().children;
+    assert_eq!(children[0].state, ChildState::Faulted as i32);
+}
+
+#[tokio::test]
+async fn nexus_fault_injection_delay() {
+    let test = create_compose_test().await;
+
+    let StorageBuilder {
+        pool_0: _,
+        pool_1: _,
+        repl_0: _,
+        repl_1: _,
+        nex_0,
+    } = create_test_storage(&test).await;
+
+    let children = nex_0.get_nexus().await.unwrap().children;
+    let dev_name = children[0].device_name.as_ref().unwrap();
+
+    // Delay the writes to the child by 50 to 100 ms.
+    let inj_uri = format!(
+        "inject://{dev_name}?op=write&type=delay&delay_us=50000\
+        &delay_max_us=100000"
+    );
+    nex_0.inject_nexus_fault(&inj_uri).await.unwrap();
+
+    // The writes are slower, but must not fail.
+    let start = std::time::Instant::now();
+    test_write_to_nexus(
+        &nex_0,
+        DataSize::from_bytes(0),
+        4,
+        DataSize::from_kb(4),
+    )
+    .await
+    .unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let children = nex_0.get_nexus().await.unwrap().children;
+    assert_eq!(children[0].state, ChildState::Online as i32);
+    assert_eq!(children[1].state, ChildState::Online as i32);
+}
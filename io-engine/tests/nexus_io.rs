@@ -5,6 +5,7 @@ use io_engine::{
         nexus_create,
         nexus_create_v2,
         nexus_lookup_mut,
+        NexusLayout,
         NexusNvmeParams,
         NvmeAnaState,
     },
@@ -377,6 +378,7 @@ async fn nexus_io_resv_acquire() {
                 nvme_params,
                 &[format!("nvmf://{ip0}:8420/{HOSTNQN}:{REPL_UUID}")],
                 None,
+                NexusLayout::Mirror,
             )
             .await
             .unwrap();
@@ -573,6 +575,7 @@ async fn nexus_io_resv_preempt() {
                 nvme_params,
                 &[format!("nvmf://{ip0}:8420/{HOSTNQN}:{REPL_UUID}")],
                 None,
+                NexusLayout::Mirror,
             )
             .await
             .unwrap();
@@ -849,6 +852,7 @@ async fn nexus_io_resv_preempt_tabled() {
                         nvme_params,
                         &[format!("nvmf://{ip0}:8420/{HOSTNQN}:{REPL_UUID}")],
                         None,
+                        NexusLayout::Mirror,
                     )
                     .await
                     .unwrap();
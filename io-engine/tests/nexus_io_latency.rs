@@ -0,0 +1,63 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev::nexus::{nexus_create, nexus_lookup_mut},
+    core::{MayastorCliArgs, UntypedBdev},
+};
+use spdk_rs::DmaBuf;
+pub mod common;
+
+const NEXUS_NAME: &str = "latency_nexus";
+
+/// Checks that the I/Os completed by a nexus are recorded in its latency
+/// histograms, and that the histograms can be reset.
+#[tokio::test]
+async fn nexus_io_latency() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        nexus_create(
+            NEXUS_NAME,
+            32 * 1024 * 1024,
+            None,
+            &[
+                "malloc:///latency0?size_mb=64".to_string(),
+                "malloc:///latency1?size_mb=64".to_string(),
+            ],
+        )
+        .await
+        .unwrap();
+
+        let handle = UntypedBdev::open_by_name(NEXUS_NAME, true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+
+        let mut buf = DmaBuf::new(4096, 9).unwrap();
+        buf.fill(0xa5);
+        for i in 0 .. 10 {
+            handle.write_at(i * 4096, &buf).await.unwrap();
+        }
+        for i in 0 .. 5 {
+            handle.read_at(i * 4096, &mut buf).await.unwrap();
+        }
+
+        let nexus = nexus_lookup_mut(NEXUS_NAME).unwrap();
+        let latency = nexus.io_latency(true);
+        assert_eq!(latency.write.count(), 10);
+        assert_eq!(latency.read.count(), 5);
+        assert_eq!(latency.unmap.count(), 0);
+        assert!(latency.write.sum_ns() > 0);
+        assert!(latency.write.percentile(99.0).unwrap() > 0);
+
+        let latency = nexus.io_latency(false);
+        assert_eq!(latency.write.count(), 0);
+        assert_eq!(latency.read.count(), 0);
+
+        drop(handle);
+        nexus_lookup_mut(NEXUS_NAME)
+            .unwrap()
+            .destroy()
+            .await
+            .unwrap();
+    })
+    .await;
+}
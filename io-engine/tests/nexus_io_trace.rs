@@ -0,0 +1,98 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev::nexus::{
+        nexus_create,
+        nexus_lookup_mut,
+        IoTraceCapture,
+        IoTraceOp,
+        IoTraceStatus,
+        IO_TRACE_NEXUS,
+    },
+    core::{MayastorCliArgs, UntypedBdev},
+};
+use spdk_rs::DmaBuf;
+pub mod common;
+
+const NEXUS_NAME: &str = "trace_nexus";
+const CHILD0: &str = "malloc:///trace0?size_mb=64";
+const CHILD1: &str = "malloc:///trace1?size_mb=64";
+
+/// Traces the writes to a nexus, and checks the capture survives the round
+/// trip through the capture file format.
+#[tokio::test]
+async fn nexus_io_trace() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    let capture = ms
+        .spawn(async {
+            nexus_create(
+                NEXUS_NAME,
+                32 * 1024 * 1024,
+                None,
+                &[CHILD0.to_string(), CHILD1.to_string()],
+            )
+            .await
+            .unwrap();
+
+            let handle = UntypedBdev::open_by_name(NEXUS_NAME, true)
+                .unwrap()
+                .into_handle()
+                .unwrap();
+            let mut buf = DmaBuf::new(4096, 9).unwrap();
+            buf.fill(0x5a);
+
+            // I/Os are not traced until the tracing is enabled.
+            handle.write_at(0, &buf).await.unwrap();
+            let nexus = nexus_lookup_mut(NEXUS_NAME).unwrap();
+            assert_eq!(nexus.io_trace_status(), (false, 0));
+
+            nexus.set_io_trace(true, 0);
+            for i in 0 .. 4 {
+                handle.write_at(i * 4096, &buf).await.unwrap();
+            }
+            nexus.set_io_trace(false, 0);
+            handle.write_at(0, &buf).await.unwrap();
+
+            let capture = nexus.io_trace_capture();
+            drop(handle);
+            nexus_lookup_mut(NEXUS_NAME)
+                .unwrap()
+                .destroy()
+                .await
+                .unwrap();
+            capture
+        })
+        .await;
+
+    // One record per nexus write and per child write.
+    assert_eq!(capture.records.len(), 12);
+    assert!(capture
+        .records
+        .windows(2)
+        .all(|w| w[0].timestamp_ns <= w[1].timestamp_ns));
+    assert!(capture.records.iter().all(|r| r.op() == IoTraceOp::Write
+        && r.status() == IoTraceStatus::Success
+        && r.num_blocks == 8));
+
+    let nexus_records = capture
+        .records
+        .iter()
+        .filter(|r| r.child == IO_TRACE_NEXUS)
+        .collect::<Vec<_>>();
+    assert_eq!(nexus_records.len(), 4);
+    let offsets = nexus_records.iter().map(|r| r.offset).collect::<Vec<_>>();
+    assert_eq!(offsets, vec![0, 8, 16, 24]);
+
+    let mut uris = capture
+        .header
+        .children
+        .iter()
+        .map(|c| c.uri.as_str())
+        .collect::<Vec<_>>();
+    uris.sort_unstable();
+    assert_eq!(uris, vec![CHILD0, CHILD1]);
+
+    let mut file = Vec::new();
+    capture.write_to(&mut file).unwrap();
+    assert_eq!(IoTraceCapture::read_from(file.as_slice()).unwrap(), capture);
+    assert!(IoTraceCapture::read_from(&file[1 ..]).is_err());
+}
@@ -0,0 +1,120 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev::nexus::{
+        nexus_create_v2,
+        nexus_lookup_mut,
+        NexusLayout,
+        NexusNvmeParams,
+    },
+    core::{
+        partition::DATA_PARTITION_OFFSET,
+        MayastorCliArgs,
+        UntypedBdev,
+        UntypedBdevHandle,
+    },
+};
+pub mod common;
+
+const STRIPE_SIZE: u64 = 64 * 1024;
+
+async fn create_nexus(name: &str, layout: NexusLayout, children: &[&str]) {
+    let children: Vec<String> = children
+        .iter()
+        .map(|c| format!("malloc:///{c}?size_mb=16"))
+        .collect();
+
+    nexus_create_v2(
+        name,
+        20 * 1024 * 1024,
+        &uuid::Uuid::new_v4().to_string(),
+        NexusNvmeParams::default(),
+        &children,
+        None,
+        layout,
+    )
+    .await
+    .unwrap();
+}
+
+/// Checks a striped nexus is larger than its children, places its stripes
+/// on the children in turn, and refuses to add children.
+#[tokio::test]
+async fn nexus_stripe() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        create_nexus(
+            "stripe_nexus",
+            NexusLayout::Stripe {
+                stripe_size: STRIPE_SIZE,
+            },
+            &["stripe0", "stripe1", "stripe2"],
+        )
+        .await;
+
+        let nexus = nexus_lookup_mut("stripe_nexus").unwrap();
+        assert!(nexus.size_in_bytes() > 16 * 1024 * 1024);
+
+        let h = UntypedBdev::open_by_name("stripe_nexus", true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = h.dma_malloc(3 * STRIPE_SIZE).unwrap();
+        for (i, b) in buf.as_mut_slice().iter_mut().enumerate() {
+            *b = (i as u64 / STRIPE_SIZE) as u8 + 1;
+        }
+        h.write_at(0, &buf).await.unwrap();
+
+        let mut read = h.dma_malloc(3 * STRIPE_SIZE).unwrap();
+        h.read_at(0, &mut read).await.unwrap();
+        assert_eq!(read.as_slice(), buf.as_slice());
+        drop(h);
+
+        for (i, child) in ["stripe0", "stripe1", "stripe2"].iter().enumerate() {
+            let h = UntypedBdevHandle::open(child, false, false).unwrap();
+            let mut read = h.dma_malloc(STRIPE_SIZE).unwrap();
+            h.read_at(DATA_PARTITION_OFFSET, &mut read).await.unwrap();
+            assert!(read.as_slice().iter().all(|b| *b == i as u8 + 1));
+        }
+
+        assert!(nexus
+            .as_mut()
+            .add_child("malloc:///stripe3?size_mb=16", true)
+            .await
+            .is_err());
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
+
+/// Checks a concatenated nexus is larger than its children, and reads back
+/// the data of an I/O which spans two children.
+#[tokio::test]
+async fn nexus_concat() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        create_nexus("concat_nexus", NexusLayout::Concat, &["cat0", "cat1"])
+            .await;
+
+        let nexus = nexus_lookup_mut("concat_nexus").unwrap();
+        assert!(nexus.size_in_bytes() > 16 * 1024 * 1024);
+
+        let h = UntypedBdev::open_by_name("concat_nexus", true)
+            .unwrap()
+            .into_handle()
+            .unwrap();
+        let mut buf = h.dma_malloc(2 * 1024 * 1024).unwrap();
+        for (i, b) in buf.as_mut_slice().iter_mut().enumerate() {
+            *b = (i / 4096) as u8;
+        }
+        h.write_at(10 * 1024 * 1024, &buf).await.unwrap();
+
+        let mut read = h.dma_malloc(2 * 1024 * 1024).unwrap();
+        h.read_at(10 * 1024 * 1024, &mut read).await.unwrap();
+        assert_eq!(read.as_slice(), buf.as_slice());
+        drop(h);
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
@@ -0,0 +1,135 @@
+use std::time::Duration;
+
+use common::MayastorTest;
+use io_engine::{
+    bdev::nexus::{
+        nexus_create_v2,
+        nexus_lookup_mut,
+        FaultReason,
+        NexusLayout,
+        NexusNvmeParams,
+        NexusStatus,
+    },
+    core::{MayastorCliArgs, UntypedBdev, UntypedBdevHandle},
+    rebuild::RebuildState,
+};
+pub mod common;
+
+const NEXUS_NAME: &str = "parity_nexus";
+const UNIT_SIZE: u64 = 64 * 1024;
+
+fn child_uri(name: &str) -> String {
+    format!("malloc:///{name}?size_mb=16")
+}
+
+fn nexus_handle() -> UntypedBdevHandle {
+    UntypedBdev::open_by_name(NEXUS_NAME, true)
+        .unwrap()
+        .into_handle()
+        .unwrap()
+}
+
+/// Reads back the data written by `parity_nexus`: rows of units filled with
+/// their index, overwritten by a write within a unit.
+async fn check_data(h: &UntypedBdevHandle) {
+    let mut read = h.dma_malloc(6 * UNIT_SIZE).unwrap();
+    h.read_at(0, &mut read).await.unwrap();
+    for (i, b) in read.as_slice().iter().enumerate() {
+        let expected =
+            if (UNIT_SIZE + 4096 .. UNIT_SIZE + 8192).contains(&(i as u64)) {
+                0xa5
+            } else {
+                (i as u64 / UNIT_SIZE) as u8 + 1
+            };
+        assert_eq!(*b, expected);
+    }
+}
+
+/// Checks a parity nexus reads back what it wrote, reconstructs the data of
+/// a faulted child, and regenerates a child which replaces it.
+#[tokio::test]
+async fn parity_nexus() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        let children: Vec<String> =
+            ["parity0", "parity1", "parity2"].map(child_uri).to_vec();
+        nexus_create_v2(
+            NEXUS_NAME,
+            20 * 1024 * 1024,
+            &uuid::Uuid::new_v4().to_string(),
+            NexusNvmeParams::default(),
+            &children,
+            None,
+            NexusLayout::Parity {
+                parity_children: 1,
+                stripe_size: UNIT_SIZE,
+            },
+        )
+        .await
+        .unwrap();
+
+        let mut nexus = nexus_lookup_mut(NEXUS_NAME).unwrap();
+        assert!(nexus.size_in_bytes() > 16 * 1024 * 1024);
+
+        // Three rows, and a write within a unit which reads the rest of its
+        // row to update the parity.
+        let h = nexus_handle();
+        let mut buf = h.dma_malloc(6 * UNIT_SIZE).unwrap();
+        for (i, b) in buf.as_mut_slice().iter_mut().enumerate() {
+            *b = (i as u64 / UNIT_SIZE) as u8 + 1;
+        }
+        h.write_at(0, &buf).await.unwrap();
+        let mut small = h.dma_malloc(4096).unwrap();
+        small.fill(0xa5);
+        h.write_at(UNIT_SIZE + 4096, &small).await.unwrap();
+        check_data(&h).await;
+        drop(h);
+
+        // The units of the faulted child are reconstructed from the others.
+        nexus
+            .as_mut()
+            .fault_child(&children[1], FaultReason::OfflinePermanent)
+            .await
+            .unwrap();
+        assert_eq!(nexus.status(), NexusStatus::Degraded);
+        let h = nexus_handle();
+        check_data(&h).await;
+        drop(h);
+
+        // The children of a parity nexus cannot be added.
+        assert!(nexus
+            .as_mut()
+            .add_child(&child_uri("parity3"), true)
+            .await
+            .is_err());
+
+        let new_uri = child_uri("parity3");
+        nexus
+            .as_mut()
+            .replace_child(&children[1], &new_uri)
+            .await
+            .unwrap();
+        common::wait_for_rebuild(
+            new_uri.clone(),
+            RebuildState::Completed,
+            Duration::from_secs(20),
+        )
+        .await;
+        assert!(nexus.child(&new_uri).unwrap().is_healthy());
+        assert_eq!(nexus.status(), NexusStatus::Online);
+
+        // The regenerated child holds the units of the replaced child: the
+        // data reads back with another child faulted.
+        nexus
+            .as_mut()
+            .fault_child(&children[0], FaultReason::OfflinePermanent)
+            .await
+            .unwrap();
+        let h = nexus_handle();
+        check_data(&h).await;
+        drop(h);
+
+        nexus.destroy().await.unwrap();
+    })
+    .await;
+}
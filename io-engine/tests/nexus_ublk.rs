@@ -0,0 +1,133 @@
+use io_engine::{
+    bdev::nexus::{nexus_create, nexus_lookup_mut},
+    core::{mayastor_env_stop, MayastorCliArgs, Protocol, Reactor},
+};
+use std::{
+    fs::OpenOptions,
+    io::{Read, Seek, SeekFrom, Write},
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
+};
+
+pub mod common;
+use common::MayastorTest;
+
+/// Control device of the ublk_drv kernel module.
+const UBLK_CONTROL: &str = "/dev/ublk-control";
+
+/// Returns the path of the block device of a ublk share URI.
+fn device_path(uri: &str) -> String {
+    uri.strip_prefix("file://").unwrap().to_string()
+}
+
+/// A block aligned for direct I/O.
+#[repr(C, align(4096))]
+struct Block([u8; 4096]);
+
+/// Writes a block to the device and reads it back, bypassing the page cache.
+fn write_read(path: &str, pattern: u8) {
+    let mut f = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(libc::O_DIRECT)
+        .open(path)
+        .unwrap();
+
+    let mut buf = Box::new(Block([pattern; 4096]));
+    f.seek(SeekFrom::Start(8192)).unwrap();
+    f.write_all(&buf.0).unwrap();
+
+    buf.0.fill(0);
+    f.seek(SeekFrom::Start(8192)).unwrap();
+    f.read_exact(&mut buf.0).unwrap();
+    assert!(buf.0.iter().all(|b| *b == pattern));
+}
+
+#[tokio::test]
+async fn nexus_ublk_share_unshare() {
+    if !Path::new(UBLK_CONTROL).exists() {
+        eprintln!("{UBLK_CONTROL} not found, skipping (is ublk_drv loaded?)");
+        return;
+    }
+
+    let args = MayastorCliArgs {
+        reactor_mask: "0x3".into(),
+        ..Default::default()
+    };
+    let ms = MayastorTest::new(args);
+
+    // share two nexuses, which must get distinct devices
+    let uris = ms
+        .spawn(async {
+            let mut uris = Vec::new();
+            for i in 0 .. 2 {
+                let name = format!("nexus{i}");
+                Reactor::block_on(async move {
+                    nexus_create(
+                        &name,
+                        32 * 1024 * 1024,
+                        None,
+                        &[format!("malloc:///malloc{i}?size_mb=64")],
+                    )
+                    .await
+                    .unwrap();
+                });
+            }
+            for i in 0 .. 2 {
+                let mut nexus = nexus_lookup_mut(&format!("nexus{i}")).unwrap();
+                let uri =
+                    nexus.as_mut().share(Protocol::Ublk, None).await.unwrap();
+                assert!(uri.starts_with("file:///dev/ublkb"));
+
+                // sharing again over the same protocol is idempotent
+                let uri2 =
+                    nexus.as_mut().share(Protocol::Ublk, None).await.unwrap();
+                assert_eq!(uri, uri2);
+
+                // but not over another protocol
+                assert!(nexus
+                    .as_mut()
+                    .share(Protocol::Nvmf, None)
+                    .await
+                    .is_err());
+                uris.push(uri);
+            }
+            uris
+        })
+        .await;
+
+    assert_ne!(uris[0], uris[1]);
+
+    // the I/O of the devices is served by the reactors of the engine
+    for (i, uri) in uris.iter().enumerate() {
+        let path = device_path(uri);
+        assert!(Path::new(&path).exists());
+        let pattern = 0xa0 + i as u8;
+        tokio::task::spawn_blocking(move || write_read(&path, pattern))
+            .await
+            .unwrap();
+    }
+
+    ms.spawn(async {
+        for i in 0 .. 2 {
+            let nexus = nexus_lookup_mut(&format!("nexus{i}")).unwrap();
+            nexus.unshare_nexus().await.unwrap();
+            let nexus = nexus_lookup_mut(&format!("nexus{i}")).unwrap();
+            assert_eq!(nexus.get_share_uri(), None);
+        }
+    })
+    .await;
+
+    for uri in &uris {
+        assert!(!Path::new(&device_path(uri)).exists());
+    }
+
+    ms.spawn(async {
+        for i in 0 .. 2 {
+            let nexus = nexus_lookup_mut(&format!("nexus{i}")).unwrap();
+            nexus.destroy().await.unwrap();
+        }
+        mayastor_env_stop(0);
+    })
+    .await;
+}
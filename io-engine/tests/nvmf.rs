@@ -11,16 +11,24 @@ use io_engine::{
 };
 
 pub mod common;
-use common::compose::{
-    rpc::v0::{
-        mayastor::{BdevShareRequest, BdevUri, CreateReply},
-        GrpcConnect,
+use common::{
+    compose::{
+        rpc::{
+            v0::{
+                mayastor::{BdevShareRequest, BdevUri, CreateReply},
+                GrpcConnect,
+            },
+            v1,
+        },
+        Binary,
+        Builder,
+        ComposeTest,
     },
-    Binary,
-    Builder,
-    ComposeTest,
+    nexus::NexusBuilder,
+    nvme::nvme_discover,
 };
 use regex::Regex;
+use std::collections::BTreeSet;
 
 static DISKNAME1: &str = "/tmp/disk1.img";
 static BDEVNAME1: &str = "aio:///tmp/disk1.img?blk_size=512";
@@ -195,3 +203,72 @@ async fn nvmf_set_target_interface() {
     // test_fail("10.15.0.0/16", vec!["-T", "mac:123"]).await;
     // test_fail("10.15.0.0/16", vec!["-T", "ip:hello"]).await;
 }
+
+#[tokio::test]
+async fn nvmf_nexus_listeners() {
+    common::composer_init();
+
+    let test = Builder::new()
+        .name("cargo-test")
+        .network("10.15.0.0/16")
+        .unwrap()
+        .add_container_bin(
+            "ms1",
+            Binary::from_dbg("io-engine")
+                .with_args(vec!["-T", "subnet:10.15.0.0/16,name:lo"]),
+        )
+        .with_clean(true)
+        .build()
+        .await
+        .unwrap();
+
+    let conn = v1::GrpcConnect::new(&test);
+    let ms1 = conn.grpc_handle_shared("ms1").await.unwrap();
+    let ip = ms1.endpoint().ip().to_string();
+
+    let mut nex = NexusBuilder::new(ms1)
+        .with_name("nexus0")
+        .with_new_uuid()
+        .with_size_mb(32)
+        .with_bdev("malloc:///mem0?size_mb=64");
+    nex.create().await.unwrap();
+
+    // the addresses the target advertises for the nexus
+    let listeners = || {
+        nvme_discover(&ip)
+            .into_iter()
+            .filter(|entry| entry.get("subnqn") == Some(&nex.nqn()))
+            .map(|entry| entry["traddr"].clone())
+            .collect::<BTreeSet<_>>()
+    };
+    let addresses =
+        |ips: &[&str]| ips.iter().map(|ip| ip.to_string()).collect::<Vec<_>>();
+
+    // listen on the given address only
+    let nexus = nex.publish_on(addresses(&[&ip])).await.unwrap();
+    assert!(nexus.device_uri.contains(&ip));
+    assert_eq!(listeners(), BTreeSet::from([ip.clone()]));
+
+    // publishing again changes the listeners
+    nex.publish_on(addresses(&[&ip, "127.0.0.1"]))
+        .await
+        .unwrap();
+    assert_eq!(
+        listeners(),
+        BTreeSet::from([ip.clone(), "127.0.0.1".to_string()])
+    );
+
+    nex.publish_on(addresses(&["127.0.0.1"])).await.unwrap();
+    assert_eq!(listeners(), BTreeSet::from(["127.0.0.1".to_string()]));
+
+    // an address the target does not listen on is refused
+    assert!(nex.publish_on(addresses(&["10.16.0.1"])).await.is_err());
+    assert_eq!(listeners(), BTreeSet::from(["127.0.0.1".to_string()]));
+
+    // no address means all of them
+    nex.publish_on(vec![]).await.unwrap();
+    assert_eq!(
+        listeners(),
+        BTreeSet::from([ip.clone(), "127.0.0.1".to_string()])
+    );
+}
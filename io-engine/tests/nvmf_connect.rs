@@ -12,6 +12,7 @@ use io_engine::{
     core::{CoreError, MayastorCliArgs, Share},
     lvs::{Lvs, LvsLvol},
     pool_backend::PoolArgs,
+    subsys::NvmfSubsystem,
 };
 
 static MAYASTOR: OnceCell<MayastorTest> = OnceCell::new();
@@ -83,6 +84,32 @@ async fn nvmf_connect_async_drop() {
     deinit_nvmf_share().await;
 }
 
+/// Lists the hosts connected to a replica subsystem and disconnects them.
+#[tokio::test]
+async fn nvmf_connected_hosts() {
+    common::composer_init();
+
+    let uri = init_nvmf_share().await;
+    let nqn = url::Url::parse(&uri).unwrap().path()[1 ..].to_string();
+
+    spawn_device_create(&uri).await;
+
+    get_ms()
+        .spawn(async move {
+            let ss = NvmfSubsystem::lookup_by_nqn(&nqn).unwrap();
+            let hosts = ss.controllers();
+            assert_eq!(hosts.len(), 1);
+
+            let host = hosts[0].host_nqn.clone();
+            assert!(ss.is_host_connected(&host));
+            ss.disconnect_host(&host).await.unwrap();
+        })
+        .await;
+
+    spawn_device_destroy(&uri).await.unwrap();
+    deinit_nvmf_share().await;
+}
+
 const POOL_SIZE: u64 = 64 * 1024 * 1024;
 const BDEV_NAME: &str = "malloc:///mem0?size_mb=128";
 const POOL_NAME: &str = "pool_0";
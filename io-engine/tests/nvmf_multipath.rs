@@ -0,0 +1,196 @@
+use std::{pin::Pin, time::Duration};
+
+use once_cell::sync::OnceCell;
+
+pub mod common;
+
+use common::MayastorTest;
+
+use io_engine::{
+    bdev::{
+        device_create,
+        device_destroy,
+        device_open,
+        nexus::NvmeAnaState,
+        NvmePathState,
+        NVME_CONTROLLERS,
+    },
+    core::{MayastorCliArgs, Share, ShareProps},
+    lvs::{Lvs, LvsLvol},
+    pool_backend::PoolArgs,
+    subsys::NvmfSubsystem,
+};
+
+static MAYASTOR: OnceCell<MayastorTest> = OnceCell::new();
+
+fn get_ms() -> &'static MayastorTest<'static> {
+    MAYASTOR.get_or_init(|| MayastorTest::new(MayastorCliArgs::default()))
+}
+
+const POOL_SIZE: u64 = 64 * 1024 * 1024;
+const BDEV_NAME: &str = "malloc:///mem0?size_mb=128";
+const POOL_NAME: &str = "pool_0";
+const REPL_NAME: &str = "repl_0";
+const REPL_UUID: &str = "65acdaac-14c4-41d8-a55e-d03bfd7185a4";
+
+const ANA_BDEV_NAME: &str = "malloc:///mem1?size_mb=128";
+const ANA_POOL_NAME: &str = "pool_1";
+const ANA_REPL_NAME: &str = "repl_1";
+const ANA_REPL_UUID: &str = "a0d6b3b2-0b8e-4d3c-9a52-0e6f1f6f3c11";
+
+/// Connects to a replica with an alternate path, which is not reachable,
+/// and checks that the controller reports both paths.
+#[tokio::test]
+async fn nvmf_multipath_paths() {
+    common::composer_init();
+
+    let uri = get_ms()
+        .spawn(async {
+            let pool = Lvs::create_or_import(PoolArgs {
+                name: POOL_NAME.to_string(),
+                disks: vec![BDEV_NAME.to_string()],
+                uuid: None,
+            })
+            .await
+            .unwrap();
+
+            let mut lvol = pool
+                .create_lvol(REPL_NAME, POOL_SIZE, Some(REPL_UUID), false)
+                .await
+                .unwrap();
+
+            let mut lvol = Pin::new(&mut lvol);
+            lvol.as_mut().share_nvmf(None).await.unwrap();
+            lvol.as_bdev().share_uri().unwrap()
+        })
+        .await;
+
+    // the primary path is listed as an alternate path as well, and must be
+    // ignored
+    let host = url::Url::parse(&uri).unwrap();
+    let primary =
+        format!("{}:{}", host.host_str().unwrap(), host.port().unwrap());
+    let uri = format!("{uri}&paths={primary},127.0.0.1:4499");
+
+    get_ms()
+        .spawn({
+            let uri = uri.clone();
+            async move {
+                let name = device_create(&uri).await.unwrap();
+
+                let ctrlr = NVME_CONTROLLERS
+                    .lookup_by_name(name.strip_suffix("n1").unwrap())
+                    .unwrap();
+                let paths = ctrlr.lock().path_info();
+
+                assert_eq!(paths.len(), 2);
+                assert_eq!(paths[0].state, NvmePathState::Active);
+                assert_eq!(paths[1].state, NvmePathState::Standby);
+                assert_eq!(paths[1].address, "127.0.0.1");
+                assert_eq!(paths[1].port, "4499");
+
+                device_destroy(&uri).await.unwrap();
+            }
+        })
+        .await;
+
+    get_ms()
+        .spawn(async {
+            Lvs::lookup(POOL_NAME).unwrap().destroy().await.unwrap();
+        })
+        .await;
+}
+
+/// Makes the namespace inaccessible over the only reachable path and checks
+/// that I/O fails over the paths, and is failed rather than retried forever
+/// once none of them is usable.
+#[tokio::test]
+async fn nvmf_multipath_ana_failover() {
+    common::composer_init();
+
+    let uri = get_ms()
+        .spawn(async {
+            let pool = Lvs::create_or_import(PoolArgs {
+                name: ANA_POOL_NAME.to_string(),
+                disks: vec![ANA_BDEV_NAME.to_string()],
+                uuid: None,
+            })
+            .await
+            .unwrap();
+
+            let mut lvol = pool
+                .create_lvol(
+                    ANA_REPL_NAME,
+                    POOL_SIZE,
+                    Some(ANA_REPL_UUID),
+                    false,
+                )
+                .await
+                .unwrap();
+
+            let mut lvol = Pin::new(&mut lvol);
+            lvol.as_mut()
+                .share_nvmf(Some(ShareProps::new().with_ana(true)))
+                .await
+                .unwrap();
+            lvol.as_bdev().share_uri().unwrap()
+        })
+        .await;
+
+    let url = url::Url::parse(&uri).unwrap();
+    let nqn = url.path().trim_start_matches('/').to_string();
+    let uri = format!("{uri}&paths=127.0.0.1:4499");
+
+    let name = get_ms()
+        .spawn({
+            let uri = uri.clone();
+            async move {
+                let name = device_create(&uri).await.unwrap();
+                let handle =
+                    device_open(&name, false).unwrap().into_handle().unwrap();
+                let buf = handle.dma_malloc(4096).unwrap();
+                handle.write_at(0, &buf).await.unwrap();
+
+                let ss = NvmfSubsystem::lookup_by_nqn(&nqn).unwrap();
+                ss.pause().await.unwrap();
+                ss.set_ana_state(NvmeAnaState::InaccessibleState as u32)
+                    .await
+                    .unwrap();
+                ss.resume().await.unwrap();
+                name
+            }
+        })
+        .await;
+
+    // the alternate path is unreachable and the namespace is inaccessible
+    // over the primary one, so the I/O must eventually fail
+    let paths = tokio::time::timeout(
+        Duration::from_secs(60),
+        get_ms().spawn(async move {
+            let handle =
+                device_open(&name, false).unwrap().into_handle().unwrap();
+            let buf = handle.dma_malloc(4096).unwrap();
+            assert!(handle.write_at(0, &buf).await.is_err());
+
+            NVME_CONTROLLERS
+                .lookup_by_name(name.strip_suffix("n1").unwrap())
+                .map(|c| c.lock().path_info())
+        }),
+    )
+    .await
+    .expect("I/O was not failed after failing over all paths");
+
+    if let Some(paths) = paths {
+        assert_eq!(paths.len(), 2);
+        assert_eq!(paths[0].state, NvmePathState::Inaccessible);
+        assert_eq!(paths[0].ana_state, NvmeAnaState::InaccessibleState);
+        assert_eq!(paths[1].state, NvmePathState::Failed);
+    }
+
+    get_ms()
+        .spawn(async move {
+            device_destroy(&uri).await.ok();
+            Lvs::lookup(ANA_POOL_NAME).unwrap().destroy().await.unwrap();
+        })
+        .await;
+}
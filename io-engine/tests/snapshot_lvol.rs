@@ -12,8 +12,9 @@ use io_engine::{
         SnapshotParams,
         SnapshotXattrs,
         UntypedBdev,
+        UntypedBdevHandle,
     },
-    lvs::{Lvol, Lvs},
+    lvs::{Lvol, LvolRange, Lvs},
     pool_backend::PoolArgs,
 };
 
@@ -498,3 +499,110 @@ async fn test_list_pool_snapshots() {
     })
     .await;
 }
+
+#[tokio::test]
+async fn test_lvol_snapshot_changed_ranges() {
+    let ms = get_ms();
+
+    ms.spawn(async move {
+        // Create a pool and a thin lvol.
+        let pool =
+            create_test_pool("pool7", "malloc:///disk7?size_mb=64".to_string())
+                .await;
+        let lvol = pool
+            .create_lvol(
+                "lvol7",
+                16 * 1024 * 1024,
+                Some(&Uuid::new_v4().to_string()),
+                true,
+            )
+            .await
+            .expect("Failed to create test lvol");
+        let cluster_size = lvol.usage().cluster_size;
+
+        let handle = UntypedBdevHandle::open(&lvol.name(), true, false)
+            .expect("Failed to open test lvol");
+        let buf = handle.dma_malloc(4096).unwrap();
+
+        // Write the first cluster and take the first snapshot.
+        handle.write_at(0, &buf).await.expect("Failed to write");
+        let snapshot_params = SnapshotParams::new(
+            Some(String::from("e71")),
+            Some(lvol.uuid()),
+            Some(Uuid::new_v4().to_string()),
+            Some(String::from("snap71")),
+            Some(Uuid::new_v4().to_string()),
+        );
+        let snap1 = lvol
+            .create_snapshot(snapshot_params)
+            .await
+            .expect("Failed to create a snapshot");
+
+        // Write the third cluster and take the second snapshot.
+        handle
+            .write_at(2 * cluster_size, &buf)
+            .await
+            .expect("Failed to write");
+        let snapshot_params = SnapshotParams::new(
+            Some(String::from("e72")),
+            Some(lvol.uuid()),
+            Some(Uuid::new_v4().to_string()),
+            Some(String::from("snap72")),
+            Some(Uuid::new_v4().to_string()),
+        );
+        let snap2 = lvol
+            .create_snapshot(snapshot_params)
+            .await
+            .expect("Failed to create a snapshot");
+        drop(handle);
+
+        assert_eq!(
+            lvol.parent_snapshot().map(|s| s.uuid()),
+            Some(snap2.uuid())
+        );
+        assert_eq!(
+            snap2.parent_snapshot().map(|s| s.uuid()),
+            Some(snap1.uuid())
+        );
+
+        // Only the third cluster changed since the first snapshot.
+        let ranges = snap2.changed_ranges(Some(&snap1)).unwrap();
+        assert_eq!(
+            ranges,
+            vec![LvolRange {
+                offset: 2 * cluster_size,
+                len: cluster_size,
+            }]
+        );
+
+        // Without a base, all the data of the snapshot is covered.
+        let ranges = snap2.changed_ranges(None).unwrap();
+        assert_eq!(
+            ranges,
+            vec![
+                LvolRange {
+                    offset: 0,
+                    len: cluster_size,
+                },
+                LvolRange {
+                    offset: 2 * cluster_size,
+                    len: cluster_size,
+                }
+            ]
+        );
+        let ranges = snap1.changed_ranges(None).unwrap();
+        assert_eq!(
+            ranges,
+            vec![LvolRange {
+                offset: 0,
+                len: cluster_size,
+            }]
+        );
+
+        // The base must be older than the snapshot.
+        snap1
+            .changed_ranges(Some(&snap2))
+            .expect_err("Newer snapshot accepted as base");
+    })
+    .await;
+}
@@ -23,8 +23,11 @@ use io_engine::{
         device_destroy,
         device_open,
         nexus::{
+            create_group_snapshot,
             nexus_create,
             nexus_lookup_mut,
+            FaultReason,
+            NexusGroupSnapshotDescriptor,
             NexusReplicaSnapshotDescriptor,
         },
         Nexus,
@@ -33,7 +36,7 @@ use io_engine::{
     subsys::{Config, NvmeBdevOpts},
 };
 
-use std::{pin::Pin, str};
+use std::{pin::Pin, str, time::Duration};
 use uuid::Uuid;
 
 static MAYASTOR: OnceCell<MayastorTest> = OnceCell::new();
@@ -229,6 +232,7 @@ async fn test_replica_handle_snapshot() {
         .list_snapshot(ListSnapshotsRequest {
             source_uuid: None,
             snapshot_uuid: None,
+            group_id: None,
         })
         .await
         .expect("Failed to list snapshots on replica node")
@@ -269,6 +273,7 @@ async fn test_replica_handle_snapshot() {
         .list_snapshot(ListSnapshotsRequest {
             source_uuid: None,
             snapshot_uuid: None,
+            group_id: None,
         })
         .await
         .expect("Failed to list snapshots on replica node")
@@ -286,10 +291,14 @@ async fn test_replica_handle_snapshot() {
 #[tokio::test]
 async fn test_multireplica_nexus_snapshot() {
     let ms = get_ms();
-    let (_test, urls) = launch_instance(true).await;
+    let (test, urls) = launch_instance(true).await;
 
     ms.spawn(async move {
-        let nexus = create_nexus(&urls).await;
+        let uris = [
+            format!("{}?uuid={}", urls[0], replica1_uuid()),
+            format!("{}?uuid={}", urls[1], replica2_uuid()),
+        ];
+        let nexus = create_nexus(&uris).await;
 
         let snapshot_params = SnapshotParams::new(
             Some(String::from("e1")),
@@ -312,12 +321,88 @@ async fn test_multireplica_nexus_snapshot() {
             },
         ];
 
-        nexus
+        let res = nexus
             .create_snapshot(snapshot_params, replicas)
             .await
-            .expect_err(
-                "Snapshot successfully created for a multireplica nexus",
+            .expect("Failed to create multireplica nexus snapshot");
+
+        assert_eq!(res.replicas_skipped.len(), 0);
+        assert_eq!(res.replicas_done.len(), 2);
+        for r in &res.replicas_done {
+            assert_eq!(
+                r.status, 0,
+                "Nexus snapshot operation failed on replica {}",
+                r.replica_uuid
             );
+        }
+    })
+    .await;
+
+    let conn = GrpcConnect::new(&test);
+    let mut ms1 = conn.grpc_handle("ms1").await.unwrap();
+    let snapshots = ms1
+        .snapshot
+        .list_snapshot(ListSnapshotsRequest {
+            source_uuid: None,
+            snapshot_uuid: None,
+            group_id: None,
+        })
+        .await
+        .expect("Failed to list snapshots on replica node")
+        .into_inner()
+        .snapshots;
+
+    assert_eq!(snapshots.len(), 2, "Snapshots not created on all replicas");
+}
+
+#[tokio::test]
+async fn test_nexus_snapshot_skips_unhealthy_replicas() {
+    let ms = get_ms();
+    let (_test, urls) = launch_instance(true).await;
+
+    ms.spawn(async move {
+        let uris = [
+            format!("{}?uuid={}", urls[0], replica1_uuid()),
+            format!("{}?uuid={}", urls[1], replica2_uuid()),
+        ];
+        let mut nexus = create_nexus(&uris).await;
+
+        nexus
+            .as_mut()
+            .fault_child(&uris[1], FaultReason::OfflinePermanent)
+            .await
+            .expect("Failed to fault replica");
+
+        let snapshot_params = SnapshotParams::new(
+            Some(String::from("e1")),
+            Some(String::from("p1")),
+            Some(Uuid::new_v4().to_string()),
+            Some(String::from("s1")),
+            Some(Uuid::new_v4().to_string()),
+        );
+
+        let replicas = vec![
+            NexusReplicaSnapshotDescriptor {
+                replica_uuid: replica1_uuid(),
+                skip: false,
+                snapshot_uuid: Some(Uuid::new_v4().to_string()),
+            },
+            NexusReplicaSnapshotDescriptor {
+                replica_uuid: replica2_uuid(),
+                skip: false,
+                snapshot_uuid: Some(Uuid::new_v4().to_string()),
+            },
+        ];
+
+        let res = nexus
+            .create_snapshot(snapshot_params, replicas)
+            .await
+            .expect("Failed to create nexus snapshot with a faulted replica");
+
+        assert_eq!(res.replicas_skipped, vec![replica2_uuid()]);
+        assert_eq!(res.replicas_done.len(), 1);
+        assert_eq!(res.replicas_done[0].replica_uuid, replica1_uuid());
+        assert_eq!(res.replicas_done[0].status, 0);
     })
     .await;
 }
@@ -348,6 +433,7 @@ async fn test_list_no_snapshots() {
         .list_snapshot(ListSnapshotsRequest {
             source_uuid: None,
             snapshot_uuid: None,
+            group_id: None,
         })
         .await
         .expect("Failed to list snapshots on replica node")
@@ -387,6 +473,7 @@ async fn test_nexus_snapshot() {
         .list_snapshot(ListSnapshotsRequest {
             source_uuid: None,
             snapshot_uuid: None,
+            group_id: None,
         })
         .await
         .expect("Failed to list snapshots on replica node")
@@ -449,6 +536,7 @@ async fn test_nexus_snapshot() {
         .list_snapshot(ListSnapshotsRequest {
             source_uuid: None,
             snapshot_uuid: None,
+            group_id: None,
         })
         .await
         .expect("Failed to list snapshots on replica node")
@@ -462,3 +550,80 @@ async fn test_nexus_snapshot() {
             .expect("Snapshot is not created on remote replica"),
     );
 }
+
+#[tokio::test]
+async fn test_group_snapshot() {
+    let ms = get_ms();
+    let (test, urls) = launch_instance(true).await;
+    let group_id = Uuid::new_v4().to_string();
+
+    let gid = group_id.clone();
+    ms.spawn(async move {
+        // Create two single replica nexuses.
+        let nexuses = [
+            ("group_nexus1", replica1_uuid(), &urls[0]),
+            ("group_nexus2", replica2_uuid(), &urls[1]),
+        ];
+        let mut group = Vec::new();
+
+        for (name, replica_uuid, url) in nexuses {
+            let nexus_uuid = Uuid::new_v4().to_string();
+            nexus_create(
+                name,
+                REPLICA_SIZE,
+                Some(&nexus_uuid),
+                &[format!("{url}?uuid={replica_uuid}")],
+            )
+            .await
+            .expect("Failed to create a nexus");
+
+            group.push(NexusGroupSnapshotDescriptor {
+                snapshot: SnapshotParams::new(
+                    Some(String::from("e1")),
+                    Some(nexus_uuid.clone()),
+                    None,
+                    Some(format!("{name}-snap")),
+                    None,
+                ),
+                nexus_uuid,
+                replicas: vec![NexusReplicaSnapshotDescriptor {
+                    replica_uuid,
+                    skip: false,
+                    snapshot_uuid: Some(Uuid::new_v4().to_string()),
+                }],
+            });
+        }
+
+        let res = create_group_snapshot(&gid, group, Duration::from_secs(5))
+            .await
+            .expect("Failed to create group snapshot");
+
+        assert_eq!(res.nexuses.len(), 2);
+        for (_, status) in &res.nexuses {
+            assert_eq!(status.replicas_done.len(), 1);
+            assert_eq!(status.replicas_done[0].status, 0);
+        }
+
+        for name in ["group_nexus1", "group_nexus2"] {
+            nexus_lookup_mut(name).unwrap().destroy().await.unwrap();
+        }
+    })
+    .await;
+
+    let conn = GrpcConnect::new(&test);
+    let mut ms1 = conn.grpc_handle("ms1").await.unwrap();
+    let snapshots = ms1
+        .snapshot
+        .list_snapshot(ListSnapshotsRequest {
+            source_uuid: None,
+            snapshot_uuid: None,
+            group_id: Some(group_id.clone()),
+        })
+        .await
+        .expect("Failed to list snapshots on replica node")
+        .into_inner()
+        .snapshots;
+
+    assert_eq!(snapshots.len(), 2, "Group snapshots not found");
+    assert!(snapshots.iter().all(|s| s.txn_id == group_id));
+}
@@ -0,0 +1,188 @@
+use std::{pin::Pin, time::Duration};
+
+use once_cell::sync::OnceCell;
+
+pub mod common;
+
+use common::MayastorTest;
+
+use io_engine::{
+    core::{
+        LogicalVolume,
+        MayastorCliArgs,
+        Share,
+        SnapshotOps,
+        SnapshotParams,
+        UntypedBdevHandle,
+    },
+    lvs::{Lvol, Lvs, LvsLvol},
+    pool_backend::PoolArgs,
+    rebuild::{RebuildState, SnapshotReplicationJob},
+};
+use uuid::Uuid;
+
+static MAYASTOR: OnceCell<MayastorTest> = OnceCell::new();
+
+fn get_ms() -> &'static MayastorTest<'static> {
+    MAYASTOR.get_or_init(|| MayastorTest::new(MayastorCliArgs::default()))
+}
+
+const LVOL_SIZE: u64 = 16 * 1024 * 1024;
+const BDEV_NAME: &str = "malloc:///mem0?size_mb=128";
+const POOL_NAME: &str = "pool_0";
+const SRC_NAME: &str = "src_0";
+const SRC_UUID: &str = "5a21e4c8-0d7e-4b8a-9d6c-2f54e2c6c0a1";
+const DST_NAME: &str = "dst_0";
+const DST_UUID: &str = "8c3f3b4e-7a55-4f0e-9b1f-6a9e8b0c2d17";
+
+/// Must be called only in Mayastor context.
+async fn write_pattern(lvol: &Lvol, offset: u64, pattern: u8) {
+    let handle = UntypedBdevHandle::open(&lvol.name(), true, false).unwrap();
+    let mut buf = handle.dma_malloc(4096).unwrap();
+    buf.fill(pattern);
+    handle.write_at(offset, &buf).await.unwrap();
+}
+
+/// Must be called only in Mayastor context.
+async fn read_pattern(lvol: &Lvol, offset: u64) -> u8 {
+    let handle = UntypedBdevHandle::open(&lvol.name(), false, false).unwrap();
+    let mut buf = handle.dma_malloc(4096).unwrap();
+    handle.read_at(offset, &mut buf).await.unwrap();
+    assert!(buf.as_slice().iter().all(|b| *b == buf.as_slice()[0]));
+    buf.as_slice()[0]
+}
+
+/// Must be called only in Mayastor context.
+async fn create_snapshot(lvol: &Lvol, name: &str) -> Lvol {
+    lvol.create_snapshot(SnapshotParams::new(
+        Some(name.to_string()),
+        Some(lvol.uuid()),
+        Some(Uuid::new_v4().to_string()),
+        Some(name.to_string()),
+        Some(Uuid::new_v4().to_string()),
+    ))
+    .await
+    .unwrap()
+}
+
+/// Waits for the given number of replications to finish.
+async fn wait_replications(count: usize) {
+    for _ in 0 .. 100 {
+        let done = get_ms()
+            .spawn(async { SnapshotReplicationJob::history().len() })
+            .await;
+        if done >= count {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    panic!("snapshot replication did not finish");
+}
+
+/// Replicates a snapshot into another lvol shared over NVMe-oF, then the
+/// changes of a second snapshot, and checks the data of the target.
+#[tokio::test]
+async fn snapshot_replication_incremental() {
+    common::composer_init();
+
+    let (target_uri, cluster_size) = get_ms()
+        .spawn(async {
+            let pool = Lvs::create_or_import(PoolArgs {
+                name: POOL_NAME.to_string(),
+                disks: vec![BDEV_NAME.to_string()],
+                uuid: None,
+            })
+            .await
+            .unwrap();
+
+            let src = pool
+                .create_lvol(SRC_NAME, LVOL_SIZE, Some(SRC_UUID), true)
+                .await
+                .unwrap();
+            let mut dst = pool
+                .create_lvol(DST_NAME, LVOL_SIZE, Some(DST_UUID), true)
+                .await
+                .unwrap();
+            let mut dst = Pin::new(&mut dst);
+            dst.as_mut().share_nvmf(None).await.unwrap();
+
+            let cluster_size = src.usage().cluster_size;
+            write_pattern(&src, 0, 0xa5).await;
+            create_snapshot(&src, "snap1").await;
+
+            (dst.as_bdev().share_uri().unwrap(), cluster_size)
+        })
+        .await;
+
+    // Ship the whole first snapshot.
+    get_ms()
+        .spawn({
+            let target_uri = target_uri.clone();
+            async move {
+                let snap1 = Lvs::lookup(POOL_NAME)
+                    .unwrap()
+                    .lvols()
+                    .unwrap()
+                    .find(|l| l.name() == "snap1")
+                    .unwrap();
+                SnapshotReplicationJob::start(&snap1, None, &target_uri)
+                    .await
+                    .unwrap();
+            }
+        })
+        .await;
+    wait_replications(1).await;
+
+    // Ship the changes of the second snapshot.
+    get_ms()
+        .spawn({
+            let target_uri = target_uri.clone();
+            async move {
+                let pool = Lvs::lookup(POOL_NAME).unwrap();
+                let src = pool
+                    .lvols()
+                    .unwrap()
+                    .find(|l| l.name() == SRC_NAME)
+                    .unwrap();
+                write_pattern(&src, 2 * cluster_size, 0x5a).await;
+                let snap2 = create_snapshot(&src, "snap2").await;
+                let snap1 = snap2.parent_snapshot().unwrap();
+
+                SnapshotReplicationJob::start(
+                    &snap2,
+                    Some(&snap1),
+                    &target_uri,
+                )
+                .await
+                .unwrap();
+            }
+        })
+        .await;
+    wait_replications(2).await;
+
+    get_ms()
+        .spawn(async move {
+            let history = SnapshotReplicationJob::history();
+            assert!(history
+                .iter()
+                .all(|r| r.record.state == RebuildState::Completed));
+
+            // Only the changed cluster was copied the second time.
+            let rec = &history[1].record;
+            assert!(history[1].base_snapshot_uuid.is_some());
+            assert_eq!(rec.blocks_transferred * rec.block_size, cluster_size);
+
+            let pool = Lvs::lookup(POOL_NAME).unwrap();
+            let dst = pool
+                .lvols()
+                .unwrap()
+                .find(|l| l.name() == DST_NAME)
+                .unwrap();
+            assert_eq!(read_pattern(&dst, 0).await, 0xa5);
+            assert_eq!(read_pattern(&dst, cluster_size).await, 0);
+            assert_eq!(read_pattern(&dst, 2 * cluster_size).await, 0x5a);
+
+            pool.destroy().await.unwrap();
+        })
+        .await;
+}
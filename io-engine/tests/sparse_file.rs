@@ -0,0 +1,70 @@
+use std::os::unix::fs::MetadataExt;
+
+use common::MayastorTest;
+use io_engine::{
+    bdev::sparse_file::sparse_file_resize,
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{MayastorCliArgs, UntypedBdev, UntypedBdevHandle},
+};
+pub mod common;
+
+const FILE: &str = "/tmp/sparse_file.img";
+const DISK: &str = "aio:///tmp/sparse_file.img?size=64MiB&create=true";
+
+/// Allocated size of the file, in bytes.
+fn allocated() -> u64 {
+    std::fs::metadata(FILE).unwrap().blocks() * 512
+}
+
+/// Checks a sparse file device creates its file, stays thin when its blocks
+/// are zeroed, and grows with its file.
+#[tokio::test]
+async fn sparse_file() {
+    common::delete_file(&[FILE.into()]);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        assert_eq!(bdev_create(DISK).await.unwrap(), FILE);
+        assert_eq!(std::fs::metadata(FILE).unwrap().len(), 64 << 20);
+        assert!(allocated() < 1 << 20);
+
+        let bdev = UntypedBdev::lookup_by_name(FILE).unwrap();
+        assert_eq!(bdev.num_blocks(), (64 << 20) / 512);
+
+        let h = UntypedBdevHandle::open(FILE, true, false).unwrap();
+        let mut buf = h.dma_malloc(1 << 20).unwrap();
+        buf.fill(0xff);
+        h.write_at(0, &buf).await.unwrap();
+        assert!(allocated() >= 1 << 20);
+
+        // Zeroing the blocks punches a hole into the file.
+        h.write_zeroes_at(0, 1 << 20).await.unwrap();
+        assert!(allocated() < 1 << 20);
+        h.read_at(0, &mut buf).await.unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0));
+        drop(h);
+
+        sparse_file_resize(FILE, 128 << 20).unwrap();
+        assert_eq!(std::fs::metadata(FILE).unwrap().len(), 128 << 20);
+        let bdev = UntypedBdev::lookup_by_name(FILE).unwrap();
+        assert_eq!(bdev.num_blocks(), (128 << 20) / 512);
+
+        // Shrinking is refused.
+        assert!(matches!(
+            sparse_file_resize(FILE, 64 << 20),
+            Err(BdevError::ResizeBdevInvalid { .. })
+        ));
+
+        bdev_destroy(DISK).await.unwrap();
+        assert!(UntypedBdev::lookup_by_name(FILE).is_none());
+
+        // Recreating the device keeps the larger file.
+        bdev_create(DISK).await.unwrap();
+        let bdev = UntypedBdev::lookup_by_name(FILE).unwrap();
+        assert_eq!(bdev.num_blocks(), (128 << 20) / 512);
+        bdev_destroy(DISK).await.unwrap();
+    })
+    .await;
+
+    common::delete_file(&[FILE.into()]);
+}
@@ -0,0 +1,73 @@
+use common::MayastorTest;
+use io_engine::{
+    bdev::{
+        uring_ng::uring_bdev_modes,
+        util::uring::{kernel_support, probe_modes},
+    },
+    bdev_api::{bdev_create, bdev_destroy, BdevError},
+    core::{MayastorCliArgs, UntypedBdev, UntypedBdevHandle},
+};
+pub mod common;
+
+const FILE: &str = "/tmp/uring_ng.img";
+const DISK: &str = "uring:///tmp/uring_ng.img?fixed_files=true&\
+    fixed_buffers=true&queue_depth=64";
+
+/// Checks an io_uring device with fixed files and buffers reads back what it
+/// wrote, and reports the modes the kernel accepted.
+#[tokio::test]
+async fn uring_ng() {
+    if !kernel_support() {
+        return;
+    }
+    common::truncate_file(FILE, 64 * 1024);
+
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        assert_eq!(bdev_create(DISK).await.unwrap(), FILE);
+        let bdev = UntypedBdev::lookup_by_name(FILE).unwrap();
+        assert_eq!(bdev.driver(), "uring_ng");
+        assert_eq!(bdev.num_blocks(), (64 << 20) / 512);
+
+        let modes = uring_bdev_modes(FILE).unwrap();
+        let kernel = probe_modes();
+        assert!(!modes.sqpoll);
+        assert_eq!(modes.fixed_files, kernel.fixed_files);
+        assert_eq!(modes.fixed_buffers, kernel.fixed_buffers);
+
+        let h = UntypedBdevHandle::open(FILE, true, false).unwrap();
+        let mut buf = h.dma_malloc(1 << 20).unwrap();
+        buf.fill(0xa5);
+        h.write_at(4096, &buf).await.unwrap();
+        buf.fill(0);
+        h.read_at(4096, &mut buf).await.unwrap();
+        assert!(buf.as_slice().iter().all(|b| *b == 0xa5));
+        drop(h);
+
+        bdev_destroy(DISK).await.unwrap();
+        assert!(UntypedBdev::lookup_by_name(FILE).is_none());
+    })
+    .await;
+
+    common::delete_file(&[FILE.into()]);
+}
+
+/// Checks invalid io_uring options are refused.
+#[tokio::test]
+async fn uring_ng_invalid() {
+    let ms = MayastorTest::new(MayastorCliArgs::default());
+    ms.spawn(async {
+        for uri in [
+            "uring:///tmp/uring_ng.img?queue_depth=100",
+            "uring:///tmp/uring_ng.img?sqpoll=maybe",
+            "uring:///tmp/uring_ng.img?sqpoll=true&size=1MiB",
+        ] {
+            assert!(matches!(
+                bdev_create(uri).await,
+                Err(BdevError::InvalidUri { .. })
+                    | Err(BdevError::BoolParamParseFailed { .. })
+            ));
+        }
+    })
+    .await;
+}
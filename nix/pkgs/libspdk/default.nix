@@ -117,6 +117,7 @@ let
     [
       "--with-uring"
       "--without-uring-zns"
+      "--with-ublk"
       "--disable-unit-tests"
       "--disable-tests"
     ];